@@ -1,21 +1,49 @@
 // Anchor client for on-chain program verification
-// Verifies registry and profiles program accounts
+// Verifies registry, profiles and domains program accounts
 
+use sha2::{Digest, Sha256};
+use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use base64::Engine;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::Message;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use std::env;
 use std::str::FromStr;
 
-// Program IDs (should match programs/shadow-registry and programs/shadow-profiles)
-const REGISTRY_PROGRAM_ID: &str = "7Y8Zx9qR3sN2mP1wV5tU4fG6hK8jL0dA";
-const PROFILES_PROGRAM_ID: &str = "8Z9Ax0rS4tN3nQ2xW6uV5gH7iL9kM1eB";
+// Program IDs (should match programs/shadow-registry, shadow-profiles and shadow-domains)
+// Override with SHADOW_REGISTRY_PROGRAM_ID / SHADOW_PROFILES_PROGRAM_ID /
+// SHADOW_DOMAINS_PROGRAM_ID after deploying
+const REGISTRY_PROGRAM_ID: &str = "EzNTEahGqF6srHRjbjyUmGRPezzhMih3hrEjoiKMnw5k";
+const PROFILES_PROGRAM_ID: &str = "DvHmeTn3wawgkdMWJqow1ca5JCS4BDhhQHyfj5uDAD8G";
+const DOMAINS_PROGRAM_ID: &str = "7sn3PyHKqdUG28HXYa1oCdZk9iQwx5tnJaYK6WQRrTSH";
+
+const SITE_SEED: &[u8] = b"site";
+const DEPLOYMENT_SEED: &[u8] = b"deployment";
+const PROFILE_SEED: &[u8] = b"profile";
+const DOMAIN_SEED: &[u8] = b"domain";
+
+/// getMultipleAccounts accepts at most 100 keys per call
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+/// Mirrors `shadow_registry::MAX_MAINTAINERS`
+pub const MAX_MAINTAINERS: usize = 5;
+/// Deployment accounts closed per transaction, to stay under the size limit
+pub const MAX_CLOSES_PER_TRANSACTION: usize = 20;
 
 pub struct AnchorClient {
     rpc_url: String,
     registry_program: Pubkey,
     profiles_program: Pubkey,
+    domains_program: Pubkey,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SiteAccount {
     pub owner: Pubkey,
     pub program_address: Pubkey,
@@ -24,9 +52,22 @@ pub struct SiteAccount {
     pub storage_cid: String,
     pub created_at: i64,
     pub updated_at: i64,
+    pub version: u32, // Latest deployment; 0 before versioning
+    pub maintainers: Vec<Pubkey>, // May update, deploy and roll back besides the owner
 }
 
-#[derive(Debug, Clone)]
+/// `shadow_registry::Deployment`, one entry of a site's deployment log
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeploymentAccount {
+    pub site: Pubkey,
+    pub version: u32,
+    pub storage_cid: String,
+    pub deployer: Pubkey,
+    pub deployed_at: i64,
+    pub rollback_of: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProfileAccount {
     pub wallet: Pubkey,
     pub profile_cid: String,
@@ -35,22 +76,151 @@ pub struct ProfileAccount {
     pub updated_at: i64,
 }
 
+/// A registry or profiles account, told apart by its discriminator
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexedAccount {
+    Site(SiteAccount),
+    Deployment(DeploymentAccount),
+    Profile(ProfileAccount),
+}
+
+/// `shadow_domains::DomainRecord`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainAccount {
+    pub name: String,
+    pub owner: Pubkey,
+    pub target: Pubkey,
+    pub registered_at: i64,
+    pub updated_at: i64,
+    pub expires_at: i64,
+    pub pending_owner: Option<Pubkey>, // Recipient of an open transfer offer
+    pub offer_expires_at: i64,
+}
+
+impl SiteAccount {
+    /// Deserialize a `shadow_registry::Site` account, checking its discriminator
+    pub fn try_deserialize(data: &[u8]) -> Result<Self, String> {
+        let mut reader = BorshReader::for_account(data, "Site")?;
+        Ok(Self {
+            owner: reader.pubkey()?,
+            program_address: reader.pubkey()?,
+            name: reader.string()?,
+            description: reader.string()?,
+            storage_cid: reader.string()?,
+            created_at: reader.i64()?,
+            updated_at: reader.i64()?,
+            version: reader.u32()?,
+            maintainers: reader.vec_pubkey()?,
+        })
+    }
+
+    pub fn can_maintain(&self, wallet: &Pubkey) -> bool {
+        self.owner == *wallet || self.maintainers.contains(wallet)
+    }
+}
+
+impl DeploymentAccount {
+    /// Deserialize a `shadow_registry::Deployment` account, checking its discriminator
+    pub fn try_deserialize(data: &[u8]) -> Result<Self, String> {
+        let mut reader = BorshReader::for_account(data, "Deployment")?;
+        Ok(Self {
+            site: reader.pubkey()?,
+            version: reader.u32()?,
+            storage_cid: reader.string()?,
+            deployer: reader.pubkey()?,
+            deployed_at: reader.i64()?,
+            rollback_of: reader.option_u32()?,
+        })
+    }
+}
+
+impl ProfileAccount {
+    /// Deserialize a `shadow_profiles::Profile` account, checking its discriminator
+    pub fn try_deserialize(data: &[u8]) -> Result<Self, String> {
+        let mut reader = BorshReader::for_account(data, "Profile")?;
+        Ok(Self {
+            wallet: reader.pubkey()?,
+            profile_cid: reader.string()?,
+            is_public: reader.bool()?,
+            created_at: reader.i64()?,
+            updated_at: reader.i64()?,
+        })
+    }
+}
+
+impl DomainAccount {
+    /// Deserialize a `shadow_domains::DomainRecord` account, checking its discriminator
+    pub fn try_deserialize(data: &[u8]) -> Result<Self, String> {
+        let mut reader = BorshReader::for_account(data, "DomainRecord")?;
+        Ok(Self {
+            name: reader.string()?,
+            owner: reader.pubkey()?,
+            target: reader.pubkey()?,
+            registered_at: reader.i64()?,
+            updated_at: reader.i64()?,
+            expires_at: reader.i64()?,
+            pending_owner: reader.option_pubkey()?,
+            offer_expires_at: reader.i64()?,
+        })
+    }
+}
+
 impl AnchorClient {
     pub fn new(rpc_url: String) -> Result<Self, String> {
-        let registry_program = Pubkey::from_str(REGISTRY_PROGRAM_ID)
+        let registry_id = env::var("SHADOW_REGISTRY_PROGRAM_ID")
+            .unwrap_or_else(|_| REGISTRY_PROGRAM_ID.to_string());
+        let profiles_id = env::var("SHADOW_PROFILES_PROGRAM_ID")
+            .unwrap_or_else(|_| PROFILES_PROGRAM_ID.to_string());
+        let domains_id = env::var("SHADOW_DOMAINS_PROGRAM_ID")
+            .unwrap_or_else(|_| DOMAINS_PROGRAM_ID.to_string());
+
+        let registry_program = Pubkey::from_str(&registry_id)
             .map_err(|e| format!("Invalid registry program ID: {}", e))?;
-        
-        let profiles_program = Pubkey::from_str(PROFILES_PROGRAM_ID)
+
+        let profiles_program = Pubkey::from_str(&profiles_id)
             .map_err(|e| format!("Invalid profiles program ID: {}", e))?;
 
+        let domains_program = Pubkey::from_str(&domains_id)
+            .map_err(|e| format!("Invalid domains program ID: {}", e))?;
+
         Ok(Self {
             rpc_url,
             registry_program,
             profiles_program,
+            domains_program,
         })
     }
 
+    /// Site PDA: findProgramAddress(["site", program_address], registry)
+    pub fn site_pda(&self, program_address: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[SITE_SEED, program_address.as_ref()], &self.registry_program).0
+    }
+
+    /// Deployment PDA: findProgramAddress(["deployment", program_address, version_le], registry)
+    pub fn deployment_pda(&self, program_address: &Pubkey, version: u32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[DEPLOYMENT_SEED, program_address.as_ref(), &version.to_le_bytes()],
+            &self.registry_program,
+        ).0
+    }
+
+    /// Profile PDA: findProgramAddress(["profile", wallet], profiles)
+    pub fn profile_pda(&self, wallet: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[PROFILE_SEED, wallet.as_ref()], &self.profiles_program).0
+    }
+
+    /// Domain PDA: findProgramAddress(["domain", sha256(name)], domains)
+    ///
+    /// The name is hashed because a single seed is limited to 32 bytes.
+    pub fn domain_pda(&self, name: &str) -> Pubkey {
+        let name_hash = Sha256::digest(name.as_bytes());
+        Pubkey::find_program_address(&[DOMAIN_SEED, &name_hash[..]], &self.domains_program).0
+    }
+
     /// Verify a site is registered on-chain
+    ///
+    /// Returns the registry's `Site` account for `program_address`; callers
+    /// compare its `owner` with the requester before trusting it.
     pub fn verify_site_registration(
         &self,
         program_address: &str,
@@ -58,39 +228,34 @@ impl AnchorClient {
     ) -> Result<Option<SiteAccount>, String> {
         let program = Pubkey::from_str(program_address)
             .map_err(|e| format!("Invalid program pubkey: {}", e))?;
-        
-        let owner = Pubkey::from_str(owner_pubkey)
+
+        Pubkey::from_str(owner_pubkey)
             .map_err(|e| format!("Invalid owner pubkey: {}", e))?;
 
-        let client = RpcClient::new(&self.rpc_url);
-        
-        // Derive site PDA: findProgramAddress(["site", program_address])
-        // For now, we'll use a simplified approach
-        // In production, use anchor_lang::prelude::Pubkey::find_program_address
-        
-        // Get account data
-        // This is a placeholder - in production, you'd:
-        // 1. Derive the PDA using the seeds ["site", program_address]
-        // 2. Fetch the account
-        // 3. Deserialize using Anchor's AccountDeserialize
-        
-        // Simplified check: verify program exists and is owned by registry
-        match client.get_account(&program) {
-            Ok(account) if account.owner == self.registry_program => {
-                // Account exists and is owned by registry program
-                // In production, deserialize the account data here
-                Ok(Some(SiteAccount {
-                    owner,
-                    program_address: program,
-                    name: String::new(), // Would be deserialized from account data
-                    description: String::new(),
-                    storage_cid: String::new(),
-                    created_at: 0,
-                    updated_at: 0,
-                }))
-            }
-            _ => Ok(None),
-        }
+        Ok(self.fetch_sites(&[program])?.pop().flatten())
+    }
+
+    /// `verify_site_registration`, plus the slot the account was read at
+    ///
+    /// Anything written to the index from this read must carry that slot, so a
+    /// later write from an older read can't overwrite it.
+    pub fn read_site_registration(
+        &self,
+        program_address: &str,
+        owner_pubkey: &str,
+    ) -> Result<(u64, Option<SiteAccount>), String> {
+        let program = Pubkey::from_str(program_address)
+            .map_err(|e| format!("Invalid program pubkey: {}", e))?;
+
+        Pubkey::from_str(owner_pubkey)
+            .map_err(|e| format!("Invalid owner pubkey: {}", e))?;
+
+        let (slot, mut accounts) = self.fetch_indexed_accounts(&[self.site_pda(&program)])?;
+        let site = match accounts.pop().flatten() {
+            Some(IndexedAccount::Site(site)) => Some(site),
+            _ => None,
+        };
+        Ok((slot, site))
     }
 
     /// Verify a profile exists on-chain
@@ -98,22 +263,340 @@ impl AnchorClient {
         &self,
         wallet: &str,
     ) -> Result<Option<ProfileAccount>, String> {
-        let _wallet_pubkey = Pubkey::from_str(wallet)
+        let wallet_pubkey = Pubkey::from_str(wallet)
             .map_err(|e| format!("Invalid wallet pubkey: {}", e))?;
 
-        let _client = RpcClient::new(&self.rpc_url);
-        
-        // Derive profile PDA: findProgramAddress(["profile", wallet])
-        // For now, simplified approach
-        
-        // In production:
-        // 1. Derive PDA using seeds ["profile", wallet]
-        // 2. Fetch account
-        // 3. Deserialize using Anchor's AccountDeserialize
-        
-        // Simplified: check if any account owned by profiles program exists
-        // This is a placeholder - full implementation would derive and fetch the PDA
-        Ok(None)
+        Ok(self.fetch_profiles(&[wallet_pubkey])?.pop().flatten())
+    }
+
+    /// Fetch the registry `Site` accounts for several programs, in input order
+    pub fn fetch_sites(&self, program_addresses: &[Pubkey]) -> Result<Vec<Option<SiteAccount>>, String> {
+        let pdas: Vec<Pubkey> = program_addresses.iter().map(|p| self.site_pda(p)).collect();
+        self.fetch_accounts(&pdas, &self.registry_program, SiteAccount::try_deserialize)
+    }
+
+    /// Fetch a site's deployment log entries for `versions`, in input order
+    pub fn fetch_deployments(
+        &self,
+        program_address: &Pubkey,
+        versions: &[u32],
+    ) -> Result<Vec<Option<DeploymentAccount>>, String> {
+        let pdas: Vec<Pubkey> = versions.iter().map(|v| self.deployment_pda(program_address, *v)).collect();
+        self.fetch_accounts(&pdas, &self.registry_program, DeploymentAccount::try_deserialize)
+    }
+
+    /// Fetch the `Profile` accounts for several wallets, in input order
+    pub fn fetch_profiles(&self, wallets: &[Pubkey]) -> Result<Vec<Option<ProfileAccount>>, String> {
+        let pdas: Vec<Pubkey> = wallets.iter().map(|w| self.profile_pda(w)).collect();
+        self.fetch_accounts(&pdas, &self.profiles_program, ProfileAccount::try_deserialize)
+    }
+
+    /// Fetch the on-chain record for a domain name
+    pub fn fetch_domain(&self, name: &str) -> Result<Option<DomainAccount>, String> {
+        let pda = self.domain_pda(name);
+        Ok(self.fetch_accounts(&[pda], &self.domains_program, DomainAccount::try_deserialize)?
+            .pop()
+            .flatten())
+    }
+
+    /// Fetch every domain record owned by the domains program
+    pub fn fetch_all_domains(&self) -> Result<Vec<DomainAccount>, String> {
+        self.fetch_program_accounts(&self.domains_program, "DomainRecord", DomainAccount::try_deserialize)
+    }
+
+    /// Every site, deployment and profile account, with a slot the snapshot is no older than
+    ///
+    /// getProgramAccounts doesn't report the slot it read at, so the
+    /// confirmed slot is taken first as a lower bound.
+    pub fn fetch_all_indexed(&self) -> Result<(u64, Vec<IndexedAccount>), String> {
+        let slot = RpcClient::new(&self.rpc_url)
+            .get_slot_with_commitment(CommitmentConfig::confirmed())
+            .map_err(|e| format!("RPC error: {}", e))?;
+
+        let sites = self.fetch_program_accounts(&self.registry_program, "Site", SiteAccount::try_deserialize)?;
+        let deployments = self.fetch_program_accounts(&self.registry_program, "Deployment", DeploymentAccount::try_deserialize)?;
+        let profiles = self.fetch_program_accounts(&self.profiles_program, "Profile", ProfileAccount::try_deserialize)?;
+
+        let accounts = sites.into_iter().map(IndexedAccount::Site)
+            .chain(deployments.into_iter().map(IndexedAccount::Deployment))
+            .chain(profiles.into_iter().map(IndexedAccount::Profile))
+            .collect();
+        Ok((slot, accounts))
+    }
+
+    /// Confirmed state of registry and profiles accounts, in input order, and
+    /// the slot it was read at
+    ///
+    /// Closed accounts and ones the two programs don't own come back as `None`.
+    pub fn fetch_indexed_accounts(&self, addresses: &[Pubkey]) -> Result<(u64, Vec<Option<IndexedAccount>>), String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let mut slot = u64::MAX;
+        let mut results = Vec::with_capacity(addresses.len());
+
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let response = client.get_multiple_accounts_with_commitment(chunk, CommitmentConfig::confirmed())
+                .map_err(|e| format!("RPC error: {}", e))?;
+            // Chunks may be served at different slots; keep the oldest
+            slot = slot.min(response.context.slot);
+
+            results.extend(response.value.into_iter().map(|account| {
+                account.and_then(|account| self.decode_indexed_account(&account.owner, &account.data))
+            }));
+        }
+
+        Ok((if slot == u64::MAX { 0 } else { slot }, results))
+    }
+
+    /// Decode a registry or profiles account owned by `owner`
+    pub fn decode_indexed_account(&self, owner: &Pubkey, data: &[u8]) -> Option<IndexedAccount> {
+        if *owner == self.registry_program {
+            SiteAccount::try_deserialize(data).map(IndexedAccount::Site)
+                .or_else(|_| DeploymentAccount::try_deserialize(data).map(IndexedAccount::Deployment))
+                .ok()
+        } else if *owner == self.profiles_program {
+            ProfileAccount::try_deserialize(data).map(IndexedAccount::Profile).ok()
+        } else {
+            None
+        }
+    }
+
+    /// getProgramAccounts for one account type, skipping accounts that fail to decode
+    fn fetch_program_accounts<T>(
+        &self,
+        program: &Pubkey,
+        account_name: &str,
+        deserialize: fn(&[u8]) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                0,
+                account_discriminator(account_name).to_vec(),
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let accounts = client.get_program_accounts_with_config(program, config)
+            .map_err(|e| format!("RPC error: {}", e))?;
+
+        Ok(accounts
+            .into_iter()
+            .filter_map(|(address, account)| {
+                deserialize(&account.data)
+                    .map_err(|e| tracing::warn!("Failed to decode {} {}: {}", account_name, address, e))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Instruction for a shadow-domains method taking the name, then Borsh `args`
+    fn domain_instruction(&self, method: &str, name: &str, args: &[u8], accounts: Vec<AccountMeta>) -> Instruction {
+        let mut data = instruction_discriminator(method).to_vec();
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(args);
+        Instruction::new_with_bytes(self.domains_program, &data, accounts)
+    }
+
+    /// Accounts for the owner-signed `UpdateDomain` context
+    fn owner_accounts(&self, name: &str, owner: &Pubkey) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.domain_pda(name), false),
+            AccountMeta::new_readonly(*owner, true),
+        ]
+    }
+
+    /// `renew_domain`, to be signed by the owner
+    pub fn renew_domain_instruction(&self, name: &str, owner: &Pubkey) -> Instruction {
+        self.domain_instruction("renew_domain", name, &[], self.owner_accounts(name, owner))
+    }
+
+    /// `offer_transfer`, to be signed by the owner
+    pub fn offer_transfer_instruction(
+        &self,
+        name: &str,
+        owner: &Pubkey,
+        recipient: &Pubkey,
+        expires_in: i64,
+    ) -> Instruction {
+        let mut args = recipient.to_bytes().to_vec();
+        args.extend_from_slice(&expires_in.to_le_bytes());
+        self.domain_instruction("offer_transfer", name, &args, self.owner_accounts(name, owner))
+    }
+
+    /// `cancel_transfer`, to be signed by the owner
+    pub fn cancel_transfer_instruction(&self, name: &str, owner: &Pubkey) -> Instruction {
+        self.domain_instruction("cancel_transfer", name, &[], self.owner_accounts(name, owner))
+    }
+
+    /// `accept_transfer`, to be signed by the offer's recipient
+    pub fn accept_transfer_instruction(&self, name: &str, recipient: &Pubkey) -> Instruction {
+        self.domain_instruction("accept_transfer", name, &[], vec![
+            AccountMeta::new(self.domain_pda(name), false),
+            AccountMeta::new_readonly(*recipient, true),
+        ])
+    }
+
+    /// `release_domain`; anyone may sign as caller once the grace period is over
+    pub fn release_domain_instruction(&self, name: &str, owner: &Pubkey, caller: &Pubkey) -> Instruction {
+        self.domain_instruction("release_domain", name, &[], vec![
+            AccountMeta::new(self.domain_pda(name), false),
+            AccountMeta::new(*owner, false),
+            AccountMeta::new_readonly(*caller, true),
+        ])
+    }
+
+    /// Instruction for a shadow-registry method with Borsh `args`
+    fn registry_instruction(&self, method: &str, args: &[u8], accounts: Vec<AccountMeta>) -> Instruction {
+        let mut data = instruction_discriminator(method).to_vec();
+        data.extend_from_slice(args);
+        Instruction::new_with_bytes(self.registry_program, &data, accounts)
+    }
+
+    /// Accounts for the owner-signed `OwnerOnly` and `CloseSite` contexts,
+    /// followed by the deployment log entries to close
+    fn site_owner_accounts(&self, program_address: &Pubkey, owner: &Pubkey, closing: &[u32]) -> Vec<AccountMeta> {
+        let mut accounts = vec![
+            AccountMeta::new(self.site_pda(program_address), false),
+            AccountMeta::new(*owner, true),
+        ];
+        accounts.extend(closing.iter().map(|v| AccountMeta::new(self.deployment_pda(program_address, *v), false)));
+        accounts
+    }
+
+    /// `rollback_site`, to be signed by the owner or a maintainer
+    ///
+    /// `current_version` is the site's latest version; the rollback is
+    /// written as the one after it.
+    pub fn rollback_site_instruction(
+        &self,
+        program_address: &Pubkey,
+        authority: &Pubkey,
+        version: u32,
+        current_version: u32,
+    ) -> Instruction {
+        self.registry_instruction("rollback_site", &version.to_le_bytes(), vec![
+            AccountMeta::new(self.site_pda(program_address), false),
+            AccountMeta::new_readonly(self.deployment_pda(program_address, version), false),
+            AccountMeta::new(self.deployment_pda(program_address, current_version + 1), false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ])
+    }
+
+    /// `transfer_site`, to be signed by the owner
+    pub fn transfer_site_instruction(&self, program_address: &Pubkey, owner: &Pubkey, new_owner: &Pubkey) -> Instruction {
+        self.registry_instruction("transfer_site", new_owner.as_ref(), self.site_owner_accounts(program_address, owner, &[]))
+    }
+
+    /// `set_maintainers`, to be signed by the owner
+    pub fn set_maintainers_instruction(&self, program_address: &Pubkey, owner: &Pubkey, maintainers: &[Pubkey]) -> Instruction {
+        let mut args = (maintainers.len() as u32).to_le_bytes().to_vec();
+        for maintainer in maintainers {
+            args.extend_from_slice(maintainer.as_ref());
+        }
+        let mut accounts = self.site_owner_accounts(program_address, owner, &[]);
+        // The site grows to fit a longer list
+        accounts.push(AccountMeta::new_readonly(solana_sdk::system_program::id(), false));
+        self.registry_instruction("set_maintainers", &args, accounts)
+    }
+
+    /// `close_deployments` for some of a site's log entries, to be signed by the owner
+    pub fn close_deployments_instruction(&self, program_address: &Pubkey, owner: &Pubkey, versions: &[u32]) -> Instruction {
+        self.registry_instruction("close_deployments", &[], self.site_owner_accounts(program_address, owner, versions))
+    }
+
+    /// `close_site`, closing `versions` of its log along with it
+    pub fn close_site_instruction(&self, program_address: &Pubkey, owner: &Pubkey, versions: &[u32]) -> Instruction {
+        self.registry_instruction("close_site", &[], self.site_owner_accounts(program_address, owner, versions))
+    }
+
+    /// Instructions that close a site and its whole deployment log, one per transaction
+    ///
+    /// Long logs are cleared in batches first so every transaction stays
+    /// under the size limit; `close_site` comes last.
+    pub fn close_site_instructions(&self, program_address: &Pubkey, owner: &Pubkey, version: u32) -> Vec<Instruction> {
+        let versions: Vec<u32> = (1..=version).collect();
+        let mut batches: Vec<&[u32]> = versions.chunks(MAX_CLOSES_PER_TRANSACTION).collect();
+        let last = batches.pop().unwrap_or(&[]);
+
+        batches.into_iter()
+            .map(|batch| self.close_deployments_instruction(program_address, owner, batch))
+            .chain(std::iter::once(self.close_site_instruction(program_address, owner, last)))
+            .collect()
+    }
+
+    /// Unsigned transaction for `payer`'s wallet to sign, base64 bincode
+    pub fn build_unsigned_transaction(&self, instruction: Instruction, payer: &Pubkey) -> Result<String, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let blockhash = client.get_latest_blockhash()
+            .map_err(|e| format!("RPC error: {}", e))?;
+
+        let message = Message::new_with_blockhash(&[instruction], Some(payer), &blockhash);
+        let transaction = Transaction::new_unsigned(message);
+        let bytes = bincode::serialize(&transaction)
+            .map_err(|e| format!("Failed to serialize transaction: {}", e))?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+
+    /// Unsigned renewal transaction for the owner's wallet
+    pub fn build_renew_transaction(&self, name: &str, owner: &Pubkey) -> Result<String, String> {
+        self.build_unsigned_transaction(self.renew_domain_instruction(name, owner), owner)
+    }
+
+    /// Release a lapsed domain with the backend's own keypair paying the fee
+    pub fn release_lapsed_domain(&self, name: &str, owner: &Pubkey, caller: &Keypair) -> Result<String, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let blockhash = client.get_latest_blockhash()
+            .map_err(|e| format!("RPC error: {}", e))?;
+
+        let transaction = Transaction::new_signed_with_payer(
+            &[self.release_domain_instruction(name, owner, &caller.pubkey())],
+            Some(&caller.pubkey()),
+            &[caller],
+            blockhash,
+        );
+        client.send_and_confirm_transaction(&transaction)
+            .map(|signature| signature.to_string())
+            .map_err(|e| format!("Failed to release domain: {}", e))
+    }
+
+    /// Batched getMultipleAccounts lookup
+    ///
+    /// Accounts that are missing, owned by another program, or fail to
+    /// deserialize come back as `None` rather than failing the whole batch.
+    fn fetch_accounts<T>(
+        &self,
+        addresses: &[Pubkey],
+        expected_owner: &Pubkey,
+        deserialize: fn(&[u8]) -> Result<T, String>,
+    ) -> Result<Vec<Option<T>>, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let mut results = Vec::with_capacity(addresses.len());
+
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = client.get_multiple_accounts(chunk)
+                .map_err(|e| format!("RPC error: {}", e))?;
+
+            for (address, account) in chunk.iter().zip(accounts) {
+                results.push(account.and_then(|account: Account| {
+                    if account.owner != *expected_owner {
+                        tracing::warn!("Account {} is not owned by {}", address, expected_owner);
+                        return None;
+                    }
+                    deserialize(&account.data)
+                        .map_err(|e| tracing::warn!("Failed to decode account {}: {}", address, e))
+                        .ok()
+                }));
+            }
+        }
+
+        Ok(results)
     }
 
     /// Get registry program ID
@@ -127,3 +610,356 @@ impl AnchorClient {
     }
 }
 
+/// Anchor account discriminator: first 8 bytes of sha256("account:<Name>")
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Anchor event discriminator: first 8 bytes of sha256("event:<Name>")
+pub fn event_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("event:{}", name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Program addresses from `SiteClosed` events in a transaction's logs
+///
+/// Any program can print lookalike events, so callers check the chain
+/// before acting on them.
+pub fn site_closed_events(logs: &[String]) -> Vec<Pubkey> {
+    let discriminator = event_discriminator("SiteClosed");
+    logs.iter()
+        .filter_map(|line| line.strip_prefix("Program data: "))
+        .filter_map(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+        .filter(|event| event.len() >= 8 + 32 && event[..8] == discriminator)
+        .filter_map(|event| Pubkey::try_from(&event[8..40]).ok())
+        .collect()
+}
+
+/// Anchor instruction discriminator: first 8 bytes of sha256("global:<method>")
+pub fn instruction_discriminator(method: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("global:{}", method).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Minimal Borsh cursor for the fixed account layouts above
+struct BorshReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BorshReader<'a> {
+    /// Check the Anchor discriminator and position the reader after it
+    fn for_account(data: &'a [u8], name: &str) -> Result<Self, String> {
+        if data.len() < 8 {
+            return Err("Account data too short".to_string());
+        }
+        if data[..8] != account_discriminator(name) {
+            return Err(format!("Account is not a {}", name));
+        }
+        Ok(Self { data, offset: 8 })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.offset.checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| "Unexpected end of account data".to_string())?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn pubkey(&mut self) -> Result<Pubkey, String> {
+        let bytes = self.take(32)?;
+        Ok(Pubkey::new_from_array(bytes.try_into().expect("32 bytes")))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")) as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "Invalid UTF-8 in account string".to_string())
+    }
+
+    fn option_pubkey(&mut self) -> Result<Option<Pubkey>, String> {
+        match self.take(1)?[0] {
+            0 => Ok(None),
+            1 => self.pubkey().map(Some),
+            other => Err(format!("Invalid option tag {}", other)),
+        }
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("Invalid bool byte {}", other)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    fn vec_pubkey(&mut self) -> Result<Vec<Pubkey>, String> {
+        let len = self.u32()? as usize;
+        (0..len).map(|_| self.pubkey()).collect()
+    }
+
+    fn option_u32(&mut self) -> Result<Option<u32>, String> {
+        match self.take(1)?[0] {
+            0 => Ok(None),
+            1 => self.u32().map(Some),
+            other => Err(format!("Invalid option tag {}", other)),
+        }
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn site_bytes(site: &SiteAccount) -> Vec<u8> {
+        let mut data = account_discriminator("Site").to_vec();
+        data.extend_from_slice(site.owner.as_ref());
+        data.extend_from_slice(site.program_address.as_ref());
+        borsh_string(&mut data, &site.name);
+        borsh_string(&mut data, &site.description);
+        borsh_string(&mut data, &site.storage_cid);
+        data.extend_from_slice(&site.created_at.to_le_bytes());
+        data.extend_from_slice(&site.updated_at.to_le_bytes());
+        data.extend_from_slice(&site.version.to_le_bytes());
+        data.extend_from_slice(&(site.maintainers.len() as u32).to_le_bytes());
+        for maintainer in &site.maintainers {
+            data.extend_from_slice(maintainer.as_ref());
+        }
+        // Anchor allocates the full `space`, so real accounts carry zero padding
+        data.resize(8 + 32 + 32 + 104 + 504 + 104 + 16 + 4 + (4 + 32 * MAX_MAINTAINERS), 0);
+        data
+    }
+
+    #[test]
+    fn default_program_ids_are_valid() {
+        for id in [REGISTRY_PROGRAM_ID, PROFILES_PROGRAM_ID, DOMAINS_PROGRAM_ID] {
+            assert!(Pubkey::from_str(id).is_ok(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn discriminator_matches_anchor() {
+        // sha256("account:Site")[..8]
+        let hash = Sha256::digest(b"account:Site");
+        assert_eq!(account_discriminator("Site"), hash[..8]);
+        assert_ne!(account_discriminator("Site"), account_discriminator("Profile"));
+    }
+
+    #[test]
+    fn deserializes_site_account() {
+        let site = SiteAccount {
+            owner: Pubkey::new_unique(),
+            program_address: Pubkey::new_unique(),
+            name: "Shadow".to_string(),
+            description: "A site".to_string(),
+            storage_cid: "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string(),
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_100,
+            version: 3,
+            maintainers: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        };
+
+        assert_eq!(SiteAccount::try_deserialize(&site_bytes(&site)).unwrap(), site);
+    }
+
+    #[test]
+    fn deserializes_deployment_log_entries() {
+        let site = Pubkey::new_unique();
+        let deployer = Pubkey::new_unique();
+        let mut data = account_discriminator("Deployment").to_vec();
+        data.extend_from_slice(site.as_ref());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        borsh_string(&mut data, "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi");
+        data.extend_from_slice(deployer.as_ref());
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        data.push(1);
+        data.extend_from_slice(&2u32.to_le_bytes());
+
+        let deployment = DeploymentAccount::try_deserialize(&data).unwrap();
+        assert_eq!(deployment.site, site);
+        assert_eq!(deployment.version, 4);
+        assert_eq!(deployment.deployer, deployer);
+        assert_eq!(deployment.rollback_of, Some(2));
+        assert!(SiteAccount::try_deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_discriminator_and_truncation() {
+        let site = SiteAccount {
+            owner: Pubkey::new_unique(),
+            program_address: Pubkey::new_unique(),
+            name: "Shadow".to_string(),
+            description: String::new(),
+            storage_cid: String::new(),
+            created_at: 0,
+            updated_at: 0,
+            version: 0,
+            maintainers: vec![],
+        };
+        let data = site_bytes(&site);
+
+        assert!(ProfileAccount::try_deserialize(&data).is_err());
+        assert!(SiteAccount::try_deserialize(&data[..50]).is_err());
+    }
+
+    #[test]
+    fn deserializes_domain_record() {
+        let owner = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+        let mut data = account_discriminator("DomainRecord").to_vec();
+        borsh_string(&mut data, "alice.shadow");
+        data.extend_from_slice(owner.as_ref());
+        data.extend_from_slice(target.as_ref());
+        for ts in [100i64, 200, 300] {
+            data.extend_from_slice(&ts.to_le_bytes());
+        }
+        let header_len = data.len();
+        data.push(0); // no pending owner
+        data.extend_from_slice(&0i64.to_le_bytes());
+        data.push(254); // bump
+
+        let domain = DomainAccount::try_deserialize(&data).unwrap();
+        assert_eq!(domain.name, "alice.shadow");
+        assert_eq!(domain.owner, owner);
+        assert_eq!(domain.target, target);
+        assert_eq!(domain.expires_at, 300);
+        assert_eq!(domain.pending_owner, None);
+
+        // With an open offer
+        let recipient = Pubkey::new_unique();
+        data.truncate(header_len);
+        data.push(1);
+        data.extend_from_slice(recipient.as_ref());
+        data.extend_from_slice(&250i64.to_le_bytes());
+        data.push(254);
+
+        let domain = DomainAccount::try_deserialize(&data).unwrap();
+        assert_eq!(domain.pending_owner, Some(recipient));
+        assert_eq!(domain.offer_expires_at, 250);
+    }
+
+    #[test]
+    fn domain_pda_is_stable_per_name() {
+        let client = AnchorClient {
+            rpc_url: "http://localhost:8899".to_string(),
+            registry_program: Pubkey::new_unique(),
+            profiles_program: Pubkey::new_unique(),
+            domains_program: Pubkey::from_str(DOMAINS_PROGRAM_ID).unwrap(),
+        };
+        assert_eq!(client.domain_pda("alice.shadow"), client.domain_pda("alice.shadow"));
+        assert_ne!(client.domain_pda("alice.shadow"), client.domain_pda("bob.shadow"));
+    }
+
+    #[test]
+    fn encodes_domain_instruction_data() {
+        let client = AnchorClient {
+            rpc_url: "http://localhost:8899".to_string(),
+            registry_program: Pubkey::new_unique(),
+            profiles_program: Pubkey::new_unique(),
+            domains_program: Pubkey::from_str(DOMAINS_PROGRAM_ID).unwrap(),
+        };
+        let owner = Pubkey::new_unique();
+        let ix = client.renew_domain_instruction("alice.shadow", &owner);
+
+        assert_eq!(ix.data[..8], instruction_discriminator("renew_domain"));
+        assert_eq!(ix.data[8..12], 12u32.to_le_bytes());
+        assert_eq!(&ix.data[12..], b"alice.shadow");
+        assert!(ix.accounts[1].is_signer);
+
+        let recipient = Pubkey::new_unique();
+        let ix = client.offer_transfer_instruction("alice.shadow", &owner, &recipient, 3600);
+        assert_eq!(ix.data[..8], instruction_discriminator("offer_transfer"));
+        assert_eq!(&ix.data[24..56], recipient.as_ref());
+        assert_eq!(ix.data[56..], 3600i64.to_le_bytes());
+    }
+
+    #[test]
+    fn finds_site_closed_events_in_logs() {
+        let program = Pubkey::new_unique();
+        let mut event = event_discriminator("SiteClosed").to_vec();
+        event.extend_from_slice(program.as_ref());
+        event.extend_from_slice(Pubkey::new_unique().as_ref());
+        let mut other = event_discriminator("SiteDeployed").to_vec();
+        other.extend_from_slice(Pubkey::new_unique().as_ref());
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        let logs = vec![
+            "Program 7Y8Zx invoke [1]".to_string(),
+            "Program log: Instruction: CloseSite".to_string(),
+            format!("Program data: {}", engine.encode(&other)),
+            format!("Program data: {}", engine.encode(&event)),
+            "Program data: not base64!".to_string(),
+        ];
+        assert_eq!(site_closed_events(&logs), vec![program]);
+    }
+
+    #[test]
+    fn closes_long_deployment_logs_in_batches() {
+        let client = AnchorClient {
+            rpc_url: "http://localhost:8899".to_string(),
+            registry_program: Pubkey::new_unique(),
+            profiles_program: Pubkey::new_unique(),
+            domains_program: Pubkey::new_unique(),
+        };
+        let program = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let ixs = client.close_site_instructions(&program, &owner, 45);
+        assert_eq!(ixs.len(), 3);
+        assert_eq!(ixs[0].data[..8], instruction_discriminator("close_deployments"));
+        assert_eq!(ixs[2].data[..8], instruction_discriminator("close_site"));
+        // Site and owner, then the entries being closed
+        assert_eq!(ixs[0].accounts.len(), 2 + MAX_CLOSES_PER_TRANSACTION);
+        assert_eq!(ixs[2].accounts.len(), 2 + 5);
+        assert_eq!(ixs[2].accounts[6].pubkey, client.deployment_pda(&program, 45));
+
+        // Sites from before versioning have no log to close
+        let ixs = client.close_site_instructions(&program, &owner, 0);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(ixs[0].accounts.len(), 2);
+
+        let maintainer = Pubkey::new_unique();
+        let ix = client.set_maintainers_instruction(&program, &owner, &[maintainer]);
+        assert_eq!(ix.data[8..12], 1u32.to_le_bytes());
+        assert_eq!(&ix.data[12..], maintainer.as_ref());
+    }
+
+    #[test]
+    fn deserializes_profile_account() {
+        let wallet = Pubkey::new_unique();
+        let mut data = account_discriminator("Profile").to_vec();
+        data.extend_from_slice(wallet.as_ref());
+        borsh_string(&mut data, "bafyprofile");
+        data.push(1);
+        data.extend_from_slice(&5i64.to_le_bytes());
+        data.extend_from_slice(&6i64.to_le_bytes());
+
+        let profile = ProfileAccount::try_deserialize(&data).unwrap();
+        assert_eq!(profile.wallet, wallet);
+        assert_eq!(profile.profile_cid, "bafyprofile");
+        assert!(profile.is_public);
+        assert_eq!(profile.updated_at, 6);
+    }
+}
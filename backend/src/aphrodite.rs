@@ -1,12 +1,51 @@
 // Aphrodite - Goddess of Beauty and Collectibles
 // Handles NFT operations, metadata, and transfers
 
+use chrono::{DateTime, Utc};
 use mongodb::{Collection, Database};
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::str::FromStr;
+use std::time::Duration;
+use crate::hephaestus::{CachedContent, HephaestusCache};
+use crate::storage::StorageRegistry;
+
+// Metaplex Token Metadata program
+const TOKEN_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+// Account key discriminator for MetadataV1 accounts
+const METADATA_V1_KEY: u8 = 4;
+
+// TokenStandard::ProgrammableNonFungible
+const TOKEN_STANDARD_PROGRAMMABLE: u8 = 4;
+
+// Token Metadata `Transfer` instruction and its `TransferArgs::V1` variant
+const TOKEN_METADATA_TRANSFER_IX: u8 = 49;
+
+pub(crate) const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+const TOKEN_AUTH_RULES_PROGRAM_ID: &str = "auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg";
+const BUBBLEGUM_PROGRAM_ID: &str = "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY";
+const SPL_NOOP_PROGRAM_ID: &str = "noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV";
+const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID: &str = "cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK";
+
+// Anchor discriminator of Bubblegum `transfer`: sha256("global:transfer")[..8]
+const BUBBLEGUM_TRANSFER_DISCRIMINATOR: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+
+// pNFT transfers run rule set validation and need more than the default budget
+const PROGRAMMABLE_TRANSFER_COMPUTE_UNITS: u32 = 400_000;
+
+const MAX_METADATA_BYTES: usize = 256 * 1024; // 256KB JSON documents
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024; // 5MB images
+const MAX_ATTRIBUTES: usize = 100;
+
+/// How long resolved metadata is served from cache, in Hephaestus and `nft_metadata`
+pub const METADATA_CACHE_TTL: Duration = Duration::from_secs(6 * 3600);
+const IMAGE_CACHE_TTL: Duration = Duration::from_secs(24 * 3600);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NFT {
@@ -26,30 +65,288 @@ pub struct NFTTransferRequest {
     pub destination: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NFTMetadata {
     pub mint: String,
     pub name: String,
+    #[serde(default)]
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub uri: Option<String>, // Off-chain JSON metadata URI from the on-chain account
     pub description: Option<String>,
-    pub image: Option<String>,
+    pub image: Option<String>, // Backend proxy URL, never the original host
+    #[serde(default)]
+    pub image_source: Option<String>, // Original image URI, only fetched server-side
     pub attributes: Vec<Attribute>,
     pub collection: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// An `nft_metadata` row; a TTL index on `cached_at` drops it once stale
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedNftMetadata {
+    #[serde(flatten)]
+    pub metadata: NFTMetadata,
+    // Rows written before this field existed, including "Unknown NFT" placeholders, have none
+    #[serde(default, with = "optional_bson_datetime")]
+    pub cached_at: Option<DateTime<Utc>>,
+}
+
+impl CachedNftMetadata {
+    /// Whether the row can be served instead of resolving again
+    pub fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        let ttl = chrono::Duration::from_std(METADATA_CACHE_TTL).unwrap_or_default();
+        self.metadata.uri.is_some() && self.cached_at.is_some_and(|cached_at| now - cached_at < ttl)
+    }
+}
+
+mod optional_bson_datetime {
+    use chrono::{DateTime, Utc};
+    use mongodb::bson;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(bson::DateTime::from_chrono).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<bson::DateTime>::deserialize(deserializer)?.map(bson::DateTime::to_chrono))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Attribute {
     pub trait_type: String,
     pub value: String,
 }
 
+/// Fields read from a Metaplex metadata account
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnChainMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub token_standard: Option<u8>,
+    pub rule_set: Option<Pubkey>,
+}
+
+/// How an NFT has to be moved between wallets
+#[derive(Debug, Clone, PartialEq)]
+pub enum NftKind {
+    /// Plain SPL token with supply 1
+    Standard,
+    /// Metaplex programmable NFT, moved through Token Metadata `Transfer`
+    Programmable { rule_set: Option<Pubkey> },
+    /// Bubblegum compressed NFT, identified by its asset ID rather than a mint
+    Compressed,
+}
+
+/// Leaf data and proof needed to transfer a compressed NFT
+#[derive(Debug, Clone)]
+pub struct CompressedAssetProof {
+    pub tree: Pubkey,
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub root: [u8; 32],
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub leaf_index: u32,
+    pub proof: Vec<Pubkey>,
+}
+
+/// Validated off-chain JSON metadata document
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataDocument {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub collection: Option<String>,
+}
+
+/// Resolves off-chain NFT metadata and images through the storage layer.
+/// IPFS and Arweave URIs go through their storage providers; plain HTTPS
+/// is fetched directly but only towards public hosts.
+pub struct NftMetadataResolver {
+    storage: Arc<StorageRegistry>,
+    cache: Option<Arc<HephaestusCache>>,
+}
+
+impl NftMetadataResolver {
+    pub fn new(cache: Option<Arc<HephaestusCache>>) -> Self {
+        Self {
+            storage: Arc::new(StorageRegistry::standard()),
+            cache,
+        }
+    }
+
+    /// Read through the shared storage providers and their gateway pools
+    pub fn with_storage(mut self, storage: Arc<StorageRegistry>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Fetch raw bytes behind an `ipfs://`, `ar://`/`arweave://` or `https://` URI
+    pub async fn fetch_uri(&self, uri: &str, max_bytes: usize) -> Result<Vec<u8>, String> {
+        let uri = uri.trim();
+
+        // Other registered stores (local:// in development) stay private to the backend
+        let bytes = if uri.starts_with("ipfs://") || uri.starts_with("ar://") || uri.starts_with("arweave://") {
+            self.storage.get(uri).await?
+        } else if uri.starts_with("https://") {
+            Self::fetch_https(uri, max_bytes).await?
+        } else {
+            return Err(format!("Unsupported metadata URI scheme: {}", uri));
+        };
+
+        if bytes.len() > max_bytes {
+            return Err(format!("Metadata resource too large ({} bytes)", bytes.len()));
+        }
+
+        Ok(bytes)
+    }
+
+    async fn fetch_https(uri: &str, max_bytes: usize) -> Result<Vec<u8>, String> {
+        let mut url = reqwest::Url::parse(uri)
+            .map_err(|e| format!("Invalid metadata URL: {}", e))?;
+
+        // Redirects are followed by hand so every hop is resolved and checked the same way
+        let mut hops = 0;
+        let mut response = loop {
+            let client = pinned_client(&url).await?;
+            let response = client.get(url.clone())
+                .send()
+                .await
+                .map_err(|e| format!("Failed to fetch metadata: {}", e))?;
+
+            if !response.status().is_redirection() {
+                break response;
+            }
+            if hops == MAX_METADATA_REDIRECTS {
+                return Err("Too many metadata redirects".to_string());
+            }
+            let location = response.headers().get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| format!("Metadata redirect without a location: {}", response.status()))?;
+            url = url.join(location)
+                .map_err(|e| format!("Invalid metadata redirect: {}", e))?;
+            hops += 1;
+        };
+
+        if !response.status().is_success() {
+            return Err(format!("Metadata fetch error: {}", response.status()));
+        }
+
+        if let Some(len) = response.content_length() {
+            if len as usize > max_bytes {
+                return Err(format!("Metadata resource too large ({} bytes)", len));
+            }
+        }
+
+        // Content-Length can be absent or wrong, so count what actually arrives
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| format!("Failed to read metadata: {}", e))?
+        {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(format!("Metadata resource too large (over {} bytes)", max_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Resolve and validate the JSON document behind a metadata URI (cached)
+    pub async fn resolve_document(&self, uri: &str) -> Result<MetadataDocument, String> {
+        let key = format!("nft:doc:{}", uri);
+
+        if let Some(cache) = &self.cache {
+            if let Some(cached) = cache.get(&key).await {
+                return parse_metadata_document(&cached.content);
+            }
+        }
+
+        let bytes = self.fetch_uri(uri, MAX_METADATA_BYTES).await?;
+        let document = parse_metadata_document(&bytes)?;
+
+        if let Some(cache) = &self.cache {
+            cache.set(key, bytes, "application/json".to_string(), Some(METADATA_CACHE_TTL)).await?;
+        }
+
+        Ok(document)
+    }
+
+    /// Fetch an NFT image for the proxy endpoint (cached per mint)
+    pub async fn fetch_image(&self, mint: &str, source: &str) -> Result<CachedContent, String> {
+        let key = format!("nft:image:{}", mint);
+
+        if let Some(cache) = &self.cache {
+            if let Some(cached) = cache.get(&key).await {
+                return Ok(cached);
+            }
+        }
+
+        let bytes = self.fetch_uri(source, MAX_IMAGE_BYTES).await?;
+        let content_type = sniff_image_type(&bytes)
+            .ok_or_else(|| "Resource is not a supported image".to_string())?;
+
+        if let Some(cache) = &self.cache {
+            cache.set(key.clone(), bytes.clone(), content_type.to_string(), Some(IMAGE_CACHE_TTL)).await?;
+            if let Some(cached) = cache.get(&key).await {
+                return Ok(cached);
+            }
+        }
+
+        let now = chrono::Utc::now();
+        Ok(CachedContent {
+            size_bytes: bytes.len(),
+            etag: format!("\"{}\"", crate::utils::hash_content(&bytes)),
+            content: bytes,
+            content_type: content_type.to_string(),
+            cached_at: now,
+            expires_at: now,
+        })
+    }
+}
+
 pub struct AphroditeNFTManager {
     db: Arc<Database>,
     solana_rpc_url: String,
+    das_rpc_url: String, // RPC endpoint implementing the Metaplex DAS API
+    resolver: NftMetadataResolver,
 }
 
 impl AphroditeNFTManager {
     pub fn new(db: Arc<Database>, solana_rpc_url: String) -> Self {
-        Self { db, solana_rpc_url }
+        Self {
+            db,
+            das_rpc_url: solana_rpc_url.clone(),
+            solana_rpc_url,
+            resolver: NftMetadataResolver::new(None),
+        }
+    }
+
+    /// Query DAS (compressed NFTs, proofs) somewhere other than the plain RPC
+    pub fn with_das_rpc_url(mut self, das_rpc_url: String) -> Self {
+        self.das_rpc_url = das_rpc_url;
+        self
+    }
+
+    /// Cache resolved documents and images in Hephaestus
+    pub fn with_cache(mut self, cache: Arc<HephaestusCache>) -> Self {
+        self.resolver.cache = Some(cache);
+        self
+    }
+
+    /// Resolve metadata through the shared storage providers
+    pub fn with_storage(mut self, storage: Arc<StorageRegistry>) -> Self {
+        self.resolver = self.resolver.with_storage(storage);
+        self
+    }
+
+    fn get_metadata_collection(&self) -> Collection<CachedNftMetadata> {
+        self.db.collection::<CachedNftMetadata>("nft_metadata")
     }
 
     /// Get all NFTs owned by a wallet
@@ -80,9 +377,9 @@ impl AphroditeNFTManager {
                         .map(|m| m.name.clone())
                         .unwrap_or_else(|| "Unknown NFT".to_string()),
                     symbol: metadata.as_ref()
-                        .and_then(|m| m.collection.clone())
+                        .and_then(|m| m.symbol.clone())
                         .unwrap_or_else(|| "NFT".to_string()),
-                    uri: metadata.as_ref().and_then(|m| m.image.clone()),
+                    uri: metadata.as_ref().and_then(|m| m.uri.clone()),
                     image_uri: metadata.as_ref().and_then(|m| m.image.clone()),
                     collection: metadata.as_ref().and_then(|m| m.collection.clone()),
                     owner: wallet_pubkey.to_string(),
@@ -93,57 +390,909 @@ impl AphroditeNFTManager {
         Ok(nfts)
     }
 
-    /// Get NFT metadata (cached in database)
+    /// Get NFT metadata (cached in database once the off-chain document resolves)
     pub async fn get_nft_metadata(&self, mint: &str) -> Result<NFTMetadata, String> {
-        let collection: Collection<NFTMetadata> = self.db.collection("nft_metadata");
+        let collection = self.get_metadata_collection();
 
-        // Check cache first
+        // Check cache first; placeholders and stale rows are resolved again
         if let Some(cached) = collection
             .find_one(doc! { "mint": mint }, None)
             .await
             .map_err(|e| format!("Database error: {}", e))? {
-            return Ok(cached);
+            if cached.is_fresh(Utc::now()) {
+                return Ok(cached.metadata);
+            }
         }
 
-        // Fetch from on-chain (Metaplex Token Metadata)
-        // For now, return basic metadata
-        let metadata = NFTMetadata {
+        let mut metadata = NFTMetadata {
             mint: mint.to_string(),
             name: "Unknown NFT".to_string(),
+            symbol: None,
+            uri: None,
             description: None,
             image: None,
+            image_source: None,
             attributes: Vec::new(),
             collection: None,
         };
 
+        let on_chain = match self.fetch_on_chain_metadata(mint).await? {
+            Some(on_chain) => on_chain,
+            None => return Ok(metadata),
+        };
+
+        if !on_chain.name.is_empty() {
+            metadata.name = on_chain.name.clone();
+        }
+        if !on_chain.symbol.is_empty() {
+            metadata.symbol = Some(on_chain.symbol.clone());
+        }
+        if on_chain.uri.is_empty() {
+            return Ok(metadata);
+        }
+        metadata.uri = Some(on_chain.uri.clone());
+
+        // Only persist once the document resolved so gateway hiccups are retried
+        let document = match self.resolver.resolve_document(&on_chain.uri).await {
+            Ok(document) => document,
+            Err(e) => {
+                tracing::warn!("Failed to resolve NFT metadata for {}: {}", mint, e);
+                return Ok(metadata);
+            }
+        };
+
+        if let Some(name) = document.name {
+            metadata.name = name;
+        }
+        if metadata.symbol.is_none() {
+            metadata.symbol = document.symbol;
+        }
+        metadata.description = document.description;
+        metadata.image = document.image.as_ref().map(|_| image_proxy_url(mint));
+        metadata.image_source = document.image;
+        metadata.attributes = document.attributes;
+        metadata.collection = document.collection;
+
         // Cache it
+        let cached = CachedNftMetadata { metadata, cached_at: Some(Utc::now()) };
         collection
-            .insert_one(&metadata, None)
+            .replace_one(
+                doc! { "mint": mint },
+                &cached,
+                mongodb::options::ReplaceOptions::builder()
+                    .upsert(true)
+                    .build(),
+            )
             .await
             .map_err(|e| format!("Database error: {}", e))?;
 
-        Ok(metadata)
+        Ok(cached.metadata)
+    }
+
+    /// Get the image bytes behind an NFT's proxied image URL
+    pub async fn get_nft_image(&self, mint: &str) -> Result<Option<CachedContent>, String> {
+        let metadata = self.get_nft_metadata(mint).await?;
+
+        match metadata.image_source {
+            Some(source) => self.resolver.fetch_image(mint, &source).await.map(Some),
+            None => Ok(None),
+        }
     }
 
-    /// Create NFT transfer transaction
+    /// Read name, symbol and URI from the Metaplex metadata PDA of a mint
+    async fn fetch_on_chain_metadata(&self, mint: &str) -> Result<Option<OnChainMetadata>, String> {
+        use crate::solana::SolanaClient;
+        let client = SolanaClient::new(self.solana_rpc_url.clone());
+
+        let mint_pubkey = Pubkey::from_str(mint)
+            .map_err(|_| "Invalid mint pubkey".to_string())?;
+
+        let data = match client.get_account_data(&metadata_pda(&mint_pubkey)?).await? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+
+        parse_metadata_account(&data).map(Some)
+    }
+
+    /// Work out whether a mint is a plain, programmable or compressed NFT
+    pub async fn detect_nft_kind(&self, mint: &str) -> Result<NftKind, String> {
+        if let Some(on_chain) = self.fetch_on_chain_metadata(mint).await? {
+            return Ok(match on_chain.token_standard {
+                Some(TOKEN_STANDARD_PROGRAMMABLE) => NftKind::Programmable { rule_set: on_chain.rule_set },
+                _ => NftKind::Standard,
+            });
+        }
+
+        // Compressed NFTs have no metadata account; ask the DAS indexer
+        let asset = self.das_request("getAsset", serde_json::json!({ "id": mint })).await?;
+        if asset["compression"]["compressed"].as_bool() == Some(true) {
+            Ok(NftKind::Compressed)
+        } else {
+            Err("NFT not found".to_string())
+        }
+    }
+
+    /// Create an unsigned NFT transfer transaction (base64, bincode encoded)
     pub async fn create_transfer_transaction(
         &self,
         wallet_pubkey: &str,
         mint: &str,
         destination: &str,
     ) -> Result<String, String> {
-        // NFTs are SPL tokens, so use token transfer
-        use crate::dionysus::DionysusTokenManager;
-        let token_manager = DionysusTokenManager::new(
-            Arc::clone(&self.db),
-            self.solana_rpc_url.clone(),
+        use crate::solana::SolanaClient;
+        let client = SolanaClient::new(self.solana_rpc_url.clone());
+
+        let owner = Pubkey::from_str(wallet_pubkey)
+            .map_err(|_| "Invalid source pubkey".to_string())?;
+        let destination = Pubkey::from_str(destination)
+            .map_err(|_| "Invalid destination pubkey".to_string())?;
+        let mint_pubkey = Pubkey::from_str(mint)
+            .map_err(|_| "Invalid mint pubkey".to_string())?;
+
+        if owner == destination {
+            return Err("Destination must differ from the current owner".to_string());
+        }
+
+        let instructions = match self.detect_nft_kind(mint).await? {
+            NftKind::Standard => build_standard_transfer(&owner, &mint_pubkey, &destination)?,
+            NftKind::Programmable { rule_set } => {
+                build_programmable_transfer(&owner, &mint_pubkey, &destination, rule_set.as_ref())?
+            }
+            NftKind::Compressed => {
+                let proof = self.fetch_compressed_proof(mint).await?;
+                if proof.owner != owner {
+                    return Err("Compressed NFT is not owned by this wallet".to_string());
+                }
+                vec![build_compressed_transfer(&proof, &destination)?]
+            }
+        };
+
+        let blockhash = client
+            .get_recent_blockhash()
+            .await
+            .map_err(|e| format!("Failed to get blockhash: {}", e))?;
+
+        // Unsigned; Poseidon signs it once the user approves
+        let message = solana_sdk::message::Message::new_with_blockhash(
+            &instructions,
+            Some(&owner),
+            &blockhash,
         );
+        let transaction = solana_sdk::transaction::Transaction::new_unsigned(message);
+
+        use base64::{Engine as _, engine::general_purpose};
+        let tx_bytes = bincode::serialize(&transaction)
+            .map_err(|_| "Failed to serialize transaction".to_string())?;
+
+        Ok(general_purpose::STANDARD.encode(&tx_bytes))
+    }
+
+    /// Fetch leaf data and Merkle proof of a compressed NFT, trimmed to the tree's canopy
+    async fn fetch_compressed_proof(&self, asset_id: &str) -> Result<CompressedAssetProof, String> {
+        use crate::solana::SolanaClient;
+
+        let asset = self.das_request("getAsset", serde_json::json!({ "id": asset_id })).await?;
+        let proof = self.das_request("getAssetProof", serde_json::json!({ "id": asset_id })).await?;
+
+        let compression = &asset["compression"];
+        let tree = parse_pubkey_field(&proof["tree_id"], "tree_id")?;
+        let owner = parse_pubkey_field(&asset["ownership"]["owner"], "owner")?;
+        let delegate = match asset["ownership"]["delegate"].as_str() {
+            Some(delegate) => Pubkey::from_str(delegate)
+                .map_err(|_| "Invalid delegate in DAS response".to_string())?,
+            None => owner,
+        };
+        let leaf_index = compression["leaf_id"].as_u64()
+            .and_then(|id| u32::try_from(id).ok())
+            .ok_or_else(|| "Missing leaf_id in DAS response".to_string())?;
+
+        let mut nodes = proof["proof"].as_array()
+            .ok_or_else(|| "Missing proof in DAS response".to_string())?
+            .iter()
+            .map(|node| parse_pubkey_field(node, "proof"))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        // Transfer 1 NFT (amount = 1)
-        token_manager
-            .create_transfer_transaction(wallet_pubkey, mint, destination, 1)
+        // Nodes covered by the on-chain canopy must not be passed again
+        let client = SolanaClient::new(self.solana_rpc_url.clone());
+        let tree_data = client.get_account_data(&tree).await?
+            .ok_or_else(|| "Merkle tree account not found".to_string())?;
+        let canopy_depth = canopy_depth(&tree_data)?;
+        nodes.truncate(nodes.len().saturating_sub(canopy_depth));
+
+        Ok(CompressedAssetProof {
+            tree,
+            owner,
+            delegate,
+            root: decode_hash_field(&proof["root"], "root")?,
+            data_hash: decode_hash_field(&compression["data_hash"], "data_hash")?,
+            creator_hash: decode_hash_field(&compression["creator_hash"], "creator_hash")?,
+            leaf_index,
+            proof: nodes,
+        })
+    }
+
+    /// Call a Metaplex DAS API method on the configured RPC endpoint
+    async fn das_request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let client = reqwest::Client::new();
+        let response = client
+            .post(&self.das_rpc_url)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": "shadow",
+                "method": method,
+                "params": params
+            }))
+            .send()
             .await
+            .map_err(|e| format!("DAS request error: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("DAS error: {}", response.status()));
+        }
+
+        let mut json: Value = response.json().await
+            .map_err(|e| format!("Failed to parse DAS response: {}", e))?;
+
+        if let Some(error) = json.get("error") {
+            return Err(format!("DAS {} failed: {}", method, error));
+        }
+
+        Ok(json["result"].take())
+    }
+}
+
+/// Backend URL that serves an NFT's image, so clients never contact the original host
+pub fn image_proxy_url(mint: &str) -> String {
+    format!("/api/nfts/{}/image", mint)
+}
+
+/// Derive the Metaplex metadata PDA: ["metadata", program, mint]
+pub fn metadata_pda(mint: &Pubkey) -> Result<Pubkey, String> {
+    let program = Pubkey::from_str(TOKEN_METADATA_PROGRAM_ID)
+        .map_err(|e| format!("Invalid token metadata program ID: {}", e))?;
+
+    let (pda, _bump) = Pubkey::find_program_address(
+        &[b"metadata", program.as_ref(), mint.as_ref()],
+        &program,
+    );
+    Ok(pda)
+}
+
+/// Parse a Metaplex metadata account
+/// Layout: key (1) + update_authority (32) + mint (32) + name + symbol + uri, followed
+/// by optional fields that older accounts may not carry
+pub fn parse_metadata_account(data: &[u8]) -> Result<OnChainMetadata, String> {
+    if data.first() != Some(&METADATA_V1_KEY) {
+        return Err("Account is not a Metaplex metadata account".to_string());
+    }
+
+    let mut offset = 1 + 32 + 32;
+    let name = read_borsh_string(data, &mut offset)?;
+    let symbol = read_borsh_string(data, &mut offset)?;
+    let uri = read_borsh_string(data, &mut offset)?;
+
+    let (token_standard, rule_set) = read_programmable_fields(data, offset).unwrap_or((None, None));
+
+    Ok(OnChainMetadata { name, symbol, uri, token_standard, rule_set })
+}
+
+// Metaplex pads strings with NUL bytes up to their maximum length
+fn read_borsh_string(data: &[u8], offset: &mut usize) -> Result<String, String> {
+    let len_bytes: [u8; 4] = data.get(*offset..*offset + 4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| "Metadata account truncated".to_string())?;
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    *offset += 4;
+
+    let bytes = data.get(*offset..*offset + len)
+        .ok_or_else(|| "Metadata account truncated".to_string())?;
+    *offset += len;
+
+    Ok(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+}
+
+// Walks the fields after `uri` up to `programmable_config`
+fn read_programmable_fields(data: &[u8], mut offset: usize) -> Option<(Option<u8>, Option<Pubkey>)> {
+    let byte_at = |offset: usize| data.get(offset).copied();
+
+    offset += 2; // seller_fee_basis_points
+    if byte_at(offset)? == 1 {
+        // creators: Vec<Creator { address, verified, share }>
+        let len = u32::from_le_bytes(data.get(offset + 1..offset + 5)?.try_into().ok()?) as usize;
+        offset += 5 + len * 34;
+    } else {
+        offset += 1;
     }
+    offset += 2; // primary_sale_happened, is_mutable
+    offset += if byte_at(offset)? == 1 { 2 } else { 1 }; // edition_nonce
+
+    let token_standard = if byte_at(offset)? == 1 {
+        offset += 2;
+        Some(byte_at(offset - 1)?)
+    } else {
+        offset += 1;
+        None
+    };
+
+    offset += if byte_at(offset)? == 1 { 34 } else { 1 }; // collection
+    offset += if byte_at(offset)? == 1 { 18 } else { 1 }; // uses
+    offset += if byte_at(offset)? == 1 { 10 } else { 1 }; // collection_details
+
+    // programmable_config: Option<ProgrammableConfig::V1 { rule_set: Option<Pubkey> }>
+    let rule_set = if byte_at(offset)? == 1 && byte_at(offset + 2)? == 1 {
+        let bytes: [u8; 32] = data.get(offset + 3..offset + 35)?.try_into().ok()?;
+        Some(Pubkey::new_from_array(bytes))
+    } else {
+        None
+    };
+
+    Some((token_standard, rule_set))
 }
 
+fn program_id(id: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(id).map_err(|e| format!("Invalid program ID {}: {}", id, e))
+}
+
+/// Derive the associated token account of `owner` for `mint`
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Result<Pubkey, String> {
+    let program = program_id(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+    let (ata, _bump) = Pubkey::find_program_address(
+        &[owner.as_ref(), spl_token::id().as_ref(), mint.as_ref()],
+        &program,
+    );
+    Ok(ata)
+}
+
+// `CreateIdempotent` so transfers to wallets that already hold the ATA still succeed
+fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Instruction, String> {
+    Ok(Instruction {
+        program_id: program_id(ASSOCIATED_TOKEN_PROGRAM_ID)?,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(associated_token_address(owner, mint)?, false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![1],
+    })
+}
+
+/// Instructions moving a plain SPL NFT between associated token accounts
+pub fn build_standard_transfer(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+) -> Result<Vec<Instruction>, String> {
+    let source_ata = associated_token_address(owner, mint)?;
+    let destination_ata = associated_token_address(destination, mint)?;
+
+    let transfer = spl_token::instruction::transfer_checked(
+        &spl_token::id(),
+        &source_ata,
+        mint,
+        &destination_ata,
+        owner,
+        &[],
+        1,
+        0,
+    )
+    .map_err(|e| format!("Failed to create transfer instruction: {}", e))?;
+
+    Ok(vec![
+        create_associated_token_account_idempotent(owner, destination, mint)?,
+        transfer,
+    ])
+}
+
+fn token_record_pda(mint: &Pubkey, token_account: &Pubkey) -> Result<Pubkey, String> {
+    let program = program_id(TOKEN_METADATA_PROGRAM_ID)?;
+    let (pda, _bump) = Pubkey::find_program_address(
+        &[b"metadata", program.as_ref(), mint.as_ref(), b"token_record", token_account.as_ref()],
+        &program,
+    );
+    Ok(pda)
+}
+
+fn master_edition_pda(mint: &Pubkey) -> Result<Pubkey, String> {
+    let program = program_id(TOKEN_METADATA_PROGRAM_ID)?;
+    let (pda, _bump) = Pubkey::find_program_address(
+        &[b"metadata", program.as_ref(), mint.as_ref(), b"edition"],
+        &program,
+    );
+    Ok(pda)
+}
+
+/// Instructions moving a programmable NFT through Token Metadata `Transfer` (V1)
+pub fn build_programmable_transfer(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    rule_set: Option<&Pubkey>,
+) -> Result<Vec<Instruction>, String> {
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+    let metadata_program = program_id(TOKEN_METADATA_PROGRAM_ID)?;
+    let source_ata = associated_token_address(owner, mint)?;
+    let destination_ata = associated_token_address(destination, mint)?;
+
+    // Unused optional accounts are passed as the Token Metadata program itself
+    let (rules_program, rules) = match rule_set {
+        Some(rule_set) => (program_id(TOKEN_AUTH_RULES_PROGRAM_ID)?, *rule_set),
+        None => (metadata_program, metadata_program),
+    };
+
+    let accounts = vec![
+        AccountMeta::new(source_ata, false),
+        AccountMeta::new_readonly(*owner, false),
+        AccountMeta::new(destination_ata, false),
+        AccountMeta::new_readonly(*destination, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(metadata_pda(mint)?, false),
+        AccountMeta::new_readonly(master_edition_pda(mint)?, false),
+        AccountMeta::new(token_record_pda(mint, &source_ata)?, false),
+        AccountMeta::new(token_record_pda(mint, &destination_ata)?, false),
+        AccountMeta::new_readonly(*owner, true), // authority
+        AccountMeta::new(*owner, true),          // payer
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        AccountMeta::new_readonly(solana_sdk::sysvar::instructions::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(program_id(ASSOCIATED_TOKEN_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(rules_program, false),
+        AccountMeta::new_readonly(rules, false),
+    ];
+
+    // TransferArgs::V1 { amount: 1, authorization_data: None }
+    let mut data = vec![TOKEN_METADATA_TRANSFER_IX, 0];
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.push(0);
+
+    Ok(vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(PROGRAMMABLE_TRANSFER_COMPUTE_UNITS),
+        Instruction { program_id: metadata_program, accounts, data },
+    ])
+}
+
+/// Bubblegum `transfer` instruction for a compressed NFT
+pub fn build_compressed_transfer(
+    proof: &CompressedAssetProof,
+    destination: &Pubkey,
+) -> Result<Instruction, String> {
+    let bubblegum = program_id(BUBBLEGUM_PROGRAM_ID)?;
+    let (tree_authority, _bump) = Pubkey::find_program_address(&[proof.tree.as_ref()], &bubblegum);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(tree_authority, false),
+        AccountMeta::new_readonly(proof.owner, true),
+        AccountMeta::new_readonly(proof.delegate, false),
+        AccountMeta::new_readonly(*destination, false),
+        AccountMeta::new(proof.tree, false),
+        AccountMeta::new_readonly(program_id(SPL_NOOP_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(program_id(SPL_ACCOUNT_COMPRESSION_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+    ];
+    accounts.extend(proof.proof.iter().map(|node| AccountMeta::new_readonly(*node, false)));
+
+    let mut data = BUBBLEGUM_TRANSFER_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&proof.root);
+    data.extend_from_slice(&proof.data_hash);
+    data.extend_from_slice(&proof.creator_hash);
+    data.extend_from_slice(&(proof.leaf_index as u64).to_le_bytes()); // nonce
+    data.extend_from_slice(&proof.leaf_index.to_le_bytes());
+
+    Ok(Instruction { program_id: bubblegum, accounts, data })
+}
+
+/// Canopy depth of an SPL concurrent Merkle tree account
+/// Header: account type (1) + version (1) + max_buffer_size (4) + max_depth (4)
+/// + authority (32) + creation_slot (8) + padding (6)
+pub fn canopy_depth(tree_account: &[u8]) -> Result<usize, String> {
+    const HEADER_LEN: usize = 56;
+
+    let read_u32 = |offset: usize| -> Result<usize, String> {
+        tree_account.get(offset..offset + 4)
+            .and_then(|b| b.try_into().ok())
+            .map(|b| u32::from_le_bytes(b) as usize)
+            .ok_or_else(|| "Merkle tree account truncated".to_string())
+    };
+    let max_buffer_size = read_u32(2)?;
+    let max_depth = read_u32(6)?;
+
+    // sequence_number, active_index, buffer_size + change log buffer + rightmost proof
+    let change_log_len = 32 + 32 * max_depth + 4 + 4;
+    let rightmost_proof_len = 32 * max_depth + 32 + 4 + 4;
+    let tree_len = 8 * 3 + max_buffer_size * change_log_len + rightmost_proof_len;
+
+    let canopy_bytes = tree_account.len()
+        .checked_sub(HEADER_LEN + tree_len)
+        .ok_or_else(|| "Merkle tree account truncated".to_string())?;
+    if canopy_bytes == 0 {
+        return Ok(0);
+    }
+
+    // The canopy stores 2^(depth + 1) - 2 nodes
+    let nodes = canopy_bytes / 32;
+    Ok(((nodes + 2) as f64).log2() as usize - 1)
+}
+
+fn parse_pubkey_field(value: &Value, field: &str) -> Result<Pubkey, String> {
+    value.as_str()
+        .and_then(|s| Pubkey::from_str(s).ok())
+        .ok_or_else(|| format!("Invalid {} in DAS response", field))
+}
+
+fn decode_hash_field(value: &Value, field: &str) -> Result<[u8; 32], String> {
+    value.as_str()
+        .and_then(|s| bs58::decode(s.trim()).into_vec().ok())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid {} in DAS response", field))
+}
+
+/// Validate an off-chain metadata JSON document (Metaplex token standard)
+pub fn parse_metadata_document(bytes: &[u8]) -> Result<MetadataDocument, String> {
+    use serde_json::Value;
+
+    let json: Value = serde_json::from_slice(bytes)
+        .map_err(|e| format!("Invalid metadata JSON: {}", e))?;
+    let object = json.as_object()
+        .ok_or_else(|| "Metadata document must be a JSON object".to_string())?;
+
+    let optional_string = |field: &str| -> Result<Option<String>, String> {
+        match object.get(field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) if s.trim().is_empty() => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.trim().to_string())),
+            Some(_) => Err(format!("Metadata field '{}' must be a string", field)),
+        }
+    };
+
+    let name = optional_string("name")?;
+    if name.as_ref().is_some_and(|n| n.chars().count() > 200) {
+        return Err("Metadata name too long (max 200 characters)".to_string());
+    }
 
+    let image = optional_string("image")?;
+    if let Some(image) = &image {
+        if !is_supported_uri(image) {
+            return Err(format!("Unsupported image URI: {}", image));
+        }
+    }
+
+    let mut attributes = Vec::new();
+    match object.get("attributes") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(entries)) => {
+            // Entries without a trait_type are common in the wild and skipped
+            for entry in entries.iter().take(MAX_ATTRIBUTES) {
+                let trait_type = match entry.get("trait_type") {
+                    Some(Value::String(t)) => t.clone(),
+                    _ => continue,
+                };
+                let value = match entry.get("value") {
+                    Some(Value::String(v)) => v.clone(),
+                    Some(Value::Number(n)) => n.to_string(),
+                    Some(Value::Bool(b)) => b.to_string(),
+                    _ => continue,
+                };
+                attributes.push(Attribute { trait_type, value });
+            }
+        }
+        Some(_) => return Err("Metadata field 'attributes' must be an array".to_string()),
+    }
+
+    let collection = match object.get("collection") {
+        Some(Value::Object(c)) => c.get("name").and_then(|n| n.as_str()).map(|s| s.to_string()),
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+
+    Ok(MetadataDocument {
+        name,
+        symbol: optional_string("symbol")?,
+        description: optional_string("description")?,
+        image,
+        attributes,
+        collection,
+    })
+}
+
+fn is_supported_uri(uri: &str) -> bool {
+    ["ipfs://", "ar://", "arweave://", "https://"]
+        .iter()
+        .any(|scheme| uri.starts_with(scheme))
+}
+
+const MAX_METADATA_REDIRECTS: usize = 3;
+
+/// Resolve the URL's host, refuse it unless every address is public, and return a client
+/// that connects to the address that was checked rather than resolving again
+async fn pinned_client(url: &reqwest::Url) -> Result<reqwest::Client, String> {
+    if !is_public_url(url) {
+        return Err("Metadata URL must point to a public host".to_string());
+    }
+    let host = url.host_str().ok_or("Metadata URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+        .await
+        .map_err(|e| format!("Failed to resolve metadata host {}: {}", host, e))?
+        .collect();
+    let addr = public_addr(host, &addrs)?;
+
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(host, addr)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// A host that resolves to any non-public address is refused outright, so a mixed answer
+/// can't be used to race the check
+fn public_addr(host: &str, addrs: &[SocketAddr]) -> Result<SocketAddr, String> {
+    match addrs.first() {
+        Some(addr) if addrs.iter().all(|a| is_public_ip(&a.ip())) => Ok(*addr),
+        Some(_) => Err("Metadata URL must point to a public host".to_string()),
+        None => Err(format!("Metadata host {} did not resolve", host)),
+    }
+}
+
+/// Reject loopback, private and link-local targets for server-side fetches
+fn is_public_url(url: &reqwest::Url) -> bool {
+    if url.scheme() != "https" {
+        return false;
+    }
+
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_lowercase(),
+        None => return false,
+    };
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(&ip),
+        Err(_) => host != "localhost" && !host.ends_with(".localhost") && !host.ends_with(".internal"),
+    }
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_loopback() || v4.is_unspecified() || v4.is_private() || v4.is_link_local()
+                || v4.is_broadcast() || v4.is_multicast() || v4.is_documentation()
+                // 0.0.0.0/8 and carrier-grade NAT 100.64.0.0/10
+                || a == 0 || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(v6) => {
+            // IPv4-mapped addresses are judged by the IPv4 address they carry
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Detect image content type from magic bytes rather than trusting the host
+pub fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).to_lowercase();
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+        if head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg")) {
+            Some("image/svg+xml")
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(s: &str, padded_len: usize) -> Vec<u8> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize(padded_len, 0);
+        let mut out = (bytes.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(&bytes);
+        out
+    }
+
+    #[test]
+    fn test_parse_metadata_account() {
+        let mut data = vec![METADATA_V1_KEY];
+        data.extend_from_slice(&[1u8; 32]);
+        data.extend_from_slice(&[2u8; 32]);
+        data.extend(borsh_string("Shadow #1", 32));
+        data.extend(borsh_string("SHDW", 10));
+        data.extend(borsh_string("ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi", 200));
+
+        // Older accounts end right after the uri
+        let parsed = parse_metadata_account(&data).unwrap();
+        assert_eq!(parsed.name, "Shadow #1");
+        assert_eq!(parsed.symbol, "SHDW");
+        assert!(parsed.uri.starts_with("ipfs://"));
+        assert_eq!(parsed.token_standard, None);
+
+        let rule_set = Pubkey::new_unique();
+        let mut pnft = data.clone();
+        pnft.extend_from_slice(&500u16.to_le_bytes()); // seller_fee_basis_points
+        pnft.push(1); // creators: Some(vec![creator])
+        pnft.extend_from_slice(&1u32.to_le_bytes());
+        pnft.extend_from_slice(&[3u8; 34]);
+        pnft.extend_from_slice(&[1, 1]); // primary_sale_happened, is_mutable
+        pnft.extend_from_slice(&[1, 255]); // edition_nonce
+        pnft.extend_from_slice(&[1, TOKEN_STANDARD_PROGRAMMABLE]);
+        pnft.push(0); // collection
+        pnft.push(0); // uses
+        pnft.push(0); // collection_details
+        pnft.extend_from_slice(&[1, 0, 1]); // programmable_config: V1 { rule_set: Some }
+        pnft.extend_from_slice(rule_set.as_ref());
+
+        let parsed = parse_metadata_account(&pnft).unwrap();
+        assert_eq!(parsed.token_standard, Some(TOKEN_STANDARD_PROGRAMMABLE));
+        assert_eq!(parsed.rule_set, Some(rule_set));
+
+        assert!(parse_metadata_account(&data[..70]).is_err());
+        assert!(parse_metadata_account(&[0u8; 100]).is_err());
+    }
+
+    #[test]
+    fn test_parse_metadata_document() {
+        let json = br#"{
+            "name": "Shadow #1",
+            "image": "ar://abc123",
+            "attributes": [
+                {"trait_type": "Background", "value": "Black"},
+                {"trait_type": "Level", "value": 7},
+                {"value": "no trait"}
+            ],
+            "collection": {"name": "Shadows", "family": "Shadow"}
+        }"#;
+
+        let doc = parse_metadata_document(json).unwrap();
+        assert_eq!(doc.name.as_deref(), Some("Shadow #1"));
+        assert_eq!(doc.image.as_deref(), Some("ar://abc123"));
+        assert_eq!(doc.attributes.len(), 2);
+        assert_eq!(doc.attributes[1].value, "7");
+        assert_eq!(doc.collection.as_deref(), Some("Shadows"));
+
+        assert!(parse_metadata_document(b"[1, 2]").is_err());
+        assert!(parse_metadata_document(br#"{"name": 5}"#).is_err());
+        assert!(parse_metadata_document(br#"{"image": "javascript:alert(1)"}"#).is_err());
+        assert!(parse_metadata_document(br#"{"attributes": "none"}"#).is_err());
+    }
+
+    #[test]
+    fn test_programmable_transfer_layout() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let instructions = build_programmable_transfer(&owner, &mint, &destination, None).unwrap();
+        let transfer = &instructions[1];
+        assert_eq!(transfer.program_id.to_string(), TOKEN_METADATA_PROGRAM_ID);
+        assert_eq!(transfer.accounts.len(), 17);
+        assert_eq!(transfer.data, vec![49, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(transfer.accounts[0].pubkey, associated_token_address(&owner, &mint).unwrap());
+        assert_eq!(transfer.accounts[15].pubkey, transfer.program_id);
+    }
+
+    #[test]
+    fn test_compressed_transfer_layout() {
+        let proof = CompressedAssetProof {
+            tree: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            root: [1u8; 32],
+            data_hash: [2u8; 32],
+            creator_hash: [3u8; 32],
+            leaf_index: 7,
+            proof: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        };
+
+        let ix = build_compressed_transfer(&proof, &Pubkey::new_unique()).unwrap();
+        assert_eq!(ix.accounts.len(), 8 + 2);
+        assert!(ix.accounts[1].is_signer);
+        assert_eq!(ix.data.len(), 8 + 32 * 3 + 8 + 4);
+        assert_eq!(&ix.data[..8], &BUBBLEGUM_TRANSFER_DISCRIMINATOR);
+        assert_eq!(&ix.data[104..112], &7u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_canopy_depth() {
+        // max_depth 14, max_buffer_size 64
+        let mut header = vec![1u8, 0];
+        header.extend_from_slice(&64u32.to_le_bytes());
+        header.extend_from_slice(&14u32.to_le_bytes());
+        header.resize(56, 0);
+        let tree_len = 24 + 64 * (32 + 32 * 14 + 8) + (32 * 14 + 40);
+
+        let mut account = header.clone();
+        account.resize(56 + tree_len, 0);
+        assert_eq!(canopy_depth(&account).unwrap(), 0);
+
+        // Canopy of depth 3 holds 2^4 - 2 = 14 nodes
+        account.resize(56 + tree_len + 14 * 32, 0);
+        assert_eq!(canopy_depth(&account).unwrap(), 3);
+
+        assert!(canopy_depth(&header).is_err());
+    }
+
+    #[test]
+    fn test_public_url_check() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_public_url(&url("https://arweave.net/abc")));
+        assert!(!is_public_url(&url("http://arweave.net/abc")));
+        assert!(!is_public_url(&url("https://localhost/metadata.json")));
+        assert!(!is_public_url(&url("https://127.0.0.1/metadata.json")));
+        assert!(!is_public_url(&url("https://10.0.0.5/metadata.json")));
+        assert!(!is_public_url(&url("https://169.254.169.254/latest")));
+        assert!(!is_public_url(&url("https://[::1]/metadata.json")));
+        assert!(!is_public_url(&url("https://[::ffff:127.0.0.1]/metadata.json")));
+        assert!(!is_public_url(&url("https://100.64.0.1/metadata.json")));
+    }
+
+    #[test]
+    fn test_resolved_addresses_must_all_be_public() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let public = addr("93.184.216.34:443");
+
+        assert_eq!(public_addr("example.com", &[public]).unwrap(), public);
+        // A public-looking name that resolves inward is refused, even alongside a public answer
+        assert!(public_addr("rebind.example", &[addr("127.0.0.1:443")]).is_err());
+        assert!(public_addr("rebind.example", &[public, addr("10.0.0.1:443")]).is_err());
+        assert!(public_addr("rebind.example", &[addr("[::ffff:169.254.169.254]:443")]).is_err());
+        assert!(public_addr("missing.example", &[]).is_err());
+        assert!(!is_public_url(&reqwest::Url::parse("https://localhost./x").unwrap()));
+    }
+
+    #[test]
+    fn test_cached_metadata_freshness() {
+        let now = Utc::now();
+        // What the original code stored for every mint it looked at
+        let placeholder = doc! {
+            "mint": "mint", "name": "Unknown NFT", "symbol": null, "uri": null,
+            "description": null, "image": null, "attributes": [], "collection": null,
+        };
+        let legacy: CachedNftMetadata = mongodb::bson::from_document(placeholder).unwrap();
+        assert_eq!(legacy.cached_at, None);
+        assert!(!legacy.is_fresh(now));
+
+        let mut resolved = legacy.clone();
+        resolved.metadata.uri = Some("https://arweave.net/abc".to_string());
+        assert!(!resolved.is_fresh(now));
+        resolved.cached_at = Some(now - chrono::Duration::hours(1));
+        assert!(resolved.is_fresh(now));
+        assert!(!resolved.is_fresh(now + chrono::Duration::hours(6)));
+
+        // Stored as a BSON date so the TTL index can expire it
+        let stored = mongodb::bson::to_document(&resolved).unwrap();
+        assert!(stored.get_datetime("cached_at").is_ok());
+        assert_eq!(stored.get_str("mint").unwrap(), "mint");
+    }
+
+    #[test]
+    fn test_sniff_image_type() {
+        assert_eq!(sniff_image_type(b"\x89PNG\r\n\x1a\nrest"), Some("image/png"));
+        assert_eq!(sniff_image_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(sniff_image_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Some("image/svg+xml"));
+        assert_eq!(sniff_image_type(b"<html></html>"), None);
+    }
+}
@@ -3,6 +3,17 @@
 
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
+use unicode_security::{GeneralSecurityProfile, MixedScript};
+
+/// .shadow labels nobody may register: the platform's own names and
+/// well-known brands. Lookalikes of these are refused too.
+pub const RESERVED_LABELS: &[&str] = &[
+    "shadow", "admin", "administrator", "root", "system", "support", "help",
+    "security", "official", "verify", "wallet", "login", "api", "www", "mail", "dns",
+    "solana", "phantom", "solflare", "backpack", "metamask", "jupiter", "raydium",
+    "magiceden", "tensor", "coinbase", "binance", "kraken",
+    "google", "apple", "microsoft", "amazon", "paypal", "github",
+];
 
 pub struct ApolloValidator;
 
@@ -20,7 +31,7 @@ impl ApolloValidator {
     /// Validate domain name format
     /// Supports both .shadow domains and custom domains
     pub fn validate_domain(domain: &str) -> Result<(), String> {
-        // Check if it's a .shadow domain
+        // Check if it's a .shadow domain (subdomains like app.mysite.shadow included)
         if domain.ends_with(".shadow") {
             let domain_name = domain.strip_suffix(".shadow").unwrap();
             
@@ -28,16 +39,31 @@ impl ApolloValidator {
                 return Err("Domain name cannot be empty".to_string());
             }
 
-            if domain_name.len() > 63 {
-                return Err("Domain name too long (max 63 characters)".to_string());
+            if domain.len() > 253 {
+                return Err("Domain too long (max 253 characters)".to_string());
             }
 
-            if !domain_name.chars().all(|c| c.is_alphanumeric() || c == '-') {
-                return Err("Domain name can only contain alphanumeric characters and hyphens".to_string());
-            }
+            for label in domain_name.split('.') {
+                if label.is_empty() {
+                    return Err("Domain labels cannot be empty".to_string());
+                }
 
-            if domain_name.starts_with('-') || domain_name.ends_with('-') {
-                return Err("Domain name cannot start or end with a hyphen".to_string());
+                if label.len() > 63 {
+                    return Err("Domain label too long (max 63 characters)".to_string());
+                }
+
+                // Internationalized names are stored as punycode (see normalize_domain)
+                if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                    return Err("Domain name can only contain letters, digits and hyphens; non-ASCII names must be punycode (xn--)".to_string());
+                }
+
+                if label.starts_with('-') || label.ends_with('-') {
+                    return Err("Domain labels cannot start or end with a hyphen".to_string());
+                }
+
+                if label.get(2..4) == Some("--") && !label.starts_with("xn--") {
+                    return Err("Labels with '--' in the third and fourth positions are reserved for IDNA".to_string());
+                }
             }
         } else {
             // Custom domain validation (more permissive)
@@ -68,6 +94,66 @@ impl ApolloValidator {
         Ok(())
     }
 
+    /// Canonical form of a domain name: lowercase, with punycode labels
+    ///
+    /// Unicode input is mapped with IDNA (UTS #46), so "Ålice.shadow" and
+    /// "xn--lice-poa.shadow" are the same name.
+    pub fn normalize_domain(domain: &str) -> Result<String, String> {
+        let ascii = idna::domain_to_ascii_strict(domain.trim_end_matches('.'))
+            .map_err(|_| "Domain is not a valid internationalized name".to_string())?;
+        Self::validate_domain(&ascii)?;
+        Ok(ascii)
+    }
+
+    /// Display form of a normalized domain name
+    pub fn unicode_domain(domain: &str) -> String {
+        idna::domain_to_unicode(domain).0
+    }
+
+    /// Naming rules beyond syntax that registrations must follow
+    ///
+    /// Every label must use characters allowed in identifiers (UTS #39) from
+    /// a single script, and .shadow names may not be, or look like, a
+    /// reserved name. `reserved` extends `RESERVED_LABELS`.
+    pub fn check_name_policy(domain: &str, reserved: &[String]) -> Result<(), String> {
+        let (unicode, decoded) = idna::domain_to_unicode(domain);
+        if decoded.is_err() {
+            return Err("Domain contains invalid punycode".to_string());
+        }
+
+        for label in unicode.split('.') {
+            if let Some(c) = label.chars().find(|c| *c != '-' && !c.identifier_allowed()) {
+                return Err(format!(
+                    "Character '{}' (U+{:04X}) is not allowed in domain names",
+                    c, c as u32
+                ));
+            }
+            if !label.is_single_script() {
+                return Err(format!("Label '{}' mixes scripts; use one script per label", label));
+            }
+        }
+
+        // Only the registered label counts; subdomains are the owner's business
+        let Some(label) = unicode.strip_suffix(".shadow").and_then(|n| n.rsplit('.').next()) else {
+            return Ok(());
+        };
+        let skeleton = Self::name_skeleton(label);
+        let reserved = RESERVED_LABELS.iter().copied()
+            .chain(reserved.iter().map(String::as_str))
+            .find(|r| *r == label || Self::name_skeleton(r) == skeleton);
+        match reserved {
+            Some(r) if r == label => Err(format!("'{}' is a reserved name", label)),
+            Some(r) => Err(format!("'{}' looks like the reserved name '{}'", label, r)),
+            None => Ok(()),
+        }
+    }
+
+    /// Confusables skeleton (UTS #39) of a name; lookalikes share one
+    pub fn name_skeleton(domain: &str) -> String {
+        let unicode = Self::unicode_domain(domain);
+        unicode_security::skeleton(&unicode).collect()
+    }
+
     /// Validate IPFS CID format (basic check)
     pub fn validate_ipfs_cid(cid: &str) -> Result<(), String> {
         if cid.starts_with("ipfs://") {
@@ -0,0 +1,760 @@
+// Atlas - Titan Who Holds Up the Sky
+// Carries uploads to storage: streamed bodies staged on disk, resumable chunked sessions,
+// per-wallet quotas and content types sniffed from the bytes against an allowlist
+
+use crate::config::UploadConfig;
+use crate::daedalus;
+use crate::error::ShadowError;
+use crate::storage::{DirectoryFile, PutOptions, StorageRegistry};
+use actix_web::web::Bytes;
+use dashmap::DashMap;
+use futures_util::{Stream, StreamExt};
+use mongodb::bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// Enough for every signature below and to tell text from binary
+const SNIFF_BYTES: usize = 512;
+pub const MAX_DIRECTORY_FILES: usize = 1000;
+const MAX_SEGMENT_BYTES: usize = 255;
+
+/// A file staged on disk on its way to a storage provider
+#[derive(Debug)]
+pub struct StagedFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub content_type: String,
+    // Request bodies are thrown away once stored; session data stays until the upload succeeds
+    temporary: bool,
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedFile {
+    pub uri: String,
+    pub name: String,
+    pub size: u64,
+    pub content_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedDirectory {
+    pub uri: String,
+    pub files: usize,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageUsage {
+    pub bytes_used: u64,
+    pub quota_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadSession {
+    pub scheme: String,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UploadSession {
+    id: String,
+    wallet: String,
+    scheme: String,
+    name: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadSessionStatus {
+    pub id: String,
+    pub scheme: String,
+    pub name: String,
+    pub size: u64,
+    pub offset: u64,
+    pub chunk_bytes: u64,
+}
+
+pub struct AtlasUploader {
+    db: Database,
+    storage: Arc<StorageRegistry>,
+    config: UploadConfig,
+    // Sessions with a chunk being written, so two writers can't interleave
+    writing: DashMap<String, ()>,
+}
+
+/// Releases a session's write lock even when the request is dropped mid-stream
+struct WriteGuard<'a> {
+    writing: &'a DashMap<String, ()>,
+    id: String,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.writing.remove(&self.id);
+    }
+}
+
+impl AtlasUploader {
+    pub fn new(db: Database, storage: Arc<StorageRegistry>, config: UploadConfig) -> Self {
+        Self {
+            db,
+            storage,
+            config,
+            writing: DashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &UploadConfig {
+        &self.config
+    }
+
+    fn usage_collection(&self) -> Collection<Document> {
+        self.db.collection::<Document>("storage_usage")
+    }
+
+    fn staging_dir(&self) -> PathBuf {
+        Path::new(&self.config.spool_dir).join("staging")
+    }
+
+    fn sessions_dir(&self) -> PathBuf {
+        Path::new(&self.config.spool_dir).join("sessions")
+    }
+
+    /// Refuse schemes nothing is registered for before any bytes are read
+    pub fn check_scheme(&self, scheme: &str) -> Result<(), ShadowError> {
+        self.storage.for_scheme(scheme).map(|_| ()).map_err(ShadowError::BadRequest)
+    }
+
+    pub async fn usage(&self, wallet: &str) -> Result<StorageUsage, ShadowError> {
+        let bytes_used = self.usage_collection()
+            .find_one(doc! { "_id": wallet }, None)
+            .await?
+            .and_then(|usage| usage.get_i64("bytes_used").ok())
+            .unwrap_or(0);
+
+        Ok(StorageUsage {
+            bytes_used: bytes_used.max(0) as u64,
+            quota_bytes: self.config.user_quota_bytes,
+        })
+    }
+
+    /// Bytes `wallet` may still upload in one request of at most `request_limit`
+    pub async fn budget(&self, wallet: &str, request_limit: u64) -> Result<u64, ShadowError> {
+        let usage = self.usage(wallet).await?;
+        let remaining = usage.quota_bytes.saturating_sub(usage.bytes_used);
+        if remaining == 0 {
+            return Err(ShadowError::TooLarge("Storage quota used up".to_string()));
+        }
+        Ok(request_limit.min(remaining))
+    }
+
+    /// Charge `bytes` against the quota, failing if it would go over
+    async fn reserve(&self, wallet: &str, bytes: u64) -> Result<(), ShadowError> {
+        let collection = self.usage_collection();
+        collection.update_one(
+            doc! { "_id": wallet },
+            doc! { "$setOnInsert": { "bytes_used": 0_i64 } },
+            UpdateOptions::builder().upsert(true).build(),
+        ).await?;
+
+        let ceiling = self.config.user_quota_bytes.checked_sub(bytes)
+            .ok_or_else(|| ShadowError::TooLarge("Upload is larger than the storage quota".to_string()))?;
+        // The check and the charge are one update, so parallel uploads can't both squeeze in
+        let result = collection.update_one(
+            doc! { "_id": wallet, "bytes_used": { "$lte": ceiling as i64 } },
+            doc! {
+                "$inc": { "bytes_used": bytes as i64 },
+                "$set": { "updated_at": mongodb::bson::DateTime::now() },
+            },
+            None,
+        ).await?;
+
+        if result.modified_count == 0 {
+            return Err(ShadowError::TooLarge("Upload would exceed the storage quota".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn release(&self, wallet: &str, bytes: u64) {
+        let result = self.usage_collection().update_one(
+            doc! { "_id": wallet },
+            doc! { "$inc": { "bytes_used": -(bytes as i64) } },
+            None,
+        ).await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to refund {} bytes of storage quota to {}: {}", bytes, wallet, e);
+        }
+    }
+
+    /// Stage a streamed body on disk, refusing it once it passes `limit` bytes or
+    /// turns out to be a type that is not allowed
+    pub async fn stage<S, E>(&self, name: &str, mut body: S, limit: u64) -> Result<StagedFile, ShadowError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let dir = self.staging_dir();
+        tokio::fs::create_dir_all(&dir).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+        let mut staged = StagedFile {
+            path: dir.join(uuid::Uuid::new_v4().to_string()),
+            name: name.to_string(),
+            size: 0,
+            content_type: String::new(),
+            temporary: true,
+        };
+        let mut file = tokio::fs::File::create(&staged.path).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to stage upload: {}", e)))?;
+        let mut head = Vec::with_capacity(SNIFF_BYTES);
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| ShadowError::BadRequest(format!("Upload interrupted: {}", e)))?;
+            staged.size += chunk.len() as u64;
+            if staged.size > limit {
+                return Err(ShadowError::TooLarge(format!("{} is larger than the {} bytes allowed", name, limit)));
+            }
+            if head.len() < SNIFF_BYTES {
+                let take = chunk.len().min(SNIFF_BYTES - head.len());
+                head.extend_from_slice(&chunk[..take]);
+            }
+            file.write_all(&chunk).await
+                .map_err(|e| ShadowError::Storage(format!("Failed to stage upload: {}", e)))?;
+        }
+        file.flush().await
+            .map_err(|e| ShadowError::Storage(format!("Failed to stage upload: {}", e)))?;
+
+        staged.content_type = self.allowed_type(name, &head)?;
+        Ok(staged)
+    }
+
+    fn allowed_type(&self, name: &str, head: &[u8]) -> Result<String, ShadowError> {
+        let content_type = sniff_content_type(name, head);
+        if !self.config.allowed_types.contains(&content_type) {
+            return Err(ShadowError::UnsupportedMediaType(format!("{} is {}, which can't be uploaded", name, content_type)));
+        }
+        Ok(content_type)
+    }
+
+    pub async fn store_file(&self, wallet: &str, scheme: &str, staged: &StagedFile) -> Result<UploadedFile, ShadowError> {
+        let provider = self.storage.for_scheme(scheme).map_err(ShadowError::BadRequest)?;
+        let name = staged.name.rsplit('/').next().unwrap_or_default().to_string();
+        let options = PutOptions {
+            name: name.clone(),
+            content_type: Some(staged.content_type.clone()),
+            tags: Vec::new(),
+        };
+
+        self.reserve(wallet, staged.size).await?;
+        match provider.put_file(&staged.path, &options).await {
+            Ok(uri) => Ok(UploadedFile {
+                uri,
+                name,
+                size: staged.size,
+                content_type: staged.content_type.clone(),
+            }),
+            Err(e) => {
+                self.release(wallet, staged.size).await;
+                Err(ShadowError::Storage(e))
+            }
+        }
+    }
+
+    /// Store `files` under one root; their names are their paths inside it
+    pub async fn store_directory(&self, wallet: &str, scheme: &str, files: &[StagedFile]) -> Result<UploadedDirectory, ShadowError> {
+        let provider = self.storage.for_scheme(scheme).map_err(ShadowError::BadRequest)?;
+        if files.is_empty() {
+            return Err(ShadowError::BadRequest("Directory upload has no files".to_string()));
+        }
+
+        let mut seen = HashSet::new();
+        let mut entries = Vec::with_capacity(files.len());
+        for staged in files {
+            if !seen.insert(staged.name.as_str()) {
+                return Err(ShadowError::BadRequest(format!("{} appears twice", staged.name)));
+            }
+            entries.push(DirectoryFile {
+                path: staged.name.clone(),
+                file: staged.path.clone(),
+                content_type: Some(staged.content_type.clone()),
+            });
+        }
+
+        let size = files.iter().map(|staged| staged.size).sum();
+        self.reserve(wallet, size).await?;
+        match provider.put_directory(&entries).await {
+            Ok(uri) => Ok(UploadedDirectory { uri, files: files.len(), size }),
+            Err(e) => {
+                self.release(wallet, size).await;
+                Err(ShadowError::Storage(e))
+            }
+        }
+    }
+
+    /// Start a resumable upload of `size` bytes; chunks follow with PATCH
+    pub async fn create_session(&self, wallet: &str, request: &CreateUploadSession) -> Result<UploadSessionStatus, ShadowError> {
+        self.check_scheme(&request.scheme)?;
+        if request.size == 0 || request.size > self.config.max_file_bytes {
+            return Err(ShadowError::TooLarge(format!("Uploads must be 1 to {} bytes", self.config.max_file_bytes)));
+        }
+
+        // Open sessions count against the quota so they can't be used to fill the disk
+        let open: u64 = self.sessions(Some(wallet)).await?.iter().map(|session| session.size).sum();
+        let usage = self.usage(wallet).await?;
+        if usage.bytes_used + open + request.size > usage.quota_bytes {
+            return Err(ShadowError::TooLarge("Upload would exceed the storage quota".to_string()));
+        }
+
+        self.open_session(wallet, request).await
+    }
+
+    async fn open_session(&self, wallet: &str, request: &CreateUploadSession) -> Result<UploadSessionStatus, ShadowError> {
+        let session = UploadSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            wallet: wallet.to_string(),
+            scheme: request.scheme.clone(),
+            name: file_name(&request.name)?,
+            size: request.size,
+        };
+
+        let dir = self.sessions_dir();
+        tokio::fs::create_dir_all(&dir).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to create {}: {}", dir.display(), e)))?;
+        tokio::fs::File::create(self.session_data(&session.id)).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to open upload session: {}", e)))?;
+        let meta = serde_json::to_vec(&session)
+            .map_err(|e| ShadowError::Storage(format!("Failed to save upload session: {}", e)))?;
+        tokio::fs::write(self.session_meta(&session.id), meta).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to save upload session: {}", e)))?;
+
+        Ok(self.status(&session, 0))
+    }
+
+    pub async fn session_status(&self, wallet: &str, id: &str) -> Result<UploadSessionStatus, ShadowError> {
+        let session = self.load_session(wallet, id).await?;
+        let offset = self.session_offset(&session.id).await?;
+        Ok(self.status(&session, offset))
+    }
+
+    /// Append a chunk written at `offset`; the session's offset is the length of what has landed
+    pub async fn append_chunk<S, E>(&self, wallet: &str, id: &str, offset: u64, mut body: S) -> Result<UploadSessionStatus, ShadowError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let session = self.load_session(wallet, id).await?;
+        let _guard = self.lock_session(&session.id)?;
+
+        let current = self.session_offset(&session.id).await?;
+        if offset != current {
+            return Err(ShadowError::Conflict(format!("Upload is at offset {}", current)));
+        }
+        let limit = self.config.chunk_bytes.min(session.size - current);
+
+        let path = self.session_data(&session.id);
+        let mut file = tokio::fs::OpenOptions::new().append(true).open(&path).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to open upload session: {}", e)))?;
+        let mut written = 0u64;
+        let mut failure = None;
+
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                // Whatever landed stays; the client resumes from the new offset
+                Err(e) => {
+                    failure = Some(ShadowError::BadRequest(format!("Upload interrupted: {}", e)));
+                    break;
+                }
+            };
+            if written + chunk.len() as u64 > limit {
+                file.set_len(current).await
+                    .map_err(|e| ShadowError::Storage(format!("Failed to roll back chunk: {}", e)))?;
+                return Err(ShadowError::TooLarge(format!("Chunks may carry at most {} bytes here", limit)));
+            }
+            file.write_all(&chunk).await
+                .map_err(|e| ShadowError::Storage(format!("Failed to write chunk: {}", e)))?;
+            written += chunk.len() as u64;
+        }
+        file.flush().await
+            .map_err(|e| ShadowError::Storage(format!("Failed to write chunk: {}", e)))?;
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(self.status(&session, current + written)),
+        }
+    }
+
+    /// Check and store a fully received upload. The data is kept if storing fails, so
+    /// completing can be retried without sending it again.
+    pub async fn complete_session(&self, wallet: &str, id: &str) -> Result<UploadedFile, ShadowError> {
+        let session = self.load_session(wallet, id).await?;
+        let _guard = self.lock_session(&session.id)?;
+
+        let offset = self.session_offset(&session.id).await?;
+        if offset != session.size {
+            return Err(ShadowError::Conflict(format!("Upload is at offset {} of {}", offset, session.size)));
+        }
+
+        let path = self.session_data(&session.id);
+        let mut head = vec![0u8; SNIFF_BYTES.min(session.size as usize)];
+        tokio::fs::File::open(&path).await
+            .map_err(|e| ShadowError::Storage(format!("Failed to open upload session: {}", e)))?
+            .read_exact(&mut head)
+            .await
+            .map_err(|e| ShadowError::Storage(format!("Failed to read upload session: {}", e)))?;
+
+        let staged = StagedFile {
+            path,
+            name: session.name.clone(),
+            size: session.size,
+            content_type: self.allowed_type(&session.name, &head)?,
+            temporary: false,
+        };
+        let uploaded = self.store_file(wallet, &session.scheme, &staged).await?;
+
+        self.remove_session(&session.id).await;
+        Ok(uploaded)
+    }
+
+    pub async fn abort_session(&self, wallet: &str, id: &str) -> Result<(), ShadowError> {
+        let session = self.load_session(wallet, id).await?;
+        let _guard = self.lock_session(&session.id)?;
+        self.remove_session(&session.id).await;
+        Ok(())
+    }
+
+    /// Drop sessions and staged bodies nobody has written to within `ttl`
+    pub async fn sweep(&self, ttl: Duration) -> Result<usize, String> {
+        let mut removed = 0;
+        for dir in [self.sessions_dir(), self.staging_dir()] {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to list {}: {}", dir.display(), e)),
+            };
+
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                // Session data and its metadata go together, keyed by the data file's age
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    continue;
+                }
+                let idle = entry.metadata().await
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+                if idle.is_some_and(|idle| idle > ttl) {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    let _ = tokio::fs::remove_file(path.with_extension("json")).await;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    fn session_data(&self, id: &str) -> PathBuf {
+        self.sessions_dir().join(format!("{}.data", id))
+    }
+
+    fn session_meta(&self, id: &str) -> PathBuf {
+        self.sessions_dir().join(format!("{}.json", id))
+    }
+
+    fn status(&self, session: &UploadSession, offset: u64) -> UploadSessionStatus {
+        UploadSessionStatus {
+            id: session.id.clone(),
+            scheme: session.scheme.clone(),
+            name: session.name.clone(),
+            size: session.size,
+            offset,
+            chunk_bytes: self.config.chunk_bytes,
+        }
+    }
+
+    fn lock_session(&self, id: &str) -> Result<WriteGuard<'_>, ShadowError> {
+        if self.writing.insert(id.to_string(), ()).is_some() {
+            return Err(ShadowError::Conflict("Another request is writing to this upload".to_string()));
+        }
+        Ok(WriteGuard { writing: &self.writing, id: id.to_string() })
+    }
+
+    /// Sessions belong to the wallet that opened them; others get the same answer as a missing one
+    async fn load_session(&self, wallet: &str, id: &str) -> Result<UploadSession, ShadowError> {
+        let missing = || ShadowError::NotFound("Upload session not found".to_string());
+        let id = uuid::Uuid::parse_str(id).map_err(|_| missing())?.to_string();
+
+        let meta = tokio::fs::read(self.session_meta(&id)).await.map_err(|_| missing())?;
+        let session: UploadSession = serde_json::from_slice(&meta)
+            .map_err(|e| ShadowError::Storage(format!("Corrupt upload session {}: {}", id, e)))?;
+        if session.wallet != wallet {
+            return Err(missing());
+        }
+        Ok(session)
+    }
+
+    async fn sessions(&self, wallet: Option<&str>) -> Result<Vec<UploadSession>, ShadowError> {
+        let mut entries = match tokio::fs::read_dir(self.sessions_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(ShadowError::Storage(format!("Failed to list upload sessions: {}", e))),
+        };
+
+        let mut sessions = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.path().extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let session = tokio::fs::read(entry.path()).await.ok()
+                .and_then(|meta| serde_json::from_slice::<UploadSession>(&meta).ok());
+            if let Some(session) = session.filter(|s| wallet.is_none_or(|wallet| s.wallet == wallet)) {
+                sessions.push(session);
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn session_offset(&self, id: &str) -> Result<u64, ShadowError> {
+        tokio::fs::metadata(self.session_data(id)).await
+            .map(|metadata| metadata.len())
+            .map_err(|_| ShadowError::NotFound("Upload session not found".to_string()))
+    }
+
+    async fn remove_session(&self, id: &str) {
+        let _ = tokio::fs::remove_file(self.session_data(id)).await;
+        let _ = tokio::fs::remove_file(self.session_meta(id)).await;
+    }
+}
+
+/// Periodically drop resumable uploads that were abandoned
+pub fn spawn_session_sweeper(atlas: Arc<AtlasUploader>, ttl: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(ttl.min(Duration::from_secs(3600)));
+        loop {
+            ticker.tick().await;
+            match atlas.sweep(ttl).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("Dropped {} abandoned uploads", removed),
+                Err(e) => tracing::warn!("Upload sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Path of a file inside a directory upload, refusing anything that could escape the
+/// root or break the multipart headers it is sent in
+pub fn upload_path(name: &str) -> Result<String, ShadowError> {
+    let segments = daedalus::normalize_path(name)?;
+    if segments.is_empty() {
+        return Err(ShadowError::BadRequest("File name is empty".to_string()));
+    }
+    for segment in &segments {
+        if segment.len() > MAX_SEGMENT_BYTES || segment.chars().any(|c| c.is_control() || c == '"' || c == '\\') {
+            return Err(ShadowError::BadRequest(format!("Invalid file name: {}", name)));
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+/// A single file's name; paths are only meaningful in directory uploads
+pub fn file_name(name: &str) -> Result<String, ShadowError> {
+    let path = upload_path(name)?;
+    Ok(path.rsplit('/').next().unwrap_or_default().to_string())
+}
+
+/// Content type of a file from its first bytes. Binary formats are recognised by their
+/// signatures; text is typed by its file name, so a PNG named `.html` stays a PNG.
+pub fn sniff_content_type(name: &str, head: &[u8]) -> String {
+    if let Some(content_type) = sniff_signature(head) {
+        return content_type.to_string();
+    }
+    if !is_text(head) {
+        return "application/octet-stream".to_string();
+    }
+
+    let guessed = daedalus::content_type_for(name);
+    let textual = guessed.starts_with("text/")
+        || matches!(
+            guessed.as_str(),
+            "application/json" | "application/manifest+json" | "application/xml" | "image/svg+xml"
+        );
+    if textual { guessed } else { "text/plain".to_string() }
+}
+
+fn sniff_signature(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x00asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"OTTO", "font/otf"),
+        (b"\x00\x01\x00\x00", "font/ttf"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(content_type);
+    }
+
+    // RIFF and ISO media containers name their format a few bytes in
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(match &head[8..12] {
+            b"avif" | b"avis" => "image/avif",
+            _ => "video/mp4",
+        });
+    }
+    // MPEG audio frames without an ID3 header
+    if head.len() >= 2 && head[0] == 0xff && head[1] & 0xe0 == 0xe0 {
+        return Some("audio/mpeg");
+    }
+    None
+}
+
+/// UTF-8 without NUL bytes; the sample may end mid-character
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_UPLOAD_TYPES;
+    use futures_util::stream;
+
+    async fn uploader() -> AtlasUploader {
+        // Mongo is only reached for quotas, which these tests stay clear of
+        let options = mongodb::options::ClientOptions::parse("mongodb://localhost:27017").await.unwrap();
+        let db = mongodb::Client::with_options(options).unwrap().database("shadow_test");
+        let config = UploadConfig {
+            spool_dir: std::env::temp_dir().join(format!("shadow-atlas-{}", uuid::Uuid::new_v4())).to_string_lossy().into_owned(),
+            max_file_bytes: 1024,
+            max_request_bytes: 4096,
+            user_quota_bytes: 8192,
+            chunk_bytes: 4,
+            session_ttl_seconds: 60,
+            allowed_types: DEFAULT_UPLOAD_TYPES.iter().map(|t| t.to_string()).collect(),
+        };
+        AtlasUploader::new(db, Arc::new(StorageRegistry::new()), config)
+    }
+
+    fn body(chunks: &[&'static [u8]]) -> impl Stream<Item = Result<Bytes, String>> + Unpin {
+        stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn sniffs_types_from_content() {
+        assert_eq!(sniff_content_type("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        // The bytes win over a misleading name
+        assert_eq!(sniff_content_type("index.html", b"\x89PNG\r\n\x1a\n"), "image/png");
+        assert_eq!(sniff_content_type("photo.webp", b"RIFF\x10\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_content_type("clip.mp4", b"\0\0\0\x18ftypmp42\0\0\0\0"), "video/mp4");
+        assert_eq!(sniff_content_type("index.html", b"<!doctype html><p>hi</p>"), "text/html");
+        assert_eq!(sniff_content_type("app.js", b"console.log('\xc3"), "text/javascript");
+        // Text named like an image is only text
+        assert_eq!(sniff_content_type("fake.png", b"<script>alert(1)</script>"), "text/plain");
+        assert_eq!(sniff_content_type("tool.exe", b"MZ\x90\0\x03\0\0\0"), "application/octet-stream");
+    }
+
+    #[test]
+    fn validates_upload_paths() {
+        assert_eq!(upload_path("/assets//app.js").unwrap(), "assets/app.js");
+        assert!(upload_path("../etc/passwd").is_err());
+        assert!(upload_path("").is_err());
+        assert!(upload_path("a\"b.txt").is_err());
+        assert_eq!(file_name("assets/app.js").unwrap(), "app.js");
+    }
+
+    #[tokio::test]
+    async fn stages_bodies_within_limits() {
+        let atlas = uploader().await;
+
+        let staged = atlas.stage("index.html", body(&[b"<html>", b"</html>"]), 64).await.unwrap();
+        assert_eq!((staged.size, staged.content_type.as_str()), (13, "text/html"));
+        let path = staged.path.clone();
+        drop(staged);
+        assert!(!path.exists());
+
+        let too_big = atlas.stage("big.txt", body(&[b"0123456789"]), 8).await;
+        assert!(matches!(too_big, Err(ShadowError::TooLarge(_))));
+        let binary = atlas.stage("tool.exe", body(&[b"MZ\x90\0"]), 64).await;
+        assert!(matches!(binary, Err(ShadowError::UnsupportedMediaType(_))));
+
+        let _ = std::fs::remove_dir_all(&atlas.config.spool_dir);
+    }
+
+    #[tokio::test]
+    async fn resumes_chunked_uploads() {
+        let atlas = uploader().await;
+        let request = CreateUploadSession { scheme: "local".to_string(), name: "notes.txt".to_string(), size: 6 };
+        let session = atlas.open_session("wallet", &request).await.unwrap();
+
+        let status = atlas.append_chunk("wallet", &session.id, 0, body(&[b"abcd"])).await.unwrap();
+        assert_eq!(status.offset, 4);
+        // A retried chunk is refused and the client learns where to resume
+        let stale = atlas.append_chunk("wallet", &session.id, 0, body(&[b"abcd"])).await;
+        assert!(matches!(stale, Err(ShadowError::Conflict(_))));
+        // Oversized chunks are rolled back
+        let oversized = atlas.append_chunk("wallet", &session.id, 4, body(&[b"efg"])).await;
+        assert!(matches!(oversized, Err(ShadowError::TooLarge(_))));
+        assert_eq!(atlas.session_status("wallet", &session.id).await.unwrap().offset, 4);
+
+        let status = atlas.append_chunk("wallet", &session.id, 4, body(&[b"ef"])).await.unwrap();
+        assert_eq!(status.offset, 6);
+
+        // Other wallets can't see the session
+        assert!(matches!(atlas.session_status("other", &session.id).await, Err(ShadowError::NotFound(_))));
+
+        atlas.abort_session("wallet", &session.id).await.unwrap();
+        assert!(atlas.session_status("wallet", &session.id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&atlas.config.spool_dir);
+    }
+
+    #[tokio::test]
+    async fn sweeps_abandoned_sessions() {
+        let atlas = uploader().await;
+        let request = CreateUploadSession { scheme: "local".to_string(), name: "a.txt".to_string(), size: 2 };
+        let session = atlas.open_session("wallet", &request).await.unwrap();
+
+        assert_eq!(atlas.sweep(Duration::from_secs(60)).await.unwrap(), 0);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(atlas.sweep(Duration::from_millis(10)).await.unwrap(), 1);
+        assert!(atlas.session_status("wallet", &session.id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&atlas.config.spool_dir);
+    }
+}
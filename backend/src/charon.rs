@@ -0,0 +1,693 @@
+// Charon - Ferryman of the Dead
+// Carries data across to Arweave through a Bundlr/Irys node: ANS-104 data items signed
+// with a Solana ed25519 key, price quotes, balance checks and funding
+
+use crate::themis::{deep_hash, ArweaveTag, DeepHashItem};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::str::FromStr;
+
+/// ANS-104 signature type for ed25519 keys, which Solana wallets are
+pub const SIGNATURE_TYPE_ED25519: u16 = 2;
+const SIGNATURE_LENGTH: usize = 64;
+const OWNER_LENGTH: usize = 32;
+
+// Limits bundlers enforce on tags
+const MAX_TAGS: usize = 128;
+const MAX_TAG_NAME_BYTES: usize = 1024;
+const MAX_TAG_VALUE_BYTES: usize = 3072;
+
+/// An ANS-104 data item: data plus tags, signed by its owner and bundled into Arweave
+/// transactions by the node it is posted to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataItem {
+    pub signature: [u8; SIGNATURE_LENGTH],
+    pub owner: [u8; OWNER_LENGTH],
+    pub target: Option<[u8; 32]>,
+    pub anchor: Option<[u8; 32]>,
+    pub tags: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+impl DataItem {
+    /// An unsigned item; tags are checked against the limits bundlers enforce
+    pub fn new(data: Vec<u8>, tags: Vec<(String, String)>) -> Result<Self, String> {
+        if tags.len() > MAX_TAGS {
+            return Err(format!("Data items carry at most {} tags", MAX_TAGS));
+        }
+        for (name, value) in &tags {
+            if name.is_empty() || name.len() > MAX_TAG_NAME_BYTES {
+                return Err(format!("Tag names must be 1 to {} bytes: {:?}", MAX_TAG_NAME_BYTES, name));
+            }
+            if value.is_empty() || value.len() > MAX_TAG_VALUE_BYTES {
+                return Err(format!("Tag values must be 1 to {} bytes: {}", MAX_TAG_VALUE_BYTES, name));
+            }
+        }
+
+        Ok(Self {
+            signature: [0; SIGNATURE_LENGTH],
+            owner: [0; OWNER_LENGTH],
+            target: None,
+            anchor: None,
+            tags,
+            data,
+        })
+    }
+
+    /// Anchors make otherwise identical items distinct
+    pub fn with_anchor(mut self, anchor: [u8; 32]) -> Self {
+        self.anchor = Some(anchor);
+        self
+    }
+
+    /// Deep hash of every field but the signature, which is what gets signed
+    pub fn signature_data(&self) -> [u8; 48] {
+        deep_hash(&DeepHashItem::List(vec![
+            DeepHashItem::Blob(b"dataitem".to_vec()),
+            DeepHashItem::Blob(b"1".to_vec()),
+            DeepHashItem::Blob(SIGNATURE_TYPE_ED25519.to_string().into_bytes()),
+            DeepHashItem::Blob(self.owner.to_vec()),
+            DeepHashItem::Blob(self.target.map(|t| t.to_vec()).unwrap_or_default()),
+            DeepHashItem::Blob(self.anchor.map(|a| a.to_vec()).unwrap_or_default()),
+            DeepHashItem::Blob(encode_tags(&self.tags)),
+            DeepHashItem::Blob(self.data.clone()),
+        ]))
+    }
+
+    pub fn sign(&mut self, keypair: &Keypair) {
+        self.owner = keypair.pubkey().to_bytes();
+        let signature = keypair.sign_message(&self.signature_data());
+        self.signature.copy_from_slice(signature.as_ref());
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+        let owner = PublicKey::from_bytes(&self.owner)
+            .map_err(|e| format!("Invalid data item owner: {}", e))?;
+        let signature = Signature::from_bytes(&self.signature)
+            .map_err(|e| format!("Invalid data item signature: {}", e))?;
+        owner.verify(&self.signature_data(), &signature)
+            .map_err(|_| "Data item signature does not match its contents".to_string())
+    }
+
+    /// The item's id: base64url of the SHA-256 of its signature
+    pub fn id(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(self.signature))
+    }
+
+    /// Wallet address that signed the item, which is also the account it is paid from
+    pub fn owner_address(&self) -> String {
+        Pubkey::new_from_array(self.owner).to_string()
+    }
+
+    /// Serialized length, which bundlers price items by, without serializing
+    pub fn size(&self) -> u64 {
+        // Signature type, signature, owner, presence bytes, tag count and tag length
+        let header = 2 + SIGNATURE_LENGTH + OWNER_LENGTH + 2 + 8 + 8;
+        let optionals = [self.target, self.anchor].iter().flatten().count() * 32;
+        (header + optionals + encode_tags(&self.tags).len() + self.data.len()) as u64
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let tags = encode_tags(&self.tags);
+        let mut out = Vec::with_capacity(self.size() as usize);
+
+        out.extend_from_slice(&SIGNATURE_TYPE_ED25519.to_le_bytes());
+        out.extend_from_slice(&self.signature);
+        out.extend_from_slice(&self.owner);
+        for optional in [self.target, self.anchor] {
+            match optional {
+                Some(value) => {
+                    out.push(1);
+                    out.extend_from_slice(&value);
+                }
+                None => out.push(0),
+            }
+        }
+        out.extend_from_slice(&(self.tags.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+        out.extend_from_slice(&tags);
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// Parse an ed25519 data item; the signature is not checked until `verify`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        let signature_type = u16::from_le_bytes(reader.array()?);
+        if signature_type != SIGNATURE_TYPE_ED25519 {
+            return Err(format!("Unsupported data item signature type {}", signature_type));
+        }
+        let signature = reader.array()?;
+        let owner = reader.array()?;
+        let target = reader.optional()?;
+        let anchor = reader.optional()?;
+
+        let tag_count = u64::from_le_bytes(reader.array()?);
+        let tag_bytes = u64::from_le_bytes(reader.array()?);
+        if tag_count > MAX_TAGS as u64 {
+            return Err(format!("Data item has {} tags", tag_count));
+        }
+        let tag_bytes = usize::try_from(tag_bytes).map_err(|_| "Tag block too large".to_string())?;
+        let tags = decode_tags(reader.take(tag_bytes)?)?;
+        if tags.len() as u64 != tag_count {
+            return Err(format!("Data item declares {} tags but holds {}", tag_count, tags.len()));
+        }
+
+        Ok(Self {
+            signature,
+            owner,
+            target,
+            anchor,
+            tags,
+            data: bytes[reader.pos..].to_vec(),
+        })
+    }
+}
+
+/// GraphQL query for the signed fields gateways index for every data item
+pub const DATA_ITEM_QUERY: &str =
+    "query($id: ID!) { transaction(id: $id) { signature recipient anchor owner { key } tags { name value } bundledIn { id } } }";
+
+/// A bundled data item's signed fields as gateways index them. Bundled items have no
+/// `/tx/{id}` header of their own, so this is what their data is checked against.
+/// Binary fields are base64url; tags are plain text.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataItemHeader {
+    pub signature: String,
+    pub owner: DataItemOwner,
+    #[serde(default)]
+    pub recipient: Option<String>,
+    #[serde(default)]
+    pub anchor: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<ArweaveTag>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataItemOwner {
+    pub key: String,
+}
+
+impl DataItemHeader {
+    /// Whether the item was signed with an ed25519 key, the only kind checked here
+    pub fn is_ed25519(&self) -> bool {
+        b64url(&self.owner.key).is_ok_and(|owner| owner.len() == OWNER_LENGTH)
+    }
+
+    /// Check that this header is the signed item `id` and that `data` is its data
+    pub fn verify(&self, id: &str, data: &[u8]) -> Result<(), String> {
+        let optional = |field: &Option<String>| -> Result<Option<[u8; 32]>, String> {
+            match field.as_deref().filter(|value| !value.is_empty()) {
+                Some(value) => b64url(value)?.try_into()
+                    .map(Some)
+                    .map_err(|_| "Data item target and anchor are 32 bytes".to_string()),
+                None => Ok(None),
+            }
+        };
+
+        let item = DataItem {
+            signature: b64url(&self.signature)?.try_into()
+                .map_err(|_| "Data item signature is not ed25519".to_string())?,
+            owner: b64url(&self.owner.key)?.try_into()
+                .map_err(|_| "Data item owner is not an ed25519 key".to_string())?,
+            target: optional(&self.recipient)?,
+            anchor: optional(&self.anchor)?,
+            tags: self.tags.iter().map(|tag| (tag.name.clone(), tag.value.clone())).collect(),
+            data: data.to_vec(),
+        };
+        // Item ids are the hash of the signature
+        if item.id() != id {
+            return Err(format!("Header is for data item {}", item.id()));
+        }
+        item.verify()
+    }
+}
+
+#[cfg(test)]
+impl DataItem {
+    /// What a gateway's GraphQL index returns for this item
+    pub(crate) fn indexed_header(&self) -> Value {
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        serde_json::json!({
+            "signature": encode(&self.signature),
+            "owner": { "key": encode(&self.owner) },
+            "recipient": self.target.map(|t| encode(&t)).unwrap_or_default(),
+            "anchor": self.anchor.map(|a| encode(&a)).unwrap_or_default(),
+            "tags": self.tags.iter().map(|(name, value)| serde_json::json!({ "name": name, "value": value })).collect::<Vec<_>>(),
+            "bundledIn": { "id": "bundle" },
+        })
+    }
+}
+
+fn b64url(value: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| format!("Invalid base64url in data item header: {}", e))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| "Data item is truncated".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("slice has requested length"))
+    }
+
+    fn optional(&mut self) -> Result<Option<[u8; 32]>, String> {
+        match self.take(1)?[0] {
+            0 => Ok(None),
+            1 => Ok(Some(self.array()?)),
+            flag => Err(format!("Invalid presence byte {}", flag)),
+        }
+    }
+}
+
+/// Tags as an Avro array of `{name: bytes, value: bytes}` records; no tags encode to nothing
+pub fn encode_tags(tags: &[(String, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if tags.is_empty() {
+        return out;
+    }
+
+    write_avro_long(tags.len() as i64, &mut out);
+    for (name, value) in tags {
+        write_avro_long(name.len() as i64, &mut out);
+        out.extend_from_slice(name.as_bytes());
+        write_avro_long(value.len() as i64, &mut out);
+        out.extend_from_slice(value.as_bytes());
+    }
+    // End of the array
+    out.push(0);
+    out
+}
+
+pub fn decode_tags(bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    let mut tags = Vec::new();
+    if bytes.is_empty() {
+        return Ok(tags);
+    }
+
+    let mut pos = 0;
+    let text = |pos: &mut usize| -> Result<String, String> {
+        let len = usize::try_from(read_avro_long(bytes, pos)?).map_err(|_| "Negative tag length".to_string())?;
+        let end = pos.checked_add(len)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| "Tag block is truncated".to_string())?;
+        let value = String::from_utf8(bytes[*pos..end].to_vec())
+            .map_err(|_| "Tag is not UTF-8".to_string())?;
+        *pos = end;
+        Ok(value)
+    };
+
+    loop {
+        let mut count = read_avro_long(bytes, &mut pos)?;
+        if count == 0 {
+            break;
+        }
+        // A negative block count is followed by the block's size in bytes
+        if count < 0 {
+            count = -count;
+            read_avro_long(bytes, &mut pos)?;
+        }
+        for _ in 0..count {
+            if tags.len() == MAX_TAGS {
+                return Err(format!("Data items carry at most {} tags", MAX_TAGS));
+            }
+            let name = text(&mut pos)?;
+            let value = text(&mut pos)?;
+            tags.push((name, value));
+        }
+    }
+
+    if pos != bytes.len() {
+        return Err("Trailing bytes after tags".to_string());
+    }
+    Ok(tags)
+}
+
+/// Avro longs are zigzag-encoded varints
+fn write_avro_long(value: i64, out: &mut Vec<u8>) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push((zigzag as u8 & 0x7f) | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+fn read_avro_long(bytes: &[u8], pos: &mut usize) -> Result<i64, String> {
+    let mut zigzag = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or_else(|| "Tag block is truncated".to_string())?;
+        *pos += 1;
+        if shift > 63 {
+            return Err("Avro long is too long".to_string());
+        }
+        zigzag |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+}
+
+/// A Bundlr/Irys node. Amounts are in the currency's atomic unit, lamports for Solana.
+#[derive(Clone)]
+pub struct BundlrNode {
+    url: String,
+    currency: String,
+    client: reqwest::Client,
+}
+
+impl BundlrNode {
+    pub fn new(url: &str, currency: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            currency: currency.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Value, String> {
+        let response = self.client.get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .map_err(|e| format!("Bundlr request error: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("Bundlr error: {} - {}", status, error_text));
+        }
+        response.json().await
+            .map_err(|e| format!("Failed to parse Bundlr response: {}", e))
+    }
+
+    /// Price of storing an item of `bytes` serialized bytes
+    pub async fn price(&self, bytes: u64) -> Result<u128, String> {
+        let price = self.get_json(&format!("/price/{}/{}", self.currency, bytes)).await?;
+        atomic_amount(&price).ok_or_else(|| format!("Unexpected price response: {}", price))
+    }
+
+    /// Balance the node holds for `address`, which uploads signed by it draw on
+    pub async fn balance(&self, address: &str) -> Result<u128, String> {
+        let balance = self.get_json(&format!("/account/balance/{}?address={}", self.currency, address)).await?;
+        atomic_amount(&balance["balance"]).ok_or_else(|| format!("Unexpected balance response: {}", balance))
+    }
+
+    /// Address deposits in this currency are sent to
+    pub async fn deposit_address(&self) -> Result<String, String> {
+        let info = self.get_json("/info").await?;
+        info["addresses"][&self.currency].as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("Bundlr node takes no {} deposits", self.currency))
+    }
+
+    /// Ask the node to credit a confirmed deposit transaction
+    pub async fn register_deposit(&self, tx_id: &str) -> Result<(), String> {
+        let response = self.client.post(format!("{}/account/balance/{}", self.url, self.currency))
+            .json(&serde_json::json!({ "tx_id": tx_id }))
+            .send()
+            .await
+            .map_err(|e| format!("Bundlr request error: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("Bundlr rejected deposit {}: {} - {}", tx_id, status, error_text));
+        }
+        Ok(())
+    }
+
+    /// Post a signed item; returns the id the node accepted it under
+    pub async fn upload(&self, item: &DataItem) -> Result<String, String> {
+        // Read back what goes on the wire, so a bad encoding fails here rather than after paying
+        let bytes = item.to_bytes();
+        DataItem::from_bytes(&bytes)?.verify()?;
+
+        let response = self.client.post(format!("{}/tx/{}", self.url, self.currency))
+            .header("Content-Type", "application/octet-stream")
+            .body(bytes)
+            .send()
+            .await
+            .map_err(|e| format!("Bundlr upload error: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("Bundlr error: {} - {}", status, error_text));
+        }
+
+        let json: Value = response.json().await
+            .map_err(|e| format!("Failed to parse Bundlr response: {}", e))?;
+        let id = json["id"].as_str()
+            .ok_or_else(|| "Missing tx id in response".to_string())?;
+        // The id is derived from our signature, so anything else means the item was altered
+        if id != item.id() {
+            return Err(format!("Bundlr stored {} as {}", item.id(), id));
+        }
+        Ok(id.to_string())
+    }
+
+    /// Transfer `lamports` from `payer` to the node and have it credited
+    pub async fn fund(&self, rpc_url: &str, payer: &Keypair, lamports: u64) -> Result<String, String> {
+        if self.currency != "solana" {
+            return Err(format!("Funding is only supported in SOL, not {}", self.currency));
+        }
+        let to = Pubkey::from_str(&self.deposit_address().await?)
+            .map_err(|e| format!("Invalid Bundlr deposit address: {}", e))?;
+
+        let rpc_url = rpc_url.to_string();
+        let payer = Keypair::from_bytes(&payer.to_bytes())
+            .map_err(|e| format!("Invalid keypair: {}", e))?;
+        let signature = tokio::task::spawn_blocking(move || {
+            use solana_client::rpc_client::RpcClient;
+            use solana_sdk::{system_instruction, transaction::Transaction};
+
+            let client = RpcClient::new(rpc_url);
+            let blockhash = client.get_latest_blockhash()
+                .map_err(|e| format!("RPC error: {}", e))?;
+            let transfer = system_instruction::transfer(&payer.pubkey(), &to, lamports);
+            let transaction = Transaction::new_signed_with_payer(&[transfer], Some(&payer.pubkey()), &[&payer], blockhash);
+            client.send_and_confirm_transaction(&transaction)
+                .map_err(|e| format!("Funding transfer failed: {}", e))
+        })
+        .await
+        .map_err(|e| format!("Funding task failed: {}", e))??;
+
+        let tx_id = signature.to_string();
+        self.register_deposit(&tx_id).await?;
+        Ok(tx_id)
+    }
+}
+
+/// Nodes report amounts as decimal strings or plain JSON numbers
+fn atomic_amount(value: &Value) -> Option<u128> {
+    match value {
+        Value::String(amount) => amount.parse().ok(),
+        Value::Number(amount) => amount.as_u64().map(u128::from),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    // RFC 8032 section 7.1, test 1
+    const RFC8032_SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const RFC8032_PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn keypair() -> Keypair {
+        keypair_from_seed(&hex::decode(RFC8032_SECRET).unwrap()).unwrap()
+    }
+
+    fn tags() -> Vec<(String, String)> {
+        vec![
+            ("Content-Type".to_string(), "text/plain".to_string()),
+            ("App-Name".to_string(), "Shadow".to_string()),
+        ]
+    }
+
+    #[test]
+    fn encodes_avro_longs() {
+        // Examples from the Avro specification
+        for (value, encoded) in [(0, vec![0x00]), (-1, vec![0x01]), (1, vec![0x02]), (-2, vec![0x03]),
+                                 (2, vec![0x04]), (-64, vec![0x7f]), (64, vec![0x80, 0x01])] {
+            let mut out = Vec::new();
+            write_avro_long(value, &mut out);
+            assert_eq!(out, encoded, "{}", value);
+            assert_eq!(read_avro_long(&out, &mut 0).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn encodes_tags() {
+        assert!(encode_tags(&[]).is_empty());
+        let tags = vec![("a".to_string(), "b".to_string())];
+        // One record, name "a", value "b", end of array
+        assert_eq!(encode_tags(&tags), vec![0x02, 0x02, b'a', 0x02, b'b', 0x00]);
+        assert_eq!(decode_tags(&encode_tags(&self::tags())).unwrap(), self::tags());
+        // Writers may split arrays into blocks with their byte size
+        assert_eq!(decode_tags(&[0x01, 0x08, 0x02, b'a', 0x02, b'b', 0x00]).unwrap(), tags);
+        assert!(decode_tags(&[0x02, 0x02, b'a']).is_err());
+    }
+
+    #[test]
+    fn rejects_tags_bundlers_refuse() {
+        assert!(DataItem::new(vec![], vec![(String::new(), "v".to_string())]).is_err());
+        assert!(DataItem::new(vec![], vec![("n".to_string(), "v".repeat(MAX_TAG_VALUE_BYTES + 1))]).is_err());
+        let many = (0..=MAX_TAGS).map(|i| (format!("t{}", i), "v".to_string())).collect();
+        assert!(DataItem::new(vec![], many).is_err());
+    }
+
+    #[test]
+    fn lays_out_signed_items() {
+        let mut item = DataItem::new(b"hello".to_vec(), tags()).unwrap();
+        item.sign(&keypair());
+        let bytes = item.to_bytes();
+        let tag_block = encode_tags(&tags());
+
+        assert_eq!(hex::encode(item.owner), RFC8032_PUBLIC);
+        assert_eq!(&bytes[..2], &[2, 0]);
+        assert_eq!(&bytes[2..66], &item.signature);
+        assert_eq!(&bytes[66..98], &item.owner);
+        assert_eq!(&bytes[98..100], &[0, 0]);
+        assert_eq!(&bytes[100..108], &2u64.to_le_bytes());
+        assert_eq!(&bytes[108..116], &(tag_block.len() as u64).to_le_bytes());
+        assert_eq!(&bytes[116..116 + tag_block.len()], tag_block.as_slice());
+        assert_eq!(&bytes[116 + tag_block.len()..], b"hello");
+        assert_eq!(bytes.len() as u64, item.size());
+
+        // The signature is plain ed25519 over the deep hash, checkable with the RFC key alone
+        use ed25519_dalek::{PublicKey, Signature, Verifier};
+        let public = PublicKey::from_bytes(&hex::decode(RFC8032_PUBLIC).unwrap()).unwrap();
+        let signature = Signature::from_bytes(&bytes[2..66]).unwrap();
+        assert!(public.verify(&item.signature_data(), &signature).is_ok());
+
+        let id = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(&bytes[2..66]));
+        assert_eq!(item.id(), id);
+        assert_eq!(item.id().len(), 43);
+    }
+
+    #[test]
+    fn round_trips_and_verifies() {
+        let mut item = DataItem::new(b"<html></html>".to_vec(), tags()).unwrap()
+            .with_anchor([9; 32]);
+        item.target = Some([7; 32]);
+        item.sign(&keypair());
+
+        assert_eq!(item.to_bytes().len() as u64, item.size());
+        let parsed = DataItem::from_bytes(&item.to_bytes()).unwrap();
+        assert_eq!(parsed, item);
+        assert!(parsed.verify().is_ok());
+        assert_eq!(parsed.owner_address(), keypair().pubkey().to_string());
+
+        let mut tampered = parsed.clone();
+        tampered.data.push(b'!');
+        assert!(tampered.verify().is_err());
+        let mut retagged = parsed;
+        retagged.tags[0].1 = "text/html".to_string();
+        assert!(retagged.verify().is_err());
+
+        assert!(DataItem::from_bytes(&item.to_bytes()[..90]).is_err());
+    }
+
+    #[test]
+    fn signs_deterministically() {
+        // ed25519 signatures don't depend on randomness, so an item's id is fixed by its
+        // key and contents
+        let sign = || {
+            let mut item = DataItem::new(b"hello".to_vec(), tags()).unwrap();
+            item.sign(&keypair());
+            item
+        };
+        assert_eq!(sign().to_bytes(), sign().to_bytes());
+
+        let mut untagged = DataItem::new(b"hello".to_vec(), vec![]).unwrap();
+        untagged.sign(&keypair());
+        assert_ne!(untagged.id(), sign().id());
+        assert_eq!(untagged.to_bytes().len() as u64, untagged.size());
+        assert_eq!(untagged.size(), 2 + 64 + 32 + 2 + 16 + 5);
+    }
+
+    // An ed25519 item with two tags and an anchor, serialized and signed outside this crate
+    // (Python hashlib and `cryptography`, straight from the ANS-104 spec) with the RFC 8032 key
+    const REFERENCE_ITEM: &str = concat!(
+        "020019a5a0aea5eeb9d975292cfee9f81e11dfb7881c70b940fa1bc5bce40956",
+        "2b193faec657d74e38b598ee7af4d2392fb84ba24da99abb749d6a2e1283d43a",
+        "0107d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707",
+        "511a0001736861646f772d616e733130342d7265666572656e63652d616e6368",
+        "6f72212102000000000000002a000000000000000418436f6e74656e742d5479",
+        "706514746578742f706c61696e104170702d4e616d650c536861646f77006865",
+        "6c6c6f",
+    );
+    const REFERENCE_ID: &str = "NVXuzygZ5dabPg-dY0wuQRHg3v9UK3Sc7Ad0TzlN82M";
+    const REFERENCE_DEEP_HASH: &str = concat!(
+        "85b0e1eb929dc32aa7f7373a49b552fb3edc92a1c8eccddda5e21ca2f4d3f83a",
+        "03138832a390244b4bd4a1972bf04428",
+    );
+
+    #[test]
+    fn matches_the_reference_item() {
+        let expected = hex::decode(REFERENCE_ITEM).unwrap();
+
+        let mut item = DataItem::new(b"hello".to_vec(), tags()).unwrap()
+            .with_anchor(*b"shadow-ans104-reference-anchor!!");
+        item.sign(&keypair());
+        assert_eq!(hex::encode(item.signature_data()), REFERENCE_DEEP_HASH);
+        assert_eq!(item.to_bytes(), expected);
+        assert_eq!(item.id(), REFERENCE_ID);
+
+        let parsed = DataItem::from_bytes(&expected).unwrap();
+        assert!(parsed.verify().is_ok());
+        assert_eq!(parsed.id(), REFERENCE_ID);
+        assert_eq!(parsed.tags, tags());
+    }
+
+    #[test]
+    fn verifies_against_the_indexed_header() {
+        let mut item = DataItem::new(b"hello".to_vec(), tags()).unwrap().with_anchor([7; 32]);
+        item.sign(&keypair());
+        let header: DataItemHeader = serde_json::from_value(item.indexed_header()).unwrap();
+
+        assert!(header.is_ed25519());
+        assert!(header.verify(&item.id(), b"hello").is_ok());
+        assert!(header.verify(&item.id(), b"hellO").unwrap_err().contains("does not match"));
+        assert!(header.verify(REFERENCE_ID, b"hello").unwrap_err().contains("Header is for"));
+
+        let mut retagged = header.clone();
+        retagged.tags.pop();
+        assert!(retagged.verify(&item.id(), b"hello").is_err());
+    }
+
+    #[test]
+    fn reads_amounts_in_either_form() {
+        assert_eq!(atomic_amount(&serde_json::json!("340282366920938463463374607431768211455")), Some(u128::MAX));
+        assert_eq!(atomic_amount(&serde_json::json!(1500)), Some(1500));
+        assert_eq!(atomic_amount(&serde_json::json!(null)), None);
+    }
+}
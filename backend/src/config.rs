@@ -3,6 +3,19 @@ use serde::{Deserialize, Serialize};
 use std::env;
 use std::time::Duration;
 
+// Gateways that serve verifiable responses (raw blocks and CARs, /raw and /tx)
+pub const DEFAULT_IPFS_GATEWAYS: &[&str] = &["https://gateway.pinata.cloud", "https://ipfs.io", "https://dweb.link"];
+pub const DEFAULT_ARWEAVE_GATEWAYS: &[&str] = &["https://arweave.net", "https://ar-io.net"];
+
+// Content types accepted for upload, as sniffed from the bytes rather than declared
+pub const DEFAULT_UPLOAD_TYPES: &[&str] = &[
+    "text/html", "text/css", "text/javascript", "application/json", "application/manifest+json",
+    "text/plain", "text/markdown", "text/csv", "text/xml", "application/xml",
+    "image/svg+xml", "image/png", "image/jpeg", "image/gif", "image/webp", "image/avif", "image/x-icon",
+    "font/woff", "font/woff2", "font/ttf", "font/otf",
+    "application/wasm", "application/pdf", "video/mp4", "video/webm", "audio/mpeg", "audio/ogg",
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
@@ -14,6 +27,7 @@ pub struct DatabaseConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolanaConfig {
     pub rpc_url: String,
+    pub das_rpc_url: String, // Metaplex DAS API endpoint; the plain RPC when unset
     pub ws_url: String,
     pub commitment: String,
     pub timeout_seconds: u64,
@@ -25,6 +39,16 @@ pub struct StorageConfig {
     pub pinata_secret_key: Option<String>,
     pub bundlr_node_url: Option<String>,
     pub bundlr_currency: Option<String>,
+    pub bundlr_auto_fund: bool,        // Let uploads top up the node balance from the upload key
+    pub ipfs_gateways: Vec<String>,    // Read in this order until health scores say otherwise
+    pub arweave_gateways: Vec<String>, // Same for Arweave reads
+    pub gateway_hedge_delay_ms: u64,   // Quiet time before the next gateway is raced
+    pub gateway_failure_threshold: u32, // Consecutive failures that take a gateway out of rotation
+    pub gateway_cooldown_seconds: u64, // How long it stays out before a trial request
+    pub gateway_timeout_seconds: u64,  // Upper bound on a single gateway request
+    pub gateway_max_body_bytes: u64,   // Largest response read from a gateway
+    pub kubo_api_url: Option<String>,  // Self-hosted IPFS node; replaces Pinata for ipfs:// when set
+    pub local_storage_dir: Option<String>, // Serves local:// from disk for development and tests
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +74,50 @@ pub struct ServerConfig {
     pub client_timeout: Option<u64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingConfig {
+    pub price_api_url: Option<String>,
+    pub pyth_feeds: Option<String>, // "mint=price_account,..."
+    pub static_prices_file: Option<String>,
+    pub cache_ttl_seconds: u64,
+    pub max_staleness_seconds: u64,
+    pub snapshot_interval_seconds: u64, // Portfolio snapshot job period
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainsConfig {
+    pub sync_interval_seconds: u64, // Full resync with the shadow-domains program
+    pub sweep_interval_seconds: u64, // Expiry notices and release of lapsed names
+    pub sweeper_keypair_path: Option<String>, // Signs release_domain; unset skips on-chain releases
+    pub challenge_recheck_seconds: u64, // How often custom domains' TXT proof is re-checked
+    pub reserved_names: Vec<String>, // Labels refused on top of Apollo's built-in list
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsConfig {
+    pub gateway_ipv4: Vec<std::net::Ipv4Addr>, // A answers for every .shadow name
+    pub gateway_ipv6: Vec<std::net::Ipv6Addr>, // AAAA answers for every .shadow name
+    pub upstream_url: Option<String>,          // DoH server for everything else
+    pub challenge_nameservers: Vec<std::net::IpAddr>, // TXT challenge lookups; empty uses the system's
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadConfig {
+    pub spool_dir: String,          // Upload bodies and resumable sessions are staged here
+    pub max_file_bytes: u64,        // Largest single file
+    pub max_request_bytes: u64,     // Largest directory upload
+    pub user_quota_bytes: u64,      // Total a wallet may upload
+    pub chunk_bytes: u64,           // Largest chunk of a resumable upload
+    pub session_ttl_seconds: u64,   // Idle resumable uploads are dropped after this
+    pub allowed_types: Vec<String>, // Sniffed content types that may be uploaded
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexerConfig {
+    pub enabled: bool,              // Follow the registry and profiles programs into Mongo
+    pub poll_interval_seconds: u64, // How often new transactions are picked up
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShadowConfig {
     pub database: DatabaseConfig,
@@ -58,10 +126,18 @@ pub struct ShadowConfig {
     pub cache: CacheConfig,
     pub rate_limit: RateLimitConfig,
     pub server: ServerConfig,
+    pub pricing: PricingConfig,
+    pub domains: DomainsConfig,
+    pub dns: DnsConfig,
+    pub indexer: IndexerConfig,
+    pub upload: UploadConfig,
 }
 
 impl ShadowConfig {
     pub fn from_env() -> Result<Self, String> {
+        let solana_rpc_url = env::var("SOLANA_RPC_URL")
+            .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
+
         Ok(ShadowConfig {
             database: DatabaseConfig {
                 url: env::var("DATABASE_URL")
@@ -76,8 +152,11 @@ impl ShadowConfig {
                     .and_then(|s| s.parse().ok()),
             },
             solana: SolanaConfig {
-                rpc_url: env::var("SOLANA_RPC_URL")
-                    .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string()),
+                das_rpc_url: env::var("DAS_RPC_URL")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| solana_rpc_url.clone()),
+                rpc_url: solana_rpc_url,
                 ws_url: env::var("SOLANA_WS_URL")
                     .unwrap_or_else(|_| "wss://api.devnet.solana.com".to_string()),
                 commitment: env::var("SOLANA_COMMITMENT")
@@ -94,6 +173,32 @@ impl ShadowConfig {
                 bundlr_currency: env::var("BUNDLR_CURRENCY")
                     .ok()
                     .or_else(|| Some("solana".to_string())),
+                bundlr_auto_fund: env::var("BUNDLR_AUTO_FUND")
+                    .map(|v| v == "true")
+                    .unwrap_or(false),
+                ipfs_gateways: parse_urls("IPFS_GATEWAYS", DEFAULT_IPFS_GATEWAYS),
+                arweave_gateways: parse_urls("ARWEAVE_GATEWAYS", DEFAULT_ARWEAVE_GATEWAYS),
+                gateway_hedge_delay_ms: env::var("GATEWAY_HEDGE_DELAY_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(750),
+                gateway_failure_threshold: env::var("GATEWAY_FAILURE_THRESHOLD")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(5),
+                gateway_cooldown_seconds: env::var("GATEWAY_COOLDOWN_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30),
+                gateway_timeout_seconds: env::var("GATEWAY_TIMEOUT_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(30),
+                gateway_max_body_bytes: parse_megabytes("GATEWAY_MAX_BODY_MB", 256),
+                kubo_api_url: env::var("KUBO_API_URL").ok().filter(|s| !s.is_empty()),
+                local_storage_dir: env::var("LOCAL_STORAGE_DIR").ok().filter(|s| !s.is_empty()),
             },
             cache: CacheConfig {
                 max_size_mb: env::var("CACHE_MAX_SIZE_MB")
@@ -139,6 +244,84 @@ impl ShadowConfig {
                     .ok()
                     .and_then(|s| s.parse().ok()),
             },
+            pricing: PricingConfig {
+                price_api_url: env::var("PRICE_API_URL").ok(),
+                pyth_feeds: env::var("PYTH_PRICE_FEEDS").ok(),
+                static_prices_file: env::var("STATIC_PRICES_FILE").ok(),
+                cache_ttl_seconds: env::var("PRICE_CACHE_TTL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(60),
+                max_staleness_seconds: env::var("PRICE_MAX_STALENESS_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(300),
+                snapshot_interval_seconds: env::var("PORTFOLIO_SNAPSHOT_INTERVAL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(3600),
+            },
+            domains: DomainsConfig {
+                sync_interval_seconds: env::var("DOMAIN_SYNC_INTERVAL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(300),
+                sweep_interval_seconds: env::var("DOMAIN_SWEEP_INTERVAL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(3600),
+                sweeper_keypair_path: env::var("SOLANA_KEYPAIR_PATH").ok()
+                    .filter(|s| !s.is_empty()),
+                challenge_recheck_seconds: env::var("DOMAIN_CHALLENGE_RECHECK_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(86_400),
+                reserved_names: env::var("DOMAIN_RESERVED_NAMES")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|s| s.trim().to_lowercase())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            },
+            dns: DnsConfig {
+                gateway_ipv4: parse_addresses("DNS_GATEWAY_IPV4")?,
+                gateway_ipv6: parse_addresses("DNS_GATEWAY_IPV6")?,
+                upstream_url: env::var("DNS_UPSTREAM_URL").ok()
+                    .filter(|s| !s.is_empty()),
+                challenge_nameservers: parse_addresses("DNS_CHALLENGE_NAMESERVERS")?,
+            },
+            indexer: IndexerConfig {
+                enabled: env::var("INDEXER_ENABLED")
+                    .map(|s| s != "false" && s != "0")
+                    .unwrap_or(true),
+                poll_interval_seconds: env::var("INDEXER_POLL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(5),
+            },
+            upload: UploadConfig {
+                spool_dir: env::var("UPLOAD_DIR")
+                    .unwrap_or_else(|_| env::temp_dir().join("shadow-uploads").to_string_lossy().into_owned()),
+                max_file_bytes: parse_megabytes("UPLOAD_MAX_FILE_MB", 100),
+                max_request_bytes: parse_megabytes("UPLOAD_MAX_REQUEST_MB", 500),
+                user_quota_bytes: parse_megabytes("UPLOAD_USER_QUOTA_MB", 1024),
+                chunk_bytes: parse_megabytes("UPLOAD_CHUNK_MB", 8),
+                session_ttl_seconds: env::var("UPLOAD_SESSION_TTL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(86400),
+                allowed_types: env::var("UPLOAD_ALLOWED_TYPES")
+                    .ok()
+                    .map(|s| s.split(',').map(|t| t.trim().to_ascii_lowercase()).filter(|t| !t.is_empty()).collect::<Vec<_>>())
+                    .filter(|types| !types.is_empty())
+                    .unwrap_or_else(|| DEFAULT_UPLOAD_TYPES.iter().map(|t| t.to_string()).collect()),
+            },
         })
     }
     
@@ -151,6 +334,42 @@ impl ShadowConfig {
     }
 }
 
+/// Comma-separated base URLs from an env var, falling back to `default` when unset or empty
+fn parse_megabytes(var: &str, default: u64) -> u64 {
+    env::var(var)
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(default)
+        * 1024 * 1024
+}
+
+fn parse_urls(var: &str, default: &[&str]) -> Vec<String> {
+    let urls: Vec<String> = env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().trim_end_matches('/').to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    if urls.is_empty() {
+        default.iter().map(|s| s.to_string()).collect()
+    } else {
+        urls
+    }
+}
+
+/// Comma-separated IP addresses from an env var; unset means none
+fn parse_addresses<T: std::str::FromStr>(var: &str) -> Result<Vec<T>, String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|_| format!("Invalid address in {}: {}", var, s)))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,7 +384,6 @@ mod tests {
         assert_eq!(cfg.server.port, 8080);
         assert_eq!(cfg.cache.max_size_mb, 512);
         assert_eq!(cfg.rate_limit.requests_per_minute, 60);
+        assert_eq!(cfg.pricing.cache_ttl_seconds, 60);
     }
 }
-
-
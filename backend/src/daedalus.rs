@@ -0,0 +1,473 @@
+// Daedalus - Architect of the Labyrinth
+// Resolves request paths inside IPFS directories and Arweave-style path manifests
+
+use crate::error::ShadowError;
+use crate::hephaestus::HephaestusCache;
+use crate::storage::{split_uri, StorageProvider, StorageRegistry};
+use crate::themis::{self, Cid, PbLink, UnixFsNode, CODEC_RAW};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Optional routing file at the root of an IPFS site directory
+pub const SITE_MANIFEST: &str = "shadow.json";
+const MAX_SITE_MANIFEST_BYTES: usize = 64 * 1024;
+
+const INDEX_FILE: &str = "index.html";
+const MAX_PATH_SEGMENTS: usize = 64;
+
+pub const ARWEAVE_MANIFEST_TYPE: &str = "application/x.arweave-manifest+json";
+pub const ARWEAVE_MANIFEST_MARKER: &str = "arweave/paths";
+
+// Content addressed data never changes, so it only leaves the cache under memory pressure
+const CONTENT_CACHE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+pub struct SiteFile {
+    pub body: Vec<u8>,
+    pub content_type: String,
+}
+
+/// Routing options a site ships in `shadow.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct SiteManifest {
+    /// File served for directory paths, `index.html` when unset
+    #[serde(default)]
+    pub index: Option<String>,
+    /// Path served for unknown navigation routes so single-page apps can route client-side
+    #[serde(default)]
+    pub fallback: Option<String>,
+}
+
+/// An Arweave path manifest (`arweave/paths` 0.1.0 and 0.2.0)
+#[derive(Debug, Deserialize)]
+pub struct ArweaveManifest {
+    pub manifest: String,
+    #[serde(default)]
+    pub index: Option<ArweaveManifestIndex>,
+    #[serde(default)]
+    pub fallback: Option<ArweaveManifestEntry>,
+    #[serde(default)]
+    pub paths: HashMap<String, ArweaveManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArweaveManifestIndex {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArweaveManifestEntry {
+    pub id: String,
+}
+
+impl ArweaveManifest {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(data)
+            .ok()
+            .filter(|manifest| manifest.manifest == ARWEAVE_MANIFEST_MARKER)
+    }
+
+    /// The manifest in a gateway response, if its Content-Type tag (or lack of one) allows it
+    pub fn from_response(data: &[u8], content_type: Option<&str>) -> Option<Self> {
+        match content_type {
+            Some(ARWEAVE_MANIFEST_TYPE) | None => Self::parse(data),
+            Some(_) => None,
+        }
+    }
+
+    /// Transaction id serving `segments` and the file name its type is inferred from
+    pub fn resolve(&self, segments: &[String]) -> Option<(&str, String)> {
+        let path = segments.join("/");
+
+        if path.is_empty() {
+            let index = self.index.as_ref()?;
+            return self.paths.get(&index.path).map(|entry| (entry.id.as_str(), index.path.clone()));
+        }
+
+        if let Some(entry) = self.paths.get(&path) {
+            return Some((&entry.id, path));
+        }
+
+        let index_path = format!("{}/{}", path, INDEX_FILE);
+        if let Some(entry) = self.paths.get(&index_path) {
+            return Some((&entry.id, index_path));
+        }
+
+        match &self.fallback {
+            Some(fallback) if is_navigation(segments) => Some((&fallback.id, INDEX_FILE.to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    /// Raw block or UnixFS file, fetched whole through the gateway
+    File,
+    Directory(Vec<PbLink>),
+    /// HAMT sharded directory; entry names carry a hex prefix of `prefix_len` characters
+    Shard { links: Vec<PbLink>, prefix_len: usize },
+}
+
+impl From<UnixFsNode> for Node {
+    fn from(node: UnixFsNode) -> Self {
+        match node {
+            UnixFsNode::File { .. } => Node::File,
+            UnixFsNode::Directory(links) => Node::Directory(links),
+            UnixFsNode::Shard { links, prefix_len } => Node::Shard { links, prefix_len },
+        }
+    }
+}
+
+/// Serves files out of site deployments. IPFS deployments are walked block by block;
+/// every other store serves a single file or an Arweave-style path manifest.
+pub struct DaedalusRouter {
+    storage: Arc<StorageRegistry>,
+    cache: Option<Arc<HephaestusCache>>,
+}
+
+impl DaedalusRouter {
+    pub fn new(storage: Arc<StorageRegistry>, cache: Option<Arc<HephaestusCache>>) -> Self {
+        Self { storage, cache }
+    }
+
+    /// Resolve `path` inside the deployment at `storage_cid`
+    pub async fn resolve(&self, storage_cid: &str, path: &str) -> Result<SiteFile, ShadowError> {
+        let segments = normalize_path(path)?;
+        let (scheme, root) = split_uri(storage_cid)
+            .ok_or_else(|| ShadowError::BadRequest("Invalid storage CID".to_string()))?;
+        let provider = self.storage.for_scheme(scheme).map_err(ShadowError::BadRequest)?;
+
+        if provider.scheme() == "ipfs" {
+            self.resolve_ipfs(root, &segments).await
+        } else {
+            self.resolve_manifest(provider.as_ref(), root, &segments).await
+        }
+    }
+
+    async fn resolve_ipfs(&self, root: &str, segments: &[String]) -> Result<SiteFile, ShadowError> {
+        let root = Cid::parse(root).map_err(ShadowError::Storage)?;
+        let root_node = self.node(&root).await?;
+
+        // Single-file sites predate directory deployments and are served as a page
+        if matches!(root_node, Node::File) {
+            if !segments.is_empty() {
+                return Err(not_found());
+            }
+            return Ok(SiteFile {
+                body: self.ipfs_file(&root).await?,
+                content_type: "text/html".to_string(),
+            });
+        }
+
+        let manifest = self.site_manifest(&root, &root_node).await?;
+        let index = manifest.index.as_deref().unwrap_or(INDEX_FILE);
+
+        let mut found = self.lookup(&root, &root_node, segments, index).await?;
+        if found.is_none() && is_navigation(segments) {
+            if let Some(fallback) = &manifest.fallback {
+                found = self.lookup(&root, &root_node, &normalize_path(fallback)?, index).await?;
+            }
+        }
+
+        let (cid, name) = found.ok_or_else(not_found)?;
+        Ok(SiteFile {
+            body: self.ipfs_file(&cid).await?,
+            content_type: content_type_for(&name),
+        })
+    }
+
+    /// Walk `segments` down from the root, ending on a file or a directory's index file
+    async fn lookup(
+        &self,
+        root: &Cid,
+        root_node: &Node,
+        segments: &[String],
+        index: &str,
+    ) -> Result<Option<(Cid, String)>, ShadowError> {
+        let mut cid = root.clone();
+        let mut node = root_node.clone();
+
+        for segment in segments {
+            match self.find_link(&node, segment).await? {
+                Some(child) => {
+                    node = self.node(&child).await?;
+                    cid = child;
+                }
+                None => return Ok(None),
+            }
+        }
+
+        if matches!(node, Node::File) {
+            return Ok(Some((cid, segments.last().cloned().unwrap_or_default())));
+        }
+
+        match self.find_link(&node, index).await? {
+            Some(child) if matches!(self.node(&child).await?, Node::File) => {
+                Ok(Some((child, index.to_string())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// CID of the entry called `name` in a directory
+    async fn find_link(&self, node: &Node, name: &str) -> Result<Option<Cid>, ShadowError> {
+        let (links, prefix_len) = match node {
+            Node::File => return Ok(None),
+            Node::Directory(links) => {
+                return Ok(links.iter().find(|link| link.name == name).map(|link| link.cid.clone()));
+            }
+            Node::Shard { links, prefix_len } => (links, *prefix_len),
+        };
+
+        // Shards are scanned rather than followed by hash; every block is cached after the first walk
+        let mut shards = Vec::new();
+        if let Some(cid) = themis::scan_shard(links, prefix_len, name, &mut shards) {
+            return Ok(Some(cid));
+        }
+        while let Some(shard) = shards.pop() {
+            match self.node(&shard).await? {
+                Node::Shard { links, prefix_len } => {
+                    if let Some(cid) = themis::scan_shard(&links, prefix_len, name, &mut shards) {
+                        return Ok(Some(cid));
+                    }
+                }
+                _ => return Err(ShadowError::Storage("Malformed sharded directory".to_string())),
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn site_manifest(&self, root: &Cid, root_node: &Node) -> Result<SiteManifest, ShadowError> {
+        let cid = match self.find_link(root_node, SITE_MANIFEST).await? {
+            Some(cid) => cid,
+            None => return Ok(SiteManifest::default()),
+        };
+
+        let data = self.ipfs_file(&cid).await?;
+        if data.len() > MAX_SITE_MANIFEST_BYTES {
+            tracing::warn!("Ignoring oversized {} in {}", SITE_MANIFEST, root);
+            return Ok(SiteManifest::default());
+        }
+
+        // A broken manifest should not take the whole site down
+        Ok(serde_json::from_slice(&data).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid {} in {}: {}", SITE_MANIFEST, root, e);
+            SiteManifest::default()
+        }))
+    }
+
+    /// Directory structure behind `cid`; raw leaves are files without a fetch
+    async fn node(&self, cid: &Cid) -> Result<Node, ShadowError> {
+        if cid.codec == CODEC_RAW {
+            return Ok(Node::File);
+        }
+
+        let key = cid.to_string();
+        let (block, _) = self.cached(format!("ipfs:block:{}", key), || async {
+            let uri = format!("ipfs://{}", key);
+            self.storage.for_scheme("ipfs")?.get_block(&uri).await.map(|block| (block, None))
+        }).await?;
+
+        themis::decode_unixfs(cid, &block)
+            .map(Node::from)
+            .map_err(ShadowError::Storage)
+    }
+
+    async fn ipfs_file(&self, cid: &Cid) -> Result<Vec<u8>, ShadowError> {
+        let key = cid.to_string();
+        let (body, _) = self.cached(format!("ipfs:file:{}", key), || async {
+            self.storage.get(&format!("ipfs://{}", key)).await.map(|body| (body, None))
+        }).await?;
+        Ok(body)
+    }
+
+    async fn resolve_manifest(
+        &self,
+        provider: &dyn StorageProvider,
+        root: &str,
+        segments: &[String],
+    ) -> Result<SiteFile, ShadowError> {
+        let (body, content_type) = self.raw(provider, root).await?;
+
+        let manifest = match ArweaveManifest::from_response(&body, content_type.as_deref()) {
+            Some(manifest) => manifest,
+            None if segments.is_empty() => {
+                return Ok(SiteFile {
+                    body,
+                    content_type: content_type.unwrap_or_else(|| "text/html".to_string()),
+                });
+            }
+            None => return Err(not_found()),
+        };
+
+        let (id, name) = manifest.resolve(segments).ok_or_else(not_found)?;
+        let (body, content_type) = self.raw(provider, id).await?;
+
+        // Files uploaded without a Content-Type tag come back as octet-stream
+        let content_type = content_type
+            .filter(|t| t != "application/octet-stream")
+            .unwrap_or_else(|| content_type_for(&name));
+
+        Ok(SiteFile { body, content_type })
+    }
+
+    async fn raw(&self, provider: &dyn StorageProvider, id: &str) -> Result<(Vec<u8>, Option<String>), ShadowError> {
+        let uri = format!("{}://{}", provider.scheme(), id);
+        self.cached(format!("{}:raw:{}", provider.scheme(), id), || provider.get_raw(&uri)).await
+    }
+
+    /// Content addressed fetch through Hephaestus when caching is enabled
+    async fn cached<F, Fut>(&self, key: String, fetch: F) -> Result<(Vec<u8>, Option<String>), ShadowError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<(Vec<u8>, Option<String>), String>>,
+    {
+        if let Some(cache) = &self.cache {
+            if let Some(cached) = cache.get(&key).await {
+                let content_type = Some(cached.content_type).filter(|t| !t.is_empty());
+                return Ok((cached.content, content_type));
+            }
+        }
+
+        let (body, content_type) = fetch().await.map_err(ShadowError::Storage)?;
+
+        if let Some(cache) = &self.cache {
+            cache.set(key, body.clone(), content_type.clone().unwrap_or_default(), Some(CONTENT_CACHE_TTL))
+                .await
+                .map_err(ShadowError::Storage)?;
+        }
+
+        Ok((body, content_type))
+    }
+}
+
+fn not_found() -> ShadowError {
+    ShadowError::NotFound("File not found".to_string())
+}
+
+/// Split a request path into segments, refusing to climb out of the site root
+pub fn normalize_path(path: &str) -> Result<Vec<String>, ShadowError> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return Err(ShadowError::BadRequest("Path may not contain '..'".to_string())),
+            segment => segments.push(segment.to_string()),
+        }
+    }
+
+    if segments.len() > MAX_PATH_SEGMENTS {
+        return Err(ShadowError::BadRequest("Path is too deep".to_string()));
+    }
+
+    Ok(segments)
+}
+
+/// Only paths that look like pages fall back to the SPA entry point, so a missing
+/// asset still gets a 404 instead of the app's HTML
+pub fn is_navigation(segments: &[String]) -> bool {
+    segments.last().is_none_or(|last| !last.contains('.'))
+}
+
+pub fn content_type_for(name: &str) -> String {
+    mime_guess::from_path(name)
+        .first_raw()
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(path: &str) -> Vec<String> {
+        normalize_path(path).unwrap()
+    }
+
+    #[test]
+    fn normalizes_request_paths() {
+        assert!(segments("").is_empty());
+        assert_eq!(segments("/assets//./app.js"), vec!["assets", "app.js"]);
+        assert!(normalize_path("assets/../../secret").is_err());
+        assert!(normalize_path(&"a/".repeat(MAX_PATH_SEGMENTS + 1)).is_err());
+    }
+
+    #[test]
+    fn falls_back_only_for_navigation_paths() {
+        assert!(is_navigation(&segments("")));
+        assert!(is_navigation(&segments("blog/first-post")));
+        assert!(!is_navigation(&segments("assets/app.js")));
+    }
+
+    #[test]
+    fn infers_content_types_from_file_names() {
+        assert_eq!(content_type_for("index.html"), "text/html");
+        assert_eq!(content_type_for("assets/app.js"), "text/javascript");
+        assert_eq!(content_type_for("logo.svg"), "image/svg+xml");
+        assert_eq!(content_type_for("LICENSE"), "application/octet-stream");
+    }
+
+    #[test]
+    fn resolves_arweave_path_manifests() {
+        let manifest = ArweaveManifest::parse(br#"{
+            "manifest": "arweave/paths",
+            "version": "0.2.0",
+            "index": { "path": "index.html" },
+            "fallback": { "id": "fallback-tx" },
+            "paths": {
+                "index.html": { "id": "index-tx" },
+                "docs/index.html": { "id": "docs-tx" },
+                "assets/app.js": { "id": "app-tx" }
+            }
+        }"#).unwrap();
+
+        assert_eq!(manifest.resolve(&segments("/")), Some(("index-tx", "index.html".to_string())));
+        assert_eq!(manifest.resolve(&segments("assets/app.js")), Some(("app-tx", "assets/app.js".to_string())));
+        assert_eq!(manifest.resolve(&segments("docs/")), Some(("docs-tx", "docs/index.html".to_string())));
+        assert_eq!(manifest.resolve(&segments("dashboard/settings")).map(|(id, _)| id), Some("fallback-tx"));
+        assert_eq!(manifest.resolve(&segments("assets/missing.js")), None);
+
+        assert!(ArweaveManifest::parse(br#"{"manifest": "something-else"}"#).is_none());
+        assert!(ArweaveManifest::parse(b"<html></html>").is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_manifest_sites_from_any_store() {
+        use crate::storage::{LocalStorage, PutOptions};
+
+        let dir = std::env::temp_dir().join(format!("shadow-daedalus-{}", uuid::Uuid::new_v4()));
+        let store = Arc::new(LocalStorage::new(&dir));
+        let router = DaedalusRouter::new(Arc::new(StorageRegistry::new().with_provider(store.clone())), None);
+        let put = |data: &'static [u8]| {
+            let store = Arc::clone(&store);
+            async move {
+                let uri = store.put(data, &PutOptions::default()).await.unwrap();
+                uri.trim_start_matches("local://").to_string()
+            }
+        };
+
+        let index = put(b"<h1>home</h1>").await;
+        let script = put(b"console.log(1)").await;
+        let manifest = format!(
+            r#"{{"manifest":"arweave/paths","version":"0.2.0","index":{{"path":"index.html"}},
+                "paths":{{"index.html":{{"id":"{}"}},"app.js":{{"id":"{}"}}}}}}"#,
+            index, script
+        );
+        let root = store.put(manifest.as_bytes(), &PutOptions {
+            content_type: Some(ARWEAVE_MANIFEST_TYPE.to_string()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let home = router.resolve(&root, "").await.unwrap();
+        assert_eq!((home.body.as_slice(), home.content_type.as_str()), (&b"<h1>home</h1>"[..], "text/html"));
+        let app = router.resolve(&root, "app.js").await.unwrap();
+        assert_eq!(app.content_type, "text/javascript");
+        assert!(router.resolve(&root, "missing.css").await.is_err());
+        assert!(router.resolve("ipfs://bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq", "").await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,5 @@
 use mongodb::{Collection, Database};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Document};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use futures_util::TryStreamExt;
@@ -12,6 +12,8 @@ pub struct User {
     pub is_public: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub slot: u64, // Slot the indexer read the account at; 0 when written by the API
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,6 +26,25 @@ pub struct Site {
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: u32, // Latest entry of the on-chain deployment log
+    #[serde(default)]
+    pub maintainers: Vec<String>,
+    #[serde(default)]
+    pub slot: u64, // Slot the indexer read the account at; 0 when written by the API
+}
+
+/// Indexed copy of a `shadow_registry::Deployment` account
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SiteDeployment {
+    #[serde(rename = "_id")]
+    pub id: String, // "<program_address>:<version>"
+    pub program_address: String,
+    pub version: u32,
+    pub storage_cid: String,
+    pub deployer: String,
+    pub deployed_at: DateTime<Utc>,
+    pub rollback_of: Option<u32>,
 }
 
 pub fn get_users_collection(db: &Database) -> Collection<User> {
@@ -34,6 +55,10 @@ pub fn get_sites_collection(db: &Database) -> Collection<Site> {
     db.collection::<Site>("sites")
 }
 
+pub fn get_site_deployments_collection(db: &Database) -> Collection<SiteDeployment> {
+    db.collection::<SiteDeployment>("site_deployments")
+}
+
 pub async fn get_user(db: &Database, wallet: &str) -> Result<Option<User>, mongodb::error::Error> {
     let collection = get_users_collection(db);
     let filter = doc! { "_id": wallet };
@@ -131,37 +156,150 @@ pub async fn search_sites(
     Ok(sites)
 }
 
-pub async fn create_or_update_site(
-    db: &Database,
-    program_address: &str,
-    owner_pubkey: &str,
-    storage_cid: &str,
-    name: Option<&str>,
-    description: Option<&str>,
-) -> Result<(), mongodb::error::Error> {
-    let collection = get_sites_collection(db);
-    let now = Utc::now();
-    
-    let filter = doc! { "_id": program_address };
-    // Convert chrono::DateTime to mongodb::bson::DateTime
-    // Using timestamp_millis() for accurate millisecond conversion
-    let bson_now = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+/// Filter for `_id` that skips copies indexed at a later slot than `slot`
+///
+/// Slot 0 marks writes from the API, which always apply.
+fn slot_guard(id: &str, slot: u64) -> Document {
+    if slot == 0 {
+        return doc! { "_id": id };
+    }
+    doc! {
+        "_id": id,
+        "$or": [
+            { "slot": { "$exists": false } },
+            { "slot": { "$lte": slot as i64 } }
+        ]
+    }
+}
+
+/// Upsert behind a `slot_guard`; false if a newer copy was already stored
+async fn upsert_at_slot<T>(
+    collection: &Collection<T>,
+    id: &str,
+    slot: u64,
+    update: Document,
+) -> Result<bool, mongodb::error::Error> {
+    let options = mongodb::options::UpdateOptions::builder()
+        .upsert(true)
+        .build();
+
+    match collection.update_one(slot_guard(id, slot), update, options).await {
+        Ok(_) => Ok(true),
+        // The guard didn't match an existing document, so the upsert collided with it
+        Err(e) if is_duplicate_key(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    use mongodb::error::{ErrorKind, WriteFailure};
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000
+    )
+}
+
+/// Mirror a profile account, unless a newer copy was already indexed
+pub async fn index_user(db: &Database, user: &User) -> Result<bool, mongodb::error::Error> {
     let update = doc! {
         "$set": {
-            "owner_pubkey": owner_pubkey,
-            "storage_cid": storage_cid,
-            "name": name,
-            "description": description,
-            "updated_at": bson_now
+            "profile_cid": &user.profile_cid,
+            "is_public": user.is_public,
+            "updated_at": mongodb::bson::DateTime::from_chrono(user.updated_at),
+            "slot": user.slot as i64
         },
         "$setOnInsert": {
-            "created_at": bson_now
+            "created_at": mongodb::bson::DateTime::from_chrono(user.created_at)
         }
     };
-    let options = mongodb::options::UpdateOptions::builder()
+    upsert_at_slot(&get_users_collection(db), &user.wallet_pubkey, user.slot, update).await
+}
+
+/// Mirror a registry site, unless a newer copy was already indexed
+pub async fn create_or_update_site(db: &Database, site: &Site) -> Result<bool, mongodb::error::Error> {
+    let mut set = doc! {
+        "owner_pubkey": &site.owner_pubkey,
+        "storage_cid": &site.storage_cid,
+        "name": &site.name,
+        "description": &site.description,
+        "version": site.version as i64,
+        "maintainers": &site.maintainers,
+        "updated_at": mongodb::bson::DateTime::from_chrono(site.updated_at)
+    };
+    if site.slot > 0 {
+        set.insert("slot", site.slot as i64);
+    }
+    let update = doc! {
+        "$set": set,
+        "$setOnInsert": {
+            "created_at": mongodb::bson::DateTime::from_chrono(site.created_at)
+        }
+    };
+    upsert_at_slot(&get_sites_collection(db), &site.program_address, site.slot, update).await
+}
+
+/// Forget a site closed on-chain, along with its deployment log
+///
+/// Skipped if the site was indexed after `slot`, i.e. registered again.
+pub async fn delete_site(db: &Database, program_address: &str, slot: u64) -> Result<(), mongodb::error::Error> {
+    let deleted = get_sites_collection(db)
+        .delete_one(slot_guard(program_address, slot), None)
+        .await?;
+    if deleted.deleted_count > 0 {
+        get_site_deployments_collection(db)
+            .delete_many(doc! { "program_address": program_address }, None)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Store deployment log entries; re-recording an entry is a no-op
+pub async fn record_site_deployments(
+    db: &Database,
+    deployments: &[SiteDeployment],
+) -> Result<(), mongodb::error::Error> {
+    let collection = get_site_deployments_collection(db);
+    let options = mongodb::options::ReplaceOptions::builder()
         .upsert(true)
         .build();
-    
-    collection.update_one(filter, update, options).await?;
+
+    for deployment in deployments {
+        collection.replace_one(doc! { "_id": &deployment.id }, deployment, options.clone()).await?;
+    }
     Ok(())
 }
+
+/// A site's deployments, newest first
+pub async fn list_site_deployments(
+    db: &Database,
+    program_address: &str,
+    limit: i64,
+) -> Result<Vec<SiteDeployment>, mongodb::error::Error> {
+    let collection = get_site_deployments_collection(db);
+    let options = mongodb::options::FindOptions::builder()
+        .limit(limit)
+        .sort(doc! { "version": -1 })
+        .build();
+
+    let cursor = collection.find(doc! { "program_address": program_address }, options).await?;
+    cursor.try_collect().await
+}
+
+pub async fn get_site_deployment(
+    db: &Database,
+    program_address: &str,
+    version: u32,
+) -> Result<Option<SiteDeployment>, mongodb::error::Error> {
+    let collection = get_site_deployments_collection(db);
+    collection.find_one(doc! { "_id": format!("{}:{}", program_address, version) }, None).await
+}
+
+/// Highest version already indexed for a site, 0 if none
+pub async fn latest_site_deployment_version(
+    db: &Database,
+    program_address: &str,
+) -> Result<u32, mongodb::error::Error> {
+    Ok(list_site_deployments(db, program_address, 1).await?
+        .first()
+        .map_or(0, |d| d.version))
+}
@@ -9,6 +9,9 @@ pub enum ShadowError {
     NotFound(String),
     BadRequest(String),
     Unauthorized,
+    Conflict(String),
+    TooLarge(String),
+    UnsupportedMediaType(String),
 }
 
 impl fmt::Display for ShadowError {
@@ -20,6 +23,9 @@ impl fmt::Display for ShadowError {
             ShadowError::NotFound(e) => write!(f, "Not found: {}", e),
             ShadowError::BadRequest(e) => write!(f, "Bad request: {}", e),
             ShadowError::Unauthorized => write!(f, "Unauthorized"),
+            ShadowError::Conflict(e) => write!(f, "Conflict: {}", e),
+            ShadowError::TooLarge(e) => write!(f, "Too large: {}", e),
+            ShadowError::UnsupportedMediaType(e) => write!(f, "Unsupported media type: {}", e),
         }
     }
 }
@@ -57,6 +63,21 @@ impl ResponseError for ShadowError {
                     "error": "Unauthorized"
                 }))
             }
+            ShadowError::Conflict(msg) => {
+                HttpResponse::Conflict().json(serde_json::json!({
+                    "error": msg
+                }))
+            }
+            ShadowError::TooLarge(msg) => {
+                HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": msg
+                }))
+            }
+            ShadowError::UnsupportedMediaType(msg) => {
+                HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                    "error": msg
+                }))
+            }
         }
     }
 }
@@ -1,20 +1,24 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
 use crate::db;
 use crate::error::ShadowError;
-use crate::storage::{PinataStorage, BundlrStorage};
-use crate::solana::SolanaClient;
+use crate::storage::StorageRegistry;
+use crate::solana::{ProgramAuthority, SolanaClient};
 use crate::anchor_client;
 use crate::ares::{AresAuth, AuthHeader};
 use crate::apollo::ApolloValidator;
 use crate::artemis::ArtemisRateLimiter;
-use crate::olympus::OlympusCA;
+use crate::olympus::{self, OlympusCA};
+use crate::iris;
 use crate::athena::AthenaIndexer;
 use crate::chronos::ChronosManager;
 use crate::prometheus::PrometheusAnalytics;
 use crate::hephaestus::HephaestusCache;
+use crate::daedalus::DaedalusRouter;
 use crate::metrics::MetricsCollector;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use mongodb::Database;
+use std::str::FromStr;
 use std::time::Duration;
 
 #[derive(Deserialize)]
@@ -66,6 +70,7 @@ pub async fn search_profiles(
 pub async fn get_profile(
     db: web::Data<Database>,
     path: web::Path<String>,
+    anchor: web::Data<anchor_client::AnchorClient>,
     metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
     let wallet = path.into_inner();
@@ -81,6 +86,17 @@ pub async fn get_profile(
             }))
         }
         None => {
+            // Profiles created directly on-chain haven't been cached yet
+            metrics.record_solana_rpc();
+            if let Ok(Some(profile)) = anchor.verify_profile(&wallet) {
+                return Ok(HttpResponse::Ok().json(ProfileResponse {
+                    wallet_pubkey: profile.wallet.to_string(),
+                    profile_cid: Some(profile.profile_cid),
+                    is_public: profile.is_public,
+                    exists: true,
+                }));
+            }
+
             // Return exists: false if not found, but don't error
             Ok(HttpResponse::Ok().json(ProfileResponse {
                 wallet_pubkey: wallet,
@@ -186,9 +202,46 @@ pub struct SearchQuery {
 #[derive(Deserialize)]
 pub struct RegisterSiteRequest {
     pub owner_pubkey: String,
-    pub storage_cid: String,
-    pub name: Option<String>,
-    pub description: Option<String>,
+    #[serde(default)]
+    pub program_address: Option<String>, // Defaults to owner_pubkey for older clients
+    pub storage_cid: String, // Must match the registry; name and description come from there too
+}
+
+#[derive(Deserialize)]
+pub struct SiteContentQuery {
+    pub version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct StorageStatQuery {
+    pub uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct RollbackSiteRequest {
+    pub version: u32,
+}
+
+#[derive(Deserialize)]
+pub struct TransferSiteRequest {
+    pub new_owner: String,
+}
+
+#[derive(Deserialize)]
+pub struct SiteMaintainersRequest {
+    pub maintainers: Vec<String>,
+}
+
+/// Mirror a registry `Site` account and index deployments not seen yet
+async fn sync_site_from_chain(
+    db: &Database,
+    anchor: &anchor_client::AnchorClient,
+    site_account: &anchor_client::SiteAccount,
+    slot: u64,
+    metrics: &MetricsCollector,
+) -> Result<(), ShadowError> {
+    metrics.record_database_query();
+    crate::mnemosyne::index_site(db, anchor, site_account, slot).await
 }
 
 pub async fn search_sites(
@@ -249,114 +302,411 @@ pub async fn register_site(
     auth.verify(&ares)
         .map_err(|_| ShadowError::Unauthorized)?;
 
-    // Verify program address exists on-chain and is registered with registry program
-    let solana_client = SolanaClient::new(solana_rpc.to_string());
-    metrics.record_solana_rpc();
-    
-    let program_address = match solana_client.search_program(&body.owner_pubkey) {
-        Ok(Some(_)) => body.owner_pubkey.clone(),
-        _ => return Err(ShadowError::BadRequest("Program address not found on-chain".to_string()).into()),
-    };
+    let program_address = body.program_address.clone()
+        .unwrap_or_else(|| body.owner_pubkey.clone());
+    ApolloValidator::validate_pubkey(&program_address)?;
+
+    // Only the program's upgrade authority may register a site for it
+    require_program_authority(&solana_rpc, &program_address, &body.owner_pubkey, &metrics)?;
     
-    // Verify on-chain registration using Anchor client
-    if let Ok(Some(site_account)) = anchor.verify_site_registration(&program_address, &body.owner_pubkey) {
-        // Site is registered on-chain, verify ownership matches
-        if site_account.owner.to_string() != body.owner_pubkey {
-            return Err(ShadowError::Unauthorized.into());
-        }
-    } else {
-        // Site not found in registry - still allow registration but log it
-        // In production, you might want to require on-chain registration first
+    // The registry's Site account is the source of truth for ownership and content
+    metrics.record_solana_rpc();
+    let (slot, site_account) = anchor.read_site_registration(&program_address, &body.owner_pubkey)
+        .map_err(ShadowError::Solana)?;
+    let site_account = site_account.ok_or_else(|| ShadowError::BadRequest(
+        "Site is not registered with the on-chain registry".to_string()
+    ))?;
+
+    if site_account.owner.to_string() != body.owner_pubkey {
+        return Err(ShadowError::Unauthorized);
+    }
+    if site_account.storage_cid != body.storage_cid {
+        return Err(ShadowError::BadRequest(
+            "storage_cid does not match the on-chain registry".to_string()
+        ));
     }
     
-    db::create_or_update_site(
-        &db,
-        &program_address,
-        &body.owner_pubkey,
-        &body.storage_cid,
-        body.name.as_deref(),
-        body.description.as_deref(),
-    ).await?;
+    sync_site_from_chain(&db, &anchor, &site_account, slot, &metrics).await?;
 
     Ok(HttpResponse::Created().json(serde_json::json!({
         "success": true,
-        "program_address": program_address
+        "program_address": program_address,
+        "version": site_account.version
     })))
 }
 
+/// Refresh a site from the registry after its owner updated or deployed it
+///
+/// Content changes go through the registry's `deploy_site`, so the
+/// deployment log can't be bypassed by writing here.
 pub async fn update_site(
     db: web::Data<Database>,
     path: web::Path<String>,
-    body: web::Json<RegisterSiteRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
     let program_address = path.into_inner();
-    
-    db::create_or_update_site(
-        &db,
-        &program_address,
-        &body.owner_pubkey,
-        &body.storage_cid,
-        body.name.as_deref(),
-        body.description.as_deref(),
-    ).await?;
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+
+    metrics.record_solana_rpc();
+    let (slot, site_account) = anchor.read_site_registration(&program_address, &wallet)
+        .map_err(ShadowError::Solana)?;
+    let Some(site_account) = site_account else {
+        // Closed on-chain since it was indexed
+        metrics.record_database_query();
+        db::delete_site(&db, &program_address, slot).await?;
+        return Err(ShadowError::NotFound("Site is not registered on-chain".to_string()));
+    };
+    if !site_account.can_maintain(&parse_pubkey(&wallet)?) {
+        return Err(ShadowError::Unauthorized);
+    }
+
+    sync_site_from_chain(&db, &anchor, &site_account, slot, &metrics).await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "success": true
+        "success": true,
+        "version": site_account.version,
+        "storage_cid": site_account.storage_cid
     })))
 }
 
-pub async fn get_site_content(
+/// A site's deployment log, newest first
+pub async fn get_site_versions(
     db: web::Data<Database>,
-    pinata: web::Data<PinataStorage>,
-    bundlr: web::Data<BundlrStorage>,
     path: web::Path<String>,
     metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
     let program_address = path.into_inner();
-    
+
     metrics.record_database_query();
     let site = db::get_site(&db, &program_address).await?
         .ok_or_else(|| ShadowError::NotFound("Site not found".to_string()))?;
+    let versions = db::list_site_deployments(&db, &program_address, 100).await?;
 
-    let content = if site.storage_cid.starts_with("ipfs://") {
-        pinata.get(&site.storage_cid).await
-            .map_err(|e| ShadowError::Storage(e))?
-    } else if site.storage_cid.starts_with("arweave://") {
-        bundlr.get(&site.storage_cid).await
-            .map_err(|e| ShadowError::Storage(e))?
-    } else {
-        return Err(ShadowError::BadRequest("Invalid storage CID".to_string()));
-    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "program_address": program_address,
+        "current_version": site.version,
+        "storage_cid": site.storage_cid,
+        "versions": versions
+    })))
+}
 
-    Ok(HttpResponse::Ok()
-        .content_type("text/html")
-        .body(content))
+/// Build a `rollback_site` transaction back to `version` for the owner or a maintainer
+///
+/// The rollback lands as a new version; sync the site after it confirms.
+pub async fn rollback_site(
+    db: web::Data<Database>,
+    path: web::Path<String>,
+    body: web::Json<RollbackSiteRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let program_address = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+
+    metrics.record_solana_rpc();
+    let site_account = anchor.verify_site_registration(&program_address, &wallet)
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::NotFound("Site is not registered on-chain".to_string()))?;
+    let authority = parse_pubkey(&wallet)?;
+    if !site_account.can_maintain(&authority) {
+        return Err(ShadowError::Unauthorized);
+    }
+    if body.version >= site_account.version {
+        return Err(ShadowError::BadRequest(
+            format!("Can only roll back to a version before {}", site_account.version)
+        ));
+    }
+
+    metrics.record_database_query();
+    let target = db::get_site_deployment(&db, &program_address, body.version).await?
+        .ok_or_else(|| ShadowError::NotFound("Version not found".to_string()))?;
+    if target.storage_cid == site_account.storage_cid {
+        return Err(ShadowError::BadRequest(
+            "That version's content is already deployed".to_string()
+        ));
+    }
+
+    let instruction = anchor.rollback_site_instruction(
+        &site_account.program_address,
+        &authority,
+        body.version,
+        site_account.version,
+    );
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(instruction, &authority)
+        .map_err(ShadowError::Solana)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "transaction": transaction,
+        "version": site_account.version + 1,
+        "rollback_of": body.version,
+        "storage_cid": target.storage_cid
+    })))
 }
 
-pub async fn upload_ipfs(
-    pinata: web::Data<PinataStorage>,
-    body: web::Bytes,
+/// The on-chain site, if `wallet` is its owner
+fn owned_site_account(
+    anchor: &anchor_client::AnchorClient,
+    program_address: &str,
+    wallet: &str,
+    metrics: &MetricsCollector,
+) -> Result<anchor_client::SiteAccount, ShadowError> {
+    metrics.record_solana_rpc();
+    let site_account = anchor.verify_site_registration(program_address, wallet)
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::NotFound("Site is not registered on-chain".to_string()))?;
+    if site_account.owner.to_string() != wallet {
+        return Err(ShadowError::Unauthorized);
+    }
+    Ok(site_account)
+}
+
+/// Build the owner's `transfer_site` transaction; sync the site after it confirms
+pub async fn transfer_site(
+    path: web::Path<String>,
+    body: web::Json<TransferSiteRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
-    let cid = pinata.upload(&body, "upload").await
-        .map_err(|e| ShadowError::Storage(e))?;
+    let program_address = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let site_account = owned_site_account(&anchor, &program_address, &wallet, &metrics)?;
+
+    let new_owner = parse_pubkey(&body.new_owner)?;
+    if new_owner == site_account.owner {
+        return Err(ShadowError::BadRequest("New owner must differ from the current owner".to_string()));
+    }
+
+    let instruction = anchor.transfer_site_instruction(&site_account.program_address, &site_account.owner, &new_owner);
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(instruction, &site_account.owner)
+        .map_err(ShadowError::Solana)?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "cid": cid
+        "transaction": transaction,
+        "new_owner": new_owner.to_string()
     })))
 }
 
-pub async fn upload_arweave(
-    bundlr: web::Data<BundlrStorage>,
-    body: web::Bytes,
+/// Build the owner's `set_maintainers` transaction, replacing the whole list
+pub async fn set_site_maintainers(
+    path: web::Path<String>,
+    body: web::Json<SiteMaintainersRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let program_address = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let site_account = owned_site_account(&anchor, &program_address, &wallet, &metrics)?;
+
+    if body.maintainers.len() > anchor_client::MAX_MAINTAINERS {
+        return Err(ShadowError::BadRequest(
+            format!("At most {} maintainers", anchor_client::MAX_MAINTAINERS)
+        ));
+    }
+    let mut maintainers = Vec::with_capacity(body.maintainers.len());
+    for address in &body.maintainers {
+        let maintainer = parse_pubkey(address)?;
+        if maintainer == site_account.owner || maintainers.contains(&maintainer) {
+            return Err(ShadowError::BadRequest("Maintainers must be unique and not the owner".to_string()));
+        }
+        maintainers.push(maintainer);
+    }
+
+    let instruction = anchor.set_maintainers_instruction(&site_account.program_address, &site_account.owner, &maintainers);
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(instruction, &site_account.owner)
+        .map_err(ShadowError::Solana)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "transaction": transaction,
+        "maintainers": maintainers.iter().map(|m| m.to_string()).collect::<Vec<_>>()
+    })))
+}
+
+/// Build the owner's transactions closing the site and its deployment log
+///
+/// Sign and send them in order; the last one closes the site itself, after
+/// which `PUT /sites/{program}` drops it from the index.
+pub async fn close_site(
+    path: web::Path<String>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
-    let tx_id = bundlr.upload(&body, vec![]).await
-        .map_err(|e| ShadowError::Storage(e))?;
+    let program_address = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let site_account = owned_site_account(&anchor, &program_address, &wallet, &metrics)?;
+
+    let mut transactions = Vec::new();
+    for instruction in anchor.close_site_instructions(&site_account.program_address, &site_account.owner, site_account.version) {
+        metrics.record_solana_rpc();
+        transactions.push(
+            anchor.build_unsigned_transaction(instruction, &site_account.owner)
+                .map_err(ShadowError::Solana)?
+        );
+    }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "tx_id": tx_id
+        "transactions": transactions
     })))
 }
 
+/// Pin a deployment's content on its storage provider, for the owner or a maintainer
+pub async fn pin_site_version(
+    db: web::Data<Database>,
+    storage: web::Data<StorageRegistry>,
+    path: web::Path<(String, u32)>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let (program_address, version) = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let (_, storage_cid) = maintained_deployment(&db, &anchor, &program_address, version, &wallet, &metrics).await?;
+
+    storage.pin(&storage_cid).await.map_err(ShadowError::Storage)?;
+    let stat = storage.stat(&storage_cid).await.map_err(ShadowError::Storage)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "version": version,
+        "storage_cid": storage_cid,
+        "stat": stat
+    })))
+}
+
+/// Unpin an old deployment's content; the live version always stays pinned
+pub async fn unpin_site_version(
+    db: web::Data<Database>,
+    storage: web::Data<StorageRegistry>,
+    path: web::Path<(String, u32)>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let (program_address, version) = path.into_inner();
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let (site_account, storage_cid) =
+        maintained_deployment(&db, &anchor, &program_address, version, &wallet, &metrics).await?;
+
+    if storage_cid == site_account.storage_cid {
+        return Err(ShadowError::BadRequest("The live version's content can't be unpinned".to_string()));
+    }
+    storage.unpin(&storage_cid).await.map_err(ShadowError::Storage)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "version": version,
+        "storage_cid": storage_cid,
+        "unpinned": true
+    })))
+}
+
+/// The on-chain site and the content of one of its deployments, if `wallet` may maintain it
+async fn maintained_deployment(
+    db: &Database,
+    anchor: &anchor_client::AnchorClient,
+    program_address: &str,
+    version: u32,
+    wallet: &str,
+    metrics: &MetricsCollector,
+) -> Result<(anchor_client::SiteAccount, String), ShadowError> {
+    metrics.record_solana_rpc();
+    let site_account = anchor.verify_site_registration(program_address, wallet)
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::NotFound("Site is not registered on-chain".to_string()))?;
+    if !site_account.can_maintain(&parse_pubkey(wallet)?) {
+        return Err(ShadowError::Unauthorized);
+    }
+
+    metrics.record_database_query();
+    let deployment = db::get_site_deployment(db, program_address, version).await?
+        .ok_or_else(|| ShadowError::NotFound("Version not found".to_string()))?;
+
+    Ok((site_account, deployment.storage_cid))
+}
+
+pub async fn get_site_content(
+    db: web::Data<Database>,
+    hephaestus: web::Data<HephaestusCache>,
+    storage: web::Data<StorageRegistry>,
+    path: web::Path<String>,
+    query: web::Query<SiteContentQuery>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let router = DaedalusRouter::new(storage.into_inner(), Some(hephaestus.into_inner()));
+    serve_site_path(&db, &router, &path.into_inner(), "", query.version, &metrics).await
+}
+
+/// A file inside a multi-file deployment, e.g. `/sites/{program}/content/assets/app.js`
+pub async fn get_site_file(
+    db: web::Data<Database>,
+    hephaestus: web::Data<HephaestusCache>,
+    storage: web::Data<StorageRegistry>,
+    path: web::Path<(String, String)>,
+    query: web::Query<SiteContentQuery>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let (program_address, file_path) = path.into_inner();
+    let router = DaedalusRouter::new(storage.into_inner(), Some(hephaestus.into_inner()));
+    serve_site_path(&db, &router, &program_address, &file_path, query.version, &metrics).await
+}
+
+async fn serve_site_path(
+    db: &Database,
+    router: &DaedalusRouter,
+    program_address: &str,
+    file_path: &str,
+    version: Option<u32>,
+    metrics: &MetricsCollector,
+) -> ActixResult<HttpResponse, ShadowError> {
+    metrics.record_database_query();
+    let site = db::get_site(db, program_address).await?
+        .ok_or_else(|| ShadowError::NotFound("Site not found".to_string()))?;
+
+    // Earlier deployments stay viewable by version
+    let (storage_cid, version) = match version {
+        Some(version) => {
+            metrics.record_database_query();
+            let deployment = db::get_site_deployment(db, program_address, version).await?
+                .ok_or_else(|| ShadowError::NotFound("Version not found".to_string()))?;
+            (deployment.storage_cid, version)
+        }
+        None => (site.storage_cid, site.version),
+    };
+
+    let file = router.resolve(&storage_cid, file_path).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(file.content_type)
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .insert_header(("X-Shadow-Site-Version", version.to_string()))
+        .body(file.body))
+}
+
+/// Size and pin state of stored content, without fetching it
+pub async fn get_storage_stat(
+    storage: web::Data<StorageRegistry>,
+    query: web::Query<StorageStatQuery>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let stat = storage.stat(&query.uri).await
+        .map_err(ShadowError::Storage)?
+        .ok_or_else(|| ShadowError::NotFound("Content not stored".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(stat))
+}
+
 pub async fn search_solana(
     solana_rpc_url: web::Data<String>,
     query: web::Query<SearchQuery>,
@@ -393,6 +743,31 @@ pub async fn search_solana(
 
 // ========== Olympus Domain Handlers ==========
 
+/// Require `wallet` to be the upgrade authority of `program_address`
+///
+/// Immutable programs have no authority left to prove control with, so
+/// they are rejected rather than trusted to whoever asks first.
+fn require_program_authority(
+    solana_rpc_url: &str,
+    program_address: &str,
+    wallet: &str,
+    metrics: &MetricsCollector,
+) -> Result<ProgramAuthority, ShadowError> {
+    metrics.record_solana_rpc();
+    let authority = SolanaClient::new(solana_rpc_url.to_string())
+        .get_program_authority(program_address)
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::BadRequest("Program not found or not executable".to_string()))?;
+
+    match authority.upgrade_authority.as_deref() {
+        Some(upgrade_authority) if upgrade_authority == wallet => Ok(authority),
+        Some(_) => Err(ShadowError::Unauthorized),
+        None => Err(ShadowError::BadRequest(
+            "Program is immutable; ownership cannot be proven by upgrade authority".to_string()
+        )),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct RegisterDomainRequest {
     pub domain: String,
@@ -400,17 +775,69 @@ pub struct RegisterDomainRequest {
     pub owner_pubkey: String,
 }
 
+/// Pull `domain` from the shadow-domains program and check it says what the caller claims
+///
+/// Domains are registered and updated by transactions the owner signs;
+/// these endpoints only confirm the result and refresh the cache.
+async fn sync_claimed_domain(
+    olympus: &OlympusCA,
+    anchor: &anchor_client::AnchorClient,
+    domain: &str,
+    owner_pubkey: &str,
+    program_address: &str,
+    metrics: &MetricsCollector,
+) -> Result<olympus::Domain, ShadowError> {
+    metrics.record_solana_rpc();
+    let synced = olympus.sync_from_chain(domain, anchor).await
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::BadRequest(
+            "Domain is not registered on-chain; submit the shadow-domains transaction first".to_string()
+        ))?;
+
+    if synced.owner_pubkey != owner_pubkey {
+        // Names stay with their owner through the grace period
+        return Err(ShadowError::BadRequest(match synced.status(chrono::Utc::now()) {
+            olympus::DomainStatus::Lapsed => {
+                "Domain has expired and can be registered once it is released".to_string()
+            }
+            _ => match synced.grace_ends_at() {
+                Some(grace_ends_at) => format!(
+                    "Domain is registered to another wallet until {}",
+                    grace_ends_at.to_rfc3339()
+                ),
+                None => "Domain is registered to another wallet".to_string(),
+            },
+        }));
+    }
+    if synced.program_address != program_address {
+        return Err(ShadowError::BadRequest(
+            "On-chain target does not match program_address yet".to_string()
+        ));
+    }
+    Ok(synced)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn register_domain(
     olympus: web::Data<OlympusCA>,
     body: web::Json<RegisterDomainRequest>,
     ares: web::Data<AresAuth>,
     _apollo: web::Data<ApolloValidator>,
     req: HttpRequest,
+    solana_rpc_url: web::Data<String>,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
     // Validate inputs
-    ApolloValidator::validate_domain(&body.domain)?;
+    let domain_name = ApolloValidator::normalize_domain(&body.domain)?;
     ApolloValidator::validate_pubkey(&body.owner_pubkey)?;
     ApolloValidator::validate_pubkey(&body.program_address)?;
+    let custom = olympus::is_custom_domain(&domain_name);
+    if !custom && olympus::is_subdomain(&domain_name) {
+        return Err(ShadowError::BadRequest(
+            "Subdomains are created by the parent domain's owner, not registered".to_string()
+        ));
+    }
 
     // Verify authentication
     let auth_header = req.headers().get("X-Shadow-Auth")
@@ -426,31 +853,122 @@ pub async fn register_domain(
     auth.verify(&ares)
         .map_err(|_| ShadowError::Unauthorized)?;
 
-    // Register domain
-    olympus.register_domain(
-        &body.domain,
+    if let Some(reason) = olympus.name_rejection(&domain_name).await
+        .map_err(ShadowError::BadRequest)? {
+        return Err(ShadowError::BadRequest(reason));
+    }
+
+    // Only the program's upgrade authority may point a domain at it
+    require_program_authority(&solana_rpc_url, &body.program_address, &body.owner_pubkey, &metrics)?;
+
+    // Custom domains are claimed by publishing a TXT record, then verified
+    if custom {
+        let challenge = olympus.issue_challenge(&domain_name, &body.owner_pubkey, &body.program_address).await
+            .map_err(ShadowError::BadRequest)?;
+
+        return Ok(HttpResponse::Accepted().json(serde_json::json!({
+            "success": true,
+            "domain": challenge.domain,
+            "verified": false,
+            "challenge": {
+                "type": "TXT",
+                "name": challenge.record_name(),
+                "value": challenge.record_value()
+            }
+        })));
+    }
+
+    // Cache the on-chain registration
+    let domain = sync_claimed_domain(
+        &olympus,
+        &anchor,
+        &domain_name,
         &body.owner_pubkey,
         &body.program_address,
-    ).await
-    .map_err(|e| ShadowError::BadRequest(e))?;
+        &metrics,
+    ).await?;
 
     Ok(HttpResponse::Created().json(serde_json::json!({
         "success": true,
-        "domain": body.domain
+        "domain": domain.domain,
+        "expires_at": domain.expires_at,
+        "status": domain.status(chrono::Utc::now())
+    })))
+}
+
+/// Whether a name can be registered, and if not, why
+///
+/// Clients call this before submitting the on-chain registration; names
+/// that break the naming policy are registered on-chain but never served.
+pub async fn check_domain_name(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    let mut reason = olympus.name_rejection(&domain).await
+        .map_err(ShadowError::BadRequest)?;
+    if reason.is_none() && !olympus::is_custom_domain(&domain) {
+        if olympus::is_subdomain(&domain) {
+            reason = Some("Subdomains are created by the parent domain's owner, not registered".to_string());
+        } else if let Some(existing) = olympus.get_domain(&domain).await
+            .map_err(ShadowError::BadRequest)? {
+            if existing.status(chrono::Utc::now()) != olympus::DomainStatus::Lapsed {
+                reason = Some("Domain is already registered".to_string());
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain,
+        "display_name": ApolloValidator::unicode_domain(&domain),
+        "available": reason.is_none(),
+        "reason": reason
     })))
 }
 
 pub async fn get_domain(
     olympus: web::Data<OlympusCA>,
     path: web::Path<String>,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
-    let domain = path.into_inner();
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
     
-    let domain_data = olympus.get_domain(&domain).await
-        .map_err(|e| ShadowError::BadRequest(e))?
+    let cached = olympus.get_domain(&domain).await
+        .map_err(ShadowError::BadRequest)?;
+
+    // Names registered since the last sync are fetched on first lookup
+    let domain_data = match cached {
+        Some(domain_data) => domain_data,
+        None if olympus::is_custom_domain(&domain) => {
+            return Err(ShadowError::NotFound("Domain not found".to_string()));
+        }
+        None => {
+            metrics.record_solana_rpc();
+            olympus.sync_from_chain(&domain, &anchor).await
+                .map_err(ShadowError::Solana)?
+                .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(olympus::DomainView::from(domain_data)))
+}
+
+pub async fn sync_domain(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    metrics.record_solana_rpc();
+    let domain_data = olympus.sync_from_chain(&domain, &anchor).await
+        .map_err(ShadowError::Solana)?
         .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
 
-    Ok(HttpResponse::Ok().json(domain_data))
+    Ok(HttpResponse::Ok().json(olympus::DomainView::from(domain_data)))
 }
 
 pub async fn search_domains(
@@ -461,12 +979,16 @@ pub async fn search_domains(
     ApolloValidator::validate_search_query(&query.q)?;
     let limit = ApolloValidator::validate_limit(query.limit)?;
 
-    let domains = olympus.search_domains(&query.q, limit).await
-        .map_err(|e| ShadowError::BadRequest(e))?;
+    let domains: Vec<olympus::DomainView> = olympus.search_domains(&query.q, limit).await
+        .map_err(ShadowError::BadRequest)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
 
     Ok(HttpResponse::Ok().json(domains))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_domain(
     olympus: web::Data<OlympusCA>,
     path: web::Path<String>,
@@ -474,11 +996,12 @@ pub async fn update_domain(
     ares: web::Data<AresAuth>,
     _apollo: web::Data<ApolloValidator>,
     req: HttpRequest,
+    solana_rpc_url: web::Data<String>,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
 ) -> ActixResult<HttpResponse, ShadowError> {
-    let domain = path.into_inner();
-    
     // Validate
-    ApolloValidator::validate_domain(&domain)?;
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
     ApolloValidator::validate_pubkey(&body.program_address)?;
 
     // Verify ownership
@@ -500,19 +1023,38 @@ pub async fn update_domain(
     auth.verify(&ares)
         .map_err(|_| ShadowError::Unauthorized)?;
 
-    // Update domain
-    olympus.register_domain(
+    require_program_authority(&solana_rpc_url, &body.program_address, &domain_data.owner_pubkey, &metrics)?;
+
+    // Custom domains live off-chain; DNS control was proven at verification
+    if olympus::is_custom_domain(&domain) {
+        if !domain_data.verified {
+            return Err(ShadowError::BadRequest(
+                "Domain verification was revoked; verify it again first".to_string()
+            ));
+        }
+        olympus.set_program_address(&domain, &body.program_address).await
+            .map_err(ShadowError::BadRequest)?;
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true
+        })));
+    }
+
+    // Cache the on-chain target update
+    sync_claimed_domain(
+        &olympus,
+        &anchor,
         &domain,
         &domain_data.owner_pubkey,
         &body.program_address,
-    ).await
-    .map_err(|e| ShadowError::BadRequest(e))?;
+        &metrics,
+    ).await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "success": true
     })))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn verify_domain(
     olympus: web::Data<OlympusCA>,
     path: web::Path<String>,
@@ -520,8 +1062,14 @@ pub async fn verify_domain(
     req: HttpRequest,
     solana_rpc_url: web::Data<String>,
     metrics: web::Data<MetricsCollector>,
+    txt_resolver: web::Data<dyn iris::TxtResolver>,
+    config: web::Data<crate::config::ShadowConfig>,
 ) -> ActixResult<HttpResponse, ShadowError> {
-    let domain = path.into_inner();
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    if olympus::is_custom_domain(&domain) {
+        return verify_custom_domain(&olympus, &domain, &ares, &req, &solana_rpc_url, &metrics, txt_resolver.as_ref(), &config).await;
+    }
     
     // Verify ownership
     let domain_data = olympus.get_domain(&domain).await
@@ -542,23 +1090,13 @@ pub async fn verify_domain(
     auth.verify(&ares)
         .map_err(|_| ShadowError::Unauthorized)?;
 
-    // On-chain verification: Check program exists and is executable
-    metrics.record_solana_rpc();
-    let client = SolanaClient::new(solana_rpc_url.to_string());
-    match client.search_program(&domain_data.program_address) {
-        Ok(Some(program_info)) => {
-            // Program exists and is executable
-            if program_info.data_len == 0 {
-                return Err(ShadowError::BadRequest("Program account is empty".to_string()).into());
-            }
-        }
-        Ok(None) => {
-            return Err(ShadowError::BadRequest("Program not found or not executable".to_string()).into());
-        }
-        Err(e) => {
-            return Err(ShadowError::BadRequest(format!("Solana RPC error: {}", e)).into());
-        }
-    }
+    // On-chain verification: the domain owner must control the program
+    let authority = require_program_authority(
+        &solana_rpc_url,
+        &domain_data.program_address,
+        &domain_data.owner_pubkey,
+        &metrics,
+    )?;
 
     // Mark as verified (after on-chain verification)
     olympus.verify_domain(&domain).await
@@ -566,7 +1104,42 @@ pub async fn verify_domain(
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "success": true,
-        "verified": true
+        "verified": true,
+        "upgrade_authority": authority.upgrade_authority,
+        "last_deploy_slot": authority.last_deploy_slot
+    })))
+}
+
+/// Check the caller's `_shadow-challenge` TXT record for a custom domain
+#[allow(clippy::too_many_arguments)]
+async fn verify_custom_domain(
+    olympus: &OlympusCA,
+    domain: &str,
+    ares: &AresAuth,
+    req: &HttpRequest,
+    solana_rpc_url: &str,
+    metrics: &MetricsCollector,
+    txt_resolver: &dyn iris::TxtResolver,
+    config: &crate::config::ShadowConfig,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = crate::wallet_handlers::verify_auth(req, ares)?;
+    let challenge = olympus.get_challenge(domain, &wallet).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound(
+            "No claim for this domain; register it first to get a challenge".to_string()
+        ))?;
+
+    // The program may have changed hands since the claim was made
+    require_program_authority(solana_rpc_url, &challenge.program_address, &wallet, metrics)?;
+
+    let recheck = chrono::Duration::seconds(config.domains.challenge_recheck_seconds as i64);
+    let domain_data = olympus.verify_challenge(&challenge, txt_resolver, recheck).await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "verified": true,
+        "next_check_at": domain_data.custom_verification.map(|v| v.next_check_at)
     })))
 }
 
@@ -576,12 +1149,578 @@ pub async fn list_owner_domains(
 ) -> ActixResult<HttpResponse, ShadowError> {
     let wallet = path.into_inner();
     
-    let domains = olympus.list_owner_domains(&wallet).await
-        .map_err(|e| ShadowError::BadRequest(e))?;
+    let domains: Vec<olympus::DomainView> = olympus.list_owner_domains(&wallet).await
+        .map_err(ShadowError::BadRequest)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
 
     Ok(HttpResponse::Ok().json(domains))
 }
 
+/// Build the renewal transaction for the owner to sign
+///
+/// Renewal extends the on-chain term, so this only hands back the unsigned
+/// transaction; the cache picks up the new expiry on the next sync.
+pub async fn renew_domain(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    metrics.record_solana_rpc();
+    let domain_data = olympus.sync_from_chain(&domain, &anchor).await
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
+
+    // Verify authentication
+    let auth_header = req.headers().get("X-Shadow-Auth")
+        .ok_or_else(|| ShadowError::Unauthorized)?
+        .to_str()
+        .map_err(|_| ShadowError::Unauthorized)?;
+
+    let auth = AuthHeader::from_header(auth_header)?;
+    if auth.wallet != domain_data.owner_pubkey {
+        return Err(ShadowError::Unauthorized);
+    }
+
+    auth.verify(&ares)
+        .map_err(|_| ShadowError::Unauthorized)?;
+
+    let status = domain_data.status(chrono::Utc::now());
+    if status == olympus::DomainStatus::Lapsed {
+        return Err(ShadowError::BadRequest(
+            "Grace period is over; the domain can only be released".to_string()
+        ));
+    }
+
+    let owner = parse_pubkey(&domain_data.owner_pubkey)?;
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_renew_transaction(&domain, &owner)
+        .map_err(ShadowError::Solana)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain,
+        "status": status,
+        "expires_at": domain_data.expires_at,
+        "grace_ends_at": domain_data.grace_ends_at(),
+        "transaction": transaction
+    })))
+}
+
+/// Offers stay open for a week unless the owner asks otherwise
+const DEFAULT_TRANSFER_OFFER_SECONDS: i64 = 7 * 24 * 60 * 60;
+/// Mirrors the shadow-domains MAX_OFFER_PERIOD
+const MAX_TRANSFER_OFFER_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct OfferTransferRequest {
+    pub recipient: String,
+    pub expires_in_seconds: Option<i64>,
+}
+
+/// Current chain state of a domain that can change hands
+async fn transferable_domain(
+    olympus: &OlympusCA,
+    anchor: &anchor_client::AnchorClient,
+    domain: &str,
+    metrics: &MetricsCollector,
+) -> Result<olympus::Domain, ShadowError> {
+    let domain = ApolloValidator::normalize_domain(domain)?;
+
+    metrics.record_solana_rpc();
+    let domain_data = olympus.sync_from_chain(&domain, anchor).await
+        .map_err(ShadowError::Solana)?
+        .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
+
+    if domain_data.status(chrono::Utc::now()) != olympus::DomainStatus::Active {
+        return Err(ShadowError::BadRequest(
+            "Expired domains must be renewed before they can be transferred".to_string()
+        ));
+    }
+    Ok(domain_data)
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<solana_sdk::pubkey::Pubkey, ShadowError> {
+    solana_sdk::pubkey::Pubkey::from_str(pubkey)
+        .map_err(|e| ShadowError::BadRequest(format!("Invalid pubkey: {}", e)))
+}
+
+/// Pending offer and transfer history of a domain
+pub async fn get_domain_transfer(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let domain = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    let domain_data = olympus.get_domain(&domain).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
+    let history: Vec<olympus::DomainTransferView> = olympus.transfer_history(&domain).await
+        .map_err(ShadowError::BadRequest)?
+        .into_iter()
+        .map(olympus::DomainTransferView::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain,
+        "owner_pubkey": domain_data.owner_pubkey,
+        "pending_owner": domain_data.pending_owner,
+        "offer_expires_at": domain_data.offer_expires_at,
+        "history": history
+    })))
+}
+
+/// Build the owner's `offer_transfer` transaction
+///
+/// Nothing moves until the recipient accepts. Both wallets hear about the
+/// offer on their Hermes topics once it lands on-chain and is synced.
+pub async fn offer_domain_transfer(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    body: web::Json<OfferTransferRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    ApolloValidator::validate_pubkey(&body.recipient)?;
+
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let domain_data = transferable_domain(&olympus, &anchor, &path.into_inner(), &metrics).await?;
+    if wallet != domain_data.owner_pubkey {
+        return Err(ShadowError::Unauthorized);
+    }
+    if body.recipient == domain_data.owner_pubkey {
+        return Err(ShadowError::BadRequest("Recipient already owns the domain".to_string()));
+    }
+
+    let expires_in = body.expires_in_seconds.unwrap_or(DEFAULT_TRANSFER_OFFER_SECONDS);
+    if expires_in <= 0 || expires_in > MAX_TRANSFER_OFFER_SECONDS {
+        return Err(ShadowError::BadRequest(
+            "expires_in_seconds must be between 1 and 30 days".to_string()
+        ));
+    }
+
+    let owner = parse_pubkey(&domain_data.owner_pubkey)?;
+    let recipient = parse_pubkey(&body.recipient)?;
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(
+        anchor.offer_transfer_instruction(&domain_data.domain, &owner, &recipient, expires_in),
+        &owner,
+    ).map_err(ShadowError::Solana)?;
+
+    // The program caps offers at the end of the registration term
+    let requested = chrono::Utc::now() + chrono::Duration::seconds(expires_in);
+    let offer_expires_at = domain_data.expires_at.map_or(requested, |e| e.min(requested));
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain_data.domain,
+        "recipient": body.recipient,
+        "offer_expires_at": offer_expires_at,
+        "transaction": transaction
+    })))
+}
+
+/// Build the owner's `cancel_transfer` transaction for the pending offer
+pub async fn cancel_domain_transfer(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let domain_data = transferable_domain(&olympus, &anchor, &path.into_inner(), &metrics).await?;
+    if wallet != domain_data.owner_pubkey {
+        return Err(ShadowError::Unauthorized);
+    }
+    if domain_data.pending_owner.is_none() {
+        return Err(ShadowError::BadRequest("No pending transfer".to_string()));
+    }
+
+    let owner = parse_pubkey(&domain_data.owner_pubkey)?;
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(
+        anchor.cancel_transfer_instruction(&domain_data.domain, &owner),
+        &owner,
+    ).map_err(ShadowError::Solana)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain_data.domain,
+        "transaction": transaction
+    })))
+}
+
+/// Build the recipient's `accept_transfer` transaction
+pub async fn accept_domain_transfer(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+    anchor: web::Data<anchor_client::AnchorClient>,
+    metrics: web::Data<MetricsCollector>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let domain_data = transferable_domain(&olympus, &anchor, &path.into_inner(), &metrics).await?;
+
+    // Lapsed offers are dropped from the cache by the sync above
+    if domain_data.pending_owner.as_deref() != Some(wallet.as_str()) {
+        return Err(ShadowError::BadRequest(
+            "No open transfer offer for this wallet".to_string()
+        ));
+    }
+
+    let recipient = parse_pubkey(&wallet)?;
+    metrics.record_solana_rpc();
+    let transaction = anchor.build_unsigned_transaction(
+        anchor.accept_transfer_instruction(&domain_data.domain, &recipient),
+        &recipient,
+    ).map_err(ShadowError::Solana)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "domain": domain_data.domain,
+        "from": domain_data.owner_pubkey,
+        "offer_expires_at": domain_data.offer_expires_at,
+        "transaction": transaction
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct PutRecordsRequest {
+    pub records: Vec<olympus::ResourceRecord>,
+}
+
+#[derive(Deserialize)]
+pub struct SubdomainRequest {
+    pub label: String,
+    pub delegate: Option<String>,
+}
+
+/// Resolve a name (subdomains and wildcards included) to its records
+pub async fn resolve_domain(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let name = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    let resolution = olympus.resolve(&name).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(resolution))
+}
+
+pub async fn get_domain_records(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let name = olympus::normalize_record_name(&path.into_inner())?;
+
+    let set = olympus.get_record_set(&name).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound("No records for this name".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(olympus::RecordSetView::from(set)))
+}
+
+/// Replace a name's record set; the zone owner or a delegate above the name may
+pub async fn put_domain_records(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    body: web::Json<PutRecordsRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let name = olympus::normalize_record_name(&path.into_inner())?;
+
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let zone = olympus.authorize_manager(&name, &wallet).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or(ShadowError::Unauthorized)?;
+
+    let set = olympus.put_records(&name, &zone, body.into_inner().records).await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(olympus::RecordSetView::from(set)))
+}
+
+pub async fn list_subdomains(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let parent = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    let subdomains: Vec<olympus::RecordSetView> = olympus.list_subdomains(&parent).await
+        .map_err(ShadowError::BadRequest)?
+        .into_iter()
+        .map(olympus::RecordSetView::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(subdomains))
+}
+
+/// Create a subdomain under `parent`, optionally delegating it to another wallet
+///
+/// Posting an existing label changes (or clears) its delegate.
+pub async fn upsert_subdomain(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+    body: web::Json<SubdomainRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let parent = ApolloValidator::normalize_domain(&path.into_inner())?;
+
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let zone = olympus.authorize_manager(&parent, &wallet).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or(ShadowError::Unauthorized)?;
+    if zone.status(chrono::Utc::now()) != olympus::DomainStatus::Active {
+        return Err(ShadowError::BadRequest("Domain has expired".to_string()));
+    }
+
+    let body = body.into_inner();
+    let set = olympus.upsert_subdomain(&parent, &body.label, &zone, body.delegate).await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Created().json(olympus::RecordSetView::from(set)))
+}
+
+/// Delete a subdomain and everything below it
+pub async fn delete_subdomain(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<(String, String)>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let (parent, label) = path.into_inner();
+    let parent = ApolloValidator::normalize_domain(&parent)?;
+    let name = olympus::normalize_record_name(&format!("{}.{}", label, parent))?;
+
+    // Delegates can't remove their own delegation, only what's under it
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    olympus.authorize_manager(&parent, &wallet).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or(ShadowError::Unauthorized)?;
+
+    let deleted = olympus.delete_subdomain(&name).await
+        .map_err(ShadowError::BadRequest)?;
+    if deleted == 0 {
+        return Err(ShadowError::NotFound("Subdomain not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "deleted": deleted
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct SetPrimaryRequest {
+    pub domain: String,
+    pub program_address: Option<String>, // Unset sets the caller's own wallet
+}
+
+#[derive(Deserialize)]
+pub struct PrimaryQuery {
+    pub program_address: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReverseLookupRequest {
+    pub addresses: Vec<String>,
+}
+
+/// Choose the name the caller's wallet, or a program one of their domains
+/// is verified for, reverse-resolves to
+pub async fn set_primary_domain(
+    olympus: web::Data<OlympusCA>,
+    body: web::Json<SetPrimaryRequest>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+    let body = body.into_inner();
+    let domain = ApolloValidator::normalize_domain(&body.domain)?;
+
+    let domain_data = olympus.get_domain(&domain).await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound("Domain not found".to_string()))?;
+    if domain_data.owner_pubkey != wallet {
+        return Err(ShadowError::Unauthorized);
+    }
+
+    let (kind, address) = match body.program_address {
+        Some(program) => {
+            parse_pubkey(&program)?;
+            (olympus::PrimaryKind::Program, program)
+        }
+        None => (olympus::PrimaryKind::Wallet, wallet),
+    };
+    let primary = olympus.set_primary(kind, &address, &domain_data).await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(primary))
+}
+
+/// Clear the caller's primary name, or that of a program whose primary they own
+pub async fn clear_primary_domain(
+    olympus: web::Data<OlympusCA>,
+    query: web::Query<PrimaryQuery>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = crate::wallet_handlers::verify_auth(&req, &ares)?;
+
+    let cleared = match &query.program_address {
+        Some(program) => {
+            let Some(primary) = olympus.get_primary(program).await
+                .map_err(ShadowError::BadRequest)? else {
+                return Err(ShadowError::NotFound("Program has no primary domain".to_string()));
+            };
+            let owner = olympus.get_domain(&primary.domain).await
+                .map_err(ShadowError::BadRequest)?
+                .map(|d| d.owner_pubkey);
+            if owner.as_deref() != Some(wallet.as_str()) {
+                return Err(ShadowError::Unauthorized);
+            }
+            olympus.clear_primary(olympus::PrimaryKind::Program, program).await
+        }
+        None => olympus.clear_primary(olympus::PrimaryKind::Wallet, &wallet).await,
+    }.map_err(ShadowError::BadRequest)?;
+
+    if !cleared {
+        return Err(ShadowError::NotFound("No primary domain set".to_string()));
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true
+    })))
+}
+
+/// The name a wallet or program address reverse-resolves to
+pub async fn reverse_lookup(
+    olympus: web::Data<OlympusCA>,
+    path: web::Path<String>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let address = path.into_inner();
+    parse_pubkey(&address)?;
+
+    let primary = olympus.reverse_lookup(&[address]).await
+        .map_err(ShadowError::BadRequest)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ShadowError::NotFound("No primary domain for this address".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(primary))
+}
+
+/// Reverse-resolve many addresses at once; unnamed ones map to null
+pub async fn batch_reverse_lookup(
+    olympus: web::Data<OlympusCA>,
+    body: web::Json<ReverseLookupRequest>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let mut addresses = body.into_inner().addresses;
+    if addresses.len() > olympus::MAX_REVERSE_LOOKUPS {
+        return Err(ShadowError::BadRequest(format!(
+            "At most {} addresses per lookup",
+            olympus::MAX_REVERSE_LOOKUPS
+        )));
+    }
+    for address in &addresses {
+        parse_pubkey(address)?;
+    }
+    addresses.sort();
+    addresses.dedup();
+
+    let found = olympus.reverse_lookup(&addresses).await
+        .map_err(ShadowError::BadRequest)?;
+    let names: serde_json::Map<String, serde_json::Value> = addresses.into_iter()
+        .map(|address| {
+            let domain = found.iter()
+                .find(|p| p.address == address)
+                .map_or(serde_json::Value::Null, |p| p.domain.clone().into());
+            (address, domain)
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "names": names
+    })))
+}
+
+// ========== Iris DNS Handlers ==========
+
+#[derive(Deserialize)]
+pub struct DnsQuery {
+    pub dns: Option<String>,  // base64url wire message (RFC 8484)
+    pub name: Option<String>, // JSON API
+    #[serde(rename = "type")]
+    pub record_type: Option<String>,
+}
+
+fn dns_message_response(response: &hickory_proto::op::Message) -> ActixResult<HttpResponse, ShadowError> {
+    let body = response.to_vec()
+        .map_err(|e| ShadowError::BadRequest(format!("Failed to encode DNS response: {}", e)))?;
+    Ok(HttpResponse::Ok()
+        .content_type(iris::DNS_MESSAGE_CONTENT_TYPE)
+        .insert_header(("Cache-Control", format!("max-age={}", iris::cache_max_age(response))))
+        .body(body))
+}
+
+/// `GET /dns-query?dns=...` (wire format) or `?name=...&type=...` (JSON)
+pub async fn dns_query_get(
+    iris: web::Data<iris::IrisResolver>,
+    olympus: web::Data<OlympusCA>,
+    query: web::Query<DnsQuery>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    if let Some(dns) = &query.dns {
+        let request = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(dns.trim_end_matches('='))
+            .map_err(|_| ShadowError::BadRequest("dns parameter must be base64url".to_string()))?;
+        let response = iris.handle_wire(&olympus, &request).await
+            .map_err(ShadowError::BadRequest)?;
+        return dns_message_response(&response);
+    }
+
+    let name = query.name.as_deref()
+        .ok_or_else(|| ShadowError::BadRequest("Either dns or name is required".to_string()))?;
+    let response = iris.handle_json(&olympus, name, query.record_type.as_deref()).await
+        .map_err(ShadowError::BadRequest)?;
+    let max_age = response.answer.iter().map(|a| a.ttl).min().unwrap_or(iris::NEGATIVE_TTL);
+
+    Ok(HttpResponse::Ok()
+        .content_type(iris::DNS_JSON_CONTENT_TYPE)
+        .insert_header(("Cache-Control", format!("max-age={}", max_age)))
+        .json(response))
+}
+
+/// `POST /dns-query` with an `application/dns-message` body
+pub async fn dns_query_post(
+    iris: web::Data<iris::IrisResolver>,
+    olympus: web::Data<OlympusCA>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let content_type = req.headers().get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with(iris::DNS_MESSAGE_CONTENT_TYPE) {
+        return Err(ShadowError::BadRequest(
+            format!("Content-Type must be {}", iris::DNS_MESSAGE_CONTENT_TYPE)
+        ));
+    }
+
+    let response = iris.handle_wire(&olympus, &body).await
+        .map_err(ShadowError::BadRequest)?;
+    dns_message_response(&response)
+}
+
 // ========== Athena Search Handlers ==========
 
 #[derive(Deserialize)]
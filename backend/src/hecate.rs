@@ -0,0 +1,556 @@
+// Hecate - Goddess of Crossroads
+// Spreads storage reads over several gateways: health scoring, hedged requests and circuit breaking
+
+use crate::config::StorageConfig;
+use crate::metrics::{GatewayOutcome, MetricsCollector};
+use crate::themis::IntegrityViolation;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Weight of the newest sample in the moving averages
+const EWMA_ALPHA: f64 = 0.2;
+// Keeps a gateway that has been failing from dividing by zero in its score
+const MIN_SUCCESS_RATE: f64 = 0.05;
+
+#[derive(Debug, Clone)]
+pub struct GatewaySettings {
+    /// How long to wait on a gateway before racing the next one
+    pub hedge_delay: Duration,
+    /// Consecutive failures that open a gateway's circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit keeps a gateway out of rotation
+    pub cooldown: Duration,
+    /// Upper bound on a single attempt
+    pub timeout: Duration,
+    /// Largest response body `read_body` will buffer
+    pub max_body_bytes: u64,
+}
+
+impl GatewaySettings {
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self {
+            hedge_delay: Duration::from_millis(config.gateway_hedge_delay_ms),
+            failure_threshold: config.gateway_failure_threshold,
+            cooldown: Duration::from_secs(config.gateway_cooldown_seconds),
+            timeout: Duration::from_secs(config.gateway_timeout_seconds),
+            max_body_bytes: config.gateway_max_body_bytes,
+        }
+    }
+}
+
+impl Default for GatewaySettings {
+    fn default() -> Self {
+        Self {
+            hedge_delay: Duration::from_millis(750),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            timeout: Duration::from_secs(30),
+            max_body_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Why one gateway could not serve a read
+#[derive(Debug)]
+pub enum GatewayError {
+    /// Network errors, timeouts and server errors; count against the gateway's health
+    Unavailable(String),
+    /// The gateway is up but does not have the content, another one may
+    Missing(String),
+    /// The gateway served bytes that failed verification; its circuit opens at once
+    Integrity(IntegrityViolation),
+    /// Verified content that cannot be used; every gateway would serve the same
+    Rejected(String),
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Unavailable(e) | GatewayError::Missing(e) | GatewayError::Rejected(e) => write!(f, "{}", e),
+            GatewayError::Integrity(violation) => write!(f, "{}", violation),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GatewayState {
+    success_rate: f64,
+    latency_ms: f64,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    // Set while the single trial request of a half-open circuit is out
+    trial_started: Option<Instant>,
+}
+
+struct Gateway {
+    url: String,
+    state: Mutex<GatewayState>,
+}
+
+/// Ordered gateways for one storage network, shared by every reader of that network
+pub struct GatewayPool {
+    network: &'static str,
+    gateways: Vec<Gateway>,
+    settings: GatewaySettings,
+    client: reqwest::Client,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl GatewayPool {
+    pub fn new(network: &'static str, urls: &[String], settings: GatewaySettings) -> Self {
+        let gateways = urls
+            .iter()
+            .map(|url| Gateway {
+                url: url.trim_end_matches('/').to_string(),
+                // Untried gateways are assumed to answer within the hedge delay
+                state: Mutex::new(GatewayState {
+                    success_rate: 1.0,
+                    latency_ms: settings.hedge_delay.as_millis() as f64,
+                    consecutive_failures: 0,
+                    open_until: None,
+                    trial_started: None,
+                }),
+            })
+            .collect();
+
+        Self {
+            network,
+            gateways,
+            settings,
+            client: reqwest::Client::new(),
+            metrics: None,
+        }
+    }
+
+    /// Report per-gateway request counts, latencies, hedges and circuit trips
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub fn max_body_bytes(&self) -> u64 {
+        self.settings.max_body_bytes
+    }
+
+    /// Run `request` against the best gateway, racing the next one whenever the
+    /// current attempts have been quiet for the hedge delay. The first success wins.
+    pub async fn fetch<T, F, Fut>(&self, request: F) -> Result<T, String>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, GatewayError>>,
+    {
+        let mut candidates = self.candidates().into_iter();
+        let mut in_flight = FuturesUnordered::new();
+        let mut errors = Vec::new();
+
+        match candidates.by_ref().find_map(|index| self.attempt(index, &request)) {
+            Some(attempt) => in_flight.push(attempt),
+            None => return Err(format!("Every {} gateway is unavailable", self.network)),
+        }
+
+        while !in_flight.is_empty() {
+            let hedge = tokio::time::sleep(self.settings.hedge_delay);
+            tokio::select! {
+                Some((index, result, elapsed)) = in_flight.next() => {
+                    let url = &self.gateways[index].url;
+                    match result {
+                        Ok(value) => {
+                            self.record(index, GatewayOutcome::Success, elapsed);
+                            return Ok(value);
+                        }
+                        Err(GatewayError::Rejected(e)) => {
+                            self.record(index, GatewayOutcome::Success, elapsed);
+                            return Err(e);
+                        }
+                        Err(GatewayError::Missing(e)) => {
+                            self.record(index, GatewayOutcome::NotFound, elapsed);
+                            errors.push(format!("{}: {}", url, e));
+                        }
+                        Err(GatewayError::Unavailable(e)) => {
+                            self.record(index, GatewayOutcome::Failure, elapsed);
+                            errors.push(format!("{}: {}", url, e));
+                        }
+                        Err(GatewayError::Integrity(violation)) => {
+                            self.record(index, GatewayOutcome::IntegrityFailure, elapsed);
+                            errors.push(violation.report());
+                        }
+                    }
+                    if let Some(attempt) = candidates.by_ref().find_map(|index| self.attempt(index, &request)) {
+                        in_flight.push(attempt);
+                    }
+                }
+                _ = hedge, if candidates.len() > 0 => {
+                    let next = candidates.by_ref()
+                        .find_map(|index| self.attempt(index, &request).map(|attempt| (index, attempt)));
+                    if let Some((index, attempt)) = next {
+                        if let Some(metrics) = &self.metrics {
+                            metrics.record_gateway_hedge(&self.gateways[index].url);
+                        }
+                        in_flight.push(attempt);
+                    }
+                }
+            }
+        }
+
+        Err(format!("No {} gateway could serve the content: {}", self.network, errors.join("; ")))
+    }
+
+    /// Send `request` to one gateway, or None if it is half-open and another read
+    /// already holds its trial
+    fn attempt<T, F, Fut>(&self, index: usize, request: &F) -> Option<impl Future<Output = (usize, Result<T, GatewayError>, Duration)>>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, GatewayError>>,
+    {
+        if !self.claim_trial(index) {
+            return None;
+        }
+
+        let timeout = self.settings.timeout;
+        let response = request(self.gateways[index].url.clone());
+        Some(async move {
+            let started = Instant::now();
+            let result = tokio::time::timeout(timeout, response)
+                .await
+                .unwrap_or_else(|_| Err(GatewayError::Unavailable("Timed out".to_string())));
+            (index, result, started.elapsed())
+        })
+    }
+
+    /// Closed circuits always take requests; a cooled-down open one takes a single trial
+    fn claim_trial(&self, index: usize) -> bool {
+        let now = Instant::now();
+        let mut state = self.gateways[index].state.lock().unwrap();
+        let Some(open_until) = state.open_until else {
+            return true;
+        };
+        if now < open_until || trial_out(&state, now, self.settings.timeout) {
+            return false;
+        }
+        state.trial_started = Some(now);
+        true
+    }
+
+    /// Gateways to try, best score first. Open circuits sit out their cooldown and then
+    /// get a single trial request; ties keep the configured order.
+    fn candidates(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut scored = Vec::with_capacity(self.gateways.len());
+
+        for (index, gateway) in self.gateways.iter().enumerate() {
+            let state = gateway.state.lock().unwrap();
+            if let Some(open_until) = state.open_until {
+                if now < open_until || trial_out(&state, now, self.settings.timeout) {
+                    continue;
+                }
+            }
+            scored.push((index, score(&state)));
+        }
+
+        scored.sort_by(|a, b| a.1.total_cmp(&b.1));
+        scored.into_iter().map(|(index, _)| index).collect()
+    }
+
+    fn record(&self, index: usize, outcome: GatewayOutcome, elapsed: Duration) {
+        let gateway = &self.gateways[index];
+        let elapsed_ms = elapsed.as_millis() as u64;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_gateway_request(&gateway.url, outcome, elapsed_ms);
+        }
+
+        let mut state = gateway.state.lock().unwrap();
+        state.latency_ms += EWMA_ALPHA * (elapsed_ms as f64 - state.latency_ms);
+
+        let opened = match outcome {
+            // A gateway that answers, even without the content, is healthy
+            GatewayOutcome::Success | GatewayOutcome::NotFound => {
+                if outcome == GatewayOutcome::Success {
+                    state.success_rate += EWMA_ALPHA * (1.0 - state.success_rate);
+                }
+                if state.open_until.take().is_some() {
+                    tracing::info!("{} gateway {} recovered", self.network, gateway.url);
+                }
+                state.consecutive_failures = 0;
+                state.trial_started = None;
+                false
+            }
+            GatewayOutcome::Failure => {
+                state.success_rate -= EWMA_ALPHA * state.success_rate;
+                state.consecutive_failures += 1;
+                // A failed trial goes straight back to cooling down
+                state.open_until.is_some() || state.consecutive_failures >= self.settings.failure_threshold
+            }
+            GatewayOutcome::IntegrityFailure => {
+                state.success_rate = 0.0;
+                state.consecutive_failures += 1;
+                true
+            }
+        };
+
+        if opened {
+            state.open_until = Some(Instant::now() + self.settings.cooldown);
+            state.trial_started = None;
+            tracing::warn!(
+                "{} gateway {} taken out of rotation for {:?} after {} failures",
+                self.network, gateway.url, self.settings.cooldown, state.consecutive_failures
+            );
+            if let Some(metrics) = &self.metrics {
+                metrics.record_gateway_circuit_open(&gateway.url);
+            }
+        }
+    }
+}
+
+/// Expected time to a successful read; lower is better
+fn score(state: &GatewayState) -> f64 {
+    state.latency_ms / state.success_rate.max(MIN_SUCCESS_RATE)
+}
+
+/// Whether a half-open gateway's trial request is still out
+fn trial_out(state: &GatewayState, now: Instant, timeout: Duration) -> bool {
+    state.trial_started.is_some_and(|started| now.duration_since(started) < timeout)
+}
+
+/// Send a gateway request, sorting failures into missing content and unavailable gateways
+pub async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, GatewayError> {
+    let response = request.send()
+        .await
+        .map_err(|e| GatewayError::Unavailable(format!("Request failed: {}", e)))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::GONE {
+        Err(GatewayError::Missing(format!("Gateway returned {}", status)))
+    } else {
+        Err(GatewayError::Unavailable(format!("Gateway returned {}", status)))
+    }
+}
+
+/// Read a response body, giving up as soon as it passes `max_bytes`
+///
+/// The bytes are unverified at this point, so an oversized body counts against
+/// the gateway that sent it and the next one is asked.
+pub async fn read_body(mut response: reqwest::Response, max_bytes: u64) -> Result<Vec<u8>, GatewayError> {
+    let too_large = || GatewayError::Unavailable(format!("Response is larger than {} bytes", max_bytes));
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk()
+        .await
+        .map_err(|e| GatewayError::Unavailable(format!("Failed to read response: {}", e)))?
+    {
+        if (body.len() + chunk.len()) as u64 > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn pool(urls: &[&str], settings: GatewaySettings) -> GatewayPool {
+        let urls: Vec<String> = urls.iter().map(|url| url.to_string()).collect();
+        GatewayPool::new("ipfs", &urls, settings).with_metrics(Arc::new(MetricsCollector::new()))
+    }
+
+    fn settings() -> GatewaySettings {
+        GatewaySettings {
+            hedge_delay: Duration::from_millis(50),
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+            timeout: Duration::from_secs(5),
+            max_body_bytes: 1024,
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_next_gateway() {
+        let pool = pool(&["https://down.example", "https://up.example/"], settings());
+
+        let body = pool.fetch(|url| async move {
+            match url.as_str() {
+                "https://up.example" => Ok("content"),
+                _ => Err(GatewayError::Unavailable("connection refused".to_string())),
+            }
+        }).await;
+        assert_eq!(body, Ok("content"));
+
+        let metrics = pool.metrics.as_ref().unwrap().get_metrics();
+        let down = metrics.gateways.iter().find(|g| g.gateway == "https://down.example").unwrap();
+        assert_eq!((down.requests, down.failures), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn hedges_slow_gateways() {
+        let pool = pool(&["https://slow.example", "https://fast.example"], settings());
+
+        let started = Instant::now();
+        let body = pool.fetch(|url| async move {
+            if url == "https://slow.example" {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+            Ok::<_, GatewayError>(url)
+        }).await;
+
+        assert_eq!(body.as_deref(), Ok("https://fast.example"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let metrics = pool.metrics.as_ref().unwrap().get_metrics();
+        assert_eq!(metrics.gateways.iter().map(|g| g.hedged_requests).sum::<u64>(), 1);
+    }
+
+    #[tokio::test]
+    async fn opens_the_circuit_of_failing_gateways() {
+        let pool = pool(&["https://flaky.example"], settings());
+        let calls = AtomicUsize::new(0);
+
+        for _ in 0..4 {
+            let body = pool.fetch(|_| {
+                let calls = &calls;
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err::<(), _>(GatewayError::Unavailable("502".to_string()))
+                }
+            }).await;
+            assert!(body.is_err());
+        }
+
+        // Two failures trip the breaker, after which the gateway is skipped
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(pool.candidates().is_empty());
+        let metrics = pool.metrics.as_ref().unwrap().get_metrics();
+        assert_eq!(metrics.gateways[0].circuit_opens, 1);
+    }
+
+    #[tokio::test]
+    async fn lying_gateways_are_dropped_at_once() {
+        let pool = pool(&["https://liar.example", "https://honest.example"], settings());
+
+        let body = pool.fetch(|url| async move {
+            if url == "https://liar.example" {
+                return Err(GatewayError::Integrity(IntegrityViolation::new(&url, "bafy", "Block does not hash to bafy")));
+            }
+            Ok("verified")
+        }).await;
+
+        assert_eq!(body, Ok("verified"));
+        assert_eq!(pool.candidates(), vec![1]);
+        let metrics = pool.metrics.as_ref().unwrap().get_metrics();
+        let liar = metrics.gateways.iter().find(|g| g.gateway == "https://liar.example").unwrap();
+        assert_eq!((liar.integrity_failures, liar.circuit_opens), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn half_open_circuits_get_one_trial() {
+        let pool = pool(&["https://primary.example", "https://backup.example"], GatewaySettings {
+            cooldown: Duration::ZERO,
+            ..settings()
+        });
+        pool.record(0, GatewayOutcome::IntegrityFailure, Duration::from_millis(10));
+
+        // Cooldown is over: listing candidates hands nothing out, sending the trial does
+        assert!(pool.candidates().contains(&0));
+        assert!(pool.candidates().contains(&0));
+        assert!(pool.claim_trial(0));
+        assert!(!pool.claim_trial(0));
+        assert!(!pool.candidates().contains(&0));
+
+        pool.record(0, GatewayOutcome::Success, Duration::from_millis(10));
+        assert!(pool.candidates().contains(&0));
+    }
+
+    #[tokio::test]
+    async fn untried_gateways_keep_their_trial() {
+        let pool = pool(&["https://primary.example", "https://backup.example"], GatewaySettings {
+            cooldown: Duration::ZERO,
+            ..settings()
+        });
+        pool.record(0, GatewayOutcome::IntegrityFailure, Duration::from_millis(10));
+
+        // The backup ranks first and answers before the half-open primary is ever asked
+        let body = pool.fetch(|url| async move { Ok::<_, GatewayError>(url) }).await;
+        assert_eq!(body.as_deref(), Ok("https://backup.example"));
+        assert!(pool.gateways[0].state.lock().unwrap().trial_started.is_none());
+        assert!(pool.candidates().contains(&0));
+    }
+
+    /// Serve `body` once, without a Content-Length so only counting can cap it
+    async fn serve_once(body: &'static [u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+            let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n").await;
+            let _ = socket.write_all(body).await;
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn caps_response_bodies() {
+        let url = serve_once(b"0123456789").await;
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(read_body(response, 10).await.unwrap(), b"0123456789");
+
+        let url = serve_once(b"0123456789").await;
+        let response = reqwest::get(&url).await.unwrap();
+        assert!(matches!(read_body(response, 4).await, Err(GatewayError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_fall_through_to_the_next_gateway() {
+        let urls = [serve_once(b"0123456789").await, serve_once(b"0123").await];
+        let pool = pool(&[urls[0].as_str(), urls[1].as_str()], GatewaySettings {
+            hedge_delay: Duration::from_secs(5),
+            ..settings()
+        });
+
+        let body = pool.fetch(|url| async move {
+            let response = reqwest::get(&url).await.map_err(|e| GatewayError::Unavailable(e.to_string()))?;
+            read_body(response, 4).await
+        }).await;
+        assert_eq!(body.as_deref(), Ok(b"0123".as_slice()));
+        assert_eq!(pool.gateways[0].state.lock().unwrap().consecutive_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_content_stops_the_search() {
+        let pool = pool(&["https://a.example", "https://b.example"], settings());
+        let calls = AtomicUsize::new(0);
+
+        let body: Result<(), String> = pool.fetch(|_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Err(GatewayError::Rejected("is a directory".to_string())) }
+        }).await;
+
+        assert_eq!(body, Err("is a directory".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn ranks_gateways_by_expected_latency() {
+        let pool = pool(&["https://slow.example", "https://fast.example"], settings());
+        for _ in 0..10 {
+            pool.record(0, GatewayOutcome::Success, Duration::from_millis(900));
+            pool.record(1, GatewayOutcome::Success, Duration::from_millis(100));
+        }
+        assert_eq!(pool.candidates(), vec![1, 0]);
+    }
+}
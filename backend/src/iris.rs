@@ -0,0 +1,542 @@
+// Iris - Messenger between Gods and Mortals
+// Answers DNS queries for .shadow names (RFC 8484 DNS-over-HTTPS) and
+// looks up TXT challenges for custom domains
+
+use hickory_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_proto::rr::rdata::{A, AAAA, CNAME, SOA, TXT};
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use crate::config::DnsConfig;
+use crate::olympus::{self, OlympusCA, Resolution, ResourceRecord};
+
+/// RFC 8484 media type for wire-format messages
+pub const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+/// JSON flavour used by Google and Cloudflare resolvers
+pub const DNS_JSON_CONTENT_TYPE: &str = "application/dns-json";
+/// Largest DNS message we accept or produce
+pub const MAX_DNS_MESSAGE_SIZE: usize = 65_535;
+/// How long resolvers may cache NXDOMAIN and empty answers
+pub const NEGATIVE_TTL: u32 = 60;
+/// Longest CNAME chain followed inside .shadow
+const MAX_CNAME_HOPS: usize = 8;
+/// Character strings in TXT rdata are length-prefixed with one byte
+const MAX_TXT_STRING: usize = 255;
+/// Zone every .shadow name is answered from, and its SOA names
+const SHADOW_ZONE: &str = "shadow.";
+const SOA_MNAME: &str = "ns.shadow.";
+const SOA_RNAME: &str = "hostmaster.shadow.";
+
+pub struct IrisResolver {
+    gateway_ipv4: Vec<Ipv4Addr>,
+    gateway_ipv6: Vec<Ipv6Addr>,
+    upstream_url: Option<String>,
+    http: reqwest::Client,
+}
+
+/// Google-style JSON answer (`application/dns-json`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct JsonResponse {
+    pub status: u16,
+    #[serde(rename = "TC")]
+    pub tc: bool,
+    #[serde(rename = "RD")]
+    pub rd: bool,
+    #[serde(rename = "RA")]
+    pub ra: bool,
+    #[serde(rename = "AD")]
+    pub ad: bool,
+    #[serde(rename = "CD")]
+    pub cd: bool,
+    pub question: Vec<JsonQuestion>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answer: Vec<JsonAnswer>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authority: Vec<JsonAnswer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonQuestion {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonAnswer {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: u16,
+    #[serde(rename = "TTL")]
+    pub ttl: u32,
+    pub data: String,
+}
+
+impl IrisResolver {
+    pub fn new(config: &DnsConfig) -> Self {
+        Self {
+            gateway_ipv4: config.gateway_ipv4.clone(),
+            gateway_ipv6: config.gateway_ipv6.clone(),
+            upstream_url: config.upstream_url.clone(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Answer a wire-format query
+    pub async fn handle_wire(&self, olympus: &OlympusCA, request: &[u8]) -> Result<Message, String> {
+        if request.len() > MAX_DNS_MESSAGE_SIZE {
+            return Err("DNS message too large".to_string());
+        }
+        let request = Message::from_vec(request)
+            .map_err(|e| format!("Malformed DNS message: {}", e))?;
+        Ok(self.handle(olympus, &request).await)
+    }
+
+    /// Answer a JSON API query such as `?name=mysite.shadow&type=AAAA`
+    pub async fn handle_json(&self, olympus: &OlympusCA, name: &str, record_type: Option<&str>) -> Result<JsonResponse, String> {
+        let name = Name::from_ascii(name)
+            .map_err(|e| format!("Invalid name: {}", e))?;
+        let record_type = parse_record_type(record_type.unwrap_or("A"))?;
+
+        let mut request = Message::new();
+        request
+            .set_id(0)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .add_query(Query::query(name, record_type));
+
+        Ok(to_json(&self.handle(olympus, &request).await))
+    }
+
+    /// Answer .shadow questions ourselves and forward the rest upstream, if configured
+    pub async fn handle(&self, olympus: &OlympusCA, request: &Message) -> Message {
+        let mut response = response_to(request);
+
+        // Browsers and stub resolvers only ever send one question
+        let [query] = request.queries() else {
+            response.set_response_code(ResponseCode::FormErr);
+            return response;
+        };
+        if request.op_code() != OpCode::Query {
+            response.set_response_code(ResponseCode::NotImp);
+            return response;
+        }
+
+        let name = query.name().to_lowercase();
+        if !is_shadow_name(&name) {
+            return match &self.upstream_url {
+                Some(url) => self.forward(url, request).await.unwrap_or_else(|e| {
+                    tracing::warn!("Upstream DNS query failed: {}", e);
+                    let mut response = response_to(request);
+                    response.set_response_code(ResponseCode::ServFail);
+                    response
+                }),
+                None => {
+                    response.set_response_code(ResponseCode::Refused);
+                    response
+                }
+            };
+        }
+
+        response.set_authoritative(true);
+        match self.answer(olympus, &name, query.query_type()).await {
+            Ok(Some(records)) => {
+                if records.is_empty() {
+                    response.add_name_server(shadow_soa());
+                }
+                response.add_answers(records);
+            }
+            Ok(None) => {
+                response.set_response_code(ResponseCode::NXDomain);
+                response.add_name_server(shadow_soa());
+            }
+            Err(e) => {
+                tracing::warn!("Failed to resolve {}: {}", name, e);
+                response.set_response_code(ResponseCode::ServFail);
+            }
+        }
+        response
+    }
+
+    /// Answer records for a .shadow name, following CNAMEs that stay in .shadow
+    ///
+    /// `None` means the name doesn't exist. An existing name without
+    /// records of the asked type gets an empty answer (NODATA).
+    async fn answer(&self, olympus: &OlympusCA, name: &Name, record_type: RecordType) -> Result<Option<Vec<Record>>, String> {
+        let mut answers = Vec::new();
+        let mut current = name.clone();
+
+        for _ in 0..MAX_CNAME_HOPS {
+            let Some(resolution) = olympus.resolve(&dotless(&current)).await? else {
+                // A dangling alias still answers with the alias itself
+                return Ok((!answers.is_empty()).then_some(answers));
+            };
+
+            match alias_target(&resolution) {
+                Some((target, ttl)) => {
+                    answers.push(Record::from_rdata(current.clone(), ttl, RData::CNAME(CNAME(target.clone()))));
+                    if record_type == RecordType::CNAME || !is_shadow_name(&target) {
+                        return Ok(Some(answers));
+                    }
+                    current = target;
+                }
+                None => {
+                    answers.extend(self.records_for(&current, &resolution, record_type));
+                    return Ok(Some(answers));
+                }
+            }
+        }
+
+        Err(format!("CNAME chain from {} is too long", name))
+    }
+
+    /// Records of `record_type` for a resolved name
+    ///
+    /// Every name is hosted by the gateway, so A/AAAA are the gateway's
+    /// addresses. Shadow records are also published as TXT so DNS-only
+    /// clients can find the program, content and wallet behind a name.
+    fn records_for(&self, name: &Name, resolution: &Resolution, record_type: RecordType) -> Vec<Record> {
+        let ttl = resolution.records.iter().map(|r| r.ttl).min().unwrap_or(olympus::DEFAULT_RECORD_TTL);
+        let record = |ttl, rdata| Record::from_rdata(name.clone(), ttl, rdata);
+
+        match record_type {
+            RecordType::A => self.gateway_ipv4.iter().map(|ip| record(ttl, RData::A(A(*ip)))).collect(),
+            RecordType::AAAA => self.gateway_ipv6.iter().map(|ip| record(ttl, RData::AAAA(AAAA(*ip)))).collect(),
+            RecordType::TXT => resolution.records.iter()
+                .map(|r| record(r.ttl, RData::TXT(txt_rdata(&txt_value(r)))))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    async fn forward(&self, url: &str, request: &Message) -> Result<Message, String> {
+        let body = request.to_vec()
+            .map_err(|e| format!("Failed to encode DNS message: {}", e))?;
+        let mut response = self.http.post(url)
+            .header("content-type", DNS_MESSAGE_CONTENT_TYPE)
+            .header("accept", DNS_MESSAGE_CONTENT_TYPE)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("Upstream request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Upstream returned {}", response.status()));
+        }
+
+        // Nothing longer could be a DNS message, so don't buffer past it
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| format!("Upstream read failed: {}", e))?
+        {
+            if bytes.len() + chunk.len() > MAX_DNS_MESSAGE_SIZE {
+                return Err("Upstream response too large".to_string());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Message::from_vec(&bytes)
+            .map_err(|e| format!("Malformed upstream response: {}", e))
+    }
+}
+
+/// TXT lookups for domain ownership challenges
+///
+/// `Ok(None)` means the name or record doesn't exist; `Err` is a lookup
+/// failure that says nothing either way and should be retried.
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    async fn lookup_txt(&self, name: &str) -> Result<Option<Vec<String>>, String>;
+}
+
+/// Live DNS lookups, uncached so a removed record is noticed right away
+pub struct DnsTxtResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsTxtResolver {
+    /// Query `nameservers`, or the system's resolvers when none are given
+    pub fn new(nameservers: &[IpAddr]) -> Self {
+        let mut options = ResolverOpts::default();
+        options.cache_size = 0;
+        options.timeout = Duration::from_secs(5);
+
+        let config = if !nameservers.is_empty() {
+            ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from_ips_clear(nameservers, 53, true))
+        } else {
+            match hickory_resolver::system_conf::read_system_conf() {
+                Ok((config, _)) => config,
+                Err(e) => {
+                    tracing::warn!("No system DNS config ({}), using Cloudflare", e);
+                    ResolverConfig::cloudflare()
+                }
+            }
+        };
+
+        Self { resolver: TokioAsyncResolver::tokio(config, options) }
+    }
+}
+
+#[async_trait]
+impl TxtResolver for DnsTxtResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Option<Vec<String>>, String> {
+        match self.resolver.txt_lookup(name).await {
+            // Multi-string records are one value split for length; join them back
+            Ok(lookup) => Ok(Some(lookup.iter()
+                .map(|txt| txt.txt_data().iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect::<String>())
+                .collect())),
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => Ok(None),
+                _ => Err(format!("TXT lookup for {} failed: {}", name, e)),
+            },
+        }
+    }
+}
+
+/// Fixed TXT answers for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct StaticTxtResolver {
+    records: std::sync::RwLock<std::collections::HashMap<String, Vec<String>>>,
+}
+
+#[cfg(test)]
+impl StaticTxtResolver {
+    pub fn set(&self, name: &str, values: Vec<String>) {
+        self.records.write().unwrap().insert(name.to_string(), values);
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.records.write().unwrap().remove(name);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl TxtResolver for StaticTxtResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Option<Vec<String>>, String> {
+        Ok(self.records.read().unwrap().get(name).cloned())
+    }
+}
+
+/// Seconds an HTTP cache may keep this response (RFC 8484 §5.1)
+pub fn cache_max_age(response: &Message) -> u32 {
+    response.answers().iter()
+        .map(|r| r.ttl())
+        .min()
+        .unwrap_or(NEGATIVE_TTL)
+}
+
+pub fn to_json(response: &Message) -> JsonResponse {
+    JsonResponse {
+        status: u16::from(response.response_code()),
+        tc: response.truncated(),
+        rd: response.recursion_desired(),
+        ra: response.recursion_available(),
+        ad: response.authentic_data(),
+        cd: response.checking_disabled(),
+        question: response.queries().iter()
+            .map(|q| JsonQuestion {
+                name: q.name().to_ascii(),
+                record_type: u16::from(q.query_type()),
+            })
+            .collect(),
+        answer: response.answers().iter().map(json_answer).collect(),
+        authority: response.name_servers().iter().map(json_answer).collect(),
+    }
+}
+
+fn json_answer(record: &Record) -> JsonAnswer {
+    JsonAnswer {
+        name: record.name().to_ascii(),
+        record_type: u16::from(record.record_type()),
+        ttl: record.ttl(),
+        data: record.data().map(|d| d.to_string()).unwrap_or_default(),
+    }
+}
+
+/// Accept mnemonics ("AAAA") as well as numeric types ("28")
+fn parse_record_type(value: &str) -> Result<RecordType, String> {
+    match value.parse::<u16>() {
+        Ok(code) => Ok(RecordType::from(code)),
+        Err(_) => value.to_ascii_uppercase().parse::<RecordType>()
+            .map_err(|_| format!("Unknown record type {}", value)),
+    }
+}
+
+fn response_to(request: &Message) -> Message {
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_recursion_desired(request.recursion_desired())
+        .set_checking_disabled(request.checking_disabled())
+        .add_queries(request.queries().to_vec());
+    response
+}
+
+fn is_shadow_name(name: &Name) -> bool {
+    name.iter().next_back().is_some_and(|tld| tld.eq_ignore_ascii_case(b"shadow"))
+}
+
+/// Zone SOA for the authority section of NXDOMAIN and NODATA answers, whose
+/// TTL and minimum tell resolvers how long to cache them (RFC 2308 §3)
+fn shadow_soa() -> Record {
+    let name = |name| Name::from_ascii(name).expect("constant zone names are valid");
+    // Zone transfers aren't offered, so the serial never has to move
+    let soa = SOA::new(name(SOA_MNAME), name(SOA_RNAME), 1, 3600, 600, 86_400, NEGATIVE_TTL);
+    Record::from_rdata(name(SHADOW_ZONE), NEGATIVE_TTL, RData::SOA(soa))
+}
+
+/// `app.mysite.shadow.` as stored by Olympus: lowercase, no trailing dot
+fn dotless(name: &Name) -> String {
+    name.to_lowercase().to_ascii().trim_end_matches('.').to_string()
+}
+
+fn alias_target(resolution: &Resolution) -> Option<(Name, u32)> {
+    resolution.records.iter()
+        .find(|r| r.record_type == olympus::RecordType::Cname)
+        .and_then(|r| {
+            let mut target = Name::from_ascii(&r.value).ok()?;
+            target.set_fqdn(true);
+            Some((target, r.ttl))
+        })
+}
+
+fn txt_value(record: &ResourceRecord) -> String {
+    match record.record_type {
+        olympus::RecordType::Txt => record.value.clone(),
+        olympus::RecordType::Program => format!("shadow-program={}", record.value),
+        olympus::RecordType::Content => format!("shadow-content={}", record.value),
+        olympus::RecordType::Redirect => format!("shadow-redirect={}", record.value),
+        olympus::RecordType::Wallet => format!("shadow-wallet={}", record.value),
+        olympus::RecordType::Cname => format!("shadow-cname={}", record.value),
+    }
+}
+
+/// Split long values across 255-byte character strings
+fn txt_rdata(value: &str) -> TXT {
+    TXT::from_bytes(value.as_bytes().chunks(MAX_TXT_STRING).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver() -> IrisResolver {
+        IrisResolver::new(&DnsConfig {
+            gateway_ipv4: vec![Ipv4Addr::new(203, 0, 113, 7)],
+            gateway_ipv6: vec![],
+            upstream_url: None,
+            challenge_nameservers: vec![],
+        })
+    }
+
+    fn resolution(records: Vec<ResourceRecord>) -> Resolution {
+        Resolution {
+            name: "app.alice.shadow".to_string(),
+            zone: "alice.shadow".to_string(),
+            owner_pubkey: "owner".to_string(),
+            manager: "owner".to_string(),
+            matched: "app.alice.shadow".to_string(),
+            records,
+        }
+    }
+
+    fn record(record_type: olympus::RecordType, value: &str, ttl: u32) -> ResourceRecord {
+        ResourceRecord { record_type, value: value.to_string(), ttl }
+    }
+
+    #[test]
+    fn answers_gateway_addresses_and_txt() {
+        let name = Name::from_ascii("app.alice.shadow.").unwrap();
+        let res = resolution(vec![
+            record(olympus::RecordType::Txt, "hello", 120),
+            record(olympus::RecordType::Wallet, "11111111111111111111111111111111", 600),
+        ]);
+        let iris = resolver();
+
+        let a = iris.records_for(&name, &res, RecordType::A);
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].data(), Some(&RData::A(A(Ipv4Addr::new(203, 0, 113, 7)))));
+        assert_eq!(a[0].ttl(), 120);
+
+        assert!(iris.records_for(&name, &res, RecordType::AAAA).is_empty());
+
+        let txt: Vec<String> = iris.records_for(&name, &res, RecordType::TXT)
+            .iter()
+            .map(|r| r.data().unwrap().to_string())
+            .collect();
+        assert_eq!(txt, vec!["hello", "shadow-wallet=11111111111111111111111111111111"]);
+    }
+
+    #[test]
+    fn splits_long_txt_values() {
+        let txt = txt_rdata(&"x".repeat(600));
+        let lengths: Vec<usize> = txt.iter().map(|s| s.len()).collect();
+        assert_eq!(lengths, vec![255, 255, 90]);
+    }
+
+    #[test]
+    fn recognises_shadow_names_and_types() {
+        assert!(is_shadow_name(&Name::from_ascii("App.Alice.SHADOW.").unwrap()));
+        assert!(!is_shadow_name(&Name::from_ascii("example.com.").unwrap()));
+        assert_eq!(dotless(&Name::from_ascii("App.Alice.shadow.").unwrap()), "app.alice.shadow");
+
+        assert_eq!(parse_record_type("aaaa").unwrap(), RecordType::AAAA);
+        assert_eq!(parse_record_type("16").unwrap(), RecordType::TXT);
+        assert!(parse_record_type("bogus").is_err());
+    }
+
+    #[test]
+    fn round_trips_wire_responses() {
+        let mut request = Message::new();
+        request
+            .set_id(4242)
+            .set_recursion_desired(true)
+            .add_query(Query::query(Name::from_ascii("nope.example.").unwrap(), RecordType::A));
+
+        let mut response = response_to(&request);
+        response.set_response_code(ResponseCode::NXDomain);
+        response.add_name_server(shadow_soa());
+        let decoded = Message::from_vec(&response.to_vec().unwrap()).unwrap();
+
+        assert_eq!(decoded.id(), 4242);
+        assert_eq!(decoded.message_type(), MessageType::Response);
+        assert_eq!(decoded.response_code(), ResponseCode::NXDomain);
+        assert_eq!(decoded.queries().len(), 1);
+        assert_eq!(cache_max_age(&decoded), NEGATIVE_TTL);
+
+        let json = to_json(&decoded);
+        assert_eq!(json.status, 3);
+        assert_eq!(json.question[0].name, "nope.example.");
+        assert_eq!(json.question[0].record_type, 1);
+        assert_eq!(json.authority[0].name, SHADOW_ZONE);
+        assert_eq!(json.authority[0].record_type, u16::from(RecordType::SOA));
+    }
+
+    #[test]
+    fn negative_answers_carry_the_zone_soa() {
+        let soa = shadow_soa();
+        assert_eq!(soa.name(), &Name::from_ascii(SHADOW_ZONE).unwrap());
+        assert_eq!(soa.ttl(), NEGATIVE_TTL);
+        match soa.data() {
+            Some(RData::SOA(soa)) => {
+                assert_eq!(soa.minimum(), NEGATIVE_TTL);
+                assert_eq!(soa.mname(), &Name::from_ascii(SOA_MNAME).unwrap());
+            }
+            other => panic!("expected an SOA, got {:?}", other),
+        }
+    }
+}
@@ -9,6 +9,7 @@ mod solana_ws;
 mod anchor_client;
 mod ares;
 mod olympus;
+mod iris;
 mod apollo;
 mod artemis;
 mod athena;
@@ -25,9 +26,17 @@ mod dionysus;
 mod aphrodite;
 mod hestia;
 mod plutus;
+mod price_oracle;
 mod hades;
 mod wallet_handlers;
+mod mnemosyne;
+mod daedalus;
+mod themis;
+mod hecate;
 mod link_converter;
+mod atlas;
+mod charon;
+mod upload_handlers;
 
 #[path = "handlers_link.rs"]
 mod handlers_link;
@@ -66,6 +75,12 @@ async fn main() -> anyhow::Result<()> {
         .keys(mongodb::bson::doc! { "created_at": -1 })
         .build();
     sites_collection.create_index(sites_index, None).await?;
+
+    let deployments_collection = db.collection::<db::SiteDeployment>("site_deployments");
+    let deployments_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "program_address": 1, "version": -1 })
+        .build();
+    deployments_collection.create_index(deployments_index, None).await?;
     
     // Create indexes for Olympus domains
     let domains_collection = db.collection::<olympus::Domain>("domains");
@@ -79,6 +94,43 @@ async fn main() -> anyhow::Result<()> {
         .build();
     domains_collection.create_index(domains_program_index, None).await?;
 
+    let domains_skeleton_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "skeleton": 1 })
+        .build();
+    domains_collection.create_index(domains_skeleton_index, None).await?;
+
+    let records_collection = db.collection::<olympus::RecordSet>("domain_records");
+    let records_zone_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "zone": 1 })
+        .build();
+    records_collection.create_index(records_zone_index, None).await?;
+
+    let transfers_collection = db.collection::<olympus::DomainTransfer>("domain_transfers");
+    let transfers_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "domain": 1, "at": -1 })
+        .build();
+    transfers_collection.create_index(transfers_index, None).await?;
+
+    let primaries_collection = db.collection::<olympus::PrimaryName>("primary_names");
+    let primaries_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "domain": 1 })
+        .build();
+    primaries_collection.create_index(primaries_index, None).await?;
+
+    // Resolved NFT metadata is refetched once stale, so mutable metadata catches up
+    let nft_metadata_collection = db.collection::<aphrodite::CachedNftMetadata>("nft_metadata");
+    let nft_metadata_ttl_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "cached_at": 1 })
+        .options(mongodb::options::IndexOptions::builder().expire_after(aphrodite::METADATA_CACHE_TTL).build())
+        .build();
+    nft_metadata_collection.create_index(nft_metadata_ttl_index, None).await?;
+
+    let indexed_transactions = db.collection::<mnemosyne::IndexedTransaction>("indexed_transactions");
+    let indexed_transactions_index = IndexModel::builder()
+        .keys(mongodb::bson::doc! { "slot": 1 })
+        .build();
+    indexed_transactions.create_index(indexed_transactions_index, None).await?;
+
     let solana_rpc_url = env::var("SOLANA_RPC_URL")
         .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
     
@@ -145,6 +197,104 @@ async fn main() -> anyhow::Result<()> {
     let config = config::ShadowConfig::from_env()
         .map_err(|e| anyhow::anyhow!("Config error: {}", e))?;
     
+    // Initialize price oracle for Plutus portfolio valuation
+    let price_oracle = Arc::new(
+        price_oracle::PriceOracle::from_config(&config.pricing, &solana_rpc_url)
+            .map_err(|e| anyhow::anyhow!("Price oracle error: {}", e))?
+    );
+    
+    // Keep the Olympus domains cache in step with the shadow-domains program
+    olympus::spawn_domain_sync(
+        (*db).clone(),
+        Arc::clone(&anchor_client),
+        Arc::clone(&hermes_broker),
+        config.domains.reserved_names.clone(),
+        std::time::Duration::from_secs(config.domains.sync_interval_seconds),
+    );
+
+    // Mirror the registry and profiles programs into sites, site_deployments and users
+    if config.indexer.enabled {
+        mnemosyne::spawn_indexer(
+            (*db).clone(),
+            Arc::clone(&anchor_client),
+            solana_rpc_url.clone(),
+            std::time::Duration::from_secs(config.indexer.poll_interval_seconds),
+        );
+    }
+
+    // Warn owners before their names expire and release lapsed ones
+    let sweeper_keypair = match &config.domains.sweeper_keypair_path {
+        Some(path) => match solana_sdk::signature::read_keypair_file(path) {
+            Ok(keypair) => Some(keypair),
+            Err(e) => {
+                tracing::warn!("Domain sweeper keypair {} not loaded, lapsed names won't be released on-chain: {}", path, e);
+                None
+            }
+        },
+        None => None,
+    };
+    olympus::spawn_domain_sweeper(
+        (*db).clone(),
+        Arc::clone(&anchor_client),
+        Arc::clone(&hermes_broker),
+        sweeper_keypair,
+        std::time::Duration::from_secs(config.domains.sweep_interval_seconds),
+    );
+    
+    // Start Plutus portfolio snapshots for value history and P&L
+    plutus::spawn_snapshot_job(
+        Arc::clone(&db),
+        solana_rpc_url.clone(),
+        config.solana.das_rpc_url.clone(),
+        Arc::clone(&price_oracle),
+        std::time::Duration::from_secs(config.pricing.snapshot_interval_seconds),
+    );
+    
+    // Storage providers by URI scheme; reads go through gateway pools shared by every worker
+    let gateway_settings = hecate::GatewaySettings::from_config(&config.storage);
+    let bundlr = storage::BundlrStorage::new()
+        .with_gateways(Arc::new(
+            hecate::GatewayPool::new("arweave", &config.storage.arweave_gateways, gateway_settings.clone())
+                .with_metrics(Arc::clone(&metrics)),
+        ))
+        .with_funding(&config.solana.rpc_url, config.storage.bundlr_auto_fund);
+    let mut storage_registry = storage::StorageRegistry::new().with_provider(Arc::new(bundlr));
+    storage_registry = match &config.storage.kubo_api_url {
+        Some(api_url) => storage_registry.with_provider(Arc::new(storage::KuboStorage::new(api_url))),
+        None => storage_registry.with_provider(Arc::new(storage::PinataStorage::new().with_gateways(Arc::new(
+            hecate::GatewayPool::new("ipfs", &config.storage.ipfs_gateways, gateway_settings)
+                .with_metrics(Arc::clone(&metrics)),
+        )))),
+    };
+    if let Some(dir) = &config.storage.local_storage_dir {
+        storage_registry = storage_registry.with_provider(Arc::new(storage::LocalStorage::new(dir)));
+    }
+    let storage_registry = Arc::new(storage_registry);
+
+    // Uploads stage on local disk before reaching a provider; abandoned ones are swept
+    let atlas = Arc::new(atlas::AtlasUploader::new(
+        (*db).clone(),
+        Arc::clone(&storage_registry),
+        config.upload.clone(),
+    ));
+    atlas::spawn_session_sweeper(
+        Arc::clone(&atlas),
+        std::time::Duration::from_secs(config.upload.session_ttl_seconds),
+    );
+
+    // DNS-over-HTTPS resolver for .shadow names
+    let iris = Arc::new(iris::IrisResolver::new(&config.dns));
+
+    // Custom domains keep proving control through their _shadow-challenge TXT record
+    let txt_resolver: Arc<dyn iris::TxtResolver> =
+        Arc::new(iris::DnsTxtResolver::new(&config.dns.challenge_nameservers));
+    olympus::spawn_custom_domain_rechecks(
+        (*db).clone(),
+        Arc::clone(&txt_resolver),
+        Arc::clone(&hermes_broker),
+        std::time::Duration::from_secs(config.domains.challenge_recheck_seconds),
+    );
+    
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -161,12 +311,16 @@ async fn main() -> anyhow::Result<()> {
             .app_data(web::Data::from(Arc::clone(&db_clone)))
             .app_data(web::Data::new(solana_rpc_clone.clone()))
             .app_data(web::Data::new(solana_ws_clone.clone()))
-            .app_data(web::Data::new(storage::PinataStorage::new()))
-            .app_data(web::Data::new(storage::BundlrStorage::new()))
+            .app_data(web::Data::from(Arc::clone(&storage_registry)))
+            .app_data(web::Data::from(Arc::clone(&atlas)))
             .app_data(web::Data::from(Arc::clone(&ares)))
             .app_data(web::Data::from(Arc::clone(&artemis)))
             .app_data(web::Data::from(Arc::clone(&apollo)))
-            .app_data(web::Data::new(olympus::OlympusCA::new((*db_clone).clone())))
+            .app_data(web::Data::new(
+                olympus::OlympusCA::new((*db_clone).clone())
+                    .with_hermes(Arc::clone(&hermes_broker))
+                    .with_reserved_names(config.domains.reserved_names.clone())
+            ))
             .app_data(web::Data::from(Arc::clone(&athena)))
             .app_data(web::Data::from(Arc::clone(&chronos)))
             .app_data(web::Data::from(Arc::clone(&prometheus)))
@@ -174,6 +328,9 @@ async fn main() -> anyhow::Result<()> {
             .app_data(web::Data::from(Arc::clone(&metrics)))
             .app_data(web::Data::from(Arc::clone(&anchor_client)))
             .app_data(web::Data::from(Arc::clone(&hermes_broker)))
+            .app_data(web::Data::from(Arc::clone(&price_oracle)))
+            .app_data(web::Data::from(Arc::clone(&iris)))
+            .app_data(web::Data::from(Arc::clone(&txt_resolver)))
             .app_data(web::Data::new(config.clone()))
             .service(
                 web::scope("/api")
@@ -187,15 +344,51 @@ async fn main() -> anyhow::Result<()> {
                     .route("/sites", web::post().to(handlers::register_site))
                     .route("/sites/{program_address}", web::put().to(handlers::update_site))
                     .route("/sites/{program_address}/content", web::get().to(handlers::get_site_content))
-                    .route("/upload/ipfs", web::post().to(handlers::upload_ipfs))
-                    .route("/upload/arweave", web::post().to(handlers::upload_arweave))
+                    .route("/sites/{program_address}/content/{path:.*}", web::get().to(handlers::get_site_file))
+                    .route("/sites/{program_address}/versions", web::get().to(handlers::get_site_versions))
+                    .route("/sites/{program_address}/versions/{version}/pin", web::put().to(handlers::pin_site_version))
+                    .route("/sites/{program_address}/versions/{version}/pin", web::delete().to(handlers::unpin_site_version))
+                    .route("/sites/{program_address}/rollback", web::post().to(handlers::rollback_site))
+                    .route("/sites/{program_address}/transfer", web::post().to(handlers::transfer_site))
+                    .route("/sites/{program_address}/maintainers", web::put().to(handlers::set_site_maintainers))
+                    .route("/sites/{program_address}", web::delete().to(handlers::close_site))
+                    .route("/upload/usage", web::get().to(upload_handlers::get_upload_usage))
+                    .route("/upload/sessions", web::post().to(upload_handlers::create_upload_session))
+                    .route("/upload/sessions/{id}", web::get().to(upload_handlers::get_upload_session))
+                    .route("/upload/sessions/{id}", web::patch().to(upload_handlers::append_upload_chunk))
+                    .route("/upload/sessions/{id}", web::delete().to(upload_handlers::abort_upload_session))
+                    .route("/upload/sessions/{id}/complete", web::post().to(upload_handlers::complete_upload_session))
+                    .route("/upload/{scheme}", web::post().to(upload_handlers::upload_file))
+                    .route("/upload/{scheme}/directory", web::post().to(upload_handlers::upload_directory))
+                    .route("/upload/{scheme}/price", web::get().to(upload_handlers::get_upload_price))
+                    .route("/storage/stat", web::get().to(handlers::get_storage_stat))
                     .route("/solana/search", web::get().to(handlers::search_solana))
                     // Olympus domain endpoints
                     .route("/domains/search", web::get().to(handlers::search_domains))
+                    .route("/domains/check/{domain}", web::get().to(handlers::check_domain_name))
+                    .route("/domains/primary", web::put().to(handlers::set_primary_domain))
+                    .route("/domains/primary", web::delete().to(handlers::clear_primary_domain))
+                    .route("/domains/reverse", web::post().to(handlers::batch_reverse_lookup))
+                    .route("/domains/reverse/{address}", web::get().to(handlers::reverse_lookup))
                     .route("/domains/{domain}", web::get().to(handlers::get_domain))
                     .route("/domains", web::post().to(handlers::register_domain))
                     .route("/domains/{domain}", web::put().to(handlers::update_domain))
                     .route("/domains/{domain}/verify", web::post().to(handlers::verify_domain))
+                    .route("/domains/{domain}/sync", web::post().to(handlers::sync_domain))
+                    .route("/domains/{domain}/renew", web::post().to(handlers::renew_domain))
+                    .route("/domains/{domain}/transfer", web::get().to(handlers::get_domain_transfer))
+                    .route("/domains/{domain}/transfer", web::post().to(handlers::offer_domain_transfer))
+                    .route("/domains/{domain}/transfer", web::delete().to(handlers::cancel_domain_transfer))
+                    .route("/domains/{domain}/transfer/accept", web::post().to(handlers::accept_domain_transfer))
+                    .route("/domains/{domain}/resolve", web::get().to(handlers::resolve_domain))
+                    .route("/domains/{domain}/records", web::get().to(handlers::get_domain_records))
+                    .route("/domains/{domain}/records", web::put().to(handlers::put_domain_records))
+                    .route("/domains/{domain}/subdomains", web::get().to(handlers::list_subdomains))
+                    .route("/domains/{domain}/subdomains", web::post().to(handlers::upsert_subdomain))
+                    .route("/domains/{domain}/subdomains/{label}", web::delete().to(handlers::delete_subdomain))
+                    // Iris DNS-over-HTTPS (RFC 8484)
+                    .route("/dns-query", web::get().to(handlers::dns_query_get))
+                    .route("/dns-query", web::post().to(handlers::dns_query_post))
                     .route("/domains/owner/{wallet}", web::get().to(handlers::list_owner_domains))
                     // Athena search endpoints
                     .route("/search", web::get().to(handlers::search_content))
@@ -233,6 +426,8 @@ async fn main() -> anyhow::Result<()> {
                     .route("/wallet/{pubkey}/tokens", web::get().to(wallet_handlers::get_token_balances))
                     // Aphrodite - NFTs
                     .route("/wallet/{pubkey}/nfts", web::get().to(wallet_handlers::get_nfts))
+                    .route("/wallet/nft/transfer", web::post().to(wallet_handlers::transfer_nft))
+                    .route("/nfts/{mint}/image", web::get().to(wallet_handlers::get_nft_image))
                     // Hestia - dApp Connections
                     .route("/wallet/dapp/connect", web::post().to(wallet_handlers::connect_dapp))
                     .route("/wallet/dapp/connections", web::get().to(wallet_handlers::get_connections))
@@ -240,6 +435,9 @@ async fn main() -> anyhow::Result<()> {
                     // Plutus - Portfolio
                     .route("/wallet/{pubkey}/portfolio", web::get().to(wallet_handlers::get_portfolio))
                     .route("/wallet/{pubkey}/history", web::get().to(wallet_handlers::get_transaction_history))
+                    .route("/wallet/{pubkey}/portfolio/history", web::get().to(wallet_handlers::get_portfolio_value_history))
+                    .route("/wallet/{pubkey}/portfolio/changes", web::get().to(wallet_handlers::get_portfolio_changes))
+                    .route("/wallet/{pubkey}/portfolio/pnl", web::get().to(wallet_handlers::get_portfolio_pnl))
                     // Link Converter - Token-only domains
                     .route("/convert/link", web::post().to(handlers_link::convert_link))
                     .route("/convert/general-token", web::post().to(handlers_link::create_general_token))
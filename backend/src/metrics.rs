@@ -13,6 +13,35 @@ pub struct BackendMetrics {
     pub cache_misses: u64,
     pub database_queries: u64,
     pub solana_rpc_calls: u64,
+    pub gateways: Vec<GatewayMetrics>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GatewayMetrics {
+    pub gateway: String,
+    pub requests: u64,
+    pub successes: u64,
+    pub not_found: u64,
+    pub failures: u64,
+    pub integrity_failures: u64,
+    pub hedged_requests: u64,
+    pub circuit_opens: u64,
+    pub average_latency_ms: f64,
+}
+
+/// How a single storage gateway request ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatewayOutcome {
+    Success,
+    NotFound,
+    Failure,
+    IntegrityFailure,
+}
+
+#[derive(Default)]
+struct GatewayCounters {
+    metrics: GatewayMetrics,
+    total_latency_ms: u64,
 }
 
 pub struct MetricsCollector {
@@ -24,6 +53,7 @@ pub struct MetricsCollector {
     cache_misses: Arc<AtomicU64>,
     database_queries: Arc<AtomicU64>,
     solana_rpc_calls: Arc<AtomicU64>,
+    gateways: Arc<dashmap::DashMap<String, GatewayCounters>>,
 }
 
 impl MetricsCollector {
@@ -37,6 +67,7 @@ impl MetricsCollector {
             cache_misses: Arc::new(AtomicU64::new(0)),
             database_queries: Arc::new(AtomicU64::new(0)),
             solana_rpc_calls: Arc::new(AtomicU64::new(0)),
+            gateways: Arc::new(dashmap::DashMap::new()),
         }
     }
     
@@ -75,6 +106,35 @@ impl MetricsCollector {
         self.solana_rpc_calls.fetch_add(1, Ordering::Relaxed);
     }
     
+    fn gateway_counters(&self, gateway: &str) -> dashmap::mapref::one::RefMut<'_, String, GatewayCounters> {
+        self.gateways.entry(gateway.to_string()).or_insert_with(|| GatewayCounters {
+            metrics: GatewayMetrics { gateway: gateway.to_string(), ..Default::default() },
+            total_latency_ms: 0,
+        })
+    }
+    
+    pub fn record_gateway_request(&self, gateway: &str, outcome: GatewayOutcome, latency_ms: u64) {
+        let mut counters = self.gateway_counters(gateway);
+        counters.total_latency_ms += latency_ms;
+        let metrics = &mut counters.metrics;
+        metrics.requests += 1;
+        match outcome {
+            GatewayOutcome::Success => metrics.successes += 1,
+            GatewayOutcome::NotFound => metrics.not_found += 1,
+            GatewayOutcome::Failure => metrics.failures += 1,
+            GatewayOutcome::IntegrityFailure => metrics.integrity_failures += 1,
+        }
+    }
+    
+    /// A request raced against this gateway because the ones before it were slow
+    pub fn record_gateway_hedge(&self, gateway: &str) {
+        self.gateway_counters(gateway).metrics.hedged_requests += 1;
+    }
+    
+    pub fn record_gateway_circuit_open(&self, gateway: &str) {
+        self.gateway_counters(gateway).metrics.circuit_opens += 1;
+    }
+    
     pub fn get_metrics(&self) -> BackendMetrics {
         let total = self.total_requests.load(Ordering::Relaxed);
         let successful = self.successful_requests.load(Ordering::Relaxed);
@@ -93,6 +153,18 @@ impl MetricsCollector {
             0.0
         };
         
+        let mut gateways: Vec<GatewayMetrics> = self.gateways.iter()
+            .map(|entry| {
+                let counters = entry.value();
+                let mut metrics = counters.metrics.clone();
+                if metrics.requests > 0 {
+                    metrics.average_latency_ms = counters.total_latency_ms as f64 / metrics.requests as f64;
+                }
+                metrics
+            })
+            .collect();
+        gateways.sort_by(|a, b| a.gateway.cmp(&b.gateway));
+        
         BackendMetrics {
             total_requests: total,
             successful_requests: successful,
@@ -102,6 +174,7 @@ impl MetricsCollector {
             cache_misses: self.cache_misses.load(Ordering::Relaxed),
             database_queries: self.database_queries.load(Ordering::Relaxed),
             solana_rpc_calls: self.solana_rpc_calls.load(Ordering::Relaxed),
+            gateways,
         }
     }
     
@@ -114,6 +187,7 @@ impl MetricsCollector {
         self.cache_misses.store(0, Ordering::Relaxed);
         self.database_queries.store(0, Ordering::Relaxed);
         self.solana_rpc_calls.store(0, Ordering::Relaxed);
+        self.gateways.clear();
     }
 }
 
@@ -147,6 +221,29 @@ mod tests {
         assert_eq!(result.database_queries, 1);
         assert_eq!(result.solana_rpc_calls, 1);
     }
+    
+    #[test]
+    fn test_gateway_metrics() {
+        let metrics = MetricsCollector::new();
+        
+        metrics.record_gateway_request("https://ipfs.io", GatewayOutcome::Success, 100);
+        metrics.record_gateway_request("https://ipfs.io", GatewayOutcome::IntegrityFailure, 300);
+        metrics.record_gateway_hedge("https://ipfs.io");
+        metrics.record_gateway_circuit_open("https://ipfs.io");
+        metrics.record_gateway_request("https://arweave.net", GatewayOutcome::NotFound, 50);
+        
+        let result = metrics.get_metrics();
+        assert_eq!(result.gateways.len(), 2);
+        let ipfs = &result.gateways[1];
+        assert_eq!(ipfs.gateway, "https://ipfs.io");
+        assert_eq!((ipfs.requests, ipfs.successes, ipfs.integrity_failures), (2, 1, 1));
+        assert_eq!((ipfs.hedged_requests, ipfs.circuit_opens), (1, 1));
+        assert_eq!(ipfs.average_latency_ms, 200.0);
+        assert_eq!(result.gateways[0].not_found, 1);
+        
+        metrics.reset();
+        assert!(metrics.get_metrics().gateways.is_empty());
+    }
 }
 
 
@@ -0,0 +1,539 @@
+// Mnemosyne: Memory - Indexes the registry and profiles programs into Mongo
+//
+// A full getProgramAccounts backfill seeds `sites`, `site_deployments` and
+// `users`; after that each program's transactions are followed by polling
+// getSignaturesForAddress from a cursor, which unlike logsSubscribe can't
+// miss anything across reconnects. Transactions only tell us which accounts
+// changed: their current state is read back at `confirmed` and written with
+// the slot it was read at, so replays and out-of-order writes are harmless.
+// Transactions are remembered until their slot is finalized; any that
+// vanish with a fork get their accounts read again.
+
+use crate::anchor_client::{self, AnchorClient, DeploymentAccount, IndexedAccount, ProfileAccount, SiteAccount};
+use crate::db;
+use crate::error::ShadowError;
+use crate::olympus::unix_to_utc;
+use crate::solana::SolanaClient;
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeSet, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Signatures per getSignaturesForAddress page
+const SIGNATURE_PAGE: u32 = 1000;
+/// Signatures followed per program in one poll; further behind than this,
+/// a fresh backfill is cheaper than replaying them
+const MAX_SIGNATURES_PER_POLL: usize = 5000;
+
+/// How far the indexer has got through one program's history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexerCursor {
+    #[serde(rename = "_id")]
+    pub program: String,
+    pub signature: String, // Newest finalized transaction indexed
+    pub slot: u64,
+}
+
+/// A transaction applied at `confirmed`, kept until its slot is finalized
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexedTransaction {
+    #[serde(rename = "_id")]
+    pub signature: String,
+    pub program: String,
+    pub slot: u64,
+    pub sites: Vec<String>,   // Program addresses of the sites it touched
+    pub wallets: Vec<String>, // Wallets whose profile it touched
+}
+
+/// Sites and profiles affected by a set of account changes
+#[derive(Debug, Default, PartialEq)]
+struct Touched {
+    sites: BTreeSet<Pubkey>,
+    wallets: BTreeSet<Pubkey>,
+}
+
+impl Touched {
+    fn add(&mut self, account: &IndexedAccount) {
+        match account {
+            IndexedAccount::Site(site) => { self.sites.insert(site.program_address); }
+            IndexedAccount::Deployment(deployment) => { self.sites.insert(deployment.site); }
+            IndexedAccount::Profile(profile) => { self.wallets.insert(profile.wallet); }
+        }
+    }
+}
+
+pub struct MnemosyneIndexer {
+    db: Database,
+    anchor: Arc<AnchorClient>,
+    rpc_url: String,
+    solana: SolanaClient, // At `confirmed`
+}
+
+impl MnemosyneIndexer {
+    pub fn new(db: Database, anchor: Arc<AnchorClient>, rpc_url: String) -> Self {
+        Self {
+            db,
+            anchor,
+            solana: SolanaClient::new(rpc_url.clone()).with_commitment(CommitmentConfig::confirmed()),
+            rpc_url,
+        }
+    }
+
+    pub fn get_cursors_collection(&self) -> Collection<IndexerCursor> {
+        self.db.collection::<IndexerCursor>("indexer_cursors")
+    }
+
+    pub fn get_transactions_collection(&self) -> Collection<IndexedTransaction> {
+        self.db.collection::<IndexedTransaction>("indexed_transactions")
+    }
+
+    fn programs(&self) -> [Pubkey; 2] {
+        [*self.anchor.registry_program_id(), *self.anchor.profiles_program_id()]
+    }
+
+    /// Backfill if this database has never been indexed, then follow both programs
+    pub async fn poll(&self) -> Result<usize, ShadowError> {
+        let mut applied = 0;
+        for program in self.programs() {
+            if self.get_cursor(&program).await?.is_none() {
+                applied += self.backfill().await?;
+            }
+            applied += self.follow(&program).await?;
+        }
+        self.settle().await?;
+        Ok(applied)
+    }
+
+    async fn get_cursor(&self, program: &Pubkey) -> Result<Option<IndexerCursor>, ShadowError> {
+        Ok(self.get_cursors_collection()
+            .find_one(doc! { "_id": program.to_string() }, None)
+            .await?)
+    }
+
+    /// Move a program's cursor forward; never back
+    async fn advance_cursor(&self, program: &Pubkey, signature: &str, slot: u64) -> Result<(), ShadowError> {
+        let program = program.to_string();
+        let update = doc! { "$set": { "signature": signature, "slot": slot as i64 } };
+        let options = mongodb::options::UpdateOptions::builder().upsert(true).build();
+        let filter = doc! {
+            "_id": &program,
+            "$or": [{ "slot": { "$exists": false } }, { "slot": { "$lte": slot as i64 } }]
+        };
+        match self.get_cursors_collection().update_one(filter, update, options).await {
+            Ok(_) => Ok(()),
+            // A cursor further along already exists
+            Err(e) if db::is_duplicate_key(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Index every site, deployment and profile account as it stands
+    ///
+    /// Cursors are pinned to each program's newest finalized transaction
+    /// first, so whatever lands during the scan is replayed afterwards.
+    pub async fn backfill(&self) -> Result<usize, ShadowError> {
+        let finalized = SolanaClient::new(self.rpc_url.clone())
+            .with_commitment(CommitmentConfig::finalized());
+        let mut cursors = Vec::new();
+        for program in self.programs() {
+            let newest = finalized.get_signatures_for_address(&program, 1, None, None).await
+                .map_err(ShadowError::Solana)?;
+            cursors.push((program, newest.into_iter().next()));
+        }
+
+        let (slot, accounts) = self.anchor.fetch_all_indexed().map_err(ShadowError::Solana)?;
+        let count = accounts.len();
+        self.apply(&accounts, slot).await?;
+
+        for (program, newest) in cursors {
+            // An idle program has no history to start from; an empty cursor follows it from the beginning
+            let (signature, cursor_slot) = newest.map_or((String::new(), 0), |s| (s.signature, s.slot));
+            self.advance_cursor(&program, &signature, cursor_slot).await?;
+        }
+        tracing::info!("Backfilled {} registry and profile accounts at slot {}", count, slot);
+        Ok(count)
+    }
+
+    /// Apply a program's confirmed transactions newer than its cursor
+    async fn follow(&self, program: &Pubkey) -> Result<usize, ShadowError> {
+        let cursor = self.get_cursor(program).await?;
+        let until = cursor.as_ref().map(|c| c.signature.as_str()).filter(|s| !s.is_empty());
+
+        // Newest first, paging back until the cursor
+        let mut signatures = Vec::new();
+        let mut before: Option<String> = None;
+        loop {
+            let page = self.solana
+                .get_signatures_for_address(program, SIGNATURE_PAGE, before.as_deref(), until)
+                .await
+                .map_err(ShadowError::Solana)?;
+            let full = page.len() == SIGNATURE_PAGE as usize;
+            before = page.last().map(|s| s.signature.clone());
+            signatures.extend(page);
+            if !full {
+                break;
+            }
+            if signatures.len() >= MAX_SIGNATURES_PER_POLL {
+                tracing::warn!("Indexer is over {} transactions behind on {}, backfilling", MAX_SIGNATURES_PER_POLL, program);
+                return self.backfill().await;
+            }
+        }
+        signatures.reverse();
+
+        let ids: Vec<&str> = signatures.iter().map(|s| s.signature.as_str()).collect();
+        let seen: HashSet<String> = self.get_transactions_collection()
+            .find(doc! { "_id": { "$in": &ids } }, None)
+            .await?
+            .map_ok(|t| t.signature)
+            .try_collect()
+            .await?;
+        let new: Vec<_> = signatures.into_iter().filter(|s| !seen.contains(&s.signature)).collect();
+        if new.is_empty() {
+            return Ok(0);
+        }
+
+        // Failed transactions changed nothing but are still recorded, so the cursor can pass them
+        let succeeded: Vec<String> = new.iter().filter(|s| s.err.is_none()).map(|s| s.signature.clone()).collect();
+        let transactions = self.solana.get_parsed_transactions(&succeeded).await
+            .map_err(ShadowError::Solana)?;
+        let mut changes: Vec<(String, Vec<Pubkey>, Vec<Pubkey>)> = Vec::new();
+        for (signature, transaction) in succeeded.iter().zip(transactions) {
+            let Some(transaction) = transaction else {
+                tracing::warn!("Transaction {} not found, leaving it for the next poll", signature);
+                continue;
+            };
+            let (writable, closed) = parse_transaction(&transaction);
+            changes.push((signature.clone(), writable, closed));
+        }
+
+        let addresses: Vec<Pubkey> = changes.iter()
+            .flat_map(|(_, writable, _)| writable.iter().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let (slot, accounts) = self.anchor.fetch_indexed_accounts(&addresses).map_err(ShadowError::Solana)?;
+        let state: std::collections::HashMap<Pubkey, IndexedAccount> = addresses.into_iter()
+            .zip(accounts)
+            .filter_map(|(address, account)| account.map(|a| (address, a)))
+            .collect();
+
+        let mut records = Vec::new();
+        for info in &new {
+            let mut touched = Touched::default();
+            if let Some((_, writable, closed)) = changes.iter().find(|(s, _, _)| *s == info.signature) {
+                for account in writable.iter().filter_map(|a| state.get(a)) {
+                    touched.add(account);
+                }
+                touched.sites.extend(closed);
+            } else if info.err.is_none() {
+                continue;
+            }
+            records.push((info, touched));
+        }
+
+        let mut all = Touched::default();
+        for (_, touched) in &records {
+            all.sites.extend(&touched.sites);
+            all.wallets.extend(&touched.wallets);
+        }
+        let accounts: Vec<IndexedAccount> = state.into_values().collect();
+        self.apply(&accounts, slot).await?;
+        self.remove_closed(&all.sites, &accounts, slot).await?;
+
+        let documents: Vec<IndexedTransaction> = records.iter().map(|(info, touched)| IndexedTransaction {
+            signature: info.signature.clone(),
+            program: program.to_string(),
+            slot: info.slot,
+            sites: touched.sites.iter().map(|p| p.to_string()).collect(),
+            wallets: touched.wallets.iter().map(|w| w.to_string()).collect(),
+        }).collect();
+        let options = mongodb::options::InsertManyOptions::builder().ordered(false).build();
+        if !documents.is_empty() {
+            self.get_transactions_collection().insert_many(&documents, options).await?;
+        }
+        Ok(documents.len())
+    }
+
+    /// Write decoded accounts read at `slot`
+    async fn apply(&self, accounts: &[IndexedAccount], slot: u64) -> Result<(), ShadowError> {
+        // Deployments first, so sites don't go fetching log entries already in hand
+        let deployments: Vec<db::SiteDeployment> = accounts.iter()
+            .filter_map(|a| match a {
+                IndexedAccount::Deployment(deployment) => Some(deployment_record(deployment)),
+                _ => None,
+            })
+            .collect();
+        db::record_site_deployments(&self.db, &deployments).await?;
+
+        for account in accounts {
+            match account {
+                IndexedAccount::Site(site) => index_site(&self.db, &self.anchor, site, slot).await?,
+                IndexedAccount::Profile(profile) => {
+                    db::index_user(&self.db, &user_record(profile, slot)).await?;
+                }
+                IndexedAccount::Deployment(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop `sites` that no longer have a registry account
+    ///
+    /// `present` is what was just read; anything else is checked again, as
+    /// closure is only ever inferred from events anyone could forge.
+    async fn remove_closed(
+        &self,
+        sites: &BTreeSet<Pubkey>,
+        present: &[IndexedAccount],
+        slot: u64,
+    ) -> Result<(), ShadowError> {
+        let candidates: Vec<Pubkey> = sites.iter()
+            .filter(|p| !present.iter().any(|a| matches!(a, IndexedAccount::Site(s) if s.program_address == **p)))
+            .copied()
+            .collect();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let pdas: Vec<Pubkey> = candidates.iter().map(|p| self.anchor.site_pda(p)).collect();
+        let (read_at, accounts) = self.anchor.fetch_indexed_accounts(&pdas).map_err(ShadowError::Solana)?;
+        for (program_address, account) in candidates.iter().zip(accounts) {
+            match account {
+                Some(IndexedAccount::Site(site)) => index_site(&self.db, &self.anchor, &site, read_at).await?,
+                _ => db::delete_site(&self.db, &program_address.to_string(), slot.max(read_at)).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Retire transactions whose slot is now finalized
+    ///
+    /// Finalized ones move the cursor forward. Ones the cluster no longer
+    /// knows were dropped with a fork, so what they touched is read again.
+    async fn settle(&self) -> Result<(), ShadowError> {
+        let finalized_slot = self.solana.get_slot(CommitmentConfig::finalized()).await
+            .map_err(ShadowError::Solana)?;
+        let options = mongodb::options::FindOptions::builder().sort(doc! { "slot": 1 }).build();
+        let pending: Vec<IndexedTransaction> = self.get_transactions_collection()
+            .find(doc! { "slot": { "$lte": finalized_slot as i64 } }, options)
+            .await?
+            .try_collect()
+            .await?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let signatures: Vec<String> = pending.iter().map(|t| t.signature.clone()).collect();
+        let statuses = self.solana.get_signature_statuses(&signatures).await
+            .map_err(ShadowError::Solana)?;
+
+        let mut dropped = Touched::default();
+        for (transaction, status) in pending.iter().zip(&statuses) {
+            match status.as_deref() {
+                Some("finalized") => {
+                    let program = Pubkey::from_str(&transaction.program)
+                        .map_err(|e| ShadowError::Solana(e.to_string()))?;
+                    self.advance_cursor(&program, &transaction.signature, transaction.slot).await?;
+                }
+                // Its slot is finalized but it isn't yet: the status cache is lagging
+                Some(_) => continue,
+                None => {
+                    tracing::warn!("Transaction {} at slot {} was dropped by a fork", transaction.signature, transaction.slot);
+                    dropped.sites.extend(transaction.sites.iter().filter_map(|p| Pubkey::from_str(p).ok()));
+                    dropped.wallets.extend(transaction.wallets.iter().filter_map(|w| Pubkey::from_str(w).ok()));
+                }
+            }
+            self.get_transactions_collection()
+                .delete_one(doc! { "_id": &transaction.signature }, None)
+                .await?;
+        }
+
+        if !dropped.sites.is_empty() || !dropped.wallets.is_empty() {
+            self.resync(&dropped).await?;
+        }
+        Ok(())
+    }
+
+    /// Read touched sites and profiles straight from their PDAs
+    async fn resync(&self, touched: &Touched) -> Result<(), ShadowError> {
+        let pdas: Vec<Pubkey> = touched.wallets.iter().map(|w| self.anchor.profile_pda(w)).collect();
+        let (slot, accounts) = self.anchor.fetch_indexed_accounts(&pdas).map_err(ShadowError::Solana)?;
+        let accounts: Vec<IndexedAccount> = accounts.into_iter().flatten().collect();
+        self.apply(&accounts, slot).await?;
+        // Sites are all "candidates" here: present ones are re-indexed, gone ones removed
+        self.remove_closed(&touched.sites, &[], slot).await
+    }
+}
+
+/// Mirror a registry site read at `slot` and index deployments not seen yet
+///
+/// Slot 0 is for sites read by the API, which overwrite unconditionally.
+pub async fn index_site(
+    db: &Database,
+    anchor: &AnchorClient,
+    site: &SiteAccount,
+    slot: u64,
+) -> Result<(), ShadowError> {
+    let program_address = site.program_address.to_string();
+    db::create_or_update_site(db, &site_record(site, slot)).await?;
+
+    let indexed = db::latest_site_deployment_version(db, &program_address).await?;
+    if site.version <= indexed {
+        return Ok(());
+    }
+
+    let versions: Vec<u32> = (indexed + 1..=site.version).collect();
+    let deployments: Vec<db::SiteDeployment> = anchor
+        .fetch_deployments(&site.program_address, &versions)
+        .map_err(ShadowError::Solana)?
+        .iter()
+        .flatten()
+        .map(deployment_record)
+        .collect();
+
+    db::record_site_deployments(db, &deployments).await?;
+    Ok(())
+}
+
+fn site_record(site: &SiteAccount, slot: u64) -> db::Site {
+    db::Site {
+        program_address: site.program_address.to_string(),
+        owner_pubkey: site.owner.to_string(),
+        storage_cid: site.storage_cid.clone(),
+        name: Some(site.name.clone()).filter(|n| !n.is_empty()),
+        description: Some(site.description.clone()).filter(|d| !d.is_empty()),
+        created_at: unix_to_utc(site.created_at),
+        updated_at: unix_to_utc(site.updated_at),
+        version: site.version,
+        maintainers: site.maintainers.iter().map(|m| m.to_string()).collect(),
+        slot,
+    }
+}
+
+fn deployment_record(deployment: &DeploymentAccount) -> db::SiteDeployment {
+    let program_address = deployment.site.to_string();
+    db::SiteDeployment {
+        id: format!("{}:{}", program_address, deployment.version),
+        program_address,
+        version: deployment.version,
+        storage_cid: deployment.storage_cid.clone(),
+        deployer: deployment.deployer.to_string(),
+        deployed_at: unix_to_utc(deployment.deployed_at),
+        rollback_of: deployment.rollback_of,
+    }
+}
+
+fn user_record(profile: &ProfileAccount, slot: u64) -> db::User {
+    db::User {
+        wallet_pubkey: profile.wallet.to_string(),
+        profile_cid: Some(profile.profile_cid.clone()).filter(|c| !c.is_empty()),
+        is_public: profile.is_public,
+        created_at: unix_to_utc(profile.created_at),
+        updated_at: unix_to_utc(profile.updated_at),
+        slot,
+    }
+}
+
+/// Writable accounts of a `jsonParsed` transaction, and sites its logs say it closed
+fn parse_transaction(transaction: &serde_json::Value) -> (Vec<Pubkey>, Vec<Pubkey>) {
+    let writable = transaction["transaction"]["message"]["accountKeys"]
+        .as_array()
+        .map(|keys| keys.iter()
+            .filter(|k| k["writable"].as_bool().unwrap_or(false))
+            .filter_map(|k| k["pubkey"].as_str().and_then(|p| Pubkey::from_str(p).ok()))
+            .collect())
+        .unwrap_or_default();
+
+    let logs: Vec<String> = transaction["meta"]["logMessages"]
+        .as_array()
+        .map(|lines| lines.iter().filter_map(|l| l.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+
+    (writable, anchor_client::site_closed_events(&logs))
+}
+
+/// Keep `sites`, `site_deployments` and `users` in step with the chain
+pub fn spawn_indexer(
+    db: Database,
+    anchor: Arc<AnchorClient>,
+    rpc_url: String,
+    interval: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let indexer = MnemosyneIndexer::new(db, anchor, rpc_url);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match indexer.poll().await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!("Indexed {} registry and profile changes", count),
+                Err(e) => tracing::warn!("Indexer poll failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deployments_and_profiles_touch_their_owners() {
+        let site = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let mut touched = Touched::default();
+        touched.add(&IndexedAccount::Deployment(DeploymentAccount {
+            site,
+            version: 2,
+            storage_cid: "ipfs://QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG".to_string(),
+            deployer: Pubkey::new_unique(),
+            deployed_at: 0,
+            rollback_of: None,
+        }));
+        touched.add(&IndexedAccount::Profile(ProfileAccount {
+            wallet,
+            profile_cid: String::new(),
+            is_public: false,
+            created_at: 0,
+            updated_at: 0,
+        }));
+
+        assert_eq!(touched.sites.into_iter().collect::<Vec<_>>(), vec![site]);
+        assert_eq!(touched.wallets.into_iter().collect::<Vec<_>>(), vec![wallet]);
+        assert!(user_record(&ProfileAccount {
+            wallet,
+            profile_cid: String::new(),
+            is_public: false,
+            created_at: 0,
+            updated_at: 0,
+        }, 7).profile_cid.is_none());
+    }
+
+    #[test]
+    fn reads_writable_accounts_from_parsed_transactions() {
+        let site = Pubkey::new_unique();
+        let closed = Pubkey::new_unique();
+        let mut event = anchor_client::event_discriminator("SiteClosed").to_vec();
+        event.extend_from_slice(closed.as_ref());
+        event.extend_from_slice(Pubkey::new_unique().as_ref());
+        use base64::Engine;
+
+        let transaction = serde_json::json!({
+            "slot": 42,
+            "transaction": { "message": { "accountKeys": [
+                { "pubkey": site.to_string(), "writable": true, "signer": false },
+                { "pubkey": Pubkey::new_unique().to_string(), "writable": false, "signer": false }
+            ] } },
+            "meta": { "err": null, "logMessages": [
+                format!("Program data: {}", base64::engine::general_purpose::STANDARD.encode(&event))
+            ] }
+        });
+
+        assert_eq!(parse_transaction(&transaction), (vec![site], vec![closed]));
+    }
+}
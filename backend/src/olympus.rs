@@ -3,8 +3,18 @@
 
 use mongodb::{Collection, Database};
 use mongodb::bson::doc;
-use chrono::{DateTime, Utc};
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::anchor_client::{AnchorClient, DomainAccount};
+use crate::apollo::ApolloValidator;
+use crate::iris::TxtResolver;
+use crate::storage::split_uri;
+use crate::websocket::HermesBroker;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Domain {
@@ -13,9 +23,580 @@ pub struct Domain {
     pub owner_pubkey: String,              // Wallet that owns the domain
     pub program_address: String,           // Solana program address it points to
     pub verified: bool,                    // Whether domain is verified on-chain
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default, with = "optional_bson_datetime")]
     pub expires_at: Option<DateTime<Utc>>, // Optional expiration
+    #[serde(default)]
+    pub on_chain: bool,                    // Mirrors a shadow-domains record
+    #[serde(default)]
+    pub expiry_notice: Option<ExpiryNotice>, // Last lifecycle notice sent to the owner
+    #[serde(default)]
+    pub pending_owner: Option<String>,     // Recipient of an open transfer offer
+    #[serde(default, with = "optional_bson_datetime")]
+    pub offer_expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub custom_verification: Option<CustomVerification>, // DNS proof for non-.shadow domains
+    #[serde(default)]
+    pub skeleton: Option<String>,          // Confusables skeleton, shared by lookalike names
+    #[serde(default)]
+    pub blocked: Option<String>,           // Why the naming policy refuses to serve this name
+}
+
+/// After expiry only the owner may renew, mirroring the shadow-domains program
+pub const GRACE_PERIOD_DAYS: i64 = 30;
+/// Owners are warned this long before expiry
+pub const EXPIRY_WARNING_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainStatus {
+    Active,
+    GracePeriod, // Expired, renewable by the owner only
+    Lapsed,      // Past grace, waiting to be released
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryNotice {
+    ExpiringSoon,
+    Expired,
+}
+
+impl Domain {
+    pub fn grace_ends_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.map(|e| e + chrono::Duration::days(GRACE_PERIOD_DAYS))
+    }
+
+    pub fn status(&self, now: DateTime<Utc>) -> DomainStatus {
+        match (self.expires_at, self.grace_ends_at()) {
+            (Some(expires_at), _) if now < expires_at => DomainStatus::Active,
+            (Some(_), Some(grace_ends_at)) if now <= grace_ends_at => DomainStatus::GracePeriod,
+            (Some(_), _) => DomainStatus::Lapsed,
+            // Legacy records predate terms and never expire
+            (None, _) => DomainStatus::Active,
+        }
+    }
+
+    /// The lifecycle notice the owner is due, if one hasn't been sent yet
+    pub fn notice_due(&self, now: DateTime<Utc>) -> Option<ExpiryNotice> {
+        let expires_at = self.expires_at?;
+        let due = match self.status(now) {
+            DomainStatus::Active if expires_at - now <= chrono::Duration::days(EXPIRY_WARNING_DAYS) => {
+                ExpiryNotice::ExpiringSoon
+            }
+            DomainStatus::GracePeriod => ExpiryNotice::Expired,
+            _ => return None,
+        };
+        (self.expiry_notice != Some(due)).then_some(due)
+    }
+}
+
+/// API representation of a domain with its lifecycle state
+#[derive(Debug, Serialize)]
+pub struct DomainView {
+    pub domain: String,
+    pub owner_pubkey: String,
+    pub program_address: String,
+    pub verified: bool,
+    pub on_chain: bool,
+    pub status: DomainStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub grace_ends_at: Option<DateTime<Utc>>,
+    pub pending_owner: Option<String>,
+    pub offer_expires_at: Option<DateTime<Utc>>,
+    pub display_name: String,
+    pub blocked: Option<String>,
+}
+
+impl From<Domain> for DomainView {
+    fn from(domain: Domain) -> Self {
+        Self {
+            status: domain.status(Utc::now()),
+            grace_ends_at: domain.grace_ends_at(),
+            display_name: ApolloValidator::unicode_domain(&domain.domain),
+            domain: domain.domain,
+            owner_pubkey: domain.owner_pubkey,
+            program_address: domain.program_address,
+            verified: domain.verified,
+            on_chain: domain.on_chain,
+            created_at: domain.created_at,
+            updated_at: domain.updated_at,
+            expires_at: domain.expires_at,
+            pending_owner: domain.pending_owner,
+            offer_expires_at: domain.offer_expires_at,
+            blocked: domain.blocked,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferAction {
+    Offered,
+    Cancelled,
+    Expired,
+    Completed,
+}
+
+/// One step of a domain transfer, kept as the domain's transfer history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainTransfer {
+    pub domain: String,
+    pub from: String,
+    pub to: String,
+    pub action: TransferAction,
+    #[serde(default, with = "optional_bson_datetime")]
+    pub offer_expires_at: Option<DateTime<Utc>>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub at: DateTime<Utc>,
+}
+
+/// API and event representation of a transfer step
+#[derive(Debug, Serialize)]
+pub struct DomainTransferView {
+    pub domain: String,
+    pub from: String,
+    pub to: String,
+    pub action: TransferAction,
+    pub offer_expires_at: Option<DateTime<Utc>>,
+    pub at: DateTime<Utc>,
+}
+
+impl From<DomainTransfer> for DomainTransferView {
+    fn from(transfer: DomainTransfer) -> Self {
+        Self {
+            domain: transfer.domain,
+            from: transfer.from,
+            to: transfer.to,
+            action: transfer.action,
+            offer_expires_at: transfer.offer_expires_at,
+            at: transfer.at,
+        }
+    }
+}
+
+/// Lifecycle events published on the owner's `wallet:` Hermes topic
+///
+/// Transfer events go to both the owner and the recipient.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    DomainExpiring { domain: String, expires_at: DateTime<Utc> },
+    DomainExpired { domain: String, grace_ends_at: DateTime<Utc> },
+    DomainReleased { domain: String },
+    VerificationRevoked { domain: String },
+    DomainTransfer(DomainTransferView),
+}
+
+/// The transfer step a chain update represents, if any
+///
+/// Offers are only visible through the cached `pending_owner`, so an
+/// offer that was replaced and cancelled between two syncs goes unseen.
+fn transfer_change(previous: &Domain, current: &Domain, now: DateTime<Utc>) -> Option<DomainTransfer> {
+    let step = |from: &str, to: &str, action, offer_expires_at| DomainTransfer {
+        domain: current.domain.clone(),
+        from: from.to_string(),
+        to: to.to_string(),
+        action,
+        offer_expires_at,
+        at: now,
+    };
+
+    if previous.owner_pubkey != current.owner_pubkey {
+        // A new owner without a matching offer came in through release and re-registration
+        return (previous.pending_owner.as_deref() == Some(current.owner_pubkey.as_str())).then(|| {
+            step(&previous.owner_pubkey, &current.owner_pubkey, TransferAction::Completed, None)
+        });
+    }
+
+    match (&previous.pending_owner, &current.pending_owner) {
+        (_, Some(to)) if previous.pending_owner != current.pending_owner
+            || previous.offer_expires_at != current.offer_expires_at =>
+        {
+            Some(step(&current.owner_pubkey, to, TransferAction::Offered, current.offer_expires_at))
+        }
+        (Some(to), None) => {
+            let lapsed = previous.offer_expires_at.is_some_and(|e| e <= now);
+            let action = if lapsed { TransferAction::Expired } else { TransferAction::Cancelled };
+            Some(step(&current.owner_pubkey, to, action, previous.offer_expires_at))
+        }
+        _ => None,
+    }
+}
+
+/// What a zone's record sets lose when the domain changes owner between syncs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ZoneReset {
+    /// Sold: the records stay with the name, the seller's delegations don't
+    Delegations,
+    /// Released and registered again: the old owner's records go
+    Everything,
+}
+
+fn zone_reset(previous: &Domain, current: &Domain, transfer: Option<&DomainTransfer>) -> Option<ZoneReset> {
+    if previous.owner_pubkey == current.owner_pubkey {
+        return None;
+    }
+    match transfer.map(|t| t.action) {
+        Some(TransferAction::Completed) => Some(ZoneReset::Delegations),
+        _ => Some(ZoneReset::Everything),
+    }
+}
+
+/// TTL given to records that don't set one, in seconds
+pub const DEFAULT_RECORD_TTL: u32 = 300;
+pub const MIN_RECORD_TTL: u32 = 30;
+pub const MAX_RECORD_TTL: u32 = 86_400;
+pub const MAX_RECORDS_PER_NAME: usize = 32;
+const MAX_TXT_LEN: usize = 1024;
+const MAX_REDIRECT_LEN: usize = 2048;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordType {
+    Program,  // Solana program the name serves
+    Content,  // ipfs://, arweave:// or ar:// content root
+    Redirect, // http(s) URL
+    Txt,
+    Wallet,   // Solana address to pay or message
+    Cname,    // Alias to another host; stands alone, as in DNS
+}
+
+/// A typed, DNS-like record on a .shadow name
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResourceRecord {
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub value: String,
+    #[serde(default = "default_record_ttl")]
+    pub ttl: u32,
+}
+
+fn default_record_ttl() -> u32 {
+    DEFAULT_RECORD_TTL
+}
+
+impl ResourceRecord {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_RECORD_TTL..=MAX_RECORD_TTL).contains(&self.ttl) {
+            return Err(format!("TTL must be between {} and {} seconds", MIN_RECORD_TTL, MAX_RECORD_TTL));
+        }
+
+        match self.record_type {
+            RecordType::Program | RecordType::Wallet => {
+                ApolloValidator::validate_pubkey(&self.value).map(|_| ())
+            }
+            // Uploads and deployments hand back arweave://, ar:// is its short form
+            RecordType::Content => match split_uri(&self.value) {
+                Some(("arweave", tx_id)) => ApolloValidator::validate_arweave_tx(tx_id),
+                Some(("ipfs", _)) => ApolloValidator::validate_ipfs_cid(&self.value),
+                _ => Err("Content records must be ipfs://, arweave:// or ar:// URIs".to_string()),
+            },
+            RecordType::Redirect => {
+                if self.value.len() > MAX_REDIRECT_LEN {
+                    return Err("Redirect URL too long".to_string());
+                }
+                let url = reqwest::Url::parse(&self.value)
+                    .map_err(|e| format!("Invalid redirect URL: {}", e))?;
+                match url.scheme() {
+                    "http" | "https" => Ok(()),
+                    _ => Err("Redirect URLs must be http or https".to_string()),
+                }
+            }
+            RecordType::Cname => {
+                ApolloValidator::validate_domain(self.value.trim_end_matches('.'))
+            }
+            RecordType::Txt => {
+                if self.value.len() > MAX_TXT_LEN {
+                    return Err(format!("TXT records are limited to {} bytes", MAX_TXT_LEN));
+                }
+                if self.value.chars().any(|c| c.is_control()) {
+                    return Err("TXT records cannot contain control characters".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Records and delegation for one name inside a registered domain's zone
+///
+/// The registered domain itself may have a record set; subdomains exist
+/// only as record sets. A delegate manages its subdomain and everything
+/// below it, alongside the zone owner.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordSet {
+    #[serde(rename = "_id")]
+    pub name: String,
+    pub zone: String,                      // Registered domain the name belongs to
+    #[serde(default)]
+    pub delegate: Option<String>,
+    #[serde(default)]
+    pub records: Vec<ResourceRecord>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// API representation of a record set
+#[derive(Debug, Serialize)]
+pub struct RecordSetView {
+    pub name: String,
+    pub zone: String,
+    pub delegate: Option<String>,
+    pub records: Vec<ResourceRecord>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<RecordSet> for RecordSetView {
+    fn from(set: RecordSet) -> Self {
+        Self {
+            name: set.name,
+            zone: set.zone,
+            delegate: set.delegate,
+            records: set.records,
+            updated_at: set.updated_at,
+        }
+    }
+}
+
+/// Result of resolving a name by walking its labels
+#[derive(Debug, Serialize)]
+pub struct Resolution {
+    pub name: String,
+    pub zone: String,
+    pub owner_pubkey: String,
+    pub manager: String,         // Wallet currently managing the name's records
+    pub matched: String,         // Record set that answered, e.g. a wildcard
+    pub records: Vec<ResourceRecord>,
+}
+
+/// The name followed by each parent down to (but not including) the TLD
+///
+/// `app.mysite.shadow` gives `["app.mysite.shadow", "mysite.shadow"]`.
+pub fn name_ancestors(name: &str) -> Vec<String> {
+    let labels: Vec<&str> = name.split('.').collect();
+    (0..labels.len().saturating_sub(1))
+        .map(|i| labels[i..].join("."))
+        .collect()
+}
+
+/// Whether `name` has labels beyond a registrable `<label>.shadow`
+pub fn is_subdomain(name: &str) -> bool {
+    name.matches('.').count() > 1
+}
+
+/// Names with records may also be wildcards such as `*.mysite.shadow`
+pub fn validate_record_name(name: &str) -> Result<(), String> {
+    let name = name.strip_prefix("*.").unwrap_or(name);
+    ApolloValidator::validate_domain(name)?;
+    if !name.ends_with(".shadow") {
+        return Err("Records can only be set on .shadow names".to_string());
+    }
+    Ok(())
+}
+
+/// Canonical form of a record name, keeping a leading wildcard
+pub fn normalize_record_name(name: &str) -> Result<String, String> {
+    let (wildcard, base) = match name.strip_prefix("*.") {
+        Some(base) => ("*.", base),
+        None => ("", name),
+    };
+    let name = format!("{}{}", wildcard, ApolloValidator::normalize_domain(base)?);
+    validate_record_name(&name)?;
+    Ok(name)
+}
+
+/// Escape a validated record name for use in a Mongo regex
+fn escape_name(name: &str) -> String {
+    name.replace('.', "\\.").replace('*', "\\*")
+}
+
+/// Pick the answer for `name` from the zone's record sets
+///
+/// An exact record set wins; otherwise a wildcard on the immediate parent
+/// answers. The zone apex falls back to an implicit program record.
+fn resolve_in_zone(name: &str, zone: &Domain, sets: &std::collections::HashMap<String, RecordSet>) -> Option<Resolution> {
+    let (matched, mut records) = match sets.get(name) {
+        Some(set) => (set.name.clone(), set.records.clone()),
+        None if name == zone.domain => (name.to_string(), Vec::new()),
+        None => {
+            let parent = name.split_once('.')?.1;
+            let wildcard = sets.get(&format!("*.{}", parent))?;
+            (wildcard.name.clone(), wildcard.records.clone())
+        }
+    };
+
+    if name == zone.domain && !records.iter().any(|r| r.record_type == RecordType::Program) {
+        records.push(ResourceRecord {
+            record_type: RecordType::Program,
+            value: zone.program_address.clone(),
+            ttl: DEFAULT_RECORD_TTL,
+        });
+    }
+
+    // The closest delegation at or above the name manages it
+    let manager = name_ancestors(name)
+        .iter()
+        .take_while(|n| n.as_str() != zone.domain)
+        .find_map(|n| sets.get(n).and_then(|set| set.delegate.clone()))
+        .unwrap_or_else(|| zone.owner_pubkey.clone());
+
+    Some(Resolution {
+        name: name.to_string(),
+        zone: zone.domain.clone(),
+        owner_pubkey: zone.owner_pubkey.clone(),
+        manager,
+        matched,
+        records,
+    })
+}
+
+/// Custom domains prove control with a TXT record at `_shadow-challenge.<domain>`
+pub const CHALLENGE_LABEL: &str = "_shadow-challenge";
+const CHALLENGE_PREFIX: &str = "shadow-verification=";
+/// Consecutive checks without the record before verification is revoked
+pub const MAX_CHALLENGE_FAILURES: u32 = 3;
+/// Missing records are retried sooner than the regular schedule
+const CHALLENGE_RETRY_MINUTES: i64 = 60;
+
+/// A wallet's pending claim on a custom domain
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainChallenge {
+    #[serde(rename = "_id")]
+    pub id: String, // "<domain>|<wallet>"
+    pub domain: String,
+    pub owner_pubkey: String,
+    pub program_address: String,
+    pub token: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DomainChallenge {
+    pub fn record_name(&self) -> String {
+        format!("{}.{}", CHALLENGE_LABEL, self.domain)
+    }
+
+    pub fn record_value(&self) -> String {
+        format!("{}{}", CHALLENGE_PREFIX, self.token)
+    }
+}
+
+/// Ongoing DNS proof behind a verified custom domain
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CustomVerification {
+    pub token: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub verified_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub last_checked_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub next_check_at: DateTime<Utc>,
+    #[serde(default)]
+    pub failures: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChallengeCheck {
+    Present,
+    Missing,
+}
+
+/// Whether `_shadow-challenge.<domain>` currently carries `token`
+pub async fn check_challenge(
+    resolver: &dyn TxtResolver,
+    domain: &str,
+    token: &str,
+) -> Result<ChallengeCheck, String> {
+    let expected = format!("{}{}", CHALLENGE_PREFIX, token);
+    let values = resolver.lookup_txt(&format!("{}.{}", CHALLENGE_LABEL, domain)).await?;
+    Ok(match values {
+        Some(values) if values.iter().any(|v| v.trim() == expected) => ChallengeCheck::Present,
+        _ => ChallengeCheck::Missing,
+    })
+}
+
+impl CustomVerification {
+    /// State after a scheduled check; `true` means verification is revoked
+    pub fn after_check(&self, check: ChallengeCheck, now: DateTime<Utc>, recheck: chrono::Duration) -> (Self, bool) {
+        let mut next = self.clone();
+        next.last_checked_at = now;
+        match check {
+            ChallengeCheck::Present => {
+                next.failures = 0;
+                next.next_check_at = now + recheck;
+                (next, false)
+            }
+            ChallengeCheck::Missing => {
+                next.failures += 1;
+                next.next_check_at = now + recheck.min(chrono::Duration::minutes(CHALLENGE_RETRY_MINUTES));
+                let revoked = next.failures >= MAX_CHALLENGE_FAILURES;
+                (next, revoked)
+            }
+        }
+    }
+}
+
+/// Anything outside .shadow has to be proven through DNS
+pub fn is_custom_domain(name: &str) -> bool {
+    !name.ends_with(".shadow")
+}
+
+/// Most addresses one reverse lookup may ask about
+pub const MAX_REVERSE_LOOKUPS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrimaryKind {
+    Wallet,  // The owner's own address
+    Program, // A program the domain points at
+}
+
+/// The name an address reverse-resolves to, chosen by the domain's owner
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrimaryName {
+    #[serde(rename = "_id")]
+    pub address: String,
+    pub kind: PrimaryKind,
+    pub domain: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PrimaryName {
+    /// Why `domain` can't be this address's primary name, if it can't
+    ///
+    /// Reverse records are only trusted while the forward side still
+    /// points back: the wallet owns the name, or the name is verified
+    /// for the program.
+    pub fn mismatch(kind: PrimaryKind, address: &str, domain: &Domain, now: DateTime<Utc>) -> Option<&'static str> {
+        if domain.status(now) != DomainStatus::Active {
+            return Some("Domain has expired");
+        }
+        if domain.blocked.is_some() {
+            return Some("Domain is blocked by the naming policy");
+        }
+        if is_custom_domain(&domain.domain) && !domain.verified {
+            return Some("Domain verification was revoked");
+        }
+        match kind {
+            PrimaryKind::Wallet if domain.owner_pubkey != address => {
+                Some("Domain is not owned by this wallet")
+            }
+            PrimaryKind::Program if domain.program_address != address => {
+                Some("Domain does not point at this program")
+            }
+            PrimaryKind::Program if !domain.verified => {
+                Some("Domain must be verified for the program first")
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,57 +606,782 @@ pub struct DomainRegistration {
     pub owner_pubkey: String,
 }
 
-pub struct OlympusCA {
-    db: Database,
-}
+pub struct OlympusCA {
+    db: Database,
+    hermes: Option<Arc<HermesBroker>>,
+    reserved_names: Vec<String>,
+}
+
+impl OlympusCA {
+    pub fn new(db: Database) -> Self {
+        Self { db, hermes: None, reserved_names: Vec::new() }
+    }
+
+    /// Publish lifecycle and transfer events to wallets' Hermes topics
+    pub fn with_hermes(mut self, hermes: Arc<HermesBroker>) -> Self {
+        self.hermes = Some(hermes);
+        self
+    }
+
+    /// Refuse these labels on top of `apollo::RESERVED_LABELS`
+    pub fn with_reserved_names(mut self, reserved_names: Vec<String>) -> Self {
+        self.reserved_names = reserved_names;
+        self
+    }
+
+    /// An older, served name that `domain` is a lookalike of
+    async fn confusable_with(
+        &self,
+        domain: &str,
+        skeleton: &str,
+        created_before: Option<DateTime<Utc>>,
+    ) -> Result<Option<Domain>, String> {
+        let mut filter = doc! {
+            "_id": { "$ne": domain },
+            "skeleton": skeleton,
+            "blocked": null
+        };
+        if let Some(created_before) = created_before {
+            filter.insert("created_at", doc! { "$lt": mongodb::bson::DateTime::from_chrono(created_before) });
+        }
+        self.get_domains_collection()
+            .find_one(filter, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Why `domain` (already normalized) can't be registered, if it can't
+    pub async fn name_rejection(&self, domain: &str) -> Result<Option<String>, String> {
+        if let Err(reason) = ApolloValidator::check_name_policy(domain, &self.reserved_names) {
+            return Ok(Some(reason));
+        }
+        let skeleton = ApolloValidator::name_skeleton(domain);
+        Ok(self.confusable_with(domain, &skeleton, None).await?.map(|existing| {
+            format!(
+                "Too similar to the existing domain '{}'",
+                ApolloValidator::unicode_domain(&existing.domain)
+            )
+        }))
+    }
+
+    /// Skeleton and policy verdict for a name entering the cache
+    ///
+    /// Names are registered on-chain without the backend, so the policy is
+    /// applied again here: the earliest of a set of lookalikes keeps
+    /// resolving, later ones are blocked.
+    async fn screen_name(&self, domain: &str, created_at: DateTime<Utc>) -> Result<(String, Option<String>), String> {
+        let skeleton = ApolloValidator::name_skeleton(domain);
+        if let Err(reason) = ApolloValidator::check_name_policy(domain, &self.reserved_names) {
+            return Ok((skeleton, Some(reason)));
+        }
+        let blocked = self.confusable_with(domain, &skeleton, Some(created_at)).await?
+            .map(|existing| format!(
+                "Too similar to the existing domain '{}'",
+                ApolloValidator::unicode_domain(&existing.domain)
+            ));
+        Ok((skeleton, blocked))
+    }
+
+    fn get_domains_collection(&self) -> Collection<Domain> {
+        self.db.collection::<Domain>("domains")
+    }
+
+    fn get_records_collection(&self) -> Collection<RecordSet> {
+        self.db.collection::<RecordSet>("domain_records")
+    }
+
+    /// The registered domain a name lives under: its longest registered suffix
+    pub async fn find_zone(&self, name: &str) -> Result<Option<Domain>, String> {
+        let candidates = name_ancestors(name);
+        let cursor = self.get_domains_collection()
+            .find(doc! { "_id": { "$in": &candidates } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        let zones: Vec<Domain> = cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(zones.into_iter().max_by_key(|d| d.domain.len()))
+    }
+
+    async fn record_sets(&self, names: &[String]) -> Result<std::collections::HashMap<String, RecordSet>, String> {
+        let cursor = self.get_records_collection()
+            .find(doc! { "_id": { "$in": names } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        let sets: Vec<RecordSet> = cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(sets.into_iter().map(|set| (set.name.clone(), set)).collect())
+    }
+
+    /// Resolve a name by walking up its labels to the registered zone
+    ///
+    /// Names under expired zones don't resolve. Returns `None` for NXDOMAIN.
+    pub async fn resolve(&self, name: &str) -> Result<Option<Resolution>, String> {
+        let Some(zone) = self.find_zone(name).await? else { return Ok(None) };
+        if zone.status(Utc::now()) != DomainStatus::Active || zone.blocked.is_some() {
+            return Ok(None);
+        }
+
+        let mut names = name_ancestors(name);
+        if let Some((_, parent)) = name.split_once('.') {
+            names.push(format!("*.{}", parent));
+        }
+        let sets = self.record_sets(&names).await?;
+
+        Ok(resolve_in_zone(name, &zone, &sets))
+    }
+
+    /// Whether `wallet` may change records and subdomains at `name`
+    ///
+    /// Returns the zone on success so callers don't look it up twice.
+    pub async fn authorize_manager(&self, name: &str, wallet: &str) -> Result<Option<Domain>, String> {
+        let Some(zone) = self.find_zone(name.strip_prefix("*.").unwrap_or(name)).await? else {
+            return Ok(None);
+        };
+        if zone.owner_pubkey == wallet {
+            return Ok(Some(zone));
+        }
+
+        let sets = self.record_sets(&name_ancestors(name)).await?;
+        let delegated = sets.values()
+            .any(|set| set.name != zone.domain && set.delegate.as_deref() == Some(wallet));
+        Ok(delegated.then_some(zone))
+    }
+
+    pub async fn get_record_set(&self, name: &str) -> Result<Option<RecordSet>, String> {
+        self.get_records_collection()
+            .find_one(doc! { "_id": name }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Replace the records at `name`, keeping its delegation
+    ///
+    /// Subdomains must be created first; the zone apex always exists.
+    pub async fn put_records(&self, name: &str, zone: &Domain, records: Vec<ResourceRecord>) -> Result<RecordSet, String> {
+        if zone.status(Utc::now()) != DomainStatus::Active {
+            return Err("Domain has expired".to_string());
+        }
+        if records.len() > MAX_RECORDS_PER_NAME {
+            return Err(format!("At most {} records per name", MAX_RECORDS_PER_NAME));
+        }
+        for record in &records {
+            record.validate()?;
+        }
+        if records.iter().any(|r| r.record_type == RecordType::Cname) {
+            if name == zone.domain {
+                return Err("A domain's apex cannot be a CNAME".to_string());
+            }
+            if records.len() > 1 {
+                return Err("A CNAME cannot be combined with other records".to_string());
+            }
+        }
+
+        let existing = self.get_record_set(name).await?;
+        if existing.is_none() && name != zone.domain {
+            return Err("Subdomain does not exist; create it first".to_string());
+        }
+
+        let set = RecordSet {
+            name: name.to_string(),
+            zone: zone.domain.clone(),
+            delegate: existing.and_then(|set| set.delegate),
+            records,
+            updated_at: Utc::now(),
+        };
+        let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+        self.get_records_collection()
+            .replace_one(doc! { "_id": name }, &set, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(set)
+    }
+
+    /// Create `label.parent`, or change who it is delegated to
+    pub async fn upsert_subdomain(
+        &self,
+        parent: &str,
+        label: &str,
+        zone: &Domain,
+        delegate: Option<String>,
+    ) -> Result<RecordSet, String> {
+        let name = normalize_record_name(&format!("{}.{}", label, parent))?;
+        if label.contains('.') || (label.contains('*') && label != "*") {
+            return Err("Subdomain label must be a single label or *".to_string());
+        }
+        if parent.starts_with("*.") {
+            return Err("Wildcards cannot have subdomains".to_string());
+        }
+        if parent != zone.domain && self.get_record_set(parent).await?.is_none() {
+            return Err("Parent subdomain does not exist".to_string());
+        }
+        if let Some(delegate) = &delegate {
+            ApolloValidator::validate_pubkey(delegate)?;
+        }
+
+        let mut set = self.get_record_set(&name).await?.unwrap_or(RecordSet {
+            name: name.clone(),
+            zone: zone.domain.clone(),
+            delegate: None,
+            records: Vec::new(),
+            updated_at: Utc::now(),
+        });
+        set.delegate = delegate;
+        set.updated_at = Utc::now();
+
+        let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+        self.get_records_collection()
+            .replace_one(doc! { "_id": &name }, &set, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(set)
+    }
+
+    /// Direct children of `parent`
+    pub async fn list_subdomains(&self, parent: &str) -> Result<Vec<RecordSet>, String> {
+        let pattern = format!("^[^.]+\\.{}$", escape_name(parent));
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .build();
+        let cursor = self.get_records_collection()
+            .find(doc! { "_id": { "$regex": pattern } }, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Remove a subdomain along with everything below it
+    pub async fn delete_subdomain(&self, name: &str) -> Result<u64, String> {
+        let pattern = format!("\\.{}$", escape_name(name));
+        let result = self.get_records_collection()
+            .delete_many(doc! { "$or": [ { "_id": name }, { "_id": { "$regex": pattern } } ] }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(result.deleted_count)
+    }
+
+    /// Drop every record set in a zone, e.g. once the name is released
+    async fn delete_zone(&self, zone: &str) -> Result<(), String> {
+        self.get_records_collection()
+            .delete_many(doc! { "zone": zone }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    /// Revoke every subdomain delegation in a zone, e.g. once the name is sold
+    async fn clear_delegations(&self, zone: &str) -> Result<(), String> {
+        self.get_records_collection()
+            .update_many(
+                doc! { "zone": zone, "delegate": { "$ne": null } },
+                doc! { "$set": { "delegate": null, "updated_at": mongodb::bson::DateTime::from_chrono(Utc::now()) } },
+                None,
+            )
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    fn get_challenges_collection(&self) -> Collection<DomainChallenge> {
+        self.db.collection::<DomainChallenge>("domain_challenges")
+    }
+
+    /// Start (or repeat) a wallet's claim on a custom domain
+    ///
+    /// Each wallet gets its own token, so competing claims can't overwrite
+    /// one another; whoever publishes their record first wins.
+    pub async fn issue_challenge(
+        &self,
+        domain: &str,
+        owner_pubkey: &str,
+        program_address: &str,
+    ) -> Result<DomainChallenge, String> {
+        let id = format!("{}|{}", domain, owner_pubkey);
+        let collection = self.get_challenges_collection();
+
+        let token = match collection.find_one(doc! { "_id": &id }, None).await
+            .map_err(|e| format!("Database error: {}", e))?
+        {
+            Some(existing) => existing.token,
+            None => hex::encode(rand::random::<[u8; 16]>()),
+        };
+
+        let challenge = DomainChallenge {
+            id: id.clone(),
+            domain: domain.to_string(),
+            owner_pubkey: owner_pubkey.to_string(),
+            program_address: program_address.to_string(),
+            token,
+            created_at: Utc::now(),
+        };
+        let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+        collection.replace_one(doc! { "_id": &id }, &challenge, options).await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(challenge)
+    }
+
+    pub async fn get_challenge(&self, domain: &str, owner_pubkey: &str) -> Result<Option<DomainChallenge>, String> {
+        self.get_challenges_collection()
+            .find_one(doc! { "_id": format!("{}|{}", domain, owner_pubkey) }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Check a wallet's TXT record and, if it's there, hand them the domain
+    ///
+    /// A previous owner who no longer controls DNS loses the domain.
+    pub async fn verify_challenge(
+        &self,
+        challenge: &DomainChallenge,
+        resolver: &dyn TxtResolver,
+        recheck: chrono::Duration,
+    ) -> Result<Domain, String> {
+        if check_challenge(resolver, &challenge.domain, &challenge.token).await? != ChallengeCheck::Present {
+            return Err(format!(
+                "TXT record {} with value {} not found",
+                challenge.record_name(),
+                challenge.record_value()
+            ));
+        }
+
+        let now = Utc::now();
+        let previous = self.get_domain(&challenge.domain).await?;
+        let created_at = previous.as_ref()
+            .filter(|d| d.owner_pubkey == challenge.owner_pubkey)
+            .map_or(now, |d| d.created_at);
+        let (skeleton, blocked) = self.screen_name(&challenge.domain, created_at).await?;
+        let domain = Domain {
+            domain: challenge.domain.clone(),
+            owner_pubkey: challenge.owner_pubkey.clone(),
+            program_address: challenge.program_address.clone(),
+            verified: true,
+            created_at,
+            updated_at: now,
+            expires_at: None,
+            on_chain: false,
+            expiry_notice: None,
+            pending_owner: None,
+            offer_expires_at: None,
+            custom_verification: Some(CustomVerification {
+                token: challenge.token.clone(),
+                verified_at: now,
+                last_checked_at: now,
+                next_check_at: now + recheck,
+                failures: 0,
+            }),
+            skeleton: Some(skeleton),
+            blocked,
+        };
+
+        let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+        self.get_domains_collection()
+            .replace_one(doc! { "_id": &domain.domain }, &domain, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        self.prune_primaries(&domain).await?;
+        if let Some(previous) = previous.filter(|d| d.owner_pubkey != domain.owner_pubkey) {
+            self.notify(&previous.owner_pubkey, &DomainEvent::VerificationRevoked {
+                domain: domain.domain.clone(),
+            }).await;
+        }
+        Ok(domain)
+    }
+
+    /// Point a verified custom domain at another program
+    pub async fn set_program_address(&self, domain: &str, program_address: &str) -> Result<(), String> {
+        let retargeted = self.get_domains_collection()
+            .find_one_and_update(
+                doc! { "_id": domain, "on_chain": false },
+                doc! { "$set": {
+                    "program_address": program_address,
+                    "updated_at": mongodb::bson::DateTime::now()
+                } },
+                mongodb::options::FindOneAndUpdateOptions::builder()
+                    .return_document(mongodb::options::ReturnDocument::After)
+                    .build(),
+            )
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        match retargeted {
+            Some(domain) => self.prune_primaries(&domain).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Re-check custom domains whose TXT record is due, revoking missing ones
+    ///
+    /// Lookup failures are retried on the next run without counting against
+    /// the domain. Returns the number of verifications revoked.
+    pub async fn recheck_custom_domains(
+        &self,
+        resolver: &dyn TxtResolver,
+        recheck: chrono::Duration,
+    ) -> Result<usize, String> {
+        let now = Utc::now();
+        let filter = doc! {
+            "verified": true,
+            "custom_verification.next_check_at": { "$lte": mongodb::bson::DateTime::from_chrono(now) }
+        };
+        let cursor = self.get_domains_collection().find(filter, None).await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        let due: Vec<Domain> = cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let mut revoked = 0;
+        for domain in due {
+            let Some(verification) = &domain.custom_verification else { continue };
+            let check = match check_challenge(resolver, &domain.domain, &verification.token).await {
+                Ok(check) => check,
+                Err(e) => {
+                    tracing::warn!("Challenge check for {} failed: {}", domain.domain, e);
+                    continue;
+                }
+            };
+
+            let (next, revoke) = verification.after_check(check, now, recheck);
+            let next = mongodb::bson::to_bson(&next)
+                .map_err(|e| format!("Serialization error: {}", e))?;
+            self.get_domains_collection()
+                .update_one(
+                    doc! { "_id": &domain.domain },
+                    doc! { "$set": { "custom_verification": next, "verified": !revoke } },
+                    None,
+                )
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+            if revoke {
+                self.prune_primaries(&Domain { verified: false, ..domain.clone() }).await?;
+                self.notify(&domain.owner_pubkey, &DomainEvent::VerificationRevoked {
+                    domain: domain.domain.clone(),
+                }).await;
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    fn get_transfers_collection(&self) -> Collection<DomainTransfer> {
+        self.db.collection::<DomainTransfer>("domain_transfers")
+    }
 
-impl OlympusCA {
-    pub fn new(db: Database) -> Self {
-        Self { db }
+    async fn notify(&self, wallet: &str, event: &DomainEvent) {
+        if let Some(hermes) = &self.hermes {
+            hermes.notify_wallet(wallet, event).await;
+        }
     }
 
-    fn get_domains_collection(&self) -> Collection<Domain> {
-        self.db.collection::<Domain>("domains")
+    /// Append to the transfer history and tell both sides
+    async fn record_transfer(&self, transfer: DomainTransfer) -> Result<(), String> {
+        self.get_transfers_collection().insert_one(&transfer, None).await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let (from, to) = (transfer.from.clone(), transfer.to.clone());
+        let event = DomainEvent::DomainTransfer(transfer.into());
+        self.notify(&from, &event).await;
+        self.notify(&to, &event).await;
+        Ok(())
     }
 
-    /// Register a new domain (Pantheon entry)
-    /// Maps a domain to a Solana program/contract address
-    pub async fn register_domain(
-        &self,
-        domain: &str,
-        owner_pubkey: &str,
-        program_address: &str,
-    ) -> Result<(), String> {
-        // Domain validation is done by Apollo, just check it's not empty
-        if domain.is_empty() {
-            return Err("Domain cannot be empty".to_string());
+    /// Transfer history of a domain, newest first
+    pub async fn transfer_history(&self, domain: &str) -> Result<Vec<DomainTransfer>, String> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "at": -1 })
+            .limit(100)
+            .build();
+        let cursor = self.get_transfers_collection()
+            .find(doc! { "domain": domain }, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    fn get_primaries_collection(&self) -> Collection<PrimaryName> {
+        self.db.collection::<PrimaryName>("primary_names")
+    }
+
+    /// Make `domain` the name `address` reverse-resolves to
+    pub async fn set_primary(&self, kind: PrimaryKind, address: &str, domain: &Domain) -> Result<PrimaryName, String> {
+        if let Some(reason) = PrimaryName::mismatch(kind, address, domain, Utc::now()) {
+            return Err(reason.to_string());
         }
 
-        let collection = self.get_domains_collection();
+        let primary = PrimaryName {
+            address: address.to_string(),
+            kind,
+            domain: domain.domain.clone(),
+            updated_at: Utc::now(),
+        };
+        let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+        self.get_primaries_collection()
+            .replace_one(doc! { "_id": address }, &primary, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(primary)
+    }
+
+    pub async fn get_primary(&self, address: &str) -> Result<Option<PrimaryName>, String> {
+        self.get_primaries_collection()
+            .find_one(doc! { "_id": address }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Remove an address's primary name; false if it had none
+    pub async fn clear_primary(&self, kind: PrimaryKind, address: &str) -> Result<bool, String> {
+        let kind = mongodb::bson::to_bson(&kind)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+        let result = self.get_primaries_collection()
+            .delete_one(doc! { "_id": address, "kind": kind }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Drop primary names `domain` no longer backs, after a transfer or retarget
+    async fn prune_primaries(&self, domain: &Domain) -> Result<(), String> {
         let now = Utc::now();
-        let bson_now = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+        let cursor = self.get_primaries_collection()
+            .find(doc! { "domain": &domain.domain }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        let primaries: Vec<PrimaryName> = cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))?;
 
-        let filter = doc! { "_id": domain };
-        let update = doc! {
-            "$set": {
-                "owner_pubkey": owner_pubkey,
-                "program_address": program_address,
-                "verified": false,
-                "updated_at": bson_now
-            },
-            "$setOnInsert": {
-                "created_at": bson_now
+        let stale: Vec<String> = primaries.into_iter()
+            .filter(|p| PrimaryName::mismatch(p.kind, &p.address, domain, now).is_some())
+            .map(|p| p.address)
+            .collect();
+        if !stale.is_empty() {
+            self.get_primaries_collection()
+                .delete_many(doc! { "_id": { "$in": stale } }, None)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+        Ok(())
+    }
+
+    async fn delete_primaries(&self, domain: &str) -> Result<(), String> {
+        self.get_primaries_collection()
+            .delete_many(doc! { "domain": domain }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    /// Reverse-resolve addresses to their primary names
+    ///
+    /// Programs without a primary fall back to their oldest verified
+    /// domain; wallets only resolve once their owner picks a name.
+    /// Addresses with no usable name are left out.
+    pub async fn reverse_lookup(&self, addresses: &[String]) -> Result<Vec<PrimaryName>, String> {
+        use futures_util::TryStreamExt;
+        let now = Utc::now();
+
+        let primaries: Vec<PrimaryName> = self.get_primaries_collection()
+            .find(doc! { "_id": { "$in": addresses } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let names: Vec<&str> = primaries.iter().map(|p| p.domain.as_str()).collect();
+        let domains: std::collections::HashMap<String, Domain> = self.get_domains_collection()
+            .find(doc! { "_id": { "$in": names } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .try_collect::<Vec<Domain>>()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .into_iter()
+            .map(|d| (d.domain.clone(), d))
+            .collect();
+
+        // Anything that slipped past pruning is dropped on read
+        let (found, stale): (Vec<PrimaryName>, Vec<PrimaryName>) = primaries.into_iter()
+            .partition(|p| domains.get(&p.domain)
+                .is_some_and(|d| PrimaryName::mismatch(p.kind, &p.address, d, now).is_none()));
+        if !stale.is_empty() {
+            let stale: Vec<String> = stale.into_iter().map(|p| p.address).collect();
+            self.get_primaries_collection()
+                .delete_many(doc! { "_id": { "$in": stale } }, None)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        let unresolved: Vec<&String> = addresses.iter()
+            .filter(|a| !found.iter().any(|p| &p.address == *a))
+            .collect();
+        let mut resolved = found;
+        if unresolved.is_empty() {
+            return Ok(resolved);
+        }
+
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+        let candidates: Vec<Domain> = self.get_domains_collection()
+            .find(doc! { "program_address": { "$in": unresolved }, "verified": true }, options)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?
+            .try_collect()
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        for domain in candidates {
+            let address = &domain.program_address;
+            if resolved.iter().any(|p| &p.address == address)
+                || PrimaryName::mismatch(PrimaryKind::Program, address, &domain, now).is_some()
+            {
+                continue;
             }
+            resolved.push(PrimaryName {
+                address: address.clone(),
+                kind: PrimaryKind::Program,
+                domain: domain.domain,
+                updated_at: domain.updated_at,
+            });
+        }
+        Ok(resolved)
+    }
+
+    /// Cache an on-chain domain record (Pantheon entry)
+    /// Maps a domain to a Solana program/contract address
+    ///
+    /// Verification survives a sync only while the owner and target are
+    /// unchanged; anything else has to be proven again.
+    pub async fn cache_domain(&self, record: &DomainAccount) -> Result<Domain, String> {
+        let collection = self.get_domains_collection();
+        let owner = record.owner.to_string();
+        let target = record.target.to_string();
+
+        let previous = self.get_domain(&record.name).await?;
+        let verified = previous
+            .as_ref()
+            .is_some_and(|d| d.verified && d.owner_pubkey == owner && d.program_address == target);
+        let expires_at = unix_to_utc(record.expires_at);
+
+        // A renewal or new owner starts the notice cycle over
+        let expiry_notice = previous
+            .as_ref()
+            .filter(|d| d.owner_pubkey == owner && d.expires_at == Some(expires_at))
+            .and_then(|d| d.expiry_notice);
+
+        // Offers past their deadline can no longer be accepted
+        let now = Utc::now();
+        let offer_expires_at = unix_to_utc(record.offer_expires_at);
+        let pending_owner = record.pending_owner
+            .filter(|_| offer_expires_at > now)
+            .map(|p| p.to_string());
+        let created_at = unix_to_utc(record.registered_at);
+        let (skeleton, blocked) = self.screen_name(&record.name, created_at).await?;
+
+        let domain = Domain {
+            domain: record.name.clone(),
+            owner_pubkey: owner,
+            program_address: target,
+            verified,
+            created_at,
+            updated_at: unix_to_utc(record.updated_at),
+            expires_at: Some(expires_at),
+            on_chain: true,
+            expiry_notice,
+            offer_expires_at: pending_owner.as_ref().map(|_| offer_expires_at),
+            pending_owner,
+            custom_verification: None,
+            skeleton: Some(skeleton),
+            blocked,
         };
 
-        let options = mongodb::options::UpdateOptions::builder()
+        let options = mongodb::options::ReplaceOptions::builder()
             .upsert(true)
             .build();
 
-        collection.update_one(filter, update, options).await
+        collection.replace_one(doc! { "_id": &domain.domain }, &domain, options).await
             .map_err(|e| format!("Database error: {}", e))?;
 
-        Ok(())
+        if let Some(previous) = previous {
+            if previous.owner_pubkey != domain.owner_pubkey
+                || previous.program_address != domain.program_address
+                || previous.verified != domain.verified
+                || previous.blocked != domain.blocked
+            {
+                self.prune_primaries(&domain).await?;
+            }
+            let transfer = transfer_change(&previous, &domain, now);
+            match zone_reset(&previous, &domain, transfer.as_ref()) {
+                Some(ZoneReset::Delegations) => self.clear_delegations(&domain.domain).await?,
+                Some(ZoneReset::Everything) => self.delete_zone(&domain.domain).await?,
+                None => {}
+            }
+            if let Some(transfer) = transfer {
+                self.record_transfer(transfer).await?;
+            }
+        }
+
+        Ok(domain)
+    }
+
+    /// Refresh one domain from chain, dropping the cache entry if it was released
+    pub async fn sync_from_chain(
+        &self,
+        domain: &str,
+        anchor: &AnchorClient,
+    ) -> Result<Option<Domain>, String> {
+        match anchor.fetch_domain(domain)? {
+            Some(record) => self.cache_domain(&record).await.map(Some),
+            None => {
+                let removed = self.get_domains_collection()
+                    .delete_one(doc! { "_id": domain, "on_chain": true }, None)
+                    .await
+                    .map_err(|e| format!("Database error: {}", e))?;
+                if removed.deleted_count > 0 {
+                    self.delete_zone(domain).await?;
+                    self.delete_primaries(domain).await?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Mirror every on-chain domain and drop cached ones that no longer exist
+    pub async fn sync_all(&self, anchor: &AnchorClient) -> Result<usize, String> {
+        let records = anchor.fetch_all_domains()?;
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+
+        for record in &records {
+            self.cache_domain(record).await?;
+        }
+
+        self.get_domains_collection()
+            .delete_many(doc! { "on_chain": true, "_id": { "$nin": names } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        // Records of names that are gone with them
+        let zones = self.get_domains_collection()
+            .distinct("_id", None, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        self.get_records_collection()
+            .delete_many(doc! { "zone": { "$nin": &zones } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        self.get_primaries_collection()
+            .delete_many(doc! { "domain": { "$nin": zones } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(records.len())
     }
 
     /// Get domain by name
@@ -87,13 +1393,15 @@ impl OlympusCA {
             .map_err(|e| format!("Database error: {}", e))
     }
 
-    /// Get domain by program address
+    /// Get the domain a program reverse-resolves to
     pub async fn get_domain_by_program(&self, program_address: &str) -> Result<Option<Domain>, String> {
-        let collection = self.get_domains_collection();
-        let filter = doc! { "program_address": program_address };
-        
-        collection.find_one(filter, None).await
-            .map_err(|e| format!("Database error: {}", e))
+        let primary = self.reverse_lookup(&[program_address.to_string()]).await?
+            .into_iter()
+            .find(|p| p.kind == PrimaryKind::Program);
+        match primary {
+            Some(primary) => self.get_domain(&primary.domain).await,
+            None => Ok(None),
+        }
     }
 
     /// Verify domain ownership (mark as verified after on-chain verification)
@@ -116,31 +1424,6 @@ impl OlympusCA {
         Ok(())
     }
 
-    /// Transfer domain ownership
-    pub async fn transfer_domain(
-        &self,
-        domain: &str,
-        new_owner: &str,
-    ) -> Result<(), String> {
-        let collection = self.get_domains_collection();
-        let now = Utc::now();
-        let bson_now = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
-
-        let filter = doc! { "_id": domain };
-        let update = doc! {
-            "$set": {
-                "owner_pubkey": new_owner,
-                "verified": false, // Require re-verification after transfer
-                "updated_at": bson_now
-            }
-        };
-
-        collection.update_one(filter, update, None).await
-            .map_err(|e| format!("Database error: {}", e))?;
-
-        Ok(())
-    }
-
     /// List domains owned by a wallet
     pub async fn list_owner_domains(&self, owner_pubkey: &str) -> Result<Vec<Domain>, String> {
         let collection = self.get_domains_collection();
@@ -159,6 +1442,131 @@ impl OlympusCA {
         Ok(domains)
     }
 
+    /// Domains with an expiry before `before`, oldest first
+    pub async fn list_expiring(&self, before: DateTime<Utc>) -> Result<Vec<Domain>, String> {
+        let filter = doc! {
+            "expires_at": { "$ne": null, "$lte": mongodb::bson::DateTime::from_chrono(before) }
+        };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "expires_at": 1 })
+            .build();
+
+        let cursor = self.get_domains_collection().find(filter, options).await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    async fn set_expiry_notice(&self, domain: &str, notice: ExpiryNotice) -> Result<(), String> {
+        let notice = mongodb::bson::to_bson(&notice)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+        self.get_domains_collection()
+            .update_one(doc! { "_id": domain }, doc! { "$set": { "expiry_notice": notice } }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(())
+    }
+
+    async fn remove_domain(&self, domain: &str) -> Result<(), String> {
+        self.get_domains_collection()
+            .delete_one(doc! { "_id": domain }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        self.delete_primaries(domain).await?;
+        self.delete_zone(domain).await
+    }
+
+    /// Close out transfer offers nobody accepted in time
+    async fn expire_offers(&self, now: DateTime<Utc>) -> Result<(), String> {
+        let filter = doc! {
+            "pending_owner": { "$ne": null },
+            "offer_expires_at": { "$lte": mongodb::bson::DateTime::from_chrono(now) }
+        };
+        let cursor = self.get_domains_collection().find(filter, None).await
+            .map_err(|e| format!("Database error: {}", e))?;
+        use futures_util::TryStreamExt;
+        let lapsed: Vec<Domain> = cursor.try_collect().await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        for domain in lapsed {
+            self.get_domains_collection()
+                .update_one(
+                    doc! { "_id": &domain.domain },
+                    doc! { "$set": { "pending_owner": null, "offer_expires_at": null } },
+                    None,
+                )
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+
+            let Some(to) = domain.pending_owner else { continue };
+            self.record_transfer(DomainTransfer {
+                domain: domain.domain,
+                from: domain.owner_pubkey,
+                to,
+                action: TransferAction::Expired,
+                offer_expires_at: domain.offer_expires_at,
+                at: now,
+            }).await?;
+        }
+        Ok(())
+    }
+
+    /// Notify owners about upcoming and past expiry, and release lapsed names
+    ///
+    /// On-chain names are released with a `release_domain` transaction
+    /// signed by `releaser`; without one they stay cached (and unresolvable)
+    /// until someone else releases them. Returns the number released.
+    pub async fn sweep_expired(
+        &self,
+        anchor: &AnchorClient,
+        releaser: Option<&Keypair>,
+    ) -> Result<usize, String> {
+        let now = Utc::now();
+        self.expire_offers(now).await?;
+
+        let candidates = self.list_expiring(now + chrono::Duration::days(EXPIRY_WARNING_DAYS)).await?;
+        let mut released = 0;
+
+        for domain in candidates {
+            match domain.status(now) {
+                DomainStatus::Lapsed => {
+                    if domain.on_chain {
+                        let Some(releaser) = releaser else { continue };
+                        let owner = Pubkey::from_str(&domain.owner_pubkey)
+                            .map_err(|e| format!("Invalid owner pubkey: {}", e))?;
+                        if let Err(e) = anchor.release_lapsed_domain(&domain.domain, &owner, releaser) {
+                            tracing::warn!("Could not release {}: {}", domain.domain, e);
+                            continue;
+                        }
+                    }
+                    self.remove_domain(&domain.domain).await?;
+                    self.notify(
+                        &domain.owner_pubkey,
+                        &DomainEvent::DomainReleased { domain: domain.domain.clone() },
+                    ).await;
+                    released += 1;
+                }
+                _ => {
+                    let Some(notice) = domain.notice_due(now) else { continue };
+                    let event = match (notice, domain.expires_at, domain.grace_ends_at()) {
+                        (ExpiryNotice::ExpiringSoon, Some(expires_at), _) => {
+                            DomainEvent::DomainExpiring { domain: domain.domain.clone(), expires_at }
+                        }
+                        (ExpiryNotice::Expired, _, Some(grace_ends_at)) => {
+                            DomainEvent::DomainExpired { domain: domain.domain.clone(), grace_ends_at }
+                        }
+                        _ => continue,
+                    };
+                    self.notify(&domain.owner_pubkey, &event).await;
+                    self.set_expiry_notice(&domain.domain, notice).await?;
+                }
+            }
+        }
+
+        Ok(released)
+    }
+
     /// Search domains
     pub async fn search_domains(&self, query: &str, limit: i64) -> Result<Vec<Domain>, String> {
         let collection = self.get_domains_collection();
@@ -167,7 +1575,14 @@ impl OlympusCA {
                 { "_id": { "$regex": query, "$options": "i" } },
                 { "program_address": { "$regex": query, "$options": "i" } }
             ],
-            "verified": true
+            "verified": true,
+            "blocked": null,
+            "$and": [
+                { "$or": [
+                    { "expires_at": null },
+                    { "expires_at": { "$gt": mongodb::bson::DateTime::now() } }
+                ] }
+            ]
         };
 
         let options = mongodb::options::FindOptions::builder()
@@ -189,3 +1604,367 @@ impl OlympusCA {
     }
 }
 
+
+/// Keep the domains cache in step with the shadow-domains program
+pub fn spawn_domain_sync(
+    db: Database,
+    anchor: Arc<AnchorClient>,
+    hermes: Arc<HermesBroker>,
+    reserved_names: Vec<String>,
+    interval: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let olympus = OlympusCA::new(db)
+            .with_hermes(hermes)
+            .with_reserved_names(reserved_names);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match olympus.sync_all(&anchor).await {
+                Ok(count) => tracing::debug!("Synced {} on-chain domains", count),
+                Err(e) => tracing::warn!("Domain sync failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Periodically notify owners of expiring names and release lapsed ones
+pub fn spawn_domain_sweeper(
+    db: Database,
+    anchor: Arc<AnchorClient>,
+    hermes: Arc<HermesBroker>,
+    releaser: Option<Keypair>,
+    interval: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let olympus = OlympusCA::new(db).with_hermes(hermes);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match olympus.sweep_expired(&anchor, releaser.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Released {} lapsed domains", count),
+                Err(e) => tracing::warn!("Domain sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Keep custom domains' DNS proof current
+pub fn spawn_custom_domain_rechecks(
+    db: Database,
+    resolver: Arc<dyn TxtResolver>,
+    hermes: Arc<HermesBroker>,
+    recheck: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let olympus = OlympusCA::new(db).with_hermes(hermes);
+        let recheck = chrono::Duration::from_std(recheck).unwrap_or(chrono::Duration::days(1));
+        // Due checks are picked up at retry granularity
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(CHALLENGE_RETRY_MINUTES as u64 * 60));
+        loop {
+            ticker.tick().await;
+            match olympus.recheck_custom_domains(resolver.as_ref(), recheck).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Revoked verification of {} custom domains", count),
+                Err(e) => tracing::warn!("Custom domain recheck failed: {}", e),
+            }
+        }
+    });
+}
+
+pub fn unix_to_utc(timestamp: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(timestamp, 0).single().unwrap_or_else(Utc::now)
+}
+
+/// `Option<DateTime<Utc>>` stored as a BSON datetime or null
+mod optional_bson_datetime {
+    use chrono::{DateTime, Utc};
+    use mongodb::bson;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(bson::DateTime::from_chrono).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<bson::DateTime>::deserialize(deserializer)?.map(|d| d.to_chrono()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(expires_at: Option<DateTime<Utc>>) -> Domain {
+        let now = Utc::now();
+        Domain {
+            domain: "alice.shadow".to_string(),
+            owner_pubkey: "owner".to_string(),
+            program_address: "program".to_string(),
+            verified: true,
+            created_at: now,
+            updated_at: now,
+            expires_at,
+            on_chain: true,
+            expiry_notice: None,
+            pending_owner: None,
+            offer_expires_at: None,
+            custom_verification: None,
+            skeleton: None,
+            blocked: None,
+        }
+    }
+
+    #[test]
+    fn status_follows_term_and_grace() {
+        let now = Utc::now();
+        let d = |days: i64| domain(Some(now + chrono::Duration::days(days)));
+
+        assert_eq!(d(10).status(now), DomainStatus::Active);
+        assert_eq!(d(-1).status(now), DomainStatus::GracePeriod);
+        assert_eq!(d(-GRACE_PERIOD_DAYS - 1).status(now), DomainStatus::Lapsed);
+        assert_eq!(domain(None).status(now), DomainStatus::Active);
+    }
+
+    #[test]
+    fn notices_are_sent_once_per_stage() {
+        let now = Utc::now();
+        let mut expiring = domain(Some(now + chrono::Duration::days(3)));
+        assert_eq!(expiring.notice_due(now), Some(ExpiryNotice::ExpiringSoon));
+        expiring.expiry_notice = Some(ExpiryNotice::ExpiringSoon);
+        assert_eq!(expiring.notice_due(now), None);
+
+        // Moving into grace warrants a new notice
+        let later = now + chrono::Duration::days(4);
+        assert_eq!(expiring.notice_due(later), Some(ExpiryNotice::Expired));
+
+        let far = domain(Some(now + chrono::Duration::days(200)));
+        assert_eq!(far.notice_due(now), None);
+    }
+
+    #[test]
+    fn detects_transfer_steps_between_syncs() {
+        let now = Utc::now();
+        let before = domain(Some(now + chrono::Duration::days(100)));
+
+        let mut offered = before.clone();
+        offered.pending_owner = Some("bob".to_string());
+        offered.offer_expires_at = Some(now + chrono::Duration::days(1));
+        let step = transfer_change(&before, &offered, now).unwrap();
+        assert_eq!(step.action, TransferAction::Offered);
+        assert_eq!((step.from.as_str(), step.to.as_str()), ("owner", "bob"));
+        assert!(transfer_change(&offered, &offered, now).is_none());
+
+        let mut accepted = before.clone();
+        accepted.owner_pubkey = "bob".to_string();
+        let step = transfer_change(&offered, &accepted, now).unwrap();
+        assert_eq!(step.action, TransferAction::Completed);
+
+        let step = transfer_change(&offered, &before, now).unwrap();
+        assert_eq!(step.action, TransferAction::Cancelled);
+        let later = now + chrono::Duration::days(2);
+        assert_eq!(transfer_change(&offered, &before, later).unwrap().action, TransferAction::Expired);
+
+        // A different owner without an offer is a re-registration, not a transfer
+        assert!(transfer_change(&before, &accepted, now).is_none());
+
+        // A sale keeps the buyer's records but not the seller's delegations
+        let sale = transfer_change(&offered, &accepted, now);
+        assert_eq!(zone_reset(&offered, &accepted, sale.as_ref()), Some(ZoneReset::Delegations));
+        assert_eq!(zone_reset(&before, &accepted, None), Some(ZoneReset::Everything));
+        let offer = transfer_change(&before, &offered, now);
+        assert_eq!(zone_reset(&before, &offered, offer.as_ref()), None);
+
+        // Stored as BSON dates so history sorts by time; clients still get RFC 3339
+        let stored = mongodb::bson::to_document(&step).unwrap();
+        assert!(stored.get_datetime("at").is_ok());
+        let json = serde_json::to_value(DomainTransferView::from(step)).unwrap();
+        assert_eq!(json["at"], serde_json::json!(now));
+    }
+
+    #[test]
+    fn primary_names_need_the_forward_side() {
+        let now = Utc::now();
+        let active = domain(Some(now + chrono::Duration::days(100)));
+        let mismatch = |kind, address: &str, d: &Domain| PrimaryName::mismatch(kind, address, d, now);
+
+        assert_eq!(mismatch(PrimaryKind::Wallet, "owner", &active), None);
+        assert_eq!(mismatch(PrimaryKind::Program, "program", &active), None);
+        assert!(mismatch(PrimaryKind::Program, "other", &active).is_some());
+
+        // A transfer takes the old owner's primary name with it
+        let mut transferred = active.clone();
+        transferred.owner_pubkey = "bob".to_string();
+        assert!(mismatch(PrimaryKind::Wallet, "owner", &transferred).is_some());
+        assert_eq!(mismatch(PrimaryKind::Wallet, "bob", &transferred), None);
+
+        let mut unverified = active.clone();
+        unverified.verified = false;
+        assert_eq!(mismatch(PrimaryKind::Wallet, "owner", &unverified), None);
+        assert!(mismatch(PrimaryKind::Program, "program", &unverified).is_some());
+
+        let expired = domain(Some(now - chrono::Duration::days(1)));
+        assert!(mismatch(PrimaryKind::Wallet, "owner", &expired).is_some());
+
+        let mut blocked = active.clone();
+        blocked.blocked = Some("'admin' is a reserved name".to_string());
+        assert!(mismatch(PrimaryKind::Wallet, "owner", &blocked).is_some());
+    }
+
+    fn set(name: &str, delegate: Option<&str>, records: Vec<ResourceRecord>) -> (String, RecordSet) {
+        (name.to_string(), RecordSet {
+            name: name.to_string(),
+            zone: "alice.shadow".to_string(),
+            delegate: delegate.map(str::to_string),
+            records,
+            updated_at: Utc::now(),
+        })
+    }
+
+    fn txt(value: &str) -> ResourceRecord {
+        ResourceRecord { record_type: RecordType::Txt, value: value.to_string(), ttl: 60 }
+    }
+
+    #[test]
+    fn walks_labels_to_the_zone() {
+        assert_eq!(name_ancestors("x.app.alice.shadow"), vec![
+            "x.app.alice.shadow".to_string(),
+            "app.alice.shadow".to_string(),
+            "alice.shadow".to_string(),
+        ]);
+        assert!(is_subdomain("app.alice.shadow"));
+        assert!(!is_subdomain("alice.shadow"));
+    }
+
+    #[test]
+    fn resolves_exact_wildcard_and_apex_names() {
+        let zone = domain(Some(Utc::now() + chrono::Duration::days(100)));
+        let sets: std::collections::HashMap<_, _> = [
+            set("app.alice.shadow", Some("bob"), vec![txt("app")]),
+            set("*.app.alice.shadow", None, vec![txt("wild")]),
+        ].into_iter().collect();
+
+        let app = resolve_in_zone("app.alice.shadow", &zone, &sets).unwrap();
+        assert_eq!(app.records, vec![txt("app")]);
+        assert_eq!(app.manager, "bob");
+
+        // Wildcards answer for missing names and inherit the delegation above them
+        let wild = resolve_in_zone("x.app.alice.shadow", &zone, &sets).unwrap();
+        assert_eq!(wild.matched, "*.app.alice.shadow");
+        assert_eq!(wild.manager, "bob");
+
+        assert!(resolve_in_zone("docs.alice.shadow", &zone, &sets).is_none());
+
+        // The apex falls back to its program
+        let apex = resolve_in_zone("alice.shadow", &zone, &sets).unwrap();
+        assert_eq!(apex.records[0].record_type, RecordType::Program);
+        assert_eq!(apex.records[0].value, "program");
+        assert_eq!(apex.manager, "owner");
+    }
+
+    #[test]
+    fn validates_record_values() {
+        let record = |record_type, value: &str| ResourceRecord { record_type, value: value.to_string(), ttl: 300 };
+
+        assert!(record(RecordType::Wallet, "11111111111111111111111111111111").validate().is_ok());
+        assert!(record(RecordType::Wallet, "nope").validate().is_err());
+        assert!(record(RecordType::Content, "ipfs://QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG").validate().is_ok());
+        assert!(record(RecordType::Content, "arweave://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U").validate().is_ok());
+        assert!(record(RecordType::Content, "ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U").validate().is_ok());
+        assert!(record(RecordType::Content, "arweave://not-a-tx").validate().is_err());
+        assert!(record(RecordType::Content, "https://example.com").validate().is_err());
+        assert!(record(RecordType::Redirect, "https://example.com/a").validate().is_ok());
+        assert!(record(RecordType::Redirect, "javascript:alert(1)").validate().is_err());
+        assert!(record(RecordType::Txt, "v=shadow1").validate().is_ok());
+        assert!(record(RecordType::Cname, "gateway.example.com.").validate().is_ok());
+        assert!(record(RecordType::Cname, "not a host").validate().is_err());
+
+        let mut short_ttl = txt("x");
+        short_ttl.ttl = 1;
+        assert!(short_ttl.validate().is_err());
+
+        assert!(validate_record_name("*.alice.shadow").is_ok());
+        assert!(validate_record_name("a.*.alice.shadow").is_err());
+        assert!(validate_record_name("example.com").is_err());
+
+        assert_eq!(normalize_record_name("App.Alice.shadow").unwrap(), "app.alice.shadow");
+        assert_eq!(normalize_record_name("*.Ålice.shadow").unwrap(), "*.xn--lice-poa.shadow");
+        assert!(normalize_record_name("*.*.alice.shadow").is_err());
+        assert!(normalize_record_name("Example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn checks_txt_challenges_through_the_resolver() {
+        let resolver = crate::iris::StaticTxtResolver::default();
+        let check = |token: &'static str| check_challenge(&resolver, "example.com", token);
+
+        assert_eq!(check("abc").await.unwrap(), ChallengeCheck::Missing);
+
+        resolver.set("_shadow-challenge.example.com", vec![
+            "unrelated".to_string(),
+            "shadow-verification=abc".to_string(),
+        ]);
+        assert_eq!(check("abc").await.unwrap(), ChallengeCheck::Present);
+        assert_eq!(check("other").await.unwrap(), ChallengeCheck::Missing);
+
+        resolver.remove("_shadow-challenge.example.com");
+        assert_eq!(check("abc").await.unwrap(), ChallengeCheck::Missing);
+    }
+
+    #[test]
+    fn record_sets_store_bson_dates() {
+        let (_, record_set) = set("alice.shadow", None, vec![txt("x")]);
+        let updated_at = record_set.updated_at;
+
+        let stored = mongodb::bson::to_document(&record_set).unwrap();
+        assert!(stored.get_datetime("updated_at").is_ok());
+        let json = serde_json::to_value(RecordSetView::from(record_set)).unwrap();
+        assert_eq!(json["updated_at"], serde_json::json!(updated_at));
+    }
+
+    #[tokio::test]
+    async fn refuses_records_on_lapsed_domains() {
+        // The status check runs before Mongo is reached
+        let options = mongodb::options::ClientOptions::parse("mongodb://localhost:27017").await.unwrap();
+        let olympus = OlympusCA::new(mongodb::Client::with_options(options).unwrap().database("shadow_test"));
+
+        for days in [-1, -GRACE_PERIOD_DAYS - 1] {
+            let zone = domain(Some(Utc::now() + chrono::Duration::days(days)));
+            let refused = olympus.put_records("alice.shadow", &zone, vec![txt("x")]).await;
+            assert_eq!(refused.unwrap_err(), "Domain has expired");
+        }
+    }
+
+    #[test]
+    fn revokes_after_repeated_missing_records() {
+        let now = Utc::now();
+        let recheck = chrono::Duration::days(1);
+        let mut verification = CustomVerification {
+            token: "abc".to_string(),
+            verified_at: now,
+            last_checked_at: now,
+            next_check_at: now,
+            failures: 0,
+        };
+
+        for attempt in 1..MAX_CHALLENGE_FAILURES {
+            let (next, revoked) = verification.after_check(ChallengeCheck::Missing, now, recheck);
+            assert!(!revoked);
+            assert_eq!(next.failures, attempt);
+            assert!(next.next_check_at < now + recheck);
+            verification = next;
+        }
+
+        // A record that comes back resets the count
+        let (restored, revoked) = verification.after_check(ChallengeCheck::Present, now, recheck);
+        assert!(!revoked);
+        assert_eq!(restored.failures, 0);
+        assert_eq!(restored.next_check_at, now + recheck);
+
+        let (_, revoked) = verification.after_check(ChallengeCheck::Missing, now, recheck);
+        assert!(revoked);
+        assert!(is_custom_domain("example.com"));
+        assert!(!is_custom_domain("alice.shadow"));
+    }
+}
@@ -1,20 +1,29 @@
 // Plutus - God of Wealth and Portfolio
 // Handles portfolio tracking, balance aggregation, and transaction history
 
+use futures_util::TryStreamExt;
 use mongodb::{Collection, Database};
 use mongodb::bson::{doc, DateTime};
+use mongodb::options::{CreateCollectionOptions, FindOptions, TimeseriesGranularity, TimeseriesOptions};
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::str::FromStr;
+use crate::aphrodite::ASSOCIATED_TOKEN_PROGRAM_ID;
+use crate::hephaestus::HephaestusCache;
+use crate::price_oracle::{PriceOracle, SOL_MINT};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Portfolio {
     pub sol_balance: u64, // SOL in lamports
+    pub sol_price_usd: Option<f64>,
     pub sol_value_usd: f64, // Estimated USD value
     pub token_count: usize,
     pub nft_count: usize,
-    pub total_value_usd: f64, // Total portfolio value
+    pub total_value_usd: f64, // SOL plus every priced token
+    pub unpriced_token_count: usize, // Tokens excluded from the total
+    pub prices_stale: bool, // Any price used is older than the staleness threshold
     pub tokens: Vec<TokenBalance>,
     pub nfts: Vec<NFT>,
 }
@@ -26,7 +35,9 @@ pub struct TokenBalance {
     pub decimals: u8,
     pub ui_amount: f64,
     pub symbol: Option<String>,
+    pub price_usd: Option<f64>,
     pub value_usd: Option<f64>,
+    pub price_stale: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -40,23 +51,43 @@ pub struct NFT {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TransactionHistory {
     pub signature: String,
+    pub slot: u64,
     pub timestamp: DateTime,
     pub type_: TransactionType,
-    pub amount: Option<u64>,
+    pub amount: Option<u64>, // Raw units of `mint`, lamports when `mint` is None
+    pub mint: Option<String>,
     pub from: Option<String>,
     pub to: Option<String>,
+    pub fee: Option<u64>,
+    pub balance_changes: Vec<BalanceChange>, // Net movements for the wallet
+    pub programs: Vec<String>, // Top-level programs invoked
     pub status: TransactionStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionHistoryPage {
+    pub transactions: Vec<TransactionHistory>,
+    pub next_cursor: Option<String>, // Pass as `before` for the next page
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
+pub struct BalanceChange {
+    pub mint: String, // SOL is reported under the wrapped SOL mint
+    pub delta: i128,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionType {
-    Transfer,
-    TokenTransfer,
-    NftTransfer,
+    Send,
+    Receive,
     Swap,
+    NftTransfer,
     Stake,
     Unstake,
+    ProgramInteraction,
     Other,
 }
 
@@ -68,14 +99,129 @@ pub enum TransactionStatus {
     Failed,
 }
 
+pub const SNAPSHOTS_COLLECTION: &str = "portfolio_snapshots";
+
+/// Point-in-time valuation of a wallet, stored in a time-series collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortfolioSnapshot {
+    pub wallet_pubkey: String, // Series key
+    pub taken_at: DateTime, // Time field
+    pub total_value_usd: f64,
+    pub prices_stale: bool,
+    pub assets: Vec<AssetSnapshot>, // SOL is listed under the wrapped SOL mint
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AssetSnapshot {
+    pub mint: String,
+    pub amount: u64,
+    pub decimals: u8,
+    pub price_usd: Option<f64>,
+    pub value_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryRange {
+    Day,
+    Week,
+    Month,
+}
+
+impl HistoryRange {
+    pub fn parse(range: &str) -> Result<Self, String> {
+        match range {
+            "24h" | "1d" => Ok(HistoryRange::Day),
+            "7d" => Ok(HistoryRange::Week),
+            "30d" => Ok(HistoryRange::Month),
+            other => Err(format!("Unsupported range '{}', expected 24h, 7d or 30d", other)),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistoryRange::Day => "24h",
+            HistoryRange::Week => "7d",
+            HistoryRange::Month => "30d",
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            HistoryRange::Day => chrono::Duration::hours(24),
+            HistoryRange::Week => chrono::Duration::days(7),
+            HistoryRange::Month => chrono::Duration::days(30),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValuePoint {
+    pub timestamp: DateTime,
+    pub total_value_usd: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValueHistory {
+    pub range: String,
+    pub points: Vec<ValuePoint>, // Oldest first
+    pub change_usd: Option<f64>,
+    pub change_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetChange {
+    pub mint: String,
+    pub start_amount: f64, // UI units
+    pub end_amount: f64,
+    pub start_value_usd: Option<f64>,
+    pub end_value_usd: Option<f64>,
+    pub value_change_usd: Option<f64>,
+    pub price_change_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetPnl {
+    pub mint: String,
+    pub quantity: f64, // UI units still held per the analyzed transfers
+    pub cost_basis_usd: f64,
+    pub current_value_usd: Option<f64>,
+    pub realized_pnl_usd: f64,
+    pub unrealized_pnl_usd: Option<f64>,
+    pub complete: bool, // False when some transfers had no historical price, or only a later one
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PnlReport {
+    pub transactions_analyzed: usize,
+    pub since: Option<DateTime>, // Oldest transfer considered
+    pub realized_pnl_usd: f64,
+    pub unrealized_pnl_usd: f64,
+    pub assets: Vec<AssetPnl>,
+}
+
 pub struct PlutusPortfolioManager {
     db: Arc<Database>,
     solana_rpc_url: String,
+    das_rpc_url: String,
+    price_oracle: Arc<PriceOracle>,
+    cache: Option<Arc<HephaestusCache>>,
 }
 
 impl PlutusPortfolioManager {
-    pub fn new(db: Arc<Database>, solana_rpc_url: String) -> Self {
-        Self { db, solana_rpc_url }
+    pub fn new(db: Arc<Database>, solana_rpc_url: String, price_oracle: Arc<PriceOracle>) -> Self {
+        Self { db, das_rpc_url: solana_rpc_url.clone(), solana_rpc_url, price_oracle, cache: None }
+    }
+
+    /// Find NFTs through a Metaplex DAS endpoint other than the plain RPC
+    pub fn with_das_rpc_url(mut self, das_rpc_url: String) -> Self {
+        self.das_rpc_url = das_rpc_url;
+        self
+    }
+
+    /// Keep settled history between P&L requests so only new signatures are fetched
+    pub fn with_cache(mut self, cache: Arc<HephaestusCache>) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
     /// Get complete portfolio for a wallet
@@ -95,7 +241,8 @@ impl PlutusPortfolioManager {
         let nft_manager = AphroditeNFTManager::new(
             Arc::clone(&self.db),
             self.solana_rpc_url.clone(),
-        );
+        )
+        .with_das_rpc_url(self.das_rpc_url.clone());
 
         // Get SOL balance
         let sol_balance = client
@@ -115,28 +262,45 @@ impl PlutusPortfolioManager {
             .await
             .map_err(|e| format!("Failed to get NFTs: {}", e))?;
 
-        // Calculate USD values (simplified - in production use price APIs)
-        let sol_price_usd = self.get_sol_price().await.unwrap_or(100.0);
-        let sol_value_usd = (sol_balance as f64 / 1_000_000_000.0) * sol_price_usd;
+        // Price SOL and every token in one oracle round
+        let mut mints: Vec<String> = tokens.iter().map(|t| t.mint.clone()).collect();
+        mints.push(SOL_MINT.to_string());
+        let prices = self.price_oracle.get_prices(&mints).await;
 
-        // Calculate total value
-        let total_value_usd = sol_value_usd;
-        // Token USD values would be fetched from price API in production
+        let sol_price = prices.get(SOL_MINT);
+        let sol_value_usd = sol_price
+            .map(|p| (sol_balance as f64 / 1_000_000_000.0) * p.price_usd)
+            .unwrap_or(0.0);
+        let mut prices_stale = sol_price.is_some_and(|p| p.stale);
 
-        Ok(Portfolio {
-            sol_balance,
-            sol_value_usd,
-            token_count: tokens.len(),
-            nft_count: nfts.len(),
-            total_value_usd: total_value_usd,
-            tokens: tokens.into_iter().map(|t| TokenBalance {
+        let tokens: Vec<TokenBalance> = tokens.into_iter().map(|t| {
+            let price = prices.get(&t.mint);
+            TokenBalance {
+                price_usd: price.map(|p| p.price_usd),
+                value_usd: price.map(|p| t.ui_amount * p.price_usd),
+                price_stale: price.is_some_and(|p| p.stale),
                 mint: t.mint,
                 amount: t.amount,
                 decimals: t.decimals,
                 ui_amount: t.ui_amount,
                 symbol: t.symbol,
-                value_usd: None, // Would fetch from price API
-            }).collect(),
+            }
+        }).collect();
+
+        let tokens_value_usd: f64 = tokens.iter().filter_map(|t| t.value_usd).sum();
+        let unpriced_token_count = tokens.iter().filter(|t| t.value_usd.is_none()).count();
+        prices_stale |= tokens.iter().any(|t| t.price_stale);
+
+        Ok(Portfolio {
+            sol_balance,
+            sol_price_usd: sol_price.map(|p| p.price_usd),
+            sol_value_usd,
+            token_count: tokens.len(),
+            nft_count: nfts.len(),
+            total_value_usd: sol_value_usd + tokens_value_usd,
+            unpriced_token_count,
+            prices_stale,
+            tokens,
             nfts: nfts.into_iter().map(|n| NFT {
                 mint: n.mint,
                 name: n.name,
@@ -146,98 +310,1180 @@ impl PlutusPortfolioManager {
         })
     }
 
-    /// Get transaction history for a wallet
+    /// Get a page of classified transaction history for a wallet
+    ///
+    /// Pages run newest to oldest. Pass the returned `next_cursor` back as
+    /// `before` to continue; `until` stops the page at a known signature.
     pub async fn get_transaction_history(
         &self,
         wallet_pubkey: &str,
         limit: Option<u32>,
-    ) -> Result<Vec<TransactionHistory>, String> {
+        before: Option<&str>,
+        until: Option<&str>,
+    ) -> Result<TransactionHistoryPage, String> {
         use crate::solana::SolanaClient;
         let client = SolanaClient::new(self.solana_rpc_url.clone());
 
         let pubkey = Pubkey::from_str(wallet_pubkey)
             .map_err(|_| "Invalid pubkey".to_string())?;
+        let limit = limit.unwrap_or(50).clamp(1, MAX_HISTORY_PAGE);
 
-        // Get signatures for address
         let signatures = client
-            .get_signatures_for_address(&pubkey, limit.unwrap_or(50))
+            .get_signatures_for_address(&pubkey, limit, before, until)
             .await
             .map_err(|e| format!("Failed to get signatures: {}", e))?;
 
-        let mut history = Vec::new();
+        let signature_list: Vec<String> = signatures.iter().map(|s| s.signature.clone()).collect();
+        let transactions = client
+            .get_parsed_transactions(&signature_list)
+            .await
+            .map_err(|e| format!("Failed to get transactions: {}", e))?;
 
-        for sig in signatures {
-            // Parse transaction to determine type
-            let tx_type = TransactionType::Other; // Would parse transaction to determine
-            let status = TransactionStatus::Confirmed; // Would check confirmation status
+        let next_cursor = if signatures.len() as u32 == limit {
+            signatures.last().map(|s| s.signature.clone())
+        } else {
+            None
+        };
 
-            // Convert Unix timestamp to DateTime
-            let timestamp = if let Some(block_time) = sig.block_time {
-                // MongoDB DateTime uses milliseconds since epoch
-                DateTime::from_millis(block_time * 1000)
+        let mut history = Vec::with_capacity(signatures.len());
+
+        for (sig, tx) in signatures.into_iter().zip(transactions) {
+            let parsed = tx.as_ref().map(|tx| classify_transaction(wallet_pubkey, tx));
+
+            // The transaction's own meta is authoritative; fall back to the
+            // signature status when the node has already pruned the transaction
+            let error = match &parsed {
+                Some(p) => p.error.clone(),
+                None => sig.err.clone(),
+            };
+            let status = if error.is_some() {
+                TransactionStatus::Failed
+            } else if tx.is_none() || sig.confirmation_status.as_deref() == Some("processed") {
+                TransactionStatus::Pending
             } else {
-                DateTime::now()
+                TransactionStatus::Confirmed
             };
-            
+
+            // MongoDB DateTime uses milliseconds since epoch
+            let timestamp = sig.block_time
+                .map(|t| DateTime::from_millis(t * 1000))
+                .unwrap_or_else(DateTime::now);
+
+            let parsed = parsed.unwrap_or_default();
             history.push(TransactionHistory {
                 signature: sig.signature,
+                slot: sig.slot,
                 timestamp,
-                type_: tx_type,
-                amount: None,
-                from: None,
-                to: None,
+                type_: parsed.type_,
+                amount: parsed.amount,
+                mint: parsed.mint,
+                from: parsed.from,
+                to: parsed.to,
+                fee: parsed.fee,
+                balance_changes: parsed.balance_changes,
+                programs: parsed.programs,
                 status,
+                error,
             });
         }
 
-        Ok(history)
+        Ok(TransactionHistoryPage { transactions: history, next_cursor })
     }
 
-    /// Get SOL price in USD (cached)
-    async fn get_sol_price(&self) -> Result<f64, String> {
-        let collection: Collection<PriceCache> = self.db.collection("price_cache");
+    pub fn get_snapshots_collection(&self) -> Collection<PortfolioSnapshot> {
+        self.db.collection::<PortfolioSnapshot>(SNAPSHOTS_COLLECTION)
+    }
 
-        // Check cache (5 minute TTL)
-        if let Some(cached) = collection
-            .find_one(doc! { "symbol": "SOL" }, None)
+    /// Create the snapshot time-series collection if it does not exist yet
+    pub async fn ensure_snapshot_collection(db: &Database) -> Result<(), String> {
+        let existing = db
+            .list_collection_names(doc! { "name": SNAPSHOTS_COLLECTION })
             .await
-            .map_err(|e| format!("Database error: {}", e))? {
-            let age = (DateTime::now().timestamp_millis() - cached.updated_at.timestamp_millis()) / 1000;
-            if age < 300 { // 5 minutes
-                return Ok(cached.price);
-            }
+            .map_err(|e| format!("Database error: {}", e))?;
+        if !existing.is_empty() {
+            return Ok(());
         }
 
-        // Fetch from API (simplified - in production use CoinGecko or similar)
-        let price = 100.0; // Placeholder
+        let timeseries = TimeseriesOptions::builder()
+            .time_field("taken_at".to_string())
+            .meta_field(Some("wallet_pubkey".to_string()))
+            .granularity(Some(TimeseriesGranularity::Hours))
+            .build();
+        let options = CreateCollectionOptions::builder()
+            .timeseries(timeseries)
+            .expire_after_seconds(std::time::Duration::from_secs(SNAPSHOT_RETENTION_DAYS * 24 * 3600))
+            .build();
 
-        // Cache it
-        let cache = PriceCache {
-            symbol: "SOL".to_string(),
-            price,
-            updated_at: DateTime::now(),
-        };
+        db.create_collection(SNAPSHOTS_COLLECTION, options)
+            .await
+            .map_err(|e| format!("Failed to create snapshot collection: {}", e))
+    }
+
+    /// Value the wallet now without storing anything
+    pub async fn current_snapshot(&self, wallet_pubkey: &str) -> Result<PortfolioSnapshot, String> {
+        let portfolio = self.get_portfolio(wallet_pubkey).await?;
+
+        let mut assets = vec![AssetSnapshot {
+            mint: SOL_MINT.to_string(),
+            amount: portfolio.sol_balance,
+            decimals: 9,
+            price_usd: portfolio.sol_price_usd,
+            value_usd: portfolio.sol_price_usd.map(|_| portfolio.sol_value_usd),
+        }];
+        assets.extend(portfolio.tokens.into_iter().map(|t| AssetSnapshot {
+            mint: t.mint,
+            amount: t.amount,
+            decimals: t.decimals,
+            price_usd: t.price_usd,
+            value_usd: t.value_usd,
+        }));
+
+        Ok(PortfolioSnapshot {
+            wallet_pubkey: wallet_pubkey.to_string(),
+            taken_at: DateTime::now(),
+            total_value_usd: portfolio.total_value_usd,
+            prices_stale: portfolio.prices_stale,
+            assets,
+        })
+    }
+
+    /// Value the wallet now and record it in the time series
+    pub async fn take_snapshot(&self, wallet_pubkey: &str) -> Result<PortfolioSnapshot, String> {
+        let snapshot = self.current_snapshot(wallet_pubkey).await?;
+        self.get_snapshots_collection()
+            .insert_one(&snapshot, None)
+            .await
+            .map_err(|e| format!("Failed to store snapshot: {}", e))?;
+        Ok(snapshot)
+    }
 
-        collection
-            .replace_one(
-                doc! { "symbol": "SOL" },
-                &cache,
-                mongodb::options::ReplaceOptions::builder()
-                    .upsert(true)
-                    .build(),
-            )
+    /// Snapshots for a wallet taken at or after `since`, oldest first
+    pub async fn get_snapshots(
+        &self,
+        wallet_pubkey: &str,
+        since: DateTime,
+    ) -> Result<Vec<PortfolioSnapshot>, String> {
+        let options = FindOptions::builder()
+            .sort(doc! { "taken_at": 1 })
+            .build();
+        let cursor = self.get_snapshots_collection()
+            .find(doc! { "wallet_pubkey": wallet_pubkey, "taken_at": { "$gte": since } }, options)
             .await
             .map_err(|e| format!("Database error: {}", e))?;
+        cursor.try_collect().await.map_err(|e| format!("Database error: {}", e))
+    }
 
-        Ok(price)
+    /// Portfolio value over the range, from stored snapshots
+    pub async fn get_value_history(
+        &self,
+        wallet_pubkey: &str,
+        range: HistoryRange,
+    ) -> Result<ValueHistory, String> {
+        let snapshots = self.get_snapshots(wallet_pubkey, range_start(range)).await?;
+
+        let points: Vec<ValuePoint> = snapshots
+            .iter()
+            .map(|s| ValuePoint { timestamp: s.taken_at, total_value_usd: s.total_value_usd })
+            .collect();
+        let (change_usd, change_pct) = match (points.first(), points.last()) {
+            (Some(first), Some(last)) if points.len() > 1 => {
+                let change = last.total_value_usd - first.total_value_usd;
+                let pct = (first.total_value_usd > 0.0).then(|| change / first.total_value_usd * 100.0);
+                (Some(change), pct)
+            }
+            _ => (None, None),
+        };
+
+        Ok(ValueHistory { range: range.label().to_string(), points, change_usd, change_pct })
+    }
+
+    /// Per-asset change between the oldest snapshot in range and now
+    pub async fn get_asset_changes(
+        &self,
+        wallet_pubkey: &str,
+        range: HistoryRange,
+    ) -> Result<Vec<AssetChange>, String> {
+        let snapshots = self.get_snapshots(wallet_pubkey, range_start(range)).await?;
+        let current = self.current_snapshot(wallet_pubkey).await?;
+        Ok(asset_changes(snapshots.first(), &current))
+    }
+
+    /// Realized and unrealized P&L using average cost over the wallet's parsed transfers
+    ///
+    /// Historical prices come from the wallet's own snapshots, so P&L only
+    /// becomes meaningful once the snapshot job has been running for a while.
+    pub async fn get_pnl(&self, wallet_pubkey: &str) -> Result<PnlReport, String> {
+        let transactions = self.pnl_history(wallet_pubkey).await?;
+
+        let mut events: Vec<PnlEvent> = transactions
+            .iter()
+            .filter(|tx| tx.status == TransactionStatus::Confirmed)
+            .flat_map(|tx| tx.balance_changes.iter().map(move |change| PnlEvent {
+                mint: change.mint.clone(),
+                delta: change.delta as f64 / 10f64.powi(change.decimals as i32),
+                timestamp_ms: tx.timestamp.timestamp_millis(),
+                price_usd: None,
+                price_estimated: false,
+            }))
+            .collect();
+        events.sort_by_key(|e| e.timestamp_ms);
+
+        let since = events.first().map(|e| DateTime::from_millis(e.timestamp_ms));
+        let snapshots = match since {
+            Some(since) => self.get_snapshots(wallet_pubkey, since).await?,
+            None => Vec::new(),
+        };
+        let prices = PriceHistory::from_snapshots(&snapshots);
+        for event in events.iter_mut() {
+            if let Some((price, exact)) = prices.price_at(&event.mint, event.timestamp_ms) {
+                event.price_usd = Some(price);
+                event.price_estimated = !exact;
+            }
+        }
+
+        let current = self.current_snapshot(wallet_pubkey).await?;
+        let assets = compute_pnl(&events, &current, &prices);
+
+        Ok(PnlReport {
+            transactions_analyzed: transactions.len(),
+            since,
+            realized_pnl_usd: assets.iter().map(|a| a.realized_pnl_usd).sum(),
+            unrealized_pnl_usd: assets.iter().filter_map(|a| a.unrealized_pnl_usd).sum(),
+            assets,
+        })
+    }
+
+    /// Up to `MAX_PNL_TRANSACTIONS` of the wallet's newest transactions
+    ///
+    /// Settled transactions never change, so with a cache only signatures newer
+    /// than the last cached one are fetched.
+    async fn pnl_history(&self, wallet_pubkey: &str) -> Result<Vec<TransactionHistory>, String> {
+        let key = format!("pnl:txs:{}", wallet_pubkey);
+        let cached: Vec<TransactionHistory> = match &self.cache {
+            Some(cache) => cache.get(&key).await
+                .and_then(|c| serde_json::from_slice(&c.content).ok())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let until = cached.first().map(|tx| tx.signature.clone());
+
+        let mut fresh = Vec::new();
+        let mut before: Option<String> = None;
+        let mut caught_up = true;
+        while fresh.len() < MAX_PNL_TRANSACTIONS {
+            let page = self
+                .get_transaction_history(wallet_pubkey, Some(MAX_HISTORY_PAGE), before.as_deref(), until.as_deref())
+                .await?;
+            fresh.extend(page.transactions);
+            match page.next_cursor {
+                Some(cursor) => before = Some(cursor),
+                None => break,
+            }
+            caught_up = fresh.len() < MAX_PNL_TRANSACTIONS;
+        }
+
+        let (transactions, settled) = merge_history(fresh, if caught_up { cached } else { Vec::new() });
+        if let Some(cache) = &self.cache {
+            let bytes = serde_json::to_vec(&transactions[settled..]).map_err(|e| e.to_string())?;
+            cache.set(key, bytes, "application/json".to_string(), Some(PNL_HISTORY_CACHE_TTL)).await?;
+        }
+        Ok(transactions)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct PriceCache {
-    symbol: String,
-    price: f64,
-    updated_at: DateTime,
+/// Newest-first `fresh` transactions ahead of the `cached` ones, capped at
+/// `MAX_PNL_TRANSACTIONS`, and the index from which everything is settled
+///
+/// Pending transactions may still change, so they and anything newer stay out
+/// of the cache and are fetched again next time.
+fn merge_history(fresh: Vec<TransactionHistory>, cached: Vec<TransactionHistory>) -> (Vec<TransactionHistory>, usize) {
+    let mut transactions = fresh;
+    transactions.extend(cached);
+    transactions.truncate(MAX_PNL_TRANSACTIONS);
+
+    let settled = transactions.iter()
+        .rposition(|tx| tx.status == TransactionStatus::Pending)
+        .map_or(0, |i| i + 1);
+    (transactions, settled)
+}
+
+/// Largest page of history we fetch and parse in one request
+const MAX_HISTORY_PAGE: u32 = 100;
+
+/// SOL movements smaller than this alongside token movements are treated as
+/// rent for token accounts rather than part of the transfer
+const SOL_RENT_NOISE_LAMPORTS: u64 = 5_000_000;
+
+const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
+const STAKE_PROGRAM_ID: &str = "Stake11111111111111111111111111111111111111";
+const COMPUTE_BUDGET_PROGRAM_ID: &str = "ComputeBudget111111111111111111111111111111";
+
+/// Programs that only move or hold assets; anything else makes a transaction
+/// a program interaction rather than a plain send
+const TRANSFER_PROGRAM_IDS: &[&str] = &[
+    SYSTEM_PROGRAM_ID,
+    COMPUTE_BUDGET_PROGRAM_ID,
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+    "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+];
+
+/// Result of decoding one transaction from the wallet's point of view
+#[derive(Debug, Clone)]
+pub struct ParsedTransaction {
+    pub type_: TransactionType,
+    pub amount: Option<u64>,
+    pub mint: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub fee: Option<u64>,
+    pub error: Option<String>,
+    pub balance_changes: Vec<BalanceChange>,
+    pub programs: Vec<String>,
 }
 
+impl Default for ParsedTransaction {
+    fn default() -> Self {
+        Self {
+            type_: TransactionType::Other,
+            amount: None,
+            mint: None,
+            from: None,
+            to: None,
+            fee: None,
+            error: None,
+            balance_changes: Vec::new(),
+            programs: Vec::new(),
+        }
+    }
+}
+
+/// Net balance movement for one owner and asset
+#[derive(Debug, Default)]
+struct Movement {
+    delta: i128,
+    decimals: u8,
+}
 
+/// Classify a `jsonParsed` transaction for `wallet`
+///
+/// Balance deltas (pre/post SOL and token balances) decide the type for
+/// successful transactions. Failed transactions only move the fee, so their
+/// type comes from decoding the instructions they attempted.
+pub fn classify_transaction(wallet: &str, tx: &serde_json::Value) -> ParsedTransaction {
+    let meta = &tx["meta"];
+    let message = &tx["transaction"]["message"];
+
+    let account_keys: Vec<&str> = message["accountKeys"]
+        .as_array()
+        .map(|keys| keys.iter().filter_map(|k| k["pubkey"].as_str().or_else(|| k.as_str())).collect())
+        .unwrap_or_default();
+    let wallet_signed = message["accountKeys"]
+        .as_array()
+        .and_then(|keys| keys.iter().find(|k| k["pubkey"].as_str() == Some(wallet)))
+        .is_some_and(|k| k["signer"].as_bool().unwrap_or(false));
+
+    let fee = meta["fee"].as_u64();
+    let error = meta.get("err").filter(|e| !e.is_null()).map(|e| e.to_string());
+
+    let instructions: Vec<&serde_json::Value> = message["instructions"]
+        .as_array()
+        .map(|ixs| ixs.iter().collect())
+        .unwrap_or_default();
+    let mut programs: Vec<String> = Vec::new();
+    for ix in &instructions {
+        if let Some(program_id) = ix["programId"].as_str() {
+            if program_id != COMPUTE_BUDGET_PROGRAM_ID && !programs.iter().any(|p| p == program_id) {
+                programs.push(program_id.to_string());
+            }
+        }
+    }
+
+    // SOL movements per account; the fee payer's delta excludes the fee
+    let mut sol: HashMap<&str, i128> = HashMap::new();
+    if let (Some(pre), Some(post)) = (meta["preBalances"].as_array(), meta["postBalances"].as_array()) {
+        for (i, key) in account_keys.iter().enumerate() {
+            let before = pre.get(i).and_then(|v| v.as_u64()).unwrap_or(0) as i128;
+            let after = post.get(i).and_then(|v| v.as_u64()).unwrap_or(0) as i128;
+            let mut delta = after - before;
+            if i == 0 {
+                delta += fee.unwrap_or(0) as i128;
+            }
+            if delta != 0 {
+                *sol.entry(key).or_default() += delta;
+            }
+        }
+    }
+
+    // Token movements keyed by (owner, mint)
+    let mut tokens: HashMap<(String, String), Movement> = HashMap::new();
+    for (field, sign) in [("preTokenBalances", -1i128), ("postTokenBalances", 1i128)] {
+        for balance in meta[field].as_array().into_iter().flatten() {
+            let (Some(owner), Some(mint)) = (balance["owner"].as_str(), balance["mint"].as_str()) else {
+                continue;
+            };
+            let amount = balance["uiTokenAmount"]["amount"]
+                .as_str()
+                .and_then(|a| a.parse::<i128>().ok())
+                .unwrap_or(0);
+            let movement = tokens.entry((owner.to_string(), mint.to_string())).or_default();
+            movement.delta += sign * amount;
+            movement.decimals = balance["uiTokenAmount"]["decimals"].as_u64().unwrap_or(0) as u8;
+        }
+    }
+
+    let mut balance_changes: Vec<BalanceChange> = tokens
+        .iter()
+        .filter(|((owner, _), m)| owner == wallet && m.delta != 0)
+        .map(|((_, mint), m)| BalanceChange { mint: mint.clone(), delta: m.delta, decimals: m.decimals })
+        .collect();
+    balance_changes.sort_by(|a, b| a.mint.cmp(&b.mint));
+
+    let wallet_sol = sol.get(wallet).copied().unwrap_or(0);
+    let sol_is_noise = !balance_changes.is_empty() && wallet_sol.unsigned_abs() < SOL_RENT_NOISE_LAMPORTS as u128;
+    if wallet_sol != 0 && !sol_is_noise {
+        balance_changes.insert(0, BalanceChange { mint: SOL_MINT.to_string(), delta: wallet_sol, decimals: 9 });
+    }
+
+    let mut parsed = ParsedTransaction {
+        fee,
+        error,
+        programs,
+        ..Default::default()
+    };
+
+    if let Some(stake_type) = stake_instruction_type(&instructions) {
+        parsed.type_ = stake_type;
+        parsed.amount = Some(wallet_sol.unsigned_abs() as u64).filter(|a| *a > 0);
+        parsed.from = Some(wallet.to_string());
+        parsed.to = counterparty(&sol_movements(&sol), wallet, wallet_sol);
+        parsed.balance_changes = balance_changes;
+        return parsed;
+    }
+
+    let outgoing: Vec<&BalanceChange> = balance_changes.iter().filter(|c| c.delta < 0).collect();
+    let incoming: Vec<&BalanceChange> = balance_changes.iter().filter(|c| c.delta > 0).collect();
+    let only_transfer_programs = parsed.programs.iter().all(|p| TRANSFER_PROGRAM_IDS.contains(&p.as_str()));
+
+    // Prefer a token over SOL as the headline asset
+    let primary = |changes: &[&BalanceChange]| -> Option<BalanceChange> {
+        changes.iter().find(|c| c.mint != SOL_MINT).or_else(|| changes.first()).map(|c| (*c).clone())
+    };
+
+    let headline = match (outgoing.is_empty(), incoming.is_empty()) {
+        (false, false) => {
+            parsed.type_ = TransactionType::Swap;
+            parsed.from = Some(wallet.to_string());
+            primary(&outgoing)
+        }
+        (false, true) => {
+            let change = primary(&outgoing);
+            parsed.type_ = match &change {
+                Some(c) if is_nft_change(c) => TransactionType::NftTransfer,
+                _ if only_transfer_programs => TransactionType::Send,
+                _ => TransactionType::ProgramInteraction,
+            };
+            change
+        }
+        (true, false) => {
+            let change = primary(&incoming);
+            parsed.type_ = match &change {
+                Some(c) if is_nft_change(c) => TransactionType::NftTransfer,
+                _ if only_transfer_programs || !wallet_signed => TransactionType::Receive,
+                _ => TransactionType::ProgramInteraction,
+            };
+            change
+        }
+        (true, true) => {
+            // Nothing moved: either a failed transfer or a pure program call
+            if let Some(intent) = transfer_instruction_intent(wallet, &instructions) {
+                parsed.type_ = intent.type_;
+                parsed.amount = intent.amount;
+                parsed.mint = intent.mint;
+                parsed.from = intent.from;
+                parsed.to = intent.to;
+            } else if !parsed.programs.is_empty() && !only_transfer_programs {
+                parsed.type_ = TransactionType::ProgramInteraction;
+            }
+            None
+        }
+    };
+
+    if let Some(change) = headline {
+        if parsed.type_ != TransactionType::Swap {
+            let other = if change.mint == SOL_MINT {
+                counterparty(&sol_movements(&sol), wallet, change.delta)
+            } else {
+                let movements: Vec<(&str, i128)> = tokens
+                    .iter()
+                    .filter(|((_, mint), _)| *mint == change.mint)
+                    .map(|((owner, _), m)| (owner.as_str(), m.delta))
+                    .collect();
+                counterparty(&movements, wallet, change.delta)
+            };
+            if change.delta < 0 {
+                parsed.from = Some(wallet.to_string());
+                parsed.to = other;
+            } else {
+                parsed.from = other;
+                parsed.to = Some(wallet.to_string());
+            }
+        }
+        parsed.amount = u64::try_from(change.delta.unsigned_abs()).ok();
+        parsed.mint = Some(change.mint).filter(|m| m != SOL_MINT);
+    }
+
+    parsed.balance_changes = balance_changes;
+    parsed
+}
+
+fn sol_movements<'a>(sol: &HashMap<&'a str, i128>) -> Vec<(&'a str, i128)> {
+    sol.iter().map(|(k, v)| (*k, *v)).collect()
+}
+
+/// The other party with the largest movement opposite to the wallet's
+fn counterparty(movements: &[(&str, i128)], wallet: &str, wallet_delta: i128) -> Option<String> {
+    movements
+        .iter()
+        .filter(|(owner, delta)| *owner != wallet && delta.signum() == -wallet_delta.signum())
+        .max_by_key(|(_, delta)| delta.unsigned_abs())
+        .map(|(owner, _)| owner.to_string())
+}
+
+fn is_nft_change(change: &BalanceChange) -> bool {
+    change.mint != SOL_MINT && change.decimals == 0 && change.delta.unsigned_abs() == 1
+}
+
+fn stake_instruction_type(instructions: &[&serde_json::Value]) -> Option<TransactionType> {
+    instructions
+        .iter()
+        .filter(|ix| ix["programId"].as_str() == Some(STAKE_PROGRAM_ID))
+        .find_map(|ix| match ix["parsed"]["type"].as_str()? {
+            "deactivate" | "withdraw" | "deactivateDelinquent" => Some(TransactionType::Unstake),
+            "initialize" | "initializeChecked" | "delegate" | "split" | "merge" => Some(TransactionType::Stake),
+            _ => None,
+        })
+}
+
+/// What a transaction tried to move, read from its parsed transfer instructions
+fn transfer_instruction_intent(wallet: &str, instructions: &[&serde_json::Value]) -> Option<ParsedTransaction> {
+    instructions.iter().find_map(|ix| {
+        let info = &ix["parsed"]["info"];
+        let (amount, mint, from, to) = match (ix["program"].as_str()?, ix["parsed"]["type"].as_str()?) {
+            ("system", "transfer" | "transferWithSeed") => (
+                info["lamports"].as_u64(),
+                None,
+                info["source"].as_str()?,
+                info["destination"].as_str()?,
+            ),
+            ("spl-token", "transfer") => (
+                info["amount"].as_str().and_then(|a| a.parse().ok()),
+                None,
+                info["authority"].as_str().or_else(|| info["multisigAuthority"].as_str())?,
+                info["destination"].as_str()?,
+            ),
+            ("spl-token", "transferChecked") => (
+                info["tokenAmount"]["amount"].as_str().and_then(|a| a.parse().ok()),
+                info["mint"].as_str().map(|m| m.to_string()),
+                info["authority"].as_str().or_else(|| info["multisigAuthority"].as_str())?,
+                info["destination"].as_str()?,
+            ),
+            _ => return None,
+        };
+        let type_ = if from == wallet {
+            TransactionType::Send
+        } else if to == wallet {
+            TransactionType::Receive
+        } else {
+            return None;
+        };
+        Some(ParsedTransaction {
+            type_,
+            amount,
+            mint,
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+            ..Default::default()
+        })
+    })
+}
+
+/// Transfers walked when computing P&L
+const MAX_PNL_TRANSACTIONS: usize = 300;
+
+/// How long a wallet's settled history is reused for P&L
+const PNL_HISTORY_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Snapshots older than this are dropped by the time-series TTL
+const SNAPSHOT_RETENTION_DAYS: u64 = 90;
+
+/// Snapshot every tracked wallet on a fixed interval
+///
+/// Tracked wallets are the distinct pubkeys in Zeus' `wallets` collection.
+/// Wallets are valued one after another to keep RPC load flat.
+pub fn spawn_snapshot_job(
+    db: Arc<Database>,
+    solana_rpc_url: String,
+    das_rpc_url: String,
+    price_oracle: Arc<PriceOracle>,
+    interval: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = PlutusPortfolioManager::ensure_snapshot_collection(&db).await {
+            tracing::error!("Portfolio snapshots disabled: {}", e);
+            return;
+        }
+
+        let manager = PlutusPortfolioManager::new(Arc::clone(&db), solana_rpc_url, price_oracle)
+            .with_das_rpc_url(das_rpc_url);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let wallets = match db
+                .collection::<mongodb::bson::Document>("wallets")
+                .distinct("pubkey", None, None)
+                .await
+            {
+                Ok(wallets) => wallets,
+                Err(e) => {
+                    tracing::warn!("Failed to list wallets for snapshots: {}", e);
+                    continue;
+                }
+            };
+
+            for wallet in wallets.iter().filter_map(|w| w.as_str()) {
+                if let Err(e) = manager.take_snapshot(wallet).await {
+                    tracing::warn!("Snapshot failed for {}: {}", wallet, e);
+                }
+            }
+        }
+    });
+}
+
+fn range_start(range: HistoryRange) -> DateTime {
+    let since = chrono::Utc::now() - range.duration();
+    DateTime::from_millis(since.timestamp_millis())
+}
+
+fn asset_changes(start: Option<&PortfolioSnapshot>, end: &PortfolioSnapshot) -> Vec<AssetChange> {
+    let mut mints: Vec<&str> = end.assets.iter().map(|a| a.mint.as_str()).collect();
+    if let Some(start) = start {
+        for asset in &start.assets {
+            if !mints.contains(&asset.mint.as_str()) {
+                mints.push(&asset.mint);
+            }
+        }
+    }
+
+    let find = |snapshot: Option<&PortfolioSnapshot>, mint: &str| -> Option<AssetSnapshot> {
+        snapshot.and_then(|s| s.assets.iter().find(|a| a.mint == mint).cloned())
+    };
+    let ui = |asset: &Option<AssetSnapshot>| {
+        asset.as_ref().map_or(0.0, |a| a.amount as f64 / 10f64.powi(a.decimals as i32))
+    };
+
+    mints
+        .into_iter()
+        .map(|mint| {
+            let before = find(start, mint);
+            let after = find(Some(end), mint);
+            // A position that didn't exist is worth zero, not unknown
+            let start_value = if before.is_none() && start.is_some() { Some(0.0) } else { before.as_ref().and_then(|a| a.value_usd) };
+            let end_value = if after.is_none() { Some(0.0) } else { after.as_ref().and_then(|a| a.value_usd) };
+            let start_price = before.as_ref().and_then(|a| a.price_usd);
+            let end_price = after.as_ref().and_then(|a| a.price_usd);
+
+            AssetChange {
+                mint: mint.to_string(),
+                start_amount: ui(&before),
+                end_amount: ui(&after),
+                start_value_usd: start_value,
+                end_value_usd: end_value,
+                value_change_usd: start_value.zip(end_value).map(|(s, e)| e - s),
+                price_change_pct: start_price
+                    .zip(end_price)
+                    .filter(|(s, _)| *s > 0.0)
+                    .map(|(s, e)| (e - s) / s * 100.0),
+            }
+        })
+        .collect()
+}
+
+/// One balance movement feeding the P&L calculation
+#[derive(Debug, Clone)]
+struct PnlEvent {
+    mint: String,
+    delta: f64, // UI units, negative for outflows
+    timestamp_ms: i64,
+    price_usd: Option<f64>,
+    price_estimated: bool, // Priced from a snapshot taken after the transfer
+}
+
+/// Per-mint price series reconstructed from snapshots
+struct PriceHistory {
+    series: HashMap<String, Vec<(i64, f64)>>,
+}
+
+impl PriceHistory {
+    fn from_snapshots(snapshots: &[PortfolioSnapshot]) -> Self {
+        let mut series: HashMap<String, Vec<(i64, f64)>> = HashMap::new();
+        for snapshot in snapshots {
+            for asset in &snapshot.assets {
+                if let Some(price) = asset.price_usd {
+                    series.entry(asset.mint.clone()).or_default().push((snapshot.taken_at.timestamp_millis(), price));
+                }
+            }
+        }
+        for points in series.values_mut() {
+            points.sort_by_key(|(t, _)| *t);
+        }
+        Self { series }
+    }
+
+    /// Latest price at or before `timestamp_ms`, else the earliest one after it
+    ///
+    /// The flag is false for that fallback, which is only an estimate.
+    fn price_at(&self, mint: &str, timestamp_ms: i64) -> Option<(f64, bool)> {
+        let points = self.series.get(mint)?;
+        match points.iter().rev().find(|(t, _)| *t <= timestamp_ms) {
+            Some((_, price)) => Some((*price, true)),
+            None => points.first().map(|(_, price)| (*price, false)),
+        }
+    }
+
+    fn earliest(&self, mint: &str) -> Option<f64> {
+        self.series.get(mint).and_then(|points| points.first()).map(|(_, price)| *price)
+    }
+}
+
+#[derive(Default)]
+struct Position {
+    quantity: f64,
+    cost_basis: f64,
+    realized: f64,
+    complete: bool,
+}
+
+/// Average-cost P&L per mint
+///
+/// Holdings that predate the analyzed transfers are opened at the earliest
+/// known price, so short histories still produce an unrealized figure.
+fn compute_pnl(events: &[PnlEvent], current: &PortfolioSnapshot, prices: &PriceHistory) -> Vec<AssetPnl> {
+    let mut positions: HashMap<String, Position> = HashMap::new();
+
+    // Open each position with whatever the transfers don't account for
+    for asset in &current.assets {
+        let held = asset.amount as f64 / 10f64.powi(asset.decimals as i32);
+        let moved: f64 = events.iter().filter(|e| e.mint == asset.mint).map(|e| e.delta).sum();
+        let opening = held - moved;
+        let position = positions.entry(asset.mint.clone()).or_insert_with(|| Position { complete: true, ..Default::default() });
+        if opening > 0.0 {
+            let price = prices.earliest(&asset.mint).or(asset.price_usd);
+            position.quantity = opening;
+            position.cost_basis = opening * price.unwrap_or(0.0);
+            position.complete = price.is_some();
+        }
+    }
+
+    for event in events {
+        let position = positions.entry(event.mint.clone()).or_insert_with(|| Position { complete: true, ..Default::default() });
+        if event.price_usd.is_none() || event.price_estimated {
+            position.complete = false;
+        }
+        let price = event.price_usd.unwrap_or(0.0);
+
+        if event.delta > 0.0 {
+            position.quantity += event.delta;
+            position.cost_basis += event.delta * price;
+        } else {
+            let sold = (-event.delta).min(position.quantity);
+            if sold <= 0.0 {
+                continue;
+            }
+            let average_cost = position.cost_basis / position.quantity;
+            if event.price_usd.is_some() {
+                position.realized += sold * (price - average_cost);
+            }
+            position.cost_basis -= sold * average_cost;
+            position.quantity -= sold;
+        }
+    }
+
+    let mut assets: Vec<AssetPnl> = positions
+        .into_iter()
+        .map(|(mint, position)| {
+            let current_value = current
+                .assets
+                .iter()
+                .find(|a| a.mint == mint)
+                .map_or(Some(0.0), |a| a.value_usd);
+            AssetPnl {
+                unrealized_pnl_usd: current_value.map(|v| v - position.cost_basis),
+                current_value_usd: current_value,
+                quantity: position.quantity,
+                cost_basis_usd: position.cost_basis,
+                realized_pnl_usd: position.realized,
+                complete: position.complete,
+                mint,
+            }
+        })
+        .collect();
+    assets.sort_by(|a, b| a.mint.cmp(&b.mint));
+    assets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const WALLET: &str = "Wa11et1111111111111111111111111111111111111";
+    const OTHER: &str = "0ther11111111111111111111111111111111111111";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const NFT_MINT: &str = "Nft1111111111111111111111111111111111111111";
+    const DEX: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+    fn key(pubkey: &str, signer: bool) -> serde_json::Value {
+        json!({ "pubkey": pubkey, "signer": signer, "writable": true })
+    }
+
+    fn token_balance(index: u64, owner: &str, mint: &str, amount: u64, decimals: u8) -> serde_json::Value {
+        json!({
+            "accountIndex": index,
+            "owner": owner,
+            "mint": mint,
+            "uiTokenAmount": { "amount": amount.to_string(), "decimals": decimals },
+        })
+    }
+
+    #[test]
+    fn classifies_sol_send_excluding_fee() {
+        let tx = json!({
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": [10_000_000_000u64, 0, 1],
+                "postBalances": [8_999_995_000u64, 1_000_000_000, 1],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+            },
+            "transaction": { "message": {
+                "accountKeys": [key(WALLET, true), key(OTHER, false), key(SYSTEM_PROGRAM_ID, false)],
+                "instructions": [{
+                    "program": "system",
+                    "programId": SYSTEM_PROGRAM_ID,
+                    "parsed": { "type": "transfer", "info": { "source": WALLET, "destination": OTHER, "lamports": 1_000_000_000u64 } },
+                }],
+            }},
+        });
+
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::Send);
+        assert_eq!(parsed.amount, Some(1_000_000_000));
+        assert_eq!(parsed.mint, None);
+        assert_eq!(parsed.to.as_deref(), Some(OTHER));
+        assert_eq!(parsed.fee, Some(5000));
+
+        // Same transaction from the recipient's side
+        let parsed = classify_transaction(OTHER, &tx);
+        assert_eq!(parsed.type_, TransactionType::Receive);
+        assert_eq!(parsed.from.as_deref(), Some(WALLET));
+    }
+
+    #[test]
+    fn classifies_swap_and_ignores_rent() {
+        let tx = json!({
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": [1_000_000_000u64, 0, 0, 1],
+                "postBalances": [997_955_720u64, 2_039_280, 0, 1],
+                "preTokenBalances": [token_balance(2, OTHER, USDC, 500_000_000, 6)],
+                "postTokenBalances": [
+                    token_balance(1, WALLET, USDC, 25_000_000, 6),
+                    token_balance(2, OTHER, USDC, 475_000_000, 6),
+                ],
+            },
+            "transaction": { "message": {
+                "accountKeys": [key(WALLET, true), key("Ata1", false), key("Pool1", false), key(DEX, false)],
+                "instructions": [{ "programId": DEX, "accounts": [], "data": "" }],
+            }},
+        });
+
+        // Only the rent for the new token account left in SOL, so USDC in
+        // with nothing out is a program interaction the wallet signed
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::ProgramInteraction);
+        assert_eq!(parsed.balance_changes.len(), 1);
+
+        let mut tx = tx;
+        tx["meta"]["postBalances"][0] = json!(797_955_720u64);
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::Swap);
+        assert_eq!(parsed.mint, None);
+        assert_eq!(parsed.amount, Some(202_039_280));
+        assert_eq!(parsed.balance_changes.len(), 2);
+    }
+
+    #[test]
+    fn classifies_nft_transfer() {
+        let tx = json!({
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": [1_000_000u64, 0, 0],
+                "postBalances": [995_000u64, 0, 0],
+                "preTokenBalances": [token_balance(1, WALLET, NFT_MINT, 1, 0)],
+                "postTokenBalances": [token_balance(1, WALLET, NFT_MINT, 0, 0), token_balance(2, OTHER, NFT_MINT, 1, 0)],
+            },
+            "transaction": { "message": {
+                "accountKeys": [key(WALLET, true), key("Ata1", false), key("Ata2", false)],
+                "instructions": [{ "programId": "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s", "accounts": [], "data": "" }],
+            }},
+        });
+
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::NftTransfer);
+        assert_eq!(parsed.mint.as_deref(), Some(NFT_MINT));
+        assert_eq!(parsed.amount, Some(1));
+        assert_eq!(parsed.to.as_deref(), Some(OTHER));
+    }
+
+    #[test]
+    fn failed_transfer_uses_instruction_intent() {
+        let tx = json!({
+            "meta": {
+                "err": { "InstructionError": [0, { "Custom": 1 }] },
+                "fee": 5000,
+                "preBalances": [1_000_000u64, 0, 1],
+                "postBalances": [995_000u64, 0, 1],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+            },
+            "transaction": { "message": {
+                "accountKeys": [key(WALLET, true), key(OTHER, false), key(SYSTEM_PROGRAM_ID, false)],
+                "instructions": [{
+                    "program": "system",
+                    "programId": SYSTEM_PROGRAM_ID,
+                    "parsed": { "type": "transfer", "info": { "source": WALLET, "destination": OTHER, "lamports": 5_000_000u64 } },
+                }],
+            }},
+        });
+
+        let parsed = classify_transaction(WALLET, &tx);
+        assert!(parsed.error.is_some());
+        assert_eq!(parsed.type_, TransactionType::Send);
+        assert_eq!(parsed.amount, Some(5_000_000));
+        assert_eq!(parsed.to.as_deref(), Some(OTHER));
+    }
+
+    #[test]
+    fn classifies_spl_send_that_creates_the_recipient_account() {
+        const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        let tx = json!({
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": [1_000_000_000u64, 0, 0, 1, 1],
+                "postBalances": [997_955_720u64, 0, 2_039_280, 1, 1],
+                "preTokenBalances": [token_balance(1, WALLET, USDC, 100_000_000, 6)],
+                "postTokenBalances": [
+                    token_balance(1, WALLET, USDC, 75_000_000, 6),
+                    token_balance(2, OTHER, USDC, 25_000_000, 6),
+                ],
+            },
+            "transaction": { "message": {
+                "accountKeys": [
+                    key(WALLET, true), key("Ata1", false), key("Ata2", false),
+                    key(ASSOCIATED_TOKEN_PROGRAM_ID, false), key(TOKEN_PROGRAM_ID, false),
+                ],
+                "instructions": [
+                    {
+                        "program": "spl-associated-token-account",
+                        "programId": ASSOCIATED_TOKEN_PROGRAM_ID,
+                        "parsed": { "type": "createIdempotent", "info": { "wallet": OTHER, "mint": USDC, "account": "Ata2" } },
+                    },
+                    {
+                        "program": "spl-token",
+                        "programId": TOKEN_PROGRAM_ID,
+                        "parsed": { "type": "transferChecked", "info": {
+                            "source": "Ata1", "destination": "Ata2", "authority": WALLET, "mint": USDC,
+                            "tokenAmount": { "amount": "25000000", "decimals": 6 },
+                        }},
+                    },
+                ],
+            }},
+        });
+
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::Send);
+        assert_eq!(parsed.mint.as_deref(), Some(USDC));
+        assert_eq!(parsed.amount, Some(25_000_000));
+        assert_eq!(parsed.to.as_deref(), Some(OTHER));
+
+        let parsed = classify_transaction(OTHER, &tx);
+        assert_eq!(parsed.type_, TransactionType::Receive);
+        assert_eq!(parsed.from.as_deref(), Some(WALLET));
+    }
+
+    #[test]
+    fn classifies_stake_delegation() {
+        let tx = json!({
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": [3_000_000_000u64, 0],
+                "postBalances": [999_995_000u64, 2_000_000_000u64],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+            },
+            "transaction": { "message": {
+                "accountKeys": [key(WALLET, true), key("StakeAcct", true)],
+                "instructions": [
+                    { "program": "system", "programId": SYSTEM_PROGRAM_ID, "parsed": { "type": "createAccount", "info": {} } },
+                    { "program": "stake", "programId": STAKE_PROGRAM_ID, "parsed": { "type": "delegate", "info": {} } },
+                ],
+            }},
+        });
+
+        let parsed = classify_transaction(WALLET, &tx);
+        assert_eq!(parsed.type_, TransactionType::Stake);
+        assert_eq!(parsed.amount, Some(2_000_000_000));
+        assert_eq!(parsed.to.as_deref(), Some("StakeAcct"));
+    }
+
+    fn snapshot(taken_at_ms: i64, assets: Vec<(&str, u64, u8, Option<f64>)>) -> PortfolioSnapshot {
+        let assets: Vec<AssetSnapshot> = assets
+            .into_iter()
+            .map(|(mint, amount, decimals, price)| AssetSnapshot {
+                mint: mint.to_string(),
+                amount,
+                decimals,
+                price_usd: price,
+                value_usd: price.map(|p| amount as f64 / 10f64.powi(decimals as i32) * p),
+            })
+            .collect();
+        PortfolioSnapshot {
+            wallet_pubkey: WALLET.to_string(),
+            taken_at: DateTime::from_millis(taken_at_ms),
+            total_value_usd: assets.iter().filter_map(|a| a.value_usd).sum(),
+            prices_stale: false,
+            assets,
+        }
+    }
+
+    #[test]
+    fn parses_history_ranges() {
+        assert_eq!(HistoryRange::parse("24h").unwrap(), HistoryRange::Day);
+        assert_eq!(HistoryRange::parse("7d").unwrap(), HistoryRange::Week);
+        assert_eq!(HistoryRange::parse("30d").unwrap(), HistoryRange::Month);
+        assert!(HistoryRange::parse("1y").is_err());
+    }
+
+    #[test]
+    fn price_history_prefers_latest_earlier_price() {
+        let prices = PriceHistory::from_snapshots(&[
+            snapshot(1_000, vec![(USDC, 0, 6, Some(1.0))]),
+            snapshot(2_000, vec![(USDC, 0, 6, Some(0.99))]),
+        ]);
+        // Before the first snapshot the earliest price is only an estimate
+        assert_eq!(prices.price_at(USDC, 500), Some((1.0, false)));
+        assert_eq!(prices.price_at(USDC, 1_500), Some((1.0, true)));
+        assert_eq!(prices.price_at(USDC, 2_500), Some((0.99, true)));
+        assert_eq!(prices.price_at(SOL_MINT, 2_500), None);
+    }
+
+    #[test]
+    fn computes_average_cost_pnl() {
+        let prices = PriceHistory::from_snapshots(&[snapshot(0, vec![(SOL_MINT, 0, 9, Some(100.0))])]);
+        let events = vec![
+            PnlEvent { mint: SOL_MINT.to_string(), delta: 2.0, timestamp_ms: 1, price_usd: Some(100.0), price_estimated: false },
+            PnlEvent { mint: SOL_MINT.to_string(), delta: 2.0, timestamp_ms: 2, price_usd: Some(200.0), price_estimated: false },
+            PnlEvent { mint: SOL_MINT.to_string(), delta: -1.0, timestamp_ms: 3, price_usd: Some(250.0), price_estimated: false },
+        ];
+        let current = snapshot(4, vec![(SOL_MINT, 3_000_000_000, 9, Some(300.0))]);
+
+        let pnl = compute_pnl(&events, &current, &prices);
+        assert_eq!(pnl.len(), 1);
+        let sol = &pnl[0];
+        // Average cost 150, sold one at 250
+        assert!((sol.realized_pnl_usd - 100.0).abs() < 1e-9);
+        assert!((sol.cost_basis_usd - 450.0).abs() < 1e-9);
+        assert!((sol.unrealized_pnl_usd.unwrap() - 450.0).abs() < 1e-9);
+        assert!(sol.complete);
+    }
+
+    #[test]
+    fn estimated_prices_leave_pnl_incomplete() {
+        let prices = PriceHistory::from_snapshots(&[snapshot(10, vec![(SOL_MINT, 0, 9, Some(100.0))])]);
+        let event = |timestamp_ms| {
+            let (price, exact) = prices.price_at(SOL_MINT, timestamp_ms).unwrap();
+            PnlEvent { mint: SOL_MINT.to_string(), delta: 1.0, timestamp_ms, price_usd: Some(price), price_estimated: !exact }
+        };
+        let current = snapshot(20, vec![(SOL_MINT, 1_000_000_000, 9, Some(100.0))]);
+
+        assert!(!compute_pnl(&[event(5)], &current, &prices)[0].complete);
+        assert!(compute_pnl(&[event(15)], &current, &prices)[0].complete);
+    }
+
+    fn history(signature: &str, status: TransactionStatus) -> TransactionHistory {
+        TransactionHistory {
+            signature: signature.to_string(),
+            slot: 0,
+            timestamp: DateTime::from_millis(0),
+            type_: TransactionType::Other,
+            amount: None,
+            mint: None,
+            from: None,
+            to: None,
+            fee: None,
+            balance_changes: vec![],
+            programs: vec![],
+            status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn merges_fresh_history_ahead_of_cached() {
+        use TransactionStatus::*;
+        let fresh = vec![history("d", Confirmed), history("c", Pending), history("b", Failed)];
+        let (merged, settled) = merge_history(fresh, vec![history("a", Confirmed)]);
+        let signatures: Vec<&str> = merged.iter().map(|tx| tx.signature.as_str()).collect();
+        assert_eq!(signatures, ["d", "c", "b", "a"]);
+        // The pending transaction and anything newer is fetched again next time
+        assert_eq!(settled, 2);
+
+        // What gets cached reads back
+        let bytes = serde_json::to_vec(&merged[settled..]).unwrap();
+        let cached: Vec<TransactionHistory> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(cached.iter().map(|tx| tx.signature.as_str()).collect::<Vec<_>>(), ["b", "a"]);
+
+        let many = (0..MAX_PNL_TRANSACTIONS).map(|i| history(&i.to_string(), Confirmed)).collect();
+        let (merged, settled) = merge_history(many, vec![history("old", Confirmed)]);
+        assert_eq!(merged.len(), MAX_PNL_TRANSACTIONS);
+        assert_eq!(settled, 0);
+    }
+
+    #[test]
+    fn asset_changes_cover_new_and_closed_positions() {
+        let start = snapshot(0, vec![(SOL_MINT, 1_000_000_000, 9, Some(100.0)), (NFT_MINT, 1, 0, None)]);
+        let end = snapshot(1, vec![(SOL_MINT, 1_000_000_000, 9, Some(110.0)), (USDC, 5_000_000, 6, Some(1.0))]);
+
+        let changes = asset_changes(Some(&start), &end);
+        let sol = changes.iter().find(|c| c.mint == SOL_MINT).unwrap();
+        assert!((sol.price_change_pct.unwrap() - 10.0).abs() < 1e-9);
+        assert!((sol.value_change_usd.unwrap() - 10.0).abs() < 1e-9);
+
+        let usdc = changes.iter().find(|c| c.mint == USDC).unwrap();
+        assert_eq!(usdc.start_value_usd, Some(0.0));
+        assert_eq!(usdc.value_change_usd, Some(5.0));
+
+        let nft = changes.iter().find(|c| c.mint == NFT_MINT).unwrap();
+        assert_eq!(nft.end_amount, 0.0);
+        assert_eq!(nft.value_change_usd, None);
+    }
+}
@@ -0,0 +1,569 @@
+// Price Oracle - USD prices for Plutus portfolio valuation
+// Pluggable price sources (Pyth, HTTP aggregator, static file) behind a cached oracle
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::anchor_client::account_discriminator;
+
+/// Wrapped SOL mint, used as the price key for native SOL
+pub const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+const DEFAULT_PRICE_API_URL: &str = "https://api.jup.ag/price/v2";
+
+// Legacy Pyth price account constants
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PriceQuote {
+    pub mint: String,
+    pub price_usd: f64,
+    pub confidence: Option<f64>, // +/- USD, when the source reports it
+    pub published_at: i64,       // Unix timestamp of the observation
+    pub source: String,
+    pub stale: bool,             // Older than the oracle's staleness threshold
+}
+
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Quote as many of `mints` as this source knows; unknown mints are omitted
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, PriceQuote>, String>;
+}
+
+// ========== Pyth ==========
+
+/// Reads Pyth price accounts on-chain. Supports legacy v2 price accounts and
+/// pull-oracle `PriceUpdateV2` accounts.
+pub struct PythPriceSource {
+    rpc_url: String,
+    feeds: HashMap<String, PythFeed>, // mint -> price account
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PythFeed {
+    pub account: Pubkey,
+    /// Price feed id the account must carry; required for `PriceUpdateV2`
+    /// accounts, whose address alone doesn't pin the feed
+    pub feed_id: Option<[u8; 32]>,
+}
+
+impl PythPriceSource {
+    pub fn new(rpc_url: String, feeds: HashMap<String, PythFeed>) -> Self {
+        Self { rpc_url, feeds }
+    }
+
+    /// Parse a feed list of the form `mint=price_account[:feed_id],...`,
+    /// where `feed_id` is the hex Pyth price feed id
+    pub fn parse_feeds(spec: &str) -> Result<HashMap<String, PythFeed>, String> {
+        let mut feeds = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (mint, feed) = entry.split_once('=')
+                .ok_or_else(|| format!("Invalid Pyth feed entry: {}", entry))?;
+            let (account, feed_id) = match feed.split_once(':') {
+                Some((account, feed_id)) => (account, Some(feed_id.trim())),
+                None => (feed, None),
+            };
+            let account = Pubkey::from_str(account.trim())
+                .map_err(|e| format!("Invalid Pyth price account {}: {}", account, e))?;
+            let feed_id = feed_id
+                .map(|id| {
+                    hex::decode(id.trim_start_matches("0x")).ok()
+                        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                        .ok_or_else(|| format!("Invalid Pyth feed id: {}", id))
+                })
+                .transpose()?;
+            feeds.insert(mint.trim().to_string(), PythFeed { account, feed_id });
+        }
+        Ok(feeds)
+    }
+}
+
+#[async_trait]
+impl PriceSource for PythPriceSource {
+    fn name(&self) -> &str {
+        "pyth"
+    }
+
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, PriceQuote>, String> {
+        use crate::solana::SolanaClient;
+
+        let wanted: Vec<(&String, PythFeed)> = mints.iter()
+            .filter_map(|mint| self.feeds.get(mint).map(|feed| (mint, *feed)))
+            .collect();
+        if wanted.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let client = SolanaClient::new(self.rpc_url.clone());
+        let accounts: Vec<Pubkey> = wanted.iter().map(|(_, feed)| feed.account).collect();
+        let data = client.get_multiple_account_data(&accounts).await?;
+
+        let mut quotes = HashMap::new();
+        for ((mint, feed), data) in wanted.into_iter().zip(data) {
+            let parsed = data.ok_or_else(|| "Price account not found".to_string())
+                .and_then(|data| parse_pyth_price(&data, feed.feed_id.as_ref()));
+            match parsed {
+                Ok(price) => {
+                    quotes.insert(mint.clone(), PriceQuote {
+                        mint: mint.clone(),
+                        price_usd: price.price,
+                        confidence: Some(price.confidence),
+                        published_at: price.publish_time,
+                        source: self.name().to_string(),
+                        stale: false,
+                    });
+                }
+                Err(e) => tracing::warn!("Pyth price account {} unusable: {}", feed.account, e),
+            }
+        }
+
+        Ok(quotes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythPrice {
+    pub price: f64,
+    pub confidence: f64,
+    pub publish_time: i64,
+}
+
+/// Decode a Pyth price account (legacy v2 or `PriceUpdateV2`)
+///
+/// `PriceUpdateV2` accounts are only trusted when they carry `feed_id`.
+pub fn parse_pyth_price(data: &[u8], feed_id: Option<&[u8; 32]>) -> Result<PythPrice, String> {
+    let u32_at = |o: usize| data.get(o..o + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    let i32_at = |o: usize| data.get(o..o + 4).map(|b| i32::from_le_bytes(b.try_into().unwrap()));
+    let i64_at = |o: usize| data.get(o..o + 8).map(|b| i64::from_le_bytes(b.try_into().unwrap()));
+    let u64_at = |o: usize| data.get(o..o + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+    let truncated = || "Pyth account truncated".to_string();
+
+    let (price, conf, expo, publish_time) = if u32_at(0) == Some(PYTH_MAGIC) {
+        // Legacy layout: expo @20, timestamp @96, aggregate PriceInfo @208
+        if u32_at(8) != Some(PYTH_PRICE_ACCOUNT_TYPE) {
+            return Err("Pyth account is not a price account".to_string());
+        }
+        if u32_at(224).ok_or_else(truncated)? != PYTH_STATUS_TRADING {
+            return Err("Pyth price is not trading".to_string());
+        }
+        (
+            i64_at(208).ok_or_else(truncated)?,
+            u64_at(216).ok_or_else(truncated)?,
+            i32_at(20).ok_or_else(truncated)?,
+            i64_at(96).ok_or_else(truncated)?,
+        )
+    } else {
+        // PriceUpdateV2: discriminator (8) + write_authority (32) + verification_level
+        if data.get(..8) != Some(&account_discriminator("PriceUpdateV2")[..]) {
+            return Err("Account is not a Pyth price account".to_string());
+        }
+        let offset = match data.get(40) {
+            Some(1) => 41, // VerificationLevel::Full
+            Some(0) => return Err("Pyth price update is only partially verified".to_string()),
+            _ => return Err(truncated()),
+        };
+        // PriceFeedMessage: feed_id (32), price, conf, exponent, publish_time
+        let expected = feed_id.ok_or_else(|| "Pyth price update needs a configured feed id".to_string())?;
+        if data.get(offset..offset + 32).ok_or_else(truncated)? != expected {
+            return Err("Pyth price update is for a different feed".to_string());
+        }
+        (
+            i64_at(offset + 32).ok_or_else(truncated)?,
+            u64_at(offset + 40).ok_or_else(truncated)?,
+            i32_at(offset + 48).ok_or_else(truncated)?,
+            i64_at(offset + 52).ok_or_else(truncated)?,
+        )
+    };
+
+    if price <= 0 {
+        return Err("Pyth price is not positive".to_string());
+    }
+
+    let scale = 10f64.powi(expo);
+    Ok(PythPrice {
+        price: price as f64 * scale,
+        confidence: conf as f64 * scale,
+        publish_time,
+    })
+}
+
+// ========== HTTP aggregator ==========
+
+/// Batched HTTP price API returning `{"data": {"<mint>": {"price": "1.23"}}}`
+/// (Jupiter price API v2 format)
+pub struct HttpPriceSource {
+    api_url: String,
+}
+
+impl HttpPriceSource {
+    pub fn new(api_url: String) -> Self {
+        Self { api_url }
+    }
+}
+
+#[async_trait]
+impl PriceSource for HttpPriceSource {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, PriceQuote>, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+        let mut quotes = HashMap::new();
+        let now = chrono::Utc::now().timestamp();
+
+        for chunk in mints.chunks(100) {
+            let response = client.get(&self.api_url)
+                .query(&[("ids", chunk.join(","))])
+                .send()
+                .await
+                .map_err(|e| format!("Price API error: {}", e))?;
+
+            if !response.status().is_success() {
+                return Err(format!("Price API error: {}", response.status()));
+            }
+
+            let json: serde_json::Value = response.json().await
+                .map_err(|e| format!("Failed to parse price response: {}", e))?;
+
+            for mint in chunk {
+                let entry = &json["data"][mint.as_str()];
+                // Prices are returned as strings, but accept numbers too
+                let price = entry["price"].as_str()
+                    .and_then(|p| p.parse::<f64>().ok())
+                    .or_else(|| entry["price"].as_f64());
+
+                if let Some(price) = price.filter(|p| p.is_finite() && *p > 0.0) {
+                    quotes.insert(mint.clone(), PriceQuote {
+                        mint: mint.clone(),
+                        price_usd: price,
+                        confidence: None,
+                        published_at: now,
+                        source: self.name().to_string(),
+                        stale: false,
+                    });
+                }
+            }
+        }
+
+        Ok(quotes)
+    }
+}
+
+// ========== Static ==========
+
+/// Fixed prices loaded from a JSON file (`{"<mint>": 1.23}`), for tests and local development
+pub struct StaticPriceSource {
+    prices: HashMap<String, f64>,
+}
+
+impl StaticPriceSource {
+    pub fn new(prices: HashMap<String, f64>) -> Self {
+        Self { prices }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read price file {}: {}", path, e))?;
+        let prices = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid price file {}: {}", path, e))?;
+        Ok(Self::new(prices))
+    }
+}
+
+#[async_trait]
+impl PriceSource for StaticPriceSource {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, PriceQuote>, String> {
+        let now = chrono::Utc::now().timestamp();
+        Ok(mints.iter()
+            .filter_map(|mint| self.prices.get(mint).map(|price| (mint.clone(), PriceQuote {
+                mint: mint.clone(),
+                price_usd: *price,
+                confidence: None,
+                published_at: now,
+                source: self.name().to_string(),
+                stale: false,
+            })))
+            .collect())
+    }
+}
+
+// ========== Oracle ==========
+
+#[derive(Clone)]
+struct CachedQuote {
+    quote: PriceQuote,
+    fetched_at: Instant,
+}
+
+/// Queries sources in order, falling back to the next one for mints a source
+/// could not price, and caches quotes per mint.
+pub struct PriceOracle {
+    sources: Vec<Arc<dyn PriceSource>>,
+    cache: DashMap<String, CachedQuote>,
+    cache_ttl: Duration,
+    max_staleness_seconds: i64,
+}
+
+impl PriceOracle {
+    pub fn new(
+        sources: Vec<Arc<dyn PriceSource>>,
+        cache_ttl: Duration,
+        max_staleness: Duration,
+    ) -> Self {
+        Self {
+            sources,
+            cache: DashMap::new(),
+            cache_ttl,
+            max_staleness_seconds: max_staleness.as_secs() as i64,
+        }
+    }
+
+    /// Build the oracle from configuration: static file, then Pyth, then the HTTP API
+    pub fn from_config(config: &crate::config::PricingConfig, rpc_url: &str) -> Result<Self, String> {
+        let mut sources: Vec<Arc<dyn PriceSource>> = Vec::new();
+
+        if let Some(path) = &config.static_prices_file {
+            sources.push(Arc::new(StaticPriceSource::from_file(path)?));
+        }
+        if let Some(spec) = &config.pyth_feeds {
+            let feeds = PythPriceSource::parse_feeds(spec)?;
+            sources.push(Arc::new(PythPriceSource::new(rpc_url.to_string(), feeds)));
+        }
+        let api_url = config.price_api_url.clone()
+            .unwrap_or_else(|| DEFAULT_PRICE_API_URL.to_string());
+        sources.push(Arc::new(HttpPriceSource::new(api_url)));
+
+        Ok(Self::new(
+            sources,
+            Duration::from_secs(config.cache_ttl_seconds),
+            Duration::from_secs(config.max_staleness_seconds),
+        ))
+    }
+
+    /// Quote each mint; unpriceable mints are omitted from the result
+    pub async fn get_prices(&self, mints: &[String]) -> HashMap<String, PriceQuote> {
+        let mut quotes = HashMap::new();
+        let mut missing = Vec::new();
+
+        for mint in mints {
+            match self.cache.get(mint) {
+                Some(cached) if cached.fetched_at.elapsed() < self.cache_ttl => {
+                    quotes.insert(mint.clone(), self.with_staleness(cached.quote.clone()));
+                }
+                _ => {
+                    if !missing.contains(mint) {
+                        missing.push(mint.clone());
+                    }
+                }
+            }
+        }
+
+        for source in &self.sources {
+            if missing.is_empty() {
+                break;
+            }
+
+            match source.get_prices(&missing).await {
+                Ok(found) => {
+                    for (mint, quote) in found {
+                        self.cache.insert(mint.clone(), CachedQuote {
+                            quote: quote.clone(),
+                            fetched_at: Instant::now(),
+                        });
+                        quotes.insert(mint, self.with_staleness(quote));
+                    }
+                    missing.retain(|mint| !quotes.contains_key(mint));
+                }
+                Err(e) => tracing::warn!("Price source {} failed: {}", source.name(), e),
+            }
+        }
+
+        // Every source failed: serve expired cache entries flagged as stale
+        for mint in missing {
+            if let Some(cached) = self.cache.get(&mint) {
+                let mut quote = cached.quote.clone();
+                quote.stale = true;
+                quotes.insert(mint, quote);
+            }
+        }
+
+        quotes
+    }
+
+    fn with_staleness(&self, mut quote: PriceQuote) -> PriceQuote {
+        let age = chrono::Utc::now().timestamp() - quote.published_at;
+        quote.stale = age > self.max_staleness_seconds;
+        quote
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FailingSource;
+
+    #[async_trait]
+    impl PriceSource for FailingSource {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn get_prices(&self, _mints: &[String]) -> Result<HashMap<String, PriceQuote>, String> {
+            Err("unavailable".to_string())
+        }
+    }
+
+    struct CountingSource {
+        inner: StaticPriceSource,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PriceSource for CountingSource {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, PriceQuote>, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_prices(mints).await
+        }
+    }
+
+    fn static_source(prices: &[(&str, f64)]) -> StaticPriceSource {
+        StaticPriceSource::new(prices.iter().map(|(m, p)| (m.to_string(), *p)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_oracle_falls_back_and_caches() {
+        let counting = Arc::new(CountingSource {
+            inner: static_source(&[(SOL_MINT, 150.0)]),
+            calls: AtomicUsize::new(0),
+        });
+        let oracle = PriceOracle::new(
+            vec![Arc::new(FailingSource), counting.clone(), Arc::new(static_source(&[("USDC", 1.0)]))],
+            Duration::from_secs(60),
+            Duration::from_secs(120),
+        );
+
+        let mints = vec![SOL_MINT.to_string(), "USDC".to_string(), "UNKNOWN".to_string()];
+        let quotes = oracle.get_prices(&mints).await;
+        assert_eq!(quotes[SOL_MINT].price_usd, 150.0);
+        assert_eq!(quotes[SOL_MINT].source, "static");
+        assert_eq!(quotes["USDC"].price_usd, 1.0);
+        assert!(!quotes.contains_key("UNKNOWN"));
+        assert!(!quotes[SOL_MINT].stale);
+
+        // Second lookup is served from the cache
+        assert!(oracle.get_prices(&mints[..1]).await.contains_key(SOL_MINT));
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oracle_serves_expired_entries_as_stale() {
+        let oracle = PriceOracle::new(
+            vec![Arc::new(FailingSource)],
+            Duration::from_secs(0),
+            Duration::from_secs(120),
+        );
+        oracle.cache.insert(SOL_MINT.to_string(), CachedQuote {
+            quote: PriceQuote {
+                mint: SOL_MINT.to_string(),
+                price_usd: 140.0,
+                confidence: None,
+                published_at: chrono::Utc::now().timestamp(),
+                source: "static".to_string(),
+                stale: false,
+            },
+            fetched_at: Instant::now(),
+        });
+
+        let quote = oracle.get_prices(&[SOL_MINT.to_string()]).await.remove(SOL_MINT).unwrap();
+        assert_eq!(quote.price_usd, 140.0);
+        assert!(quote.stale);
+    }
+
+    #[test]
+    fn test_parse_legacy_pyth_price() {
+        let mut data = vec![0u8; 240];
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        data[20..24].copy_from_slice(&(-8i32).to_le_bytes());
+        data[96..104].copy_from_slice(&1_700_000_000i64.to_le_bytes());
+        data[208..216].copy_from_slice(&15_012_345_678i64.to_le_bytes());
+        data[216..224].copy_from_slice(&5_000_000u64.to_le_bytes());
+        data[224..228].copy_from_slice(&PYTH_STATUS_TRADING.to_le_bytes());
+
+        let price = parse_pyth_price(&data, None).unwrap();
+        assert!((price.price - 150.12345678).abs() < 1e-9);
+        assert!((price.confidence - 0.05).abs() < 1e-9);
+        assert_eq!(price.publish_time, 1_700_000_000);
+
+        data[224..228].copy_from_slice(&0u32.to_le_bytes());
+        assert!(parse_pyth_price(&data, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_price_update_v2() {
+        let feed_id = [7u8; 32];
+        let mut data = account_discriminator("PriceUpdateV2").to_vec();
+        data.extend_from_slice(&[0u8; 32]); // write_authority
+        data.push(1); // VerificationLevel::Full
+        data.extend_from_slice(&feed_id);
+        data.extend_from_slice(&99_950_000i64.to_le_bytes());
+        data.extend_from_slice(&10_000u64.to_le_bytes());
+        data.extend_from_slice(&(-8i32).to_le_bytes());
+        data.extend_from_slice(&1_700_000_100i64.to_le_bytes());
+        data.extend_from_slice(&[0u8; 40]);
+
+        let price = parse_pyth_price(&data, Some(&feed_id)).unwrap();
+        assert!((price.price - 0.9995).abs() < 1e-9);
+        assert_eq!(price.publish_time, 1_700_000_100);
+
+        // The account must be for the configured feed
+        assert!(parse_pyth_price(&data, Some(&[8u8; 32])).is_err());
+        assert!(parse_pyth_price(&data, None).is_err());
+
+        // ... and actually be a PriceUpdateV2 account
+        let mut other = data.clone();
+        other[..8].copy_from_slice(&account_discriminator("Other"));
+        assert!(parse_pyth_price(&other, Some(&feed_id)).is_err());
+
+        data[40] = 0; // VerificationLevel::Partial
+        assert!(parse_pyth_price(&data, Some(&feed_id)).is_err());
+    }
+
+    #[test]
+    fn test_parse_pyth_feeds() {
+        let account = Pubkey::new_unique();
+        let feeds = PythPriceSource::parse_feeds(&format!("{}={}, ", SOL_MINT, account)).unwrap();
+        assert_eq!(feeds.get(SOL_MINT), Some(&PythFeed { account, feed_id: None }));
+        assert!(PythPriceSource::parse_feeds("missing-separator").is_err());
+
+        let spec = format!("{}={}:0x{}", SOL_MINT, account, "ab".repeat(32));
+        let feeds = PythPriceSource::parse_feeds(&spec).unwrap();
+        assert_eq!(feeds[SOL_MINT].feed_id, Some([0xab; 32]));
+        assert!(PythPriceSource::parse_feeds(&format!("{}={}:abcd", SOL_MINT, account)).is_err());
+    }
+}
@@ -1,14 +1,23 @@
-use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 use std::str::FromStr;
 
 pub struct SolanaClient {
     rpc_url: String,
+    commitment: Option<CommitmentConfig>, // For signature listings; None leaves it to the node
 }
 
 impl SolanaClient {
     pub fn new(rpc_url: String) -> Self {
-        Self { rpc_url }
+        Self { rpc_url, commitment: None }
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = Some(commitment);
+        self
     }
 
     pub fn search_account(&self, address: &str) -> Result<Option<AccountInfo>, String> {
@@ -53,36 +62,12 @@ impl SolanaClient {
         program_address: &str,
         owner_pubkey: &str,
     ) -> Result<bool, String> {
-        let program = Pubkey::from_str(program_address)
-            .map_err(|e| format!("Invalid program pubkey: {}", e))?;
-        
         let owner = Pubkey::from_str(owner_pubkey)
             .map_err(|e| format!("Invalid owner pubkey: {}", e))?;
 
-        let client = RpcClient::new(&self.rpc_url);
-        
-        // Get program account data
-        let account = client.get_account(&program)
-            .map_err(|e| format!("Failed to get program account: {}", e))?;
-
-        // Check if account is executable (is a program)
-        if !account.executable {
-            return Ok(false);
-        }
-
-        // Get program data account (BPF upgradeable loader)
-        // For upgradeable programs, the upgrade authority is stored in the program data account
-        // This is a simplified check - in production, you'd parse the program data account
-        // For now, we'll check if the owner matches the program's owner field
-        // (which for BPF programs is the BPF loader)
-        
-        // For a more accurate check, we'd need to:
-        // 1. Get the program data account (derived from program address)
-        // 2. Parse the upgrade authority from the program data
-        // 3. Compare with owner_pubkey
-        
-        // Simplified: check if owner matches (this works for non-upgradeable programs)
-        Ok(account.owner == owner)
+        Ok(self.get_program_authority(program_address)?
+            .and_then(|authority| authority.upgrade_authority)
+            .is_some_and(|authority| authority == owner.to_string()))
     }
 
     /// Get program upgrade authority (for upgradeable programs)
@@ -90,27 +75,84 @@ impl SolanaClient {
         &self,
         program_address: &str,
     ) -> Result<Option<String>, String> {
+        Ok(self.get_program_authority(program_address)?
+            .and_then(|authority| authority.upgrade_authority))
+    }
+
+    /// Read loader state for a program
+    ///
+    /// For the upgradeable loader this follows the Program account to its
+    /// ProgramData account. Programs on the older loaders can never be
+    /// upgraded, so they report as immutable with no authority.
+    /// Returns `None` if the address is not an executable program.
+    pub fn get_program_authority(
+        &self,
+        program_address: &str,
+    ) -> Result<Option<ProgramAuthority>, String> {
         let program = Pubkey::from_str(program_address)
             .map_err(|e| format!("Invalid program pubkey: {}", e))?;
 
         let client = RpcClient::new(&self.rpc_url);
-        
-        // Get program account
-        let account = client.get_account(&program)
-            .map_err(|e| format!("Failed to get program account: {}", e))?;
+        let account = match client.get_account_with_commitment(&program, client.commitment())
+            .map_err(|e| format!("Failed to get program account: {}", e))?
+            .value
+        {
+            Some(account) if account.executable => account,
+            _ => return Ok(None),
+        };
 
-        if !account.executable {
-            return Ok(None);
+        if account.owner != bpf_loader_upgradeable::id() {
+            return Ok(Some(ProgramAuthority {
+                program_address: program_address.to_string(),
+                loader: account.owner.to_string(),
+                programdata_address: None,
+                upgrade_authority: None,
+                last_deploy_slot: None,
+                immutable: true,
+            }));
         }
 
-        // For upgradeable programs, derive program data account
-        // Program data = findProgramAddress([program, "upgrade"])
-        // This is a simplified version - full implementation would derive the PDA
-        // and parse the upgrade authority from the account data
-        
-        // Placeholder: return None for now
-        // In production, implement proper PDA derivation and data parsing
-        Ok(None)
+        let programdata_address = parse_program_account(&account.data)?;
+        let programdata = client.get_account(&programdata_address)
+            .map_err(|e| format!("Failed to get program data account: {}", e))?;
+        let (slot, upgrade_authority) = parse_programdata_header(&programdata.data)?;
+
+        Ok(Some(ProgramAuthority {
+            program_address: program_address.to_string(),
+            loader: account.owner.to_string(),
+            programdata_address: Some(programdata_address.to_string()),
+            upgrade_authority: upgrade_authority.map(|a| a.to_string()),
+            last_deploy_slot: Some(slot),
+            immutable: upgrade_authority.is_none(),
+        }))
+    }
+
+    /// Fetch raw account data, returning `None` if the account does not exist
+    pub async fn get_account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let response = client
+            .get_account_with_commitment(pubkey, client.commitment())
+            .map_err(|e| format!("RPC error: {}", e))?;
+
+        Ok(response.value.map(|account| account.data))
+    }
+
+    /// Fetch raw data for several accounts in one `getMultipleAccounts` call
+    pub async fn get_multiple_account_data(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<Vec<Option<Vec<u8>>>, String> {
+        let client = RpcClient::new(&self.rpc_url);
+        let mut results = Vec::with_capacity(pubkeys.len());
+
+        // The RPC caps getMultipleAccounts at 100 keys per request
+        for chunk in pubkeys.chunks(100) {
+            let accounts = client.get_multiple_accounts(chunk)
+                .map_err(|e| format!("RPC error: {}", e))?;
+            results.extend(accounts.into_iter().map(|a| a.map(|account| account.data)));
+        }
+
+        Ok(results)
     }
 
     /// Get SOL balance for a pubkey
@@ -139,28 +181,151 @@ impl SolanaClient {
         Ok(Vec::new())
     }
 
-    /// Get signatures for an address
+    /// Get signatures for an address, newest first
+    ///
+    /// `before` and `until` are signature cursors: results start just before
+    /// `before` and stop at (excluding) `until`.
     pub async fn get_signatures_for_address(
         &self,
         pubkey: &Pubkey,
         limit: u32,
+        before: Option<&str>,
+        until: Option<&str>,
     ) -> Result<Vec<SignatureInfo>, String> {
+        let parse_cursor = |cursor: Option<&str>| -> Result<Option<Signature>, String> {
+            cursor
+                .map(|s| Signature::from_str(s).map_err(|_| format!("Invalid signature cursor: {}", s)))
+                .transpose()
+        };
+
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before: parse_cursor(before)?,
+            until: parse_cursor(until)?,
+            limit: Some(limit as usize),
+            commitment: self.commitment,
+        };
+
         let client = RpcClient::new(&self.rpc_url);
-        
-        // Use the simpler API - get_signatures_for_address takes only the pubkey
-        let signatures = client.get_signatures_for_address(pubkey)
+        let signatures = client.get_signatures_for_address_with_config(pubkey, config)
             .map_err(|e| format!("RPC error: {}", e))?;
-        
-        // Limit the results manually
-        let signatures: Vec<_> = signatures.into_iter().take(limit as usize).collect();
 
         Ok(signatures.into_iter().map(|sig| SignatureInfo {
-            signature: sig.signature.to_string(),
+            signature: sig.signature,
+            slot: sig.slot,
             block_time: sig.block_time,
+            err: sig.err.map(|e| e.to_string()),
+            confirmation_status: sig.confirmation_status
+                .map(|status| format!("{:?}", status).to_lowercase()),
         }).collect())
     }
+
+    /// Confirmation status of each signature, searching the full history
+    ///
+    /// `None` means the cluster has no record of it, as with a transaction
+    /// dropped along with its fork.
+    pub async fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<String>>, String> {
+        let parsed = signatures.iter()
+            .map(|s| Signature::from_str(s).map_err(|_| format!("Invalid signature: {}", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let client = RpcClient::new(&self.rpc_url);
+        let mut statuses = Vec::with_capacity(parsed.len());
+        // getSignatureStatuses takes at most 256 signatures
+        for chunk in parsed.chunks(256) {
+            let response = client.get_signature_statuses_with_history(chunk)
+                .map_err(|e| format!("RPC error: {}", e))?;
+            statuses.extend(response.value.into_iter().map(|status| {
+                status.map(|s| match s.confirmation_status {
+                    Some(level) => format!("{:?}", level).to_lowercase(),
+                    None => "finalized".to_string(), // Older nodes omit it for rooted slots
+                })
+            }));
+        }
+        Ok(statuses)
+    }
+
+    /// Latest slot at `commitment`
+    pub async fn get_slot(&self, commitment: CommitmentConfig) -> Result<u64, String> {
+        RpcClient::new(&self.rpc_url)
+            .get_slot_with_commitment(commitment)
+            .map_err(|e| format!("RPC error: {}", e))
+    }
+
+    /// Fetch transactions in `jsonParsed` encoding
+    ///
+    /// Signatures are sent as JSON-RPC batches of `TX_BATCH_SIZE`, with at most
+    /// `TX_BATCH_CONCURRENCY` batches in flight. The result is in the same order
+    /// as `signatures`; transactions the node no longer has come back as `None`.
+    pub async fn get_parsed_transactions(
+        &self,
+        signatures: &[String],
+    ) -> Result<Vec<Option<serde_json::Value>>, String> {
+        use futures_util::stream::{self, StreamExt, TryStreamExt};
+
+        let http = reqwest::Client::new();
+        // Owned chunks keep the future Send, so it can run on a spawned task
+        let chunks: Vec<Vec<String>> = signatures.chunks(TX_BATCH_SIZE).map(<[String]>::to_vec).collect();
+        let http = &http;
+        let batches: Vec<Vec<Option<serde_json::Value>>> = stream::iter(chunks)
+            .map(|chunk| async move { self.get_parsed_transaction_batch(http, &chunk).await })
+            .buffered(TX_BATCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        Ok(batches.into_iter().flatten().collect())
+    }
+
+    async fn get_parsed_transaction_batch(
+        &self,
+        http: &reqwest::Client,
+        signatures: &[String],
+    ) -> Result<Vec<Option<serde_json::Value>>, String> {
+        let requests: Vec<serde_json::Value> = signatures
+            .iter()
+            .enumerate()
+            .map(|(id, signature)| serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "getTransaction",
+                "params": [signature, {
+                    "encoding": "jsonParsed",
+                    "commitment": "confirmed",
+                    "maxSupportedTransactionVersion": 0,
+                }],
+            }))
+            .collect();
+
+        let responses: Vec<serde_json::Value> = http
+            .post(&self.rpc_url)
+            .json(&requests)
+            .send()
+            .await
+            .map_err(|e| format!("RPC error: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid RPC response: {}", e))?;
+
+        // Batch responses may arrive in any order; match them back up by id
+        let mut results = vec![None; signatures.len()];
+        for response in responses {
+            if let Some(error) = response.get("error") {
+                return Err(format!("RPC error: {}", error));
+            }
+            let id = response.get("id").and_then(|v| v.as_u64()).map(|v| v as usize);
+            if let Some(slot) = id.and_then(|id| results.get_mut(id)) {
+                *slot = response.get("result").filter(|v| !v.is_null()).cloned();
+            }
+        }
+
+        Ok(results)
+    }
 }
 
+/// Signatures per `getTransaction` JSON-RPC batch
+const TX_BATCH_SIZE: usize = 20;
+/// Batches allowed in flight at once
+const TX_BATCH_CONCURRENCY: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct TokenAccountInfo {
     pub mint: String,
@@ -171,7 +336,42 @@ pub struct TokenAccountInfo {
 #[derive(Debug, Clone)]
 pub struct SignatureInfo {
     pub signature: String,
+    pub slot: u64,
     pub block_time: Option<i64>,
+    pub err: Option<String>,
+    pub confirmation_status: Option<String>,
+}
+
+/// Upgrade state of a deployed program
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProgramAuthority {
+    pub program_address: String,
+    pub loader: String,
+    pub programdata_address: Option<String>,
+    pub upgrade_authority: Option<String>,
+    pub last_deploy_slot: Option<u64>, // Slot of the last deploy or upgrade
+    pub immutable: bool,
+}
+
+/// Extract the ProgramData address from an upgradeable loader Program account
+pub fn parse_program_account(data: &[u8]) -> Result<Pubkey, String> {
+    match bincode::deserialize::<UpgradeableLoaderState>(data) {
+        Ok(UpgradeableLoaderState::Program { programdata_address }) => Ok(programdata_address),
+        Ok(_) => Err("Account is not an upgradeable Program account".to_string()),
+        Err(e) => Err(format!("Invalid loader state: {}", e)),
+    }
+}
+
+/// Read (last deploy slot, upgrade authority) from a ProgramData account header
+pub fn parse_programdata_header(data: &[u8]) -> Result<(u64, Option<Pubkey>), String> {
+    let header_len = UpgradeableLoaderState::size_of_programdata_metadata().min(data.len());
+    match bincode::deserialize::<UpgradeableLoaderState>(&data[..header_len]) {
+        Ok(UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address }) => {
+            Ok((slot, upgrade_authority_address))
+        }
+        Ok(_) => Err("Account is not a ProgramData account".to_string()),
+        Err(e) => Err(format!("Invalid loader state: {}", e)),
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -190,3 +390,40 @@ pub struct ProgramInfo {
     pub data_len: usize,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_upgradeable_program_account() {
+        let programdata_address = Pubkey::new_unique();
+        let data = bincode::serialize(&UpgradeableLoaderState::Program { programdata_address }).unwrap();
+        assert_eq!(parse_program_account(&data).unwrap(), programdata_address);
+
+        let buffer = bincode::serialize(&UpgradeableLoaderState::Buffer { authority_address: None }).unwrap();
+        assert!(parse_program_account(&buffer).is_err());
+    }
+
+    #[test]
+    fn parses_programdata_header_with_trailing_bytecode() {
+        let authority = Pubkey::new_unique();
+        let mut data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+            slot: 123_456,
+            upgrade_authority_address: Some(authority),
+        }).unwrap();
+        data.resize(UpgradeableLoaderState::size_of_programdata_metadata(), 0);
+        data.extend_from_slice(&[0x7f, b'E', b'L', b'F']);
+
+        assert_eq!(parse_programdata_header(&data).unwrap(), (123_456, Some(authority)));
+    }
+
+    #[test]
+    fn parses_immutable_programdata() {
+        let data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+            slot: 9,
+            upgrade_authority_address: None,
+        }).unwrap();
+        assert_eq!(parse_programdata_header(&data).unwrap(), (9, None));
+    }
+}
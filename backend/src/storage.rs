@@ -1,9 +1,195 @@
+use crate::charon::{BundlrNode, DataItem, DataItemHeader, DATA_ITEM_QUERY};
+use crate::config::{DEFAULT_ARWEAVE_GATEWAYS, DEFAULT_IPFS_GATEWAYS};
+use crate::daedalus::{ArweaveManifest, ARWEAVE_MANIFEST_MARKER, ARWEAVE_MANIFEST_TYPE};
+use crate::hecate::{self, GatewayError, GatewayPool, GatewaySettings};
+use crate::themis::{self, ArweaveTransaction, Cid, ContentError, IntegrityViolation, CODEC_RAW};
+use async_trait::async_trait;
+use serde::Serialize;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use solana_sdk::signature::Keypair;
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+/// What a provider knows about stored content without fetching it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectStat {
+    pub uri: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub pinned: bool,
+}
+
+/// Price of storing an upload, in the atomic unit of `currency`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    pub size: u64,
+    pub currency: String,
+    // Decimal string; atomic amounts can outgrow a JSON number
+    pub price: String,
+}
+
+/// Hints for `put`; providers keep what they can store
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    pub name: String,
+    pub content_type: Option<String>,
+    pub tags: Vec<(String, String)>,
+}
+
+/// A file staged on disk, placed at `path` inside a directory upload
+#[derive(Debug, Clone)]
+pub struct DirectoryFile {
+    pub path: String,
+    pub file: PathBuf,
+    pub content_type: Option<String>,
+}
+
+/// A content store addressed by `scheme://id[/path]` URIs
+#[async_trait]
+pub trait StorageProvider: Send + Sync {
+    /// URI scheme of the content this provider stores
+    fn scheme(&self) -> &'static str;
+
+    /// Store `data` and return its URI
+    async fn put(&self, data: &[u8], options: &PutOptions) -> Result<String, String>;
+
+    async fn get(&self, uri: &str) -> Result<Vec<u8>, String>;
+
+    /// `None` when the provider does not hold the content
+    async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String>;
+
+    /// Keep content from being garbage collected
+    async fn pin(&self, uri: &str) -> Result<(), String>;
+
+    async fn unpin(&self, uri: &str) -> Result<(), String>;
+
+    /// What storing `size` bytes would cost; `None` for providers that don't charge per upload
+    async fn quote(&self, _size: u64) -> Result<Option<Quote>, String> {
+        Ok(None)
+    }
+
+    /// Store a file staged on disk; providers that can stream it override this
+    async fn put_file(&self, file: &Path, options: &PutOptions) -> Result<String, String> {
+        let data = tokio::fs::read(file).await
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        self.put(&data, options).await
+    }
+
+    /// Store files under a single root and return the root's URI. Unless a provider has
+    /// directories of its own, the files are tied together by an Arweave-style path manifest.
+    async fn put_directory(&self, files: &[DirectoryFile]) -> Result<String, String> {
+        let mut paths = serde_json::Map::new();
+        for entry in files {
+            let options = PutOptions {
+                name: entry.path.rsplit('/').next().unwrap_or_default().to_string(),
+                content_type: entry.content_type.clone(),
+                tags: Vec::new(),
+            };
+            let uri = self.put_file(&entry.file, &options).await?;
+            let (_, id) = split_uri(&uri).ok_or_else(|| format!("Invalid storage URI: {}", uri))?;
+            paths.insert(entry.path.clone(), serde_json::json!({ "id": id }));
+        }
+
+        let mut manifest = serde_json::json!({
+            "manifest": ARWEAVE_MANIFEST_MARKER,
+            "version": "0.2.0",
+            "paths": paths,
+        });
+        if manifest["paths"].get("index.html").is_some() {
+            manifest["index"] = serde_json::json!({ "path": "index.html" });
+        }
+
+        let options = PutOptions {
+            name: "manifest.json".to_string(),
+            content_type: Some(ARWEAVE_MANIFEST_TYPE.to_string()),
+            tags: Vec::new(),
+        };
+        self.put(manifest.to_string().as_bytes(), &options).await
+    }
+
+    /// Stored bytes without path or manifest resolution, with their content type if known
+    async fn get_raw(&self, uri: &str) -> Result<(Vec<u8>, Option<String>), String> {
+        Ok((self.get(uri).await?, None))
+    }
+
+    /// A single block, for walking IPFS directories
+    async fn get_block(&self, uri: &str) -> Result<Vec<u8>, String> {
+        Err(format!("{}:// storage does not serve blocks ({})", self.scheme(), uri))
+    }
+}
+
+/// Split `scheme://rest`; `ar://` is the short form of `arweave://`
+pub fn split_uri(uri: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = uri.split_once("://")?;
+    Some((if scheme == "ar" { "arweave" } else { scheme }, rest))
+}
+
+/// Providers by the URI scheme they serve
+#[derive(Clone, Default)]
+pub struct StorageRegistry {
+    providers: HashMap<&'static str, Arc<dyn StorageProvider>>,
+}
+
+impl StorageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pinata for IPFS and Bundlr for Arweave, read through the default gateways
+    pub fn standard() -> Self {
+        Self::new()
+            .with_provider(Arc::new(PinataStorage::new()))
+            .with_provider(Arc::new(BundlrStorage::new()))
+    }
+
+    /// Register `provider` for its scheme, replacing any earlier one
+    pub fn with_provider(mut self, provider: Arc<dyn StorageProvider>) -> Self {
+        self.providers.insert(provider.scheme(), provider);
+        self
+    }
+
+    pub fn for_scheme(&self, scheme: &str) -> Result<&Arc<dyn StorageProvider>, String> {
+        self.providers
+            .get(scheme)
+            .ok_or_else(|| format!("No storage provider for {}://", scheme))
+    }
+
+    /// Provider serving `uri`
+    pub fn provider(&self, uri: &str) -> Result<&Arc<dyn StorageProvider>, String> {
+        let (scheme, _) = split_uri(uri).ok_or_else(|| format!("Invalid storage URI: {}", uri))?;
+        self.for_scheme(scheme)
+    }
+
+    pub async fn get(&self, uri: &str) -> Result<Vec<u8>, String> {
+        self.provider(uri)?.get(uri).await
+    }
+
+    pub async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String> {
+        self.provider(uri)?.stat(uri).await
+    }
+
+    pub async fn pin(&self, uri: &str) -> Result<(), String> {
+        self.provider(uri)?.pin(uri).await
+    }
+
+    pub async fn unpin(&self, uri: &str) -> Result<(), String> {
+        self.provider(uri)?.unpin(uri).await
+    }
+}
+
+fn default_pool(network: &'static str, urls: &[&str]) -> Arc<GatewayPool> {
+    let urls: Vec<String> = urls.iter().map(|url| url.to_string()).collect();
+    Arc::new(GatewayPool::new(network, &urls, GatewaySettings::default()))
+}
+
+#[derive(Clone)]
 pub struct PinataStorage {
     api_key: Option<String>,
     secret: Option<String>,
+    gateways: Arc<GatewayPool>,
 }
 
 impl PinataStorage {
@@ -11,24 +197,43 @@ impl PinataStorage {
         Self {
             api_key: env::var("PINATA_API_KEY").ok(),
             secret: env::var("PINATA_SECRET").ok(),
+            gateways: default_pool("ipfs", DEFAULT_IPFS_GATEWAYS),
         }
     }
 
-    pub async fn upload(&self, data: &[u8], name: &str) -> Result<String, String> {
-        if self.api_key.is_none() || self.secret.is_none() {
-            return Err("Pinata credentials not configured".to_string());
+    /// Read through a configured pool shared with other readers
+    pub fn with_gateways(mut self, gateways: Arc<GatewayPool>) -> Self {
+        self.gateways = gateways;
+        self
+    }
+
+    fn credentials(&self) -> Result<(&str, &str), String> {
+        match (&self.api_key, &self.secret) {
+            (Some(key), Some(secret)) => Ok((key, secret)),
+            _ => Err("Pinata credentials not configured".to_string()),
         }
+    }
+
+    fn api_request(&self, method: reqwest::Method, path: &str) -> Result<reqwest::RequestBuilder, String> {
+        let (key, secret) = self.credentials()?;
+        Ok(self.gateways.client()
+            .request(method, format!("https://api.pinata.cloud{}", path))
+            .header("pinata_api_key", key)
+            .header("pinata_secret_api_key", secret))
+    }
+
+    pub async fn upload(&self, data: &[u8], name: &str) -> Result<String, String> {
+        let part = reqwest::multipart::Part::bytes(data.to_vec()).file_name(name.to_string());
+        self.pin_files(reqwest::multipart::Form::new().part("file", part), name).await
+    }
 
-        let client = reqwest::Client::new();
-        let form = reqwest::multipart::Form::new()
+    /// Pin every `file` part of `form`; several parts sharing a top directory pin that directory
+    async fn pin_files(&self, form: reqwest::multipart::Form, name: &str) -> Result<String, String> {
+        let form = form
             .text("pinataOptions", r#"{"cidVersion":1}"#)
-            .text("pinataMetadata", format!(r#"{{"name":"{}"}}"#, name))
-            .part("file", reqwest::multipart::Part::bytes(data.to_vec()).file_name(name.to_string()));
+            .text("pinataMetadata", serde_json::json!({ "name": name }).to_string());
 
-        let response = client
-            .post("https://api.pinata.cloud/pinning/pinFileToIPFS")
-            .header("pinata_api_key", self.api_key.as_ref().unwrap())
-            .header("pinata_secret_api_key", self.secret.as_ref().unwrap())
+        let response = self.api_request(reqwest::Method::POST, "/pinning/pinFileToIPFS")?
             .multipart(form)
             .send()
             .await
@@ -47,164 +252,899 @@ impl PinataStorage {
         Ok(format!("ipfs://{}", ipfs_hash))
     }
 
+    /// Fetch a file by `cid` or `cid/path`. Gateways answer with a CAR whose blocks
+    /// are each checked against their CID before the file is put back together.
     pub async fn get(&self, cid: &str) -> Result<Vec<u8>, String> {
+        let path = cid.strip_prefix("ipfs://").unwrap_or(cid);
+        let (root, segments) = match path.split_once('/') {
+            Some((root, rest)) => (root, rest.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>()),
+            None => (path, Vec::new()),
+        };
+        let root = Cid::parse(root)?;
+        if segments.is_empty() {
+            if let Some(data) = root.inline_data() {
+                return Ok(data.to_vec());
+            }
+        }
+
+        self.gateways.fetch(|gateway| {
+            let request = self.gateways.client()
+                .get(format!("{}/ipfs/{}?format=car&dag-scope=entity", gateway, path))
+                .header("Accept", "application/vnd.ipld.car; version=1");
+            let (root, segments) = (&root, &segments);
+            async move {
+                let car = hecate::read_body(hecate::send(request).await?, self.gateways.max_body_bytes()).await?;
+                let violation = |reason| GatewayError::Integrity(IntegrityViolation::new(&gateway, path, reason));
+
+                let blocks = themis::read_car(&car).map_err(violation)?;
+                let content_error = |error| match error {
+                    ContentError::Integrity(reason) => violation(reason),
+                    ContentError::NotAFile => GatewayError::Rejected(format!("IPFS content at {} is not a file", path)),
+                    ContentError::TooLarge => GatewayError::Rejected(format!(
+                        "IPFS content at {} is larger than {} bytes", path, self.gateways.max_body_bytes(),
+                    )),
+                };
+                let target = themis::resolve_path(root, segments, &blocks).map_err(content_error)?;
+                themis::unixfs_file(&target, &blocks, self.gateways.max_body_bytes()).map_err(content_error)
+            }
+        }).await
+    }
+
+    /// Fetch a single block as stored and check it against its CID
+    pub async fn get_block(&self, cid: &str) -> Result<Vec<u8>, String> {
         let cid = cid.strip_prefix("ipfs://").unwrap_or(cid);
-        let url = format!("https://gateway.pinata.cloud/ipfs/{}", cid);
-        
-        let client = reqwest::Client::new();
-        let response = client.get(&url)
+        let parsed = Cid::parse(cid)?;
+        if let Some(data) = parsed.inline_data() {
+            return Ok(data.to_vec());
+        }
+
+        self.gateways.fetch(|gateway| {
+            let request = self.gateways.client()
+                .get(format!("{}/ipfs/{}?format=raw", gateway, cid))
+                .header("Accept", "application/vnd.ipld.raw");
+            let parsed = &parsed;
+            async move {
+                let block = hecate::read_body(hecate::send(request).await?, self.gateways.max_body_bytes()).await?;
+                parsed.verify(&block)
+                    .map_err(|reason| GatewayError::Integrity(IntegrityViolation::new(&gateway, cid, reason)))?;
+                Ok(block)
+            }
+        }).await
+    }
+}
+
+#[async_trait]
+impl StorageProvider for PinataStorage {
+    fn scheme(&self) -> &'static str {
+        "ipfs"
+    }
+
+    async fn put(&self, data: &[u8], options: &PutOptions) -> Result<String, String> {
+        let name = if options.name.is_empty() { "upload" } else { &options.name };
+        self.upload(data, name).await
+    }
+
+    async fn put_file(&self, file: &Path, options: &PutOptions) -> Result<String, String> {
+        let name = if options.name.is_empty() { "upload" } else { &options.name };
+        let part = file_part(file, name, options.content_type.as_deref()).await?;
+        self.pin_files(reqwest::multipart::Form::new().part("file", part), name).await
+    }
+
+    /// Files go up under a common top directory, whose CID becomes the root
+    async fn put_directory(&self, files: &[DirectoryFile]) -> Result<String, String> {
+        // Paths are sent as they are; Pinata does not decode percent-encoded file names
+        let mut form = reqwest::multipart::Form::new().percent_encode_noop();
+        for entry in files {
+            let name = format!("{}/{}", DIRECTORY_ROOT, entry.path);
+            form = form.part("file", file_part(&entry.file, &name, entry.content_type.as_deref()).await?);
+        }
+        self.pin_files(form, DIRECTORY_ROOT).await
+    }
+
+    async fn get(&self, uri: &str) -> Result<Vec<u8>, String> {
+        PinataStorage::get(self, uri).await
+    }
+
+    async fn get_block(&self, uri: &str) -> Result<Vec<u8>, String> {
+        PinataStorage::get_block(self, uri).await
+    }
+
+    /// Only content pinned to this Pinata account is reported
+    async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String> {
+        let cid = ipfs_root(uri)?;
+        let response = self.api_request(reqwest::Method::GET, "/data/pinList")?
+            .query(&[("hashContains", cid), ("status", "pinned")])
             .send()
             .await
-            .map_err(|e| format!("Failed to fetch from IPFS: {}", e))?;
+            .map_err(|e| format!("Pinata pin list error: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Pinata error: {}", response.status()));
+        }
+
+        let json: Value = response.json().await
+            .map_err(|e| format!("Failed to parse Pinata response: {}", e))?;
+        let row = json["rows"].as_array()
+            .and_then(|rows| rows.iter().find(|row| row["ipfs_pin_hash"].as_str() == Some(cid)));
+
+        Ok(row.map(|row| ObjectStat {
+            uri: format!("ipfs://{}", cid),
+            size: row["size"].as_u64().unwrap_or(0),
+            content_type: None,
+            pinned: true,
+        }))
+    }
+
+    async fn pin(&self, uri: &str) -> Result<(), String> {
+        let cid = ipfs_root(uri)?;
+        let response = self.api_request(reqwest::Method::POST, "/pinning/pinByHash")?
+            .json(&serde_json::json!({ "hashToPin": cid }))
+            .send()
+            .await
+            .map_err(|e| format!("Pinata pin error: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Pinata error: {}", response.status()));
+        }
+        Ok(())
+    }
 
+    async fn unpin(&self, uri: &str) -> Result<(), String> {
+        let cid = ipfs_root(uri)?;
+        let response = self.api_request(reqwest::Method::DELETE, &format!("/pinning/unpin/{}", cid))?
+            .send()
+            .await
+            .map_err(|e| format!("Pinata unpin error: {}", e))?;
         if !response.status().is_success() {
-            return Err(format!("IPFS fetch error: {}", response.status()));
+            return Err(format!("Pinata error: {}", response.status()));
         }
+        Ok(())
+    }
+}
+
+// Top directory of IPFS directory uploads; only its CID is kept
+const DIRECTORY_ROOT: &str = "site";
 
-        let bytes = response.bytes().await
-            .map_err(|e| format!("Failed to read IPFS data: {}", e))?;
+/// Multipart part streaming a staged file from disk
+async fn file_part(file: &Path, name: &str, content_type: Option<&str>) -> Result<reqwest::multipart::Part, String> {
+    let handle = tokio::fs::File::open(file).await
+        .map_err(|e| format!("Failed to open {}: {}", file.display(), e))?;
+    let length = handle.metadata().await
+        .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?
+        .len();
 
-        Ok(bytes.to_vec())
+    let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(handle));
+    let part = reqwest::multipart::Part::stream_with_length(body, length).file_name(name.to_string());
+    match content_type {
+        Some(content_type) => part.mime_str(content_type).map_err(|e| format!("Invalid content type: {}", e)),
+        None => Ok(part),
     }
 }
 
+/// Root CID of an `ipfs://cid[/path]` URI; pins always apply to a whole DAG
+fn ipfs_root(uri: &str) -> Result<&str, String> {
+    let path = uri.strip_prefix("ipfs://").unwrap_or(uri);
+    let root = path.split('/').next().unwrap_or_default();
+    Cid::parse(root)?;
+    Ok(root)
+}
+
+#[derive(Clone)]
 pub struct BundlrStorage {
-    node_url: String,
+    node: BundlrNode,
     private_key: Option<String>,
+    // Solana RPC used to top up the node balance, and whether uploads may do so on their own
+    funding_rpc_url: Option<String>,
+    auto_fund: bool,
+    // Serve data that has neither a transaction header nor an indexed data item to check against
+    allow_unverified: bool,
+    gateways: Arc<GatewayPool>,
 }
 
 impl BundlrStorage {
     pub fn new() -> Self {
         Self {
-            node_url: env::var("BUNDLR_NODE_URL")
-                .unwrap_or_else(|_| "https://devnet.bundlr.network".to_string()),
+            node: BundlrNode::new(
+                &env::var("BUNDLR_NODE_URL").unwrap_or_else(|_| "https://devnet.bundlr.network".to_string()),
+                &env::var("BUNDLR_CURRENCY").unwrap_or_else(|_| "solana".to_string()),
+            ),
             private_key: env::var("BUNDLR_PRIVATE_KEY").ok(),
+            funding_rpc_url: None,
+            auto_fund: false,
+            allow_unverified: env::var("ARWEAVE_ALLOW_UNVERIFIED")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            gateways: default_pool("arweave", DEFAULT_ARWEAVE_GATEWAYS),
         }
     }
 
+    /// Read through a configured pool shared with other readers
+    pub fn with_gateways(mut self, gateways: Arc<GatewayPool>) -> Self {
+        self.gateways = gateways;
+        self
+    }
+
+    /// Fund the node over `rpc_url`; with `auto_fund`, uploads cover their own shortfall
+    pub fn with_funding(mut self, rpc_url: &str, auto_fund: bool) -> Self {
+        self.funding_rpc_url = Some(rpc_url.to_string());
+        self.auto_fund = auto_fund;
+        self
+    }
+
+    /// Sign `data` as an ANS-104 data item and post it once the node balance covers it
     pub async fn upload(&self, data: &[u8], tags: Vec<(&str, &str)>) -> Result<String, String> {
-        if self.private_key.is_none() {
-            return Err("Bundlr private key not configured".to_string());
+        let keypair = self.keypair()?;
+        let tags = tags.into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        // A random anchor keeps repeated uploads of the same bytes apart
+        let mut item = DataItem::new(data.to_vec(), tags)?.with_anchor(rand::random());
+        item.sign(&keypair);
+
+        let size = item.size();
+        let price = self.node.price(size).await?;
+        let balance = self.node.balance(&item.owner_address()).await?;
+        if balance < price {
+            let shortfall = price - balance;
+            if !self.auto_fund {
+                return Err(format!(
+                    "Bundlr balance of {} is {} short of the {} {} needed for {} bytes",
+                    item.owner_address(), shortfall, price, self.node.currency(), size,
+                ));
+            }
+            let lamports = u64::try_from(shortfall).map_err(|_| "Funding amount too large".to_string())?;
+            let tx_id = self.fund(lamports).await?;
+            tracing::info!("Funded Bundlr with {} lamports in {}", lamports, tx_id);
         }
 
-        // Parse Solana private key (base58 or hex)
-        let key_bytes = self.parse_private_key()?;
-        let keypair = solana_sdk::signer::keypair::Keypair::from_bytes(&key_bytes)
-            .map_err(|e| format!("Invalid keypair: {}", e))?;
+        let id = self.node.upload(&item).await?;
+        Ok(format!("arweave://{}", id))
+    }
+
+    /// Move `lamports` from the upload key to the node's balance
+    pub async fn fund(&self, lamports: u64) -> Result<String, String> {
+        let rpc_url = self.funding_rpc_url.as_deref()
+            .ok_or_else(|| "Bundlr funding is not configured".to_string())?;
+        self.node.fund(rpc_url, &self.keypair()?, lamports).await
+    }
+
+    /// The upload key: base58 or hex of its 64 bytes, or a Solana keypair file's JSON array
+    fn keypair(&self) -> Result<Keypair, String> {
+        let key_str = self.private_key.as_deref()
+            .ok_or_else(|| "Bundlr private key not configured".to_string())?
+            .trim();
+
+        let key_bytes = bs58::decode(key_str).into_vec().ok()
+            .filter(|bytes| bytes.len() == 64)
+            .or_else(|| hex::decode(key_str.trim_start_matches("0x")).ok().filter(|bytes| bytes.len() == 64))
+            .or_else(|| serde_json::from_str::<Vec<u8>>(key_str).ok().filter(|bytes| bytes.len() == 64))
+            .ok_or_else(|| "Invalid private key format. Expected base58, hex or a JSON byte array (64 bytes)".to_string())?;
+
+        Keypair::from_bytes(&key_bytes).map_err(|e| format!("Invalid keypair: {}", e))
+    }
+
+    /// Fetch `id` or `id/path` the way a gateway serves it, resolving path manifests,
+    /// with every transaction read checked against its signed header
+    pub async fn get(&self, tx_id: &str) -> Result<Vec<u8>, String> {
+        let path = tx_id
+            .strip_prefix("arweave://")
+            .or_else(|| tx_id.strip_prefix("ar://"))
+            .unwrap_or(tx_id);
+        let (id, rest) = path.split_once('/').unwrap_or((path, ""));
+
+        let (data, content_type) = self.get_raw(id).await?;
+        let manifest = match ArweaveManifest::from_response(&data, content_type.as_deref()) {
+            Some(manifest) => manifest,
+            None if rest.is_empty() => return Ok(data),
+            None => return Err(format!("Arweave transaction {} is not a path manifest", id)),
+        };
 
-        // Create and sign Bundlr transaction
-        // Bundlr uses Arweave transactions signed with Solana keypairs
-        let tx = self.create_bundlr_transaction(data, &tags, &keypair).await?;
+        let segments: Vec<String> = rest.split('/')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        let (file_id, _) = manifest.resolve(&segments)
+            .ok_or_else(|| format!("Path {} not found in manifest {}", rest, id))?;
+
+        Ok(self.get_raw(file_id).await?.0)
+    }
 
-        let client = reqwest::Client::new();
-        let url = format!("{}/tx", self.node_url);
+    /// Fetch transaction data without manifest resolution, along with its Content-Type tag
+    pub async fn get_raw(&self, tx_id: &str) -> Result<(Vec<u8>, Option<String>), String> {
+        let tx_id = tx_id
+            .strip_prefix("arweave://")
+            .or_else(|| tx_id.strip_prefix("ar://"))
+            .unwrap_or(tx_id);
+
+        self.gateways.fetch(|gateway| {
+            let request = self.gateways.client().get(format!("{}/raw/{}", gateway, tx_id));
+            async move {
+                let response = hecate::send(request).await?;
+                let content_type = response.headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                let data = hecate::read_body(response, self.gateways.max_body_bytes()).await?;
+
+                self.verify(&gateway, tx_id, &data).await?;
+                Ok((data, content_type))
+            }
+        }).await
+    }
 
-        let response = client
-            .post(&url)
-            .header("Content-Type", "application/octet-stream")
-            .body(tx)
+    /// Check `data` against the signature and data root in the transaction header, or
+    /// for a data item inside a bundle, against the item's own signed fields
+    async fn verify(&self, gateway: &str, tx_id: &str, data: &[u8]) -> Result<(), GatewayError> {
+        let violation = |reason| GatewayError::Integrity(IntegrityViolation::new(gateway, tx_id, reason));
+        let response = self.gateways.client()
+            .get(format!("{}/tx/{}", gateway, tx_id))
             .send()
             .await
-            .map_err(|e| format!("Bundlr upload error: {}", e))?;
+            .map_err(|e| GatewayError::Unavailable(format!("Failed to fetch Arweave transaction: {}", e)))?;
 
+        if response.status() == reqwest::StatusCode::OK {
+            let header: ArweaveTransaction = response.json().await
+                .map_err(|e| violation(format!("Malformed transaction header: {}", e)))?;
+            return header.verify(tx_id, data).map_err(violation);
+        }
         let status = response.status();
-        if !status.is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("Bundlr error: {} - {}", status, error_text));
+
+        // Every upload made here is a data item, which gateways index but serve no header for
+        if let Some(header) = self.data_item_header(gateway, tx_id).await? {
+            if header.is_ed25519() {
+                return header.verify(tx_id, data).map_err(violation);
+            }
         }
 
-        let json: Value = response.json().await
-            .map_err(|e| format!("Failed to parse Bundlr response: {}", e))?;
+        // Pending transactions and items signed with other key types
+        if self.allow_unverified {
+            tracing::warn!("Serving unverified Arweave data {} ({})", tx_id, status);
+            return Ok(());
+        }
+        Err(GatewayError::Missing(format!("Transaction {} cannot be verified: {}", tx_id, status)))
+    }
+
+    /// The signed fields a gateway indexes for a bundled data item, if `tx_id` is one
+    async fn data_item_header(&self, gateway: &str, tx_id: &str) -> Result<Option<DataItemHeader>, GatewayError> {
+        let response = self.gateways.client()
+            .post(format!("{}/graphql", gateway))
+            .json(&serde_json::json!({ "query": DATA_ITEM_QUERY, "variables": { "id": tx_id } }))
+            .send()
+            .await
+            .map_err(|e| GatewayError::Unavailable(format!("Failed to query Arweave index: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(GatewayError::Unavailable(format!("Arweave index error: {}", response.status())));
+        }
+
+        let body: Value = response.json().await
+            .map_err(|e| GatewayError::Unavailable(format!("Malformed Arweave index response: {}", e)))?;
+        let transaction = &body["data"]["transaction"];
+        if transaction.is_null() || transaction["bundledIn"].is_null() {
+            return Ok(None);
+        }
+        serde_json::from_value(transaction.clone())
+            .map(Some)
+            .map_err(|e| GatewayError::Unavailable(format!("Malformed data item header: {}", e)))
+    }
+}
 
-        let tx_id = json["id"].as_str()
-            .ok_or_else(|| "Missing tx id in response".to_string())?;
+#[async_trait]
+impl StorageProvider for BundlrStorage {
+    fn scheme(&self) -> &'static str {
+        "arweave"
+    }
 
-        Ok(format!("arweave://{}", tx_id))
+    async fn put(&self, data: &[u8], options: &PutOptions) -> Result<String, String> {
+        let mut tags: Vec<(&str, &str)> = options.tags.iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        if let Some(content_type) = &options.content_type {
+            tags.push(("Content-Type", content_type));
+        }
+        self.upload(data, tags).await
     }
 
-    fn parse_private_key(&self) -> Result<Vec<u8>, String> {
-        let key_str = self.private_key.as_ref().unwrap();
-        
-        // Try base58 first (Solana format)
-        if let Ok(bytes) = bs58::decode(key_str).into_vec() {
-            if bytes.len() == 64 {
-                return Ok(bytes);
+    async fn get(&self, uri: &str) -> Result<Vec<u8>, String> {
+        BundlrStorage::get(self, uri).await
+    }
+
+    async fn get_raw(&self, uri: &str) -> Result<(Vec<u8>, Option<String>), String> {
+        BundlrStorage::get_raw(self, uri).await
+    }
+
+    /// Sizes come from the gateways' headers; a gateway answering 404 is taken at its word
+    async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String> {
+        let (_, tx_id) = split_uri(uri).unwrap_or(("arweave", uri));
+
+        self.gateways.fetch(|gateway| {
+            let request = self.gateways.client().head(format!("{}/raw/{}", gateway, tx_id));
+            async move {
+                let response = match hecate::send(request).await {
+                    Ok(response) => response,
+                    Err(GatewayError::Missing(_)) => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                let header = |name| response.headers()
+                    .get(name)
+                    .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                    .map(str::to_string);
+
+                Ok(Some(ObjectStat {
+                    uri: format!("arweave://{}", tx_id),
+                    size: header(reqwest::header::CONTENT_LENGTH)
+                        .and_then(|len| len.parse().ok())
+                        .unwrap_or(0),
+                    content_type: header(reqwest::header::CONTENT_TYPE),
+                    pinned: true,
+                }))
             }
+        }).await
+    }
+
+    /// Arweave data is permanent once the upload is paid for
+    async fn pin(&self, _uri: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn unpin(&self, uri: &str) -> Result<(), String> {
+        Err(format!("Arweave data is permanent and cannot be unpinned: {}", uri))
+    }
+
+    /// Priced as an anchored data item carrying a Content-Type tag
+    async fn quote(&self, size: u64) -> Result<Option<Quote>, String> {
+        let template = DataItem::new(Vec::new(), vec![("Content-Type".to_string(), "application/octet-stream".to_string())])?
+            .with_anchor([0; 32]);
+        let size = size + template.size();
+        Ok(Some(Quote {
+            size,
+            currency: self.node.currency().to_string(),
+            price: self.node.price(size).await?.to_string(),
+        }))
+    }
+}
+
+/// A self-hosted IPFS node reached over the Kubo RPC API. The node checks the blocks
+/// it fetches itself, so its responses are trusted without a CAR round-trip.
+#[derive(Clone)]
+pub struct KuboStorage {
+    api_url: String,
+    client: reqwest::Client,
+}
+
+impl KuboStorage {
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
         }
-        
-        // Try hex
-        if let Ok(bytes) = hex::decode(key_str.trim_start_matches("0x")) {
-            if bytes.len() == 64 {
-                return Ok(bytes);
+    }
+
+    fn rpc(&self, command: &str) -> reqwest::RequestBuilder {
+        // Every RPC command is a POST, including reads
+        self.client.post(format!("{}/api/v0/{}", self.api_url, command))
+    }
+
+    async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let response = request.send()
+            .await
+            .map_err(|e| format!("Kubo RPC error: {}", e))?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status();
+        let message = response.json::<Value>().await
+            .ok()
+            .and_then(|json| json["Message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        Err(format!("Kubo error: {}", message))
+    }
+
+    /// Add and pin the parts of `form`, returning the URI of the entry called `root`
+    async fn add(&self, form: reqwest::multipart::Form, root: &str) -> Result<String, String> {
+        let response = Self::send(
+            self.rpc("add")
+                .query(&[("cid-version", "1"), ("pin", "true")])
+                .multipart(form),
+        ).await?;
+        let body = response.text().await
+            .map_err(|e| format!("Failed to read Kubo response: {}", e))?;
+
+        // One JSON object per added file or directory
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: Value = serde_json::from_str(line)
+                .map_err(|e| format!("Failed to parse Kubo response: {}", e))?;
+            if entry["Name"].as_str() == Some(root) {
+                let hash = entry["Hash"].as_str()
+                    .ok_or_else(|| "Missing Hash in Kubo response".to_string())?;
+                return Ok(format!("ipfs://{}", hash));
             }
         }
-        
-        Err("Invalid private key format. Expected base58 or hex (64 bytes)".to_string())
-    }
-
-    async fn create_bundlr_transaction(
-        &self,
-        data: &[u8],
-        _tags: &[(&str, &str)],
-        keypair: &solana_sdk::signer::keypair::Keypair,
-    ) -> Result<Vec<u8>, String> {
-        // Get price from Bundlr
-        let client = reqwest::Client::new();
-        let price_url = format!("{}/price/{}", self.node_url, data.len());
-        
-        let price_response = client
-            .get(&price_url)
-            .send()
+        Err(format!("Kubo did not report {}", root))
+    }
+
+    async fn bytes(request: reqwest::RequestBuilder) -> Result<Vec<u8>, String> {
+        Self::send(request).await?
+            .bytes()
             .await
-            .map_err(|e| format!("Failed to get price: {}", e))?;
-        
-        let price_json: Value = price_response.json().await
-            .map_err(|e| format!("Failed to parse price: {}", e))?;
-        
-        let _price = price_json["atomicPrice"].as_u64()
-            .ok_or_else(|| "Missing price in response".to_string())?;
+            .map(|b| b.to_vec())
+            .map_err(|e| format!("Failed to read Kubo response: {}", e))
+    }
+}
 
-        // Create transaction payload for Bundlr
-        // Bundlr accepts data with signature in a specific format
-        // We'll create a signed payload that Bundlr can verify
-        let mut payload = Vec::new();
-        
-        // Add data
-        payload.extend_from_slice(data);
-        
-        // Create signature over the data
-        use solana_sdk::signature::Signer;
-        let signature = keypair.try_sign_message(&payload)
-            .map_err(|e| format!("Failed to sign: {}", e))?;
-        
-        // Bundlr transaction format: data + signature + public key
-        let mut tx = payload;
-        tx.extend_from_slice(signature.as_ref());
-        tx.extend_from_slice(&keypair.pubkey().to_bytes());
-        
-        // Add tags as metadata (Bundlr will handle this)
-        // For now, we'll send the transaction and let Bundlr process it
-        Ok(tx)
+#[async_trait]
+impl StorageProvider for KuboStorage {
+    fn scheme(&self) -> &'static str {
+        "ipfs"
     }
 
-    pub async fn get(&self, tx_id: &str) -> Result<Vec<u8>, String> {
-        let tx_id = tx_id.strip_prefix("arweave://").unwrap_or(tx_id);
-        let url = format!("https://arweave.net/{}", tx_id);
-        
-        let client = reqwest::Client::new();
-        let response = client.get(&url)
+    async fn put(&self, data: &[u8], options: &PutOptions) -> Result<String, String> {
+        let name = if options.name.is_empty() { "upload" } else { &options.name };
+        let part = reqwest::multipart::Part::bytes(data.to_vec()).file_name(name.to_string());
+        self.add(reqwest::multipart::Form::new().part("file", part), name).await
+    }
+
+    async fn put_file(&self, file: &Path, options: &PutOptions) -> Result<String, String> {
+        let name = if options.name.is_empty() { "upload" } else { &options.name };
+        let part = file_part(file, name, options.content_type.as_deref()).await?;
+        self.add(reqwest::multipart::Form::new().part("file", part), name).await
+    }
+
+    /// Directories are sent as parts of their own, parents before their entries
+    async fn put_directory(&self, files: &[DirectoryFile]) -> Result<String, String> {
+        let mut directories = std::collections::BTreeSet::new();
+        for entry in files {
+            let mut parent = entry.path.as_str();
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                directories.insert(dir.to_string());
+                parent = dir;
+            }
+        }
+
+        let directory_part = |name: String| {
+            reqwest::multipart::Part::bytes(Vec::new())
+                .file_name(name)
+                .mime_str("application/x-directory")
+                .map_err(|e| format!("Invalid content type: {}", e))
+        };
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", directory_part(DIRECTORY_ROOT.to_string())?);
+        for dir in directories {
+            form = form.part("file", directory_part(format!("{}/{}", DIRECTORY_ROOT, dir))?);
+        }
+        for entry in files {
+            let name = format!("{}/{}", DIRECTORY_ROOT, entry.path);
+            form = form.part("file", file_part(&entry.file, &name, entry.content_type.as_deref()).await?);
+        }
+
+        self.add(form, DIRECTORY_ROOT).await
+    }
+
+    async fn get(&self, uri: &str) -> Result<Vec<u8>, String> {
+        let path = uri.strip_prefix("ipfs://").unwrap_or(uri);
+        Self::bytes(self.rpc("cat").query(&[("arg", format!("/ipfs/{}", path))])).await
+    }
+
+    async fn get_block(&self, uri: &str) -> Result<Vec<u8>, String> {
+        let cid = uri.strip_prefix("ipfs://").unwrap_or(uri);
+        Self::bytes(self.rpc("block/get").query(&[("arg", cid)])).await
+    }
+
+    /// Only content already in the node's repository is reported
+    async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String> {
+        let path = uri.strip_prefix("ipfs://").unwrap_or(uri);
+        let response = self.rpc("files/stat")
+            .query(&[("arg", format!("/ipfs/{}", path)), ("offline", "true".to_string())])
             .send()
             .await
-            .map_err(|e| format!("Failed to fetch from Arweave: {}", e))?;
-
+            .map_err(|e| format!("Kubo RPC error: {}", e))?;
         if !response.status().is_success() {
-            return Err(format!("Arweave fetch error: {}", response.status()));
+            return Ok(None);
         }
+        let json: Value = response.json().await
+            .map_err(|e| format!("Failed to parse Kubo response: {}", e))?;
 
-        let bytes = response.bytes().await
-            .map_err(|e| format!("Failed to read Arweave data: {}", e))?;
+        // Recursive pins cover the whole DAG below the root
+        let pinned = Self::send(
+            self.rpc("pin/ls").query(&[("arg", ipfs_root(uri)?), ("type", "recursive")]),
+        ).await.is_ok();
 
-        Ok(bytes.to_vec())
+        Ok(Some(ObjectStat {
+            uri: format!("ipfs://{}", path),
+            size: json["CumulativeSize"].as_u64().or_else(|| json["Size"].as_u64()).unwrap_or(0),
+            content_type: None,
+            pinned,
+        }))
+    }
+
+    async fn pin(&self, uri: &str) -> Result<(), String> {
+        Self::send(self.rpc("pin/add").query(&[("arg", ipfs_root(uri)?)])).await.map(|_| ())
+    }
+
+    async fn unpin(&self, uri: &str) -> Result<(), String> {
+        Self::send(self.rpc("pin/rm").query(&[("arg", ipfs_root(uri)?)])).await.map(|_| ())
+    }
+}
+
+/// Content-addressed store on local disk for development and tests. Objects are named by
+/// the CIDv1 of their raw bytes and served as `local://<cid>`.
+#[derive(Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn cid(uri: &str) -> Result<Cid, String> {
+        let id = uri.strip_prefix("local://").unwrap_or(uri);
+        if id.contains('/') {
+            return Err(format!("Local objects have no paths: {}", uri));
+        }
+        Cid::parse(id)
+    }
+
+    fn object_path(&self, cid: &Cid) -> PathBuf {
+        self.root.join(cid.to_string())
+    }
+
+    fn content_type_path(&self, cid: &Cid) -> PathBuf {
+        self.root.join(format!("{}.type", cid))
+    }
+}
+
+#[async_trait]
+impl StorageProvider for LocalStorage {
+    fn scheme(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, data: &[u8], options: &PutOptions) -> Result<String, String> {
+        let cid = Cid {
+            version: 1,
+            codec: CODEC_RAW,
+            hash_code: 0x12,
+            digest: Sha256::digest(data).to_vec(),
+        };
+        let path = self.object_path(&cid);
+
+        tokio::fs::create_dir_all(&self.root).await
+            .map_err(|e| format!("Failed to create {}: {}", self.root.display(), e))?;
+        if tokio::fs::metadata(&path).await.is_err() {
+            // Written aside and renamed so readers never see a partial object
+            let partial = self.root.join(format!("{}.partial", cid));
+            tokio::fs::write(&partial, data).await
+                .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+            tokio::fs::rename(&partial, &path).await
+                .map_err(|e| format!("Failed to store {}: {}", cid, e))?;
+        }
+        if let Some(content_type) = &options.content_type {
+            tokio::fs::write(self.content_type_path(&cid), content_type).await
+                .map_err(|e| format!("Failed to store content type of {}: {}", cid, e))?;
+        }
+
+        Ok(format!("local://{}", cid))
+    }
+
+    async fn get(&self, uri: &str) -> Result<Vec<u8>, String> {
+        Ok(self.get_raw(uri).await?.0)
+    }
+
+    async fn get_raw(&self, uri: &str) -> Result<(Vec<u8>, Option<String>), String> {
+        let cid = Self::cid(uri)?;
+        let data = tokio::fs::read(self.object_path(&cid)).await
+            .map_err(|e| format!("Local object {} not readable: {}", cid, e))?;
+        cid.verify(&data)
+            .map_err(|e| format!("Local object {} is corrupt: {}", cid, e))?;
+        let content_type = tokio::fs::read_to_string(self.content_type_path(&cid)).await.ok();
+
+        Ok((data, content_type))
+    }
+
+    async fn stat(&self, uri: &str) -> Result<Option<ObjectStat>, String> {
+        let cid = Self::cid(uri)?;
+        let metadata = match tokio::fs::metadata(self.object_path(&cid)).await {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+
+        Ok(Some(ObjectStat {
+            uri: format!("local://{}", cid),
+            size: metadata.len(),
+            content_type: tokio::fs::read_to_string(self.content_type_path(&cid)).await.ok(),
+            pinned: true,
+        }))
+    }
+
+    /// Everything stored locally stays until unpinned
+    async fn pin(&self, uri: &str) -> Result<(), String> {
+        match self.stat(uri).await? {
+            Some(_) => Ok(()),
+            None => Err(format!("Local object {} is not stored", uri)),
+        }
+    }
+
+    /// Nothing else references local objects, so unpinning deletes them
+    async fn unpin(&self, uri: &str) -> Result<(), String> {
+        let cid = Self::cid(uri)?;
+        for path in [self.object_path(&cid), self.content_type_path(&cid)] {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to remove {}: {}", path.display(), e)),
+            }
+        }
+        Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> LocalStorage {
+        let dir = std::env::temp_dir().join(format!("shadow-storage-{}", uuid::Uuid::new_v4()));
+        LocalStorage::new(dir)
+    }
+
+    #[tokio::test]
+    async fn local_storage_round_trip() {
+        let store = local();
+        let options = PutOptions { content_type: Some("text/plain".to_string()), ..Default::default() };
+
+        let uri = store.put(b"hello", &options).await.unwrap();
+        // CIDv1 of the raw bytes, the same one IPFS would give a single raw block
+        assert_eq!(uri, "local://bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq");
+        assert_eq!(store.put(b"hello", &options).await.unwrap(), uri);
+
+        assert_eq!(store.get(&uri).await.unwrap(), b"hello");
+        assert_eq!(store.get_raw(&uri).await.unwrap().1.as_deref(), Some("text/plain"));
+        let stat = store.stat(&uri).await.unwrap().unwrap();
+        assert_eq!((stat.size, stat.pinned), (5, true));
+
+        store.pin(&uri).await.unwrap();
+        store.unpin(&uri).await.unwrap();
+        assert_eq!(store.stat(&uri).await.unwrap(), None);
+        assert!(store.get(&uri).await.is_err());
+        assert!(store.pin(&uri).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[tokio::test]
+    async fn local_storage_detects_corruption() {
+        let store = local();
+        let uri = store.put(b"original", &PutOptions::default()).await.unwrap();
+        let cid = LocalStorage::cid(&uri).unwrap();
+        std::fs::write(store.object_path(&cid), b"tampered").unwrap();
+
+        assert!(store.get(&uri).await.unwrap_err().contains("corrupt"));
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    #[tokio::test]
+    async fn registry_routes_by_scheme() {
+        let store = local();
+        let registry = StorageRegistry::new().with_provider(Arc::new(store.clone()));
+
+        let uri = registry.for_scheme("local").unwrap().put(b"routed", &PutOptions::default()).await.unwrap();
+        assert_eq!(registry.get(&uri).await.unwrap(), b"routed");
+        assert!(registry.get("ipfs://bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq").await
+            .unwrap_err()
+            .contains("No storage provider for ipfs://"));
+        assert!(registry.get("not-a-uri").await.is_err());
+
+        let _ = std::fs::remove_dir_all(&store.root);
+    }
+
+    /// A Bundlr node and Arweave gateway in one: keeps posted items and serves them the
+    /// way gateways serve bundled items, with no `/tx` header. `tamper` flips a data byte.
+    async fn serve_arweave(tamper: bool) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let items = Arc::new(std::sync::Mutex::new(HashMap::<String, DataItem>::new()));
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let items = Arc::clone(&items);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    let (head, body_start) = loop {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break (String::from_utf8_lossy(&request[..end]).to_string(), end + 4);
+                        }
+                    };
+                    let length = head.lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    while request.len() < body_start + length {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let body = &request[body_start..];
+                    let target = head.split(' ').nth(1).unwrap_or_default().to_string();
+
+                    let (status, content_type, response) = if target.starts_with("/price/") {
+                        ("200 OK", "application/json", b"1000".to_vec())
+                    } else if target.starts_with("/account/balance/") {
+                        ("200 OK", "application/json", br#"{"balance":"5000"}"#.to_vec())
+                    } else if target == "/tx/solana" {
+                        let item = DataItem::from_bytes(body).unwrap();
+                        let id = item.id();
+                        items.lock().unwrap().insert(id.clone(), item);
+                        ("200 OK", "application/json", serde_json::json!({ "id": id }).to_string().into_bytes())
+                    } else if let Some(mut data) = target.strip_prefix("/raw/")
+                        .and_then(|id| items.lock().unwrap().get(id).map(|item| item.data.clone()))
+                    {
+                        if tamper {
+                            data[0] ^= 1;
+                        }
+                        ("200 OK", "text/plain", data)
+                    } else if target == "/graphql" {
+                        let query: Value = serde_json::from_slice(body).unwrap();
+                        let id = query["variables"]["id"].as_str().unwrap();
+                        let transaction = items.lock().unwrap().get(id).map(DataItem::indexed_header);
+                        ("200 OK", "application/json", serde_json::json!({ "data": { "transaction": transaction } }).to_string().into_bytes())
+                    } else {
+                        ("404 Not Found", "text/plain", b"Not Found".to_vec())
+                    };
+
+                    let head = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status, content_type, response.len(),
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&response).await;
+                });
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn bundlr(url: &str) -> BundlrStorage {
+        BundlrStorage {
+            node: BundlrNode::new(url, "solana"),
+            private_key: Some(bs58::encode(Keypair::new().to_bytes()).into_string()),
+            funding_rpc_url: None,
+            auto_fund: false,
+            allow_unverified: false,
+            gateways: Arc::new(GatewayPool::new("arweave", &[url.to_string()], GatewaySettings::default())),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_back_bundled_uploads() {
+        let url = serve_arweave(false).await;
+        let store = bundlr(&url);
+        let options = PutOptions { content_type: Some("text/plain".to_string()), ..Default::default() };
+
+        let uri = store.put(b"hello bundle", &options).await.unwrap();
+        assert!(uri.starts_with("arweave://"));
+        assert_eq!(store.get(&uri).await.unwrap(), b"hello bundle");
+        assert_eq!(store.get_raw(&uri).await.unwrap().1.as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_bundled_items() {
+        let url = serve_arweave(true).await;
+        let store = bundlr(&url);
+
+        let uri = store.put(b"hello bundle", &PutOptions::default()).await.unwrap();
+        let error = store.get(&uri).await.unwrap_err();
+        assert!(error.contains("does not match"), "{}", error);
+        // Unknown ids have nothing to check against and stay closed
+        assert!(store.get("arweave://unknown").await.is_err());
+    }
+
+    #[test]
+    fn short_arweave_uris_share_a_provider() {
+        assert_eq!(split_uri("ar://abc"), Some(("arweave", "abc")));
+        assert_eq!(split_uri("arweave://abc/index.html"), Some(("arweave", "abc/index.html")));
+        assert_eq!(split_uri("abc"), None);
+    }
+}
@@ -0,0 +1,1002 @@
+// Themis - Titaness of Divine Law
+// Checks that content served by storage gateways is what was asked for:
+// IPFS blocks against their CID multihash, Arweave data against its signed data root
+
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::HashMap;
+use std::fmt;
+
+// Multicodecs a UnixFS tree is built from
+pub const CODEC_RAW: u64 = 0x55;
+pub const CODEC_DAG_PB: u64 = 0x70;
+
+// Multihash functions we can check
+const MULTIHASH_IDENTITY: u64 = 0x00;
+const MULTIHASH_SHA2_256: u64 = 0x12;
+const MULTIHASH_SHA2_512: u64 = 0x13;
+
+// UnixFS node types
+const UNIXFS_RAW: u64 = 0;
+const UNIXFS_DIRECTORY: u64 = 1;
+const UNIXFS_FILE: u64 = 2;
+const UNIXFS_HAMT_SHARD: u64 = 5;
+const DEFAULT_HAMT_FANOUT: u64 = 256;
+
+/// Nodes a file may take to reassemble; a million 256KiB chunks is far past any body cap
+const MAX_FILE_NODES: usize = 1 << 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// CARv2 files open with this fixed pragma instead of a CARv1 header
+const CARV2_PRAGMA: [u8; 11] = [0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02];
+
+// Arweave chunking rules for the transaction data root
+const ARWEAVE_MAX_CHUNK_SIZE: usize = 256 * 1024;
+const ARWEAVE_MIN_CHUNK_SIZE: usize = 32 * 1024;
+const ARWEAVE_NOTE_SIZE: usize = 32;
+
+/// Content whose bytes do not check out against the identifier they were requested by.
+/// Reported with the gateway that served it so it can be taken out of rotation.
+#[derive(Debug, Clone)]
+pub struct IntegrityViolation {
+    pub gateway: String,
+    pub content: String,
+    pub reason: String,
+}
+
+impl IntegrityViolation {
+    pub fn new(gateway: &str, content: &str, reason: impl Into<String>) -> Self {
+        Self {
+            gateway: gateway.to_string(),
+            content: content.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Log the violation and turn it into the storage error callers see
+    pub fn report(self) -> String {
+        tracing::error!(
+            gateway = %self.gateway,
+            content = %self.content,
+            "Gateway served content that failed verification: {}",
+            self.reason
+        );
+        self.to_string()
+    }
+}
+
+impl fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gateway {} served unverifiable content for {}: {}", self.gateway, self.content, self.reason)
+    }
+}
+
+/// Why content from a CAR response could not be returned
+#[derive(Debug, PartialEq)]
+pub enum ContentError {
+    /// The response does not prove the requested content
+    Integrity(String),
+    /// The content checks out but is a directory rather than a file
+    NotAFile,
+    /// The content checks out but reassembles to more than the caller will hold
+    TooLarge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cid {
+    pub version: u64,
+    pub codec: u64,
+    pub hash_code: u64,
+    pub digest: Vec<u8>,
+}
+
+impl Cid {
+    /// Parse a CID in text form: base58 CIDv0, or CIDv1 in base32 (`b`) or base58 (`z`)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = if text.len() == 46 && text.starts_with("Qm") {
+            bs58::decode(text).into_vec().ok()
+        } else {
+            match text.split_at(text.len().min(1)) {
+                ("b", rest) => base32_decode(rest),
+                ("z", rest) => bs58::decode(rest).into_vec().ok(),
+                _ => None,
+            }
+        }
+        .ok_or_else(|| format!("Unsupported CID encoding: {}", text))?;
+
+        Self::decode(&bytes).map_err(|e| format!("Invalid CID {}: {}", text, e))
+    }
+
+    /// Decode a binary CID that makes up the whole of `bytes`
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut pos = 0;
+        let cid = Self::read(bytes, &mut pos)?;
+        if pos != bytes.len() {
+            return Err("Trailing bytes after CID".to_string());
+        }
+        Ok(cid)
+    }
+
+    /// Read a binary CID starting at `pos`
+    pub fn read(bytes: &[u8], pos: &mut usize) -> Result<Self, String> {
+        // A CIDv0 is a bare sha2-256 multihash
+        let (version, codec) = if bytes[*pos..].starts_with(&[0x12, 0x20]) {
+            (0, CODEC_DAG_PB)
+        } else {
+            let version = read_varint(bytes, pos)?;
+            if version != 1 {
+                return Err(format!("Unsupported CID version {}", version));
+            }
+            (version, read_varint(bytes, pos)?)
+        };
+
+        let hash_code = read_varint(bytes, pos)?;
+        let len = read_varint(bytes, pos)? as usize;
+        let end = pos.checked_add(len).filter(|end| *end <= bytes.len())
+            .ok_or("Truncated multihash")?;
+        let digest = bytes[*pos..end].to_vec();
+        *pos = end;
+
+        Ok(Self { version, codec, hash_code, digest })
+    }
+
+    pub fn multihash(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.digest.len() + 4);
+        write_varint(self.hash_code, &mut out);
+        write_varint(self.digest.len() as u64, &mut out);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.version == 0 {
+            return self.multihash();
+        }
+        let mut out = Vec::new();
+        write_varint(self.version, &mut out);
+        write_varint(self.codec, &mut out);
+        out.extend_from_slice(&self.multihash());
+        out
+    }
+
+    /// Data carried inside an identity CID, which needs no fetch
+    pub fn inline_data(&self) -> Option<&[u8]> {
+        (self.hash_code == MULTIHASH_IDENTITY).then_some(self.digest.as_slice())
+    }
+
+    /// Check that `data` hashes to this CID
+    pub fn verify(&self, data: &[u8]) -> Result<(), String> {
+        let digest = match self.hash_code {
+            MULTIHASH_IDENTITY => data.to_vec(),
+            MULTIHASH_SHA2_256 => Sha256::digest(data).to_vec(),
+            MULTIHASH_SHA2_512 => Sha512::digest(data).to_vec(),
+            code => return Err(format!("Unsupported multihash 0x{:x} in {}", code, self)),
+        };
+
+        if digest != self.digest {
+            return Err(format!("Block does not hash to {}", self));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.version == 0 {
+            write!(f, "{}", bs58::encode(self.multihash()).into_string())
+        } else {
+            write!(f, "b{}", base32_encode(&self.to_bytes()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PbLink {
+    pub cid: Cid,
+    pub name: String,
+}
+
+/// A UnixFS node decoded from a raw or dag-pb block
+#[derive(Debug, Clone)]
+pub enum UnixFsNode {
+    /// File bytes held in this block followed by the files it links to
+    File { data: Vec<u8>, links: Vec<PbLink> },
+    Directory(Vec<PbLink>),
+    /// HAMT sharded directory; entry names carry a hex prefix of `prefix_len` characters
+    Shard { links: Vec<PbLink>, prefix_len: usize },
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos).ok_or("Truncated varint")?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("Varint too long".to_string())
+}
+
+pub fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Protobuf fields of a message; dag-pb and UnixFS only use varints and length-delimited data
+fn protobuf_fields(buf: &[u8]) -> Result<Vec<(u64, Field<'_>)>, String> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = read_varint(buf, &mut pos)?;
+        let value = match key & 0x7 {
+            0 => Field::Varint(read_varint(buf, &mut pos)?),
+            2 => {
+                let len = read_varint(buf, &mut pos)? as usize;
+                let end = pos.checked_add(len).filter(|end| *end <= buf.len())
+                    .ok_or("Truncated protobuf field")?;
+                let bytes = &buf[pos..end];
+                pos = end;
+                Field::Bytes(bytes)
+            }
+            wire => return Err(format!("Unsupported protobuf wire type {}", wire)),
+        };
+        fields.push((key >> 3, value));
+    }
+    Ok(fields)
+}
+
+/// Decode the UnixFS node stored in `block` under `cid`
+pub fn decode_unixfs(cid: &Cid, block: &[u8]) -> Result<UnixFsNode, String> {
+    match cid.codec {
+        CODEC_RAW => return Ok(UnixFsNode::File { data: block.to_vec(), links: Vec::new() }),
+        CODEC_DAG_PB => {}
+        codec => return Err(format!("Unsupported codec 0x{:x} in {}", codec, cid)),
+    }
+
+    let mut links = Vec::new();
+    let mut data: &[u8] = &[];
+    for (field, value) in protobuf_fields(block)? {
+        match (field, value) {
+            (1, Field::Bytes(bytes)) => data = bytes,
+            (2, Field::Bytes(bytes)) => links.push(decode_link(bytes)?),
+            _ => return Err("Malformed dag-pb node".to_string()),
+        }
+    }
+
+    let mut node_type = None;
+    let mut file_data = Vec::new();
+    let mut fanout = DEFAULT_HAMT_FANOUT;
+    for (field, value) in protobuf_fields(data)? {
+        match (field, value) {
+            (1, Field::Varint(value)) => node_type = Some(value),
+            (2, Field::Bytes(bytes)) => file_data = bytes.to_vec(),
+            (6, Field::Varint(value)) => fanout = value,
+            _ => {}
+        }
+    }
+
+    match node_type {
+        Some(UNIXFS_RAW) | Some(UNIXFS_FILE) => Ok(UnixFsNode::File { data: file_data, links }),
+        Some(UNIXFS_DIRECTORY) => Ok(UnixFsNode::Directory(links)),
+        Some(UNIXFS_HAMT_SHARD) => {
+            if fanout < 2 || !fanout.is_power_of_two() {
+                return Err(format!("Invalid HAMT fanout {}", fanout));
+            }
+            Ok(UnixFsNode::Shard { links, prefix_len: format!("{:X}", fanout - 1).len() })
+        }
+        Some(other) => Err(format!("Unsupported UnixFS node type {}", other)),
+        None => Err("Block is not a UnixFS node".to_string()),
+    }
+}
+
+fn decode_link(buf: &[u8]) -> Result<PbLink, String> {
+    let mut cid = None;
+    let mut name = String::new();
+
+    for (field, value) in protobuf_fields(buf)? {
+        match (field, value) {
+            (1, Field::Bytes(bytes)) => cid = Some(Cid::decode(bytes)?),
+            (2, Field::Bytes(bytes)) => {
+                name = String::from_utf8(bytes.to_vec()).map_err(|_| "Link name is not UTF-8")?;
+            }
+            (3, Field::Varint(_)) => {}
+            _ => return Err("Malformed dag-pb link".to_string()),
+        }
+    }
+
+    Ok(PbLink {
+        cid: cid.ok_or("Link without a CID")?,
+        name,
+    })
+}
+
+/// Entry matching `name` among one shard's links; sub-shards are queued on `shards`
+pub fn scan_shard(links: &[PbLink], prefix_len: usize, name: &str, shards: &mut Vec<Cid>) -> Option<Cid> {
+    for link in links {
+        if link.name.len() == prefix_len {
+            shards.push(link.cid.clone());
+        } else if link.name.get(prefix_len..) == Some(name) {
+            return Some(link.cid.clone());
+        }
+    }
+    None
+}
+
+/// Blocks of a CARv1 response keyed by multihash, each checked against its CID
+pub fn read_car(car: &[u8]) -> Result<HashMap<Vec<u8>, Vec<u8>>, String> {
+    if car.starts_with(&CARV2_PRAGMA) {
+        return Err("CARv2 responses are not supported".to_string());
+    }
+
+    // The header only names the roots, which we already know
+    let mut pos = 0;
+    let header_len = read_varint(car, &mut pos)? as usize;
+    pos = pos.checked_add(header_len).filter(|end| *end <= car.len())
+        .ok_or("Truncated CAR header")?;
+
+    let mut blocks = HashMap::new();
+    while pos < car.len() {
+        let len = read_varint(car, &mut pos)? as usize;
+        let end = pos.checked_add(len).filter(|end| *end <= car.len())
+            .ok_or("Truncated CAR section")?;
+        let cid = Cid::read(&car[..end], &mut pos)?;
+        let block = &car[pos..end];
+        cid.verify(block)?;
+        blocks.insert(cid.multihash(), block.to_vec());
+        pos = end;
+    }
+
+    Ok(blocks)
+}
+
+fn car_block<'a>(cid: &'a Cid, blocks: &'a HashMap<Vec<u8>, Vec<u8>>) -> Result<&'a [u8], ContentError> {
+    if let Some(data) = cid.inline_data() {
+        return Ok(data);
+    }
+    blocks.get(&cid.multihash())
+        .map(Vec::as_slice)
+        .ok_or_else(|| ContentError::Integrity(format!("Block {} missing from CAR", cid)))
+}
+
+fn car_node(cid: &Cid, blocks: &HashMap<Vec<u8>, Vec<u8>>) -> Result<UnixFsNode, ContentError> {
+    decode_unixfs(cid, car_block(cid, blocks)?).map_err(ContentError::Integrity)
+}
+
+/// Follow `segments` from `root` through the directories in a verified CAR
+pub fn resolve_path(
+    root: &Cid,
+    segments: &[&str],
+    blocks: &HashMap<Vec<u8>, Vec<u8>>,
+) -> Result<Cid, ContentError> {
+    let mut cid = root.clone();
+
+    for segment in segments {
+        let next = match car_node(&cid, blocks)? {
+            UnixFsNode::Directory(links) => {
+                links.into_iter().find(|link| link.name == *segment).map(|link| link.cid)
+            }
+            // Only the shards on the way to the entry are in the CAR, the rest are skipped
+            UnixFsNode::Shard { links, prefix_len } => {
+                let mut shards = Vec::new();
+                let mut found = scan_shard(&links, prefix_len, segment, &mut shards);
+                while found.is_none() {
+                    let Some(shard) = shards.pop() else { break };
+                    if !blocks.contains_key(&shard.multihash()) {
+                        continue;
+                    }
+                    match car_node(&shard, blocks)? {
+                        UnixFsNode::Shard { links, prefix_len } => {
+                            found = scan_shard(&links, prefix_len, segment, &mut shards);
+                        }
+                        _ => return Err(ContentError::Integrity("Malformed sharded directory".to_string())),
+                    }
+                }
+                found
+            }
+            UnixFsNode::File { .. } => None,
+        };
+
+        cid = next.ok_or_else(|| ContentError::Integrity(format!("'{}' is not in {}", segment, cid)))?;
+    }
+
+    Ok(cid)
+}
+
+/// Reassemble the file rooted at `root` from a verified CAR, up to `max_bytes`
+///
+/// Blocks can be linked any number of times, so a small CAR can describe a huge
+/// file; both the output and the nodes walked to build it are capped.
+pub fn unixfs_file(root: &Cid, blocks: &HashMap<Vec<u8>, Vec<u8>>, max_bytes: u64) -> Result<Vec<u8>, ContentError> {
+    let mut out = Vec::new();
+    let mut visited = 0;
+    // Depth-first, children in link order
+    let mut pending = vec![root.clone()];
+
+    while let Some(cid) = pending.pop() {
+        visited += 1;
+        if visited > MAX_FILE_NODES {
+            return Err(ContentError::TooLarge);
+        }
+        match car_node(&cid, blocks)? {
+            UnixFsNode::File { data, links } => {
+                if (out.len() + data.len()) as u64 > max_bytes {
+                    return Err(ContentError::TooLarge);
+                }
+                out.extend_from_slice(&data);
+                pending.extend(links.into_iter().rev().map(|link| link.cid));
+            }
+            _ if cid == *root => return Err(ContentError::NotAFile),
+            _ => return Err(ContentError::Integrity(format!("{} links to a directory", root))),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Input to the Arweave deep hash
+pub enum DeepHashItem {
+    Blob(Vec<u8>),
+    List(Vec<DeepHashItem>),
+}
+
+/// Arweave's deep hash, which signatures of transactions and data items are made over
+pub fn deep_hash(item: &DeepHashItem) -> [u8; 48] {
+    match item {
+        DeepHashItem::Blob(data) => {
+            let tag = Sha384::digest(format!("blob{}", data.len()));
+            let mut hasher = Sha384::new();
+            hasher.update(tag);
+            hasher.update(Sha384::digest(data));
+            hasher.finalize().into()
+        }
+        DeepHashItem::List(items) => {
+            let mut acc: [u8; 48] = Sha384::digest(format!("list{}", items.len())).into();
+            for item in items {
+                let mut hasher = Sha384::new();
+                hasher.update(acc);
+                hasher.update(deep_hash(item));
+                acc = hasher.finalize().into();
+            }
+            acc
+        }
+    }
+}
+
+struct MerkleNode {
+    id: [u8; 32],
+    max_byte_range: usize,
+}
+
+fn sha256_concat(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn note(value: usize) -> [u8; ARWEAVE_NOTE_SIZE] {
+    let mut buf = [0u8; ARWEAVE_NOTE_SIZE];
+    buf[ARWEAVE_NOTE_SIZE - 8..].copy_from_slice(&(value as u64).to_be_bytes());
+    buf
+}
+
+/// Chunk boundaries as Arweave cuts them: full chunks, with the last two rebalanced when
+/// the remainder would be smaller than the minimum, and always a final (possibly empty) chunk
+fn arweave_chunks(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::new();
+    let mut cursor = 0;
+
+    while data.len() - cursor >= ARWEAVE_MAX_CHUNK_SIZE {
+        let rest = data.len() - cursor;
+        let mut chunk_size = ARWEAVE_MAX_CHUNK_SIZE;
+        let next_chunk_size = rest - ARWEAVE_MAX_CHUNK_SIZE;
+        if next_chunk_size > 0 && next_chunk_size < ARWEAVE_MIN_CHUNK_SIZE {
+            chunk_size = rest.div_ceil(2);
+        }
+        chunks.push((cursor, cursor + chunk_size));
+        cursor += chunk_size;
+    }
+
+    chunks.push((cursor, data.len()));
+    chunks
+}
+
+/// Merkle root of transaction data that Arweave format 2 transactions sign as `data_root`
+pub fn arweave_data_root(data: &[u8]) -> [u8; 32] {
+    let mut layer: Vec<MerkleNode> = arweave_chunks(data)
+        .into_iter()
+        .map(|(start, end)| {
+            let data_hash = Sha256::digest(&data[start..end]);
+            MerkleNode {
+                id: sha256_concat(&[&Sha256::digest(data_hash), &Sha256::digest(note(end))]),
+                max_byte_range: end,
+            }
+        })
+        .collect();
+
+    while layer.len() > 1 {
+        let mut next = Vec::with_capacity(layer.len().div_ceil(2));
+        let mut nodes = layer.into_iter();
+        while let Some(left) = nodes.next() {
+            match nodes.next() {
+                Some(right) => next.push(MerkleNode {
+                    id: sha256_concat(&[
+                        &Sha256::digest(left.id),
+                        &Sha256::digest(right.id),
+                        &Sha256::digest(note(left.max_byte_range)),
+                    ]),
+                    max_byte_range: right.max_byte_range,
+                }),
+                None => next.push(left),
+            }
+        }
+        layer = next;
+    }
+
+    layer[0].id
+}
+
+/// Decoded tag name and value
+type RawTag = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArweaveTag {
+    pub name: String,
+    pub value: String,
+}
+
+/// Transaction header as returned by a gateway's `/tx/{id}`; binary fields are base64url
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArweaveTransaction {
+    pub format: u32,
+    pub id: String,
+    pub last_tx: String,
+    pub owner: String,
+    #[serde(default)]
+    pub tags: Vec<ArweaveTag>,
+    pub target: String,
+    pub quantity: String,
+    pub data_size: String,
+    #[serde(default)]
+    pub data_root: String,
+    pub reward: String,
+    pub signature: String,
+}
+
+fn b64url(field: &str, value: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| format!("Invalid base64url in {}: {}", field, e))
+}
+
+impl ArweaveTransaction {
+    /// Check that this header is the signed transaction `id` and that `data` is its data
+    pub fn verify(&self, id: &str, data: &[u8]) -> Result<(), String> {
+        if self.id != id {
+            return Err(format!("Header is for transaction {}", self.id));
+        }
+
+        // Transaction ids are the hash of the signature
+        let signature = b64url("signature", &self.signature)?;
+        if Sha256::digest(&signature)[..] != b64url("id", id)?[..] {
+            return Err("Transaction id does not match its signature".to_string());
+        }
+
+        let owner = b64url("owner", &self.owner)?;
+        let message = match self.format {
+            1 => self.signature_data_v1(&owner, data)?,
+            2 => {
+                self.verify_data_root(data)?;
+                self.signature_data_v2(&owner)?.to_vec()
+            }
+            format => return Err(format!("Unsupported transaction format {}", format)),
+        };
+
+        ring::signature::RsaPublicKeyComponents { n: owner.as_slice(), e: [0x01, 0x00, 0x01].as_slice() }
+            .verify(&ring::signature::RSA_PSS_2048_8192_SHA256, &message, &signature)
+            .map_err(|_| "Invalid transaction signature".to_string())
+    }
+
+    fn verify_data_root(&self, data: &[u8]) -> Result<(), String> {
+        let data_size: usize = self.data_size.parse()
+            .map_err(|_| format!("Invalid data size {}", self.data_size))?;
+        if data.len() != data_size {
+            return Err(format!("Got {} bytes, transaction holds {}", data.len(), data_size));
+        }
+        if data_size == 0 {
+            return Ok(());
+        }
+
+        if arweave_data_root(data).as_slice() != b64url("data_root", &self.data_root)?.as_slice() {
+            return Err("Data does not match the transaction data root".to_string());
+        }
+        Ok(())
+    }
+
+    fn tag_bytes(&self) -> Result<Vec<RawTag>, String> {
+        self.tags.iter()
+            .map(|tag| Ok((b64url("tag name", &tag.name)?, b64url("tag value", &tag.value)?)))
+            .collect()
+    }
+
+    fn signature_data_v1(&self, owner: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut message = owner.to_vec();
+        message.extend(b64url("target", &self.target)?);
+        message.extend_from_slice(data);
+        message.extend_from_slice(self.quantity.as_bytes());
+        message.extend_from_slice(self.reward.as_bytes());
+        message.extend(b64url("last_tx", &self.last_tx)?);
+        for (name, value) in self.tag_bytes()? {
+            message.extend(name);
+            message.extend(value);
+        }
+        Ok(message)
+    }
+
+    fn signature_data_v2(&self, owner: &[u8]) -> Result<[u8; 48], String> {
+        let tags = self.tag_bytes()?
+            .into_iter()
+            .map(|(name, value)| DeepHashItem::List(vec![DeepHashItem::Blob(name), DeepHashItem::Blob(value)]))
+            .collect();
+
+        Ok(deep_hash(&DeepHashItem::List(vec![
+            DeepHashItem::Blob(self.format.to_string().into_bytes()),
+            DeepHashItem::Blob(owner.to_vec()),
+            DeepHashItem::Blob(b64url("target", &self.target)?),
+            DeepHashItem::Blob(self.quantity.as_bytes().to_vec()),
+            DeepHashItem::Blob(self.reward.as_bytes().to_vec()),
+            DeepHashItem::Blob(b64url("last_tx", &self.last_tx)?),
+            DeepHashItem::List(tags),
+            DeepHashItem::Blob(self.data_size.as_bytes().to_vec()),
+            DeepHashItem::Blob(b64url("data_root", &self.data_root)?),
+        ])))
+    }
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::{Padding, Rsa}, sign::{RsaPssSaltlen, Signer}};
+
+    const CID_V0: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    const CID_V1: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    const CID_RAW: &str = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+
+    // `printf 'hello world\n' | ipfs add` with kubo's defaults: one dag-pb UnixFS file block
+    const KUBO_HELLO_BLOCK: &str = "0a120802120c68656c6c6f20776f726c640a180c";
+    const KUBO_HELLO_CID: &str = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+    // `ipfs dag export` of the file above: header {roots: [cid], version: 1} then the block
+    const KUBO_HELLO_CAR: &str = concat!(
+        "38a265726f6f747381d82a582300122046d44814b9c5af141c3aaab7c05dc5e844ead5f91f12858b021eba45",
+        "768b4c0e6776657273696f6e0136122046d44814b9c5af141c3aaab7c05dc5e844ead5f91f12858b021eba45",
+        "768b4c0e0a120802120c68656c6c6f20776f726c640a180c",
+    );
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn sha256_cid(codec: u64, block: &[u8]) -> Cid {
+        Cid { version: 1, codec, hash_code: MULTIHASH_SHA2_256, digest: Sha256::digest(block).to_vec() }
+    }
+
+    fn bytes_field(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        write_varint(field << 3 | 2, out);
+        write_varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn dag_pb(unixfs_type: u8, links: &[(&str, &Cid)]) -> Vec<u8> {
+        let mut block = Vec::new();
+        for (name, cid) in links {
+            let mut link = Vec::new();
+            bytes_field(1, &cid.to_bytes(), &mut link);
+            bytes_field(2, name.as_bytes(), &mut link);
+            bytes_field(2, &link, &mut block);
+        }
+        bytes_field(1, &[0x08, unixfs_type], &mut block);
+        block
+    }
+
+    fn car(blocks: &[(&Cid, &[u8])]) -> Vec<u8> {
+        // {"roots": [], "version": 1}
+        let header = b"\xa2\x65roots\x80\x67version\x01";
+        let mut out = Vec::new();
+        write_varint(header.len() as u64, &mut out);
+        out.extend_from_slice(header);
+        for (cid, block) in blocks {
+            let cid = cid.to_bytes();
+            write_varint((cid.len() + block.len()) as u64, &mut out);
+            out.extend_from_slice(&cid);
+            out.extend_from_slice(block);
+        }
+        out
+    }
+
+    #[test]
+    fn parses_and_prints_cids() {
+        let v0 = Cid::parse(CID_V0).unwrap();
+        assert_eq!((v0.version, v0.codec, v0.digest.len()), (0, CODEC_DAG_PB, 32));
+        assert_eq!(v0.to_string(), CID_V0);
+
+        let v1 = Cid::parse(CID_V1).unwrap();
+        assert_eq!((v1.version, v1.codec, v1.hash_code), (1, CODEC_DAG_PB, MULTIHASH_SHA2_256));
+        assert_eq!(v1.to_string(), CID_V1);
+        assert_eq!(Cid::decode(&v1.to_bytes()).unwrap(), v1);
+
+        assert_eq!(Cid::parse(CID_RAW).unwrap().codec, CODEC_RAW);
+        assert!(Cid::parse("not-a-cid").is_err());
+        assert!(Cid::parse(&CID_V1[..CID_V1.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn matches_kubo_cids() {
+        let v0 = |block: &[u8]| Cid {
+            version: 0, codec: CODEC_DAG_PB, hash_code: MULTIHASH_SHA2_256, digest: Sha256::digest(block).to_vec(),
+        };
+
+        let block = hex::decode(KUBO_HELLO_BLOCK).unwrap();
+        assert_eq!(v0(&block).to_string(), KUBO_HELLO_CID);
+        match decode_unixfs(&Cid::parse(KUBO_HELLO_CID).unwrap(), &block).unwrap() {
+            UnixFsNode::File { data, links } => assert_eq!((data.as_slice(), links.len()), (b"hello world\n".as_slice(), 0)),
+            other => panic!("expected a file, got {:?}", other),
+        }
+
+        // The empty file and the empty directory
+        assert_eq!(v0(&[0x0a, 0x04, 0x08, 0x02, 0x18, 0x00]).to_string(), "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH");
+        assert_eq!(v0(&[0x0a, 0x02, 0x08, 0x01]).to_string(), "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn");
+
+        // `printf 'hello world' | ipfs add --cid-version 1` stores a raw leaf
+        let raw = Cid::parse("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        assert_eq!(raw, sha256_cid(CODEC_RAW, b"hello world"));
+        assert!(raw.verify(b"hello world").is_ok());
+    }
+
+    #[test]
+    fn reads_kubo_car_exports() {
+        let root = Cid::parse(KUBO_HELLO_CID).unwrap();
+        let blocks = read_car(&hex::decode(KUBO_HELLO_CAR).unwrap()).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(unixfs_file(&root, &blocks, u64::MAX).unwrap(), b"hello world\n");
+    }
+
+    #[test]
+    fn caps_files_that_fan_out() {
+        // Each level links the one below 100 times: four small blocks describe 1GB
+        let leaf = vec![b'x'; 1000];
+        let leaf_cid = sha256_cid(CODEC_RAW, &leaf);
+        let mut entries = vec![(leaf_cid.clone(), leaf)];
+        let mut top = leaf_cid;
+        for _ in 0..3 {
+            let node = dag_pb(2, &vec![("", &top); 100]);
+            top = sha256_cid(CODEC_DAG_PB, &node);
+            entries.push((top.clone(), node));
+        }
+        let blocks = read_car(&car(&entries.iter().map(|(cid, block)| (cid, block.as_slice())).collect::<Vec<_>>())).unwrap();
+
+        assert_eq!(unixfs_file(&top, &blocks, 1 << 20), Err(ContentError::TooLarge));
+        let level_one = &entries[1].0;
+        assert_eq!(unixfs_file(level_one, &blocks, 100_000).unwrap().len(), 100_000);
+        assert_eq!(unixfs_file(level_one, &blocks, 99_999), Err(ContentError::TooLarge));
+
+        // Links to empty leaves add nothing but still cost a visit each
+        let empty_cid = sha256_cid(CODEC_RAW, b"");
+        let mut entries = vec![(empty_cid.clone(), Vec::new())];
+        let mut top = empty_cid;
+        for _ in 0..3 {
+            let node = dag_pb(2, &vec![("", &top); 128]);
+            top = sha256_cid(CODEC_DAG_PB, &node);
+            entries.push((top.clone(), node));
+        }
+        let blocks = read_car(&car(&entries.iter().map(|(cid, block)| (cid, block.as_slice())).collect::<Vec<_>>())).unwrap();
+        assert_eq!(unixfs_file(&top, &blocks, u64::MAX), Err(ContentError::TooLarge));
+    }
+
+    #[test]
+    fn verifies_blocks_against_their_cid() {
+        let cid = sha256_cid(CODEC_RAW, b"hello");
+        assert!(cid.verify(b"hello").is_ok());
+        assert!(cid.verify(b"hellO").is_err());
+
+        let unsupported = Cid { hash_code: 0x1e, ..cid };
+        assert!(unsupported.verify(b"hello").is_err());
+    }
+
+    #[test]
+    fn reassembles_files_from_verified_cars() {
+        let (first, second) = (b"hello ".as_slice(), b"world".as_slice());
+        let (first_cid, second_cid) = (sha256_cid(CODEC_RAW, first), sha256_cid(CODEC_RAW, second));
+        let file = dag_pb(2, &[("", &first_cid), ("", &second_cid)]);
+        let file_cid = sha256_cid(CODEC_DAG_PB, &file);
+        let dir = dag_pb(1, &[("greeting.txt", &file_cid)]);
+        let dir_cid = sha256_cid(CODEC_DAG_PB, &dir);
+
+        let blocks = read_car(&car(&[(&dir_cid, dir.as_slice()), (&file_cid, file.as_slice()), (&first_cid, first), (&second_cid, second)])).unwrap();
+        let target = resolve_path(&dir_cid, &["greeting.txt"], &blocks).unwrap();
+        assert_eq!(target, file_cid);
+        assert_eq!(unixfs_file(&target, &blocks, u64::MAX).unwrap(), b"hello world");
+
+        assert_eq!(unixfs_file(&dir_cid, &blocks, u64::MAX), Err(ContentError::NotAFile));
+        assert!(matches!(resolve_path(&dir_cid, &["missing.txt"], &blocks), Err(ContentError::Integrity(_))));
+
+        // A block left out or swapped for other bytes fails the whole response
+        let partial = read_car(&car(&[(&file_cid, file.as_slice()), (&first_cid, first)])).unwrap();
+        assert!(matches!(unixfs_file(&file_cid, &partial, u64::MAX), Err(ContentError::Integrity(_))));
+        assert!(read_car(&car(&[(&file_cid, file.as_slice()), (&first_cid, b"HELLO ".as_slice())])).is_err());
+    }
+
+    #[test]
+    fn decodes_sharded_directories() {
+        let leaf = sha256_cid(CODEC_RAW, b"about");
+        let block = dag_pb(5, &[("0Fabout.html", &leaf), ("A3", &leaf)]);
+        let cid = sha256_cid(CODEC_DAG_PB, &block);
+
+        let (links, prefix_len) = match decode_unixfs(&cid, &block).unwrap() {
+            UnixFsNode::Shard { links, prefix_len } => (links, prefix_len),
+            other => panic!("expected a shard, got {:?}", other),
+        };
+        assert_eq!(prefix_len, 2);
+
+        let mut shards = Vec::new();
+        assert_eq!(scan_shard(&links, prefix_len, "about.html", &mut shards), Some(leaf.clone()));
+        assert!(shards.is_empty());
+        assert_eq!(scan_shard(&links, prefix_len, "missing.html", &mut shards), None);
+        assert_eq!(shards, vec![leaf]);
+    }
+
+    #[test]
+    fn deep_hashes_nested_lists() {
+        let blob = deep_hash(&DeepHashItem::Blob(b"abc".to_vec()));
+        let mut expected = Sha384::new();
+        expected.update(Sha384::digest(b"blob3"));
+        expected.update(Sha384::digest(b"abc"));
+        assert_eq!(blob.as_slice(), &expected.finalize()[..]);
+
+        let list = deep_hash(&DeepHashItem::List(vec![DeepHashItem::Blob(b"abc".to_vec())]));
+        let mut expected = Sha384::new();
+        expected.update(Sha384::digest(b"list1"));
+        expected.update(blob);
+        assert_eq!(list.as_slice(), &expected.finalize()[..]);
+    }
+
+    #[test]
+    fn chunks_data_like_arweave() {
+        assert_eq!(arweave_chunks(b""), vec![(0, 0)]);
+        assert_eq!(arweave_chunks(&[0; 1000]), vec![(0, 1000)]);
+
+        // An exact multiple still ends on an empty chunk
+        let max = ARWEAVE_MAX_CHUNK_SIZE;
+        assert_eq!(arweave_chunks(&vec![0; max]), vec![(0, max), (max, max)]);
+
+        // A remainder under the minimum is balanced with the chunk before it
+        let len = max + 1024;
+        let half = len.div_ceil(2);
+        assert_eq!(arweave_chunks(&vec![0; len]), vec![(0, half), (half, len)]);
+        assert_eq!(arweave_chunks(&vec![0; max + ARWEAVE_MIN_CHUNK_SIZE]).len(), 2);
+        assert_eq!(arweave_chunks(&vec![0; max + ARWEAVE_MIN_CHUNK_SIZE])[0], (0, max));
+    }
+
+    #[test]
+    fn computes_arweave_data_roots() {
+        let data = b"shadow site";
+        let leaf = sha256_concat(&[&Sha256::digest(Sha256::digest(data)), &Sha256::digest(note(data.len()))]);
+        assert_eq!(arweave_data_root(data), leaf);
+
+        let data = vec![7u8; ARWEAVE_MAX_CHUNK_SIZE + ARWEAVE_MIN_CHUNK_SIZE];
+        let (left_end, right_end) = (ARWEAVE_MAX_CHUNK_SIZE, data.len());
+        let left = sha256_concat(&[&Sha256::digest(Sha256::digest(&data[..left_end])), &Sha256::digest(note(left_end))]);
+        let right = sha256_concat(&[&Sha256::digest(Sha256::digest(&data[left_end..])), &Sha256::digest(note(right_end))]);
+        let branch = sha256_concat(&[&Sha256::digest(left), &Sha256::digest(right), &Sha256::digest(note(left_end))]);
+        assert_eq!(arweave_data_root(&data), branch);
+    }
+
+    #[test]
+    fn matches_arweave_js_vectors() {
+        // Three chunks, the last two rebalanced: 2 * 256 KiB + 1000 bytes of `i % 251`.
+        // Expected values follow arweave-js' merkle.ts and deepHash.ts
+        let data: Vec<u8> = (0..2 * ARWEAVE_MAX_CHUNK_SIZE + 1000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(arweave_chunks(&data), vec![(0, 262144), (262144, 393716), (393716, 525288)]);
+
+        let root = arweave_data_root(&data);
+        assert_eq!(b64(&root), "vMqOdpgxrPWjldaZsM3PIEDyyTRyy_hEy-ANdzEsJoI");
+        assert_eq!(
+            b64(&deep_hash(&DeepHashItem::Blob(data.clone()))),
+            "DCZ8RZUl4Scq1VQ97iSv4jxXG505Lhd2h3YyXdHA0JJArs52I0IrndYaPgHq2GQ9",
+        );
+        let nested = DeepHashItem::List(vec![
+            DeepHashItem::Blob(b"shadow".to_vec()),
+            DeepHashItem::List(vec![DeepHashItem::Blob(data), DeepHashItem::Blob(root.to_vec())]),
+        ]);
+        assert_eq!(b64(&deep_hash(&nested)), "7BJE-hBnyd4Xz3Syonek9IraAg7vEfAMJu9VAlAcGvKnEat7Vr0T6Sii0vgxj_2h");
+    }
+
+    fn signed_transaction(data: &[u8]) -> ArweaveTransaction {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut tx = ArweaveTransaction {
+            format: 2,
+            id: String::new(),
+            last_tx: b64(&[1; 48]),
+            owner: b64(&key.rsa().unwrap().n().to_vec()),
+            tags: vec![ArweaveTag { name: b64(b"Content-Type"), value: b64(b"text/html") }],
+            target: String::new(),
+            quantity: "0".to_string(),
+            data_size: data.len().to_string(),
+            data_root: b64(&arweave_data_root(data)),
+            reward: "1000".to_string(),
+            signature: String::new(),
+        };
+
+        let owner = b64url("owner", &tx.owner).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+        signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH).unwrap();
+        signer.update(&tx.signature_data_v2(&owner).unwrap()).unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        tx.id = b64(&Sha256::digest(&signature));
+        tx.signature = b64(&signature);
+        tx
+    }
+
+    #[test]
+    fn verifies_signed_arweave_transactions() {
+        let data = b"<html>shadow</html>";
+        let tx = signed_transaction(data);
+        assert!(tx.verify(&tx.id, data).is_ok());
+
+        // Altered data, a different id or a forged header all fail
+        assert!(tx.verify(&tx.id, b"<html>phish</html>").is_err());
+        assert!(tx.verify(&b64(&[0; 32]), data).is_err());
+
+        let mut forged = tx.clone();
+        forged.tags[0].value = b64(b"application/javascript");
+        assert!(forged.verify(&tx.id, data).is_err());
+    }
+}
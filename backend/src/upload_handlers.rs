@@ -0,0 +1,223 @@
+// Upload Handlers
+// Streamed single-file and directory uploads plus resumable sessions, all through Atlas
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use crate::ares::AresAuth;
+use crate::atlas::{self, AtlasUploader, CreateUploadSession, StagedFile, MAX_DIRECTORY_FILES};
+use crate::error::ShadowError;
+use crate::storage::{split_uri, StorageRegistry};
+use crate::wallet_handlers::verify_auth;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+// Form fields that aren't files are skipped, but not read forever
+const MAX_FORM_FIELD_BYTES: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    pub name: Option<String>,
+}
+
+/// Upload one file, sent as a multipart "file" field or as the raw request body
+pub async fn upload_file(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<UploadQuery>,
+    payload: web::Payload,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    let scheme = path.into_inner();
+    atlas.check_scheme(&scheme)?;
+    let limit = atlas.budget(&wallet, atlas.config().max_file_bytes).await?;
+
+    let staged = if is_multipart(&req) {
+        // Several files belong in a directory upload
+        let mut files = stage_multipart(&atlas, &req, payload, limit, 1, limit).await?;
+        files.pop().ok_or_else(|| ShadowError::BadRequest("No file in upload".to_string()))?
+    } else {
+        let name = query.name.clone()
+            .or_else(|| req.headers().get("X-File-Name").and_then(|v| v.to_str().ok()).map(str::to_string))
+            .unwrap_or_else(|| "upload".to_string());
+        atlas.stage(&atlas::file_name(&name)?, payload, limit).await?
+    };
+
+    let uploaded = atlas.store_file(&wallet, &scheme, &staged).await?;
+    Ok(HttpResponse::Ok().json(with_legacy_key(&scheme, &uploaded.uri, &uploaded)))
+}
+
+/// Upload a site as one directory; each part's file name is its path inside the root
+pub async fn upload_directory(
+    req: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Payload,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    let scheme = path.into_inner();
+    atlas.check_scheme(&scheme)?;
+    if !is_multipart(&req) {
+        return Err(ShadowError::BadRequest("Directory uploads must be multipart/form-data".to_string()));
+    }
+
+    let budget = atlas.budget(&wallet, atlas.config().max_request_bytes).await?;
+    let file_limit = atlas.config().max_file_bytes;
+    let files = stage_multipart(&atlas, &req, payload, budget, MAX_DIRECTORY_FILES, file_limit).await?;
+
+    let uploaded = atlas.store_directory(&wallet, &scheme, &files).await?;
+    Ok(HttpResponse::Ok().json(with_legacy_key(&scheme, &uploaded.uri, &uploaded)))
+}
+
+#[derive(Deserialize)]
+pub struct PriceQuery {
+    pub bytes: u64,
+}
+
+/// What an upload of `bytes` would cost on providers that charge for storage
+pub async fn get_upload_price(
+    path: web::Path<String>,
+    query: web::Query<PriceQuery>,
+    storage: web::Data<StorageRegistry>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let quote = storage.for_scheme(&path)
+        .map_err(ShadowError::BadRequest)?
+        .quote(query.bytes)
+        .await
+        .map_err(ShadowError::Storage)?
+        .ok_or_else(|| ShadowError::BadRequest(format!("{}:// uploads are not priced", path)))?;
+
+    Ok(HttpResponse::Ok().json(quote))
+}
+
+pub async fn get_upload_usage(
+    req: HttpRequest,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    Ok(HttpResponse::Ok().json(atlas.usage(&wallet).await?))
+}
+
+pub async fn create_upload_session(
+    req: HttpRequest,
+    body: web::Json<CreateUploadSession>,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    let status = atlas.create_session(&wallet, &body).await?;
+    Ok(HttpResponse::Created().json(status))
+}
+
+pub async fn get_upload_session(
+    req: HttpRequest,
+    path: web::Path<String>,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    Ok(HttpResponse::Ok().json(atlas.session_status(&wallet, &path).await?))
+}
+
+/// Append the next chunk; `Upload-Offset` must match what the server already holds
+pub async fn append_upload_chunk(
+    req: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Payload,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    let offset = req.headers().get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| ShadowError::BadRequest("Missing or invalid Upload-Offset header".to_string()))?;
+
+    let status = atlas.append_chunk(&wallet, &path, offset, payload).await?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+pub async fn complete_upload_session(
+    req: HttpRequest,
+    path: web::Path<String>,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    let uploaded = atlas.complete_session(&wallet, &path).await?;
+    let scheme = split_uri(&uploaded.uri).map(|(scheme, _)| scheme).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(with_legacy_key(scheme, &uploaded.uri, &uploaded)))
+}
+
+pub async fn abort_upload_session(
+    req: HttpRequest,
+    path: web::Path<String>,
+    atlas: web::Data<AtlasUploader>,
+    ares: web::Data<AresAuth>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let wallet = verify_auth(&req, &ares)?;
+    atlas.abort_session(&wallet, &path).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "aborted": true })))
+}
+
+fn is_multipart(req: &HttpRequest) -> bool {
+    req.headers().get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("multipart/form-data"))
+}
+
+/// Stage every file part, at most `max_files` of them, `budget` bytes in total and
+/// `file_limit` bytes each
+async fn stage_multipart(
+    atlas: &AtlasUploader,
+    req: &HttpRequest,
+    payload: web::Payload,
+    budget: u64,
+    max_files: usize,
+    file_limit: u64,
+) -> Result<Vec<StagedFile>, ShadowError> {
+    let malformed = |e: actix_multipart::MultipartError| ShadowError::BadRequest(format!("Malformed multipart body: {}", e));
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut files: Vec<StagedFile> = Vec::new();
+    let mut total = 0u64;
+
+    while let Some(mut field) = multipart.try_next().await.map_err(malformed)? {
+        let Some(name) = field.content_disposition().and_then(|cd| cd.get_filename()).map(str::to_string) else {
+            skip_field(&mut field).await?;
+            continue;
+        };
+        if files.len() == max_files {
+            return Err(ShadowError::BadRequest(format!("At most {} files per upload", max_files)));
+        }
+
+        let path = atlas::upload_path(&name)?;
+        let staged = atlas.stage(&path, field, file_limit.min(budget - total)).await?;
+        total += staged.size;
+        files.push(staged);
+    }
+    Ok(files)
+}
+
+async fn skip_field(field: &mut Field) -> Result<(), ShadowError> {
+    let mut read = 0;
+    while let Some(chunk) = field.try_next().await
+        .map_err(|e| ShadowError::BadRequest(format!("Malformed multipart body: {}", e)))?
+    {
+        read += chunk.len();
+        if read > MAX_FORM_FIELD_BYTES {
+            return Err(ShadowError::TooLarge("Form field too large".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Uploads used to answer with only `cid` or `tx_id`; keep those for existing clients
+fn with_legacy_key<T: Serialize>(scheme: &str, uri: &str, body: &T) -> serde_json::Value {
+    let mut value = serde_json::to_value(body).unwrap_or_default();
+    let key = if scheme == "arweave" { "tx_id" } else { "cid" };
+    value[key] = serde_json::Value::String(uri.to_string());
+    value
+}
@@ -6,10 +6,15 @@ use crate::error::ShadowError;
 use crate::zeus::{ZeusWalletManager, CreateWalletRequest, ImportWalletRequest};
 use crate::poseidon::{PoseidonTransactionManager, SignTransactionRequest, CreateTransactionRequest};
 use crate::dionysus::DionysusTokenManager;
-use crate::aphrodite::AphroditeNFTManager;
+use crate::aphrodite::{AphroditeNFTManager, NFTTransferRequest};
 use crate::hestia::{HestiaConnectionManager, ConnectDAppRequest};
-use crate::plutus::PlutusPortfolioManager;
+use crate::plutus::{HistoryRange, PlutusPortfolioManager};
+use crate::price_oracle::PriceOracle;
 use crate::ares::AresAuth;
+use crate::apollo::ApolloValidator;
+use crate::config::ShadowConfig;
+use crate::hephaestus::HephaestusCache;
+use crate::storage::StorageRegistry;
 use mongodb::Database;
 use serde::Deserialize;
 
@@ -221,10 +226,14 @@ pub async fn get_token_balances(
 
 // ========== Aphrodite (NFTs) ==========
 
+#[allow(clippy::too_many_arguments)]
 pub async fn get_nfts(
     path: web::Path<String>,
     db: web::Data<Database>,
     solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    hephaestus: web::Data<HephaestusCache>,
+    storage: web::Data<StorageRegistry>,
     ares: web::Data<AresAuth>,
     req: HttpRequest,
 ) -> ActixResult<HttpResponse, ShadowError> {
@@ -234,7 +243,10 @@ pub async fn get_nfts(
     let manager = AphroditeNFTManager::new(
         Arc::new(db.as_ref().clone()),
         solana_rpc.to_string(),
-    );
+    )
+    .with_das_rpc_url(config.solana.das_rpc_url.clone())
+    .with_cache(hephaestus.into_inner())
+    .with_storage(storage.into_inner());
 
     let nfts = manager
         .get_nfts(&wallet_pubkey)
@@ -244,6 +256,81 @@ pub async fn get_nfts(
     Ok(HttpResponse::Ok().json(nfts))
 }
 
+/// Build an NFT transfer (plain, programmable or compressed) and queue it in
+/// Poseidon so the user approves and signs it like any other transaction
+pub async fn transfer_nft(
+    db: web::Data<Database>,
+    body: web::Json<NFTTransferRequest>,
+    solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let user_id = verify_auth(&req, &ares)?;
+    ApolloValidator::validate_pubkey(&body.mint)?;
+    ApolloValidator::validate_pubkey(&body.destination)?;
+
+    let db = Arc::new(db.as_ref().clone());
+    let zeus = ZeusWalletManager::new(Arc::clone(&db), solana_rpc.to_string());
+    let wallet = zeus
+        .get_wallet(&user_id, &body.wallet_id)
+        .await
+        .map_err(ShadowError::BadRequest)?
+        .ok_or_else(|| ShadowError::NotFound("Wallet not found".to_string()))?;
+
+    let aphrodite = AphroditeNFTManager::new(Arc::clone(&db), solana_rpc.to_string())
+        .with_das_rpc_url(config.solana.das_rpc_url.clone());
+    let transaction = aphrodite
+        .create_transfer_transaction(&wallet.pubkey, &body.mint, &body.destination)
+        .await
+        .map_err(ShadowError::BadRequest)?;
+
+    let poseidon = PoseidonTransactionManager::new(db);
+    let message = format!("Transfer NFT {} to {}", body.mint, body.destination);
+    let pending = poseidon
+        .create_transaction(&user_id, &wallet.id, "shadow://wallet", &transaction, Some(&message))
+        .await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Created().json(pending))
+}
+
+/// Image proxy for NFT metadata, so browsers never contact the original host.
+/// Unauthenticated because it is loaded from `<img>` tags.
+pub async fn get_nft_image(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    hephaestus: web::Data<HephaestusCache>,
+    storage: web::Data<StorageRegistry>,
+) -> ActixResult<HttpResponse, ShadowError> {
+    let mint = path.into_inner();
+    ApolloValidator::validate_pubkey(&mint)?;
+
+    let manager = AphroditeNFTManager::new(
+        Arc::new(db.as_ref().clone()),
+        solana_rpc.to_string(),
+    )
+    .with_das_rpc_url(config.solana.das_rpc_url.clone())
+    .with_cache(hephaestus.into_inner())
+    .with_storage(storage.into_inner());
+
+    let image = manager
+        .get_nft_image(&mint)
+        .await
+        .map_err(ShadowError::Storage)?
+        .ok_or_else(|| ShadowError::NotFound("NFT has no image".to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(image.content_type)
+        .insert_header(("ETag", image.etag))
+        .insert_header(("Cache-Control", "public, max-age=86400"))
+        // SVGs may carry scripts; never let them execute in our origin
+        .insert_header(("Content-Security-Policy", "default-src 'none'; style-src 'unsafe-inline'; sandbox"))
+        .body(image.content))
+}
+
 // ========== Hestia (dApp Connections) ==========
 
 pub async fn connect_dapp(
@@ -317,6 +404,8 @@ pub async fn get_portfolio(
     path: web::Path<String>,
     db: web::Data<Database>,
     solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    price_oracle: web::Data<PriceOracle>,
     ares: web::Data<AresAuth>,
     req: HttpRequest,
 ) -> ActixResult<HttpResponse, ShadowError> {
@@ -326,7 +415,9 @@ pub async fn get_portfolio(
     let manager = PlutusPortfolioManager::new(
         Arc::new(db.as_ref().clone()),
         solana_rpc.to_string(),
-    );
+        price_oracle.into_inner(),
+    )
+    .with_das_rpc_url(config.solana.das_rpc_url.clone());
 
     let portfolio = manager
         .get_portfolio(&wallet_pubkey)
@@ -341,6 +432,7 @@ pub async fn get_transaction_history(
     query: web::Query<std::collections::HashMap<String, String>>,
     db: web::Data<Database>,
     solana_rpc: web::Data<String>,
+    price_oracle: web::Data<PriceOracle>,
     ares: web::Data<AresAuth>,
     req: HttpRequest,
 ) -> ActixResult<HttpResponse, ShadowError> {
@@ -349,22 +441,117 @@ pub async fn get_transaction_history(
     let limit = query.get("limit")
         .and_then(|s| s.parse::<u32>().ok())
         .unwrap_or(50);
+    let before = query.get("before").map(|s| s.as_str());
+    let until = query.get("until").map(|s| s.as_str());
 
     let manager = PlutusPortfolioManager::new(
         Arc::new(db.as_ref().clone()),
         solana_rpc.to_string(),
+        price_oracle.into_inner(),
     );
 
     let history = manager
-        .get_transaction_history(&wallet_pubkey, Some(limit))
+        .get_transaction_history(&wallet_pubkey, Some(limit), before, until)
         .await
         .map_err(|e| ShadowError::BadRequest(e))?;
 
     Ok(HttpResponse::Ok().json(history))
 }
 
+pub async fn get_portfolio_value_history(
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+    db: web::Data<Database>,
+    solana_rpc: web::Data<String>,
+    price_oracle: web::Data<PriceOracle>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    verify_auth(&req, &ares)?;
+    let wallet_pubkey = path.into_inner();
+    let range = HistoryRange::parse(query.get("range").map(|s| s.as_str()).unwrap_or("24h"))
+        .map_err(ShadowError::BadRequest)?;
+
+    let manager = PlutusPortfolioManager::new(
+        Arc::new(db.as_ref().clone()),
+        solana_rpc.to_string(),
+        price_oracle.into_inner(),
+    );
+
+    let history = manager
+        .get_value_history(&wallet_pubkey, range)
+        .await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn get_portfolio_changes(
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+    db: web::Data<Database>,
+    solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    price_oracle: web::Data<PriceOracle>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    verify_auth(&req, &ares)?;
+    let wallet_pubkey = path.into_inner();
+    let range = HistoryRange::parse(query.get("range").map(|s| s.as_str()).unwrap_or("24h"))
+        .map_err(ShadowError::BadRequest)?;
+
+    let manager = PlutusPortfolioManager::new(
+        Arc::new(db.as_ref().clone()),
+        solana_rpc.to_string(),
+        price_oracle.into_inner(),
+    )
+    .with_das_rpc_url(config.solana.das_rpc_url.clone());
+
+    let changes = manager
+        .get_asset_changes(&wallet_pubkey, range)
+        .await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "range": range.label(),
+        "assets": changes,
+    })))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn get_portfolio_pnl(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    solana_rpc: web::Data<String>,
+    config: web::Data<ShadowConfig>,
+    price_oracle: web::Data<PriceOracle>,
+    hephaestus: web::Data<HephaestusCache>,
+    ares: web::Data<AresAuth>,
+    req: HttpRequest,
+) -> ActixResult<HttpResponse, ShadowError> {
+    verify_auth(&req, &ares)?;
+    let wallet_pubkey = path.into_inner();
+
+    let manager = PlutusPortfolioManager::new(
+        Arc::new(db.as_ref().clone()),
+        solana_rpc.to_string(),
+        price_oracle.into_inner(),
+    )
+    .with_das_rpc_url(config.solana.das_rpc_url.clone())
+    .with_cache(hephaestus.into_inner());
+
+    let report = manager
+        .get_pnl(&wallet_pubkey)
+        .await
+        .map_err(ShadowError::BadRequest)?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
 // Helper function to verify authentication
-fn verify_auth(req: &HttpRequest, ares: &AresAuth) -> Result<String, ShadowError> {
+pub(crate) fn verify_auth(req: &HttpRequest, ares: &AresAuth) -> Result<String, ShadowError> {
     use crate::ares::AuthHeader;
     
     let auth_header = req.headers().get("X-Shadow-Auth")
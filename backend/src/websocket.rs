@@ -63,6 +63,41 @@ impl HermesBroker {
             let _ = sender.send(message);
         }
     }
+
+    /// Publish a JSON event to a wallet's topic
+    pub async fn notify_wallet<T: Serialize>(&self, wallet: &str, event: &T) {
+        if let Ok(json) = serde_json::to_string(event) {
+            self.publish(&format!("wallet:{}", wallet), json).await;
+        }
+    }
+}
+
+/// Forward broker messages on `topic` to a client session until either side closes
+async fn forward_topic(
+    broker: &HermesBroker,
+    session: &actix_ws::Session,
+    topic: String,
+) -> tokio::task::JoinHandle<()> {
+    let mut receiver = broker.subscribe(topic.clone()).await;
+    let mut session = session.clone();
+
+    actix_web::rt::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => {
+                    let data = serde_json::from_str(&message)
+                        .unwrap_or(serde_json::Value::String(message));
+                    let event = HermesResponse::Event { topic: topic.clone(), data };
+                    let Ok(json) = serde_json::to_string(&event) else { continue };
+                    if session.text(json).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
 }
 
 impl Default for HermesBroker {
@@ -74,11 +109,12 @@ impl Default for HermesBroker {
 pub async fn ws_handler(
     req: HttpRequest,
     body: web::Payload,
+    broker: web::Data<HermesBroker>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body)?;
 
     actix_web::rt::spawn(async move {
-        let mut subscriptions: Vec<String> = Vec::new();
+        let mut subscriptions: HashMap<String, tokio::task::JoinHandle<()>> = HashMap::new();
         
         while let Some(Ok(msg)) = msg_stream.next().await {
             match msg {
@@ -89,7 +125,10 @@ pub async fn ws_handler(
                             HermesMessage::Subscribe { wallet, program } => {
                                 if let Some(w) = wallet {
                                     let topic = format!("wallet:{}", w);
-                                    subscriptions.push(topic.clone());
+                                    if !subscriptions.contains_key(&topic) {
+                                        let forwarder = forward_topic(&broker, &session, topic.clone()).await;
+                                        subscriptions.insert(topic.clone(), forwarder);
+                                    }
                                     let response = HermesResponse::Subscribed { topic };
                                     if let Ok(json) = serde_json::to_string(&response) {
                                         let _ = session.text(json).await;
@@ -97,7 +136,10 @@ pub async fn ws_handler(
                                 }
                                 if let Some(p) = program {
                                     let topic = format!("program:{}", p);
-                                    subscriptions.push(topic.clone());
+                                    if !subscriptions.contains_key(&topic) {
+                                        let forwarder = forward_topic(&broker, &session, topic.clone()).await;
+                                        subscriptions.insert(topic.clone(), forwarder);
+                                    }
                                     let response = HermesResponse::Subscribed { topic };
                                     if let Ok(json) = serde_json::to_string(&response) {
                                         let _ = session.text(json).await;
@@ -107,7 +149,9 @@ pub async fn ws_handler(
                             HermesMessage::Unsubscribe { wallet, program } => {
                                 if let Some(w) = wallet {
                                     let topic = format!("wallet:{}", w);
-                                    subscriptions.retain(|t| t != &topic);
+                                    if let Some(forwarder) = subscriptions.remove(&topic) {
+                                        forwarder.abort();
+                                    }
                                     let response = HermesResponse::Unsubscribed { topic };
                                     if let Ok(json) = serde_json::to_string(&response) {
                                         let _ = session.text(json).await;
@@ -115,7 +159,9 @@ pub async fn ws_handler(
                                 }
                                 if let Some(p) = program {
                                     let topic = format!("program:{}", p);
-                                    subscriptions.retain(|t| t != &topic);
+                                    if let Some(forwarder) = subscriptions.remove(&topic) {
+                                        forwarder.abort();
+                                    }
                                     let response = HermesResponse::Unsubscribed { topic };
                                     if let Ok(json) = serde_json::to_string(&response) {
                                         let _ = session.text(json).await;
@@ -143,6 +189,10 @@ pub async fn ws_handler(
                 _ => {}
             }
         }
+
+        for forwarder in subscriptions.into_values() {
+            forwarder.abort();
+        }
     });
 
     Ok(response)
@@ -228,6 +228,15 @@ impl ZeusWalletManager {
         Ok(wallets)
     }
 
+    /// Get a wallet owned by a user
+    pub async fn get_wallet(&self, user_id: &str, wallet_id: &str) -> Result<Option<Wallet>, String> {
+        let collection = self.get_collection();
+        collection
+            .find_one(doc! { "_id": wallet_id, "user_id": user_id }, None)
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
     /// Get active wallet for user
     pub async fn get_active_wallet(&self, user_id: &str) -> Result<Option<WalletResponse>, String> {
         let collection = self.get_collection();
@@ -25,6 +25,50 @@ mod tests {
         assert!(ApolloValidator::validate_search_query("").is_err());
     }
     
+    #[test]
+    fn test_apollo_multi_label_domains() {
+        assert!(ApolloValidator::validate_domain("app.mysite.shadow").is_ok());
+        assert!(ApolloValidator::validate_domain("a.b.c.mysite.shadow").is_ok());
+
+        assert!(ApolloValidator::validate_domain(".shadow").is_err());
+        assert!(ApolloValidator::validate_domain("app..mysite.shadow").is_err());
+        assert!(ApolloValidator::validate_domain("-app.mysite.shadow").is_err());
+        assert!(ApolloValidator::validate_domain("app_1.mysite.shadow").is_err());
+        assert!(ApolloValidator::validate_domain(&format!("{}.mysite.shadow", "a".repeat(64))).is_err());
+    }
+    
+    #[test]
+    fn test_apollo_idn_and_reserved_names() {
+        // Unicode input is stored as lowercase punycode
+        assert_eq!(ApolloValidator::normalize_domain("Alice.shadow").unwrap(), "alice.shadow");
+        let idn = ApolloValidator::normalize_domain("bücher.shadow").unwrap();
+        assert_eq!(idn, "xn--bcher-kva.shadow");
+        assert_eq!(ApolloValidator::unicode_domain(&idn), "bücher.shadow");
+        assert!(ApolloValidator::validate_domain("bücher.shadow").is_err());
+        assert!(ApolloValidator::validate_domain("ab--cd.shadow").is_err());
+
+        assert!(ApolloValidator::check_name_policy(&idn, &[]).is_ok());
+        assert!(ApolloValidator::check_name_policy("alice.shadow", &[]).is_ok());
+
+        // Cyrillic "а" among Latin letters
+        let mixed = ApolloValidator::normalize_domain("pаypal-login.shadow").unwrap();
+        let err = ApolloValidator::check_name_policy(&mixed, &[]).unwrap_err();
+        assert!(err.contains("mixes scripts"), "{}", err);
+
+        let err = ApolloValidator::check_name_policy("admin.shadow", &[]).unwrap_err();
+        assert!(err.contains("reserved name"), "{}", err);
+        let err = ApolloValidator::check_name_policy("adrnin.shadow", &[]).unwrap_err();
+        assert!(err.contains("looks like"), "{}", err);
+        assert!(ApolloValidator::check_name_policy("acme.shadow", &["acme".to_string()]).is_err());
+        // Reserved labels only apply to the registered name
+        assert!(ApolloValidator::check_name_policy("admin.alice.shadow", &[]).is_ok());
+
+        assert_eq!(
+            ApolloValidator::name_skeleton("paypal.shadow"),
+            ApolloValidator::name_skeleton(&ApolloValidator::normalize_domain("раураl.shadow").unwrap())
+        );
+    }
+
     #[test]
     fn test_artemis_rate_limiter() {
         let limiter = ArtemisRateLimiter::new(10);
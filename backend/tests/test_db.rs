@@ -99,14 +99,19 @@ mod tests {
         let test_owner = "test_owner_123";
         let test_cid = "ipfs://test_cid_456";
         
-        db::create_or_update_site(
-            &db,
-            &test_program,
-            test_owner,
-            test_cid,
-            Some("Test Site"),
-            Some("Test Description"),
-        ).await.expect("Failed to create site");
+        let now = chrono::Utc::now();
+        db::create_or_update_site(&db, &db::Site {
+            program_address: test_program.clone(),
+            owner_pubkey: test_owner.to_string(),
+            storage_cid: test_cid.to_string(),
+            name: Some("Test Site".to_string()),
+            description: Some("Test Description".to_string()),
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            maintainers: vec![],
+            slot: 0,
+        }).await.expect("Failed to create site");
         
         // Test retrieving the site
         let site = db::get_site(&db, &test_program).await
@@ -118,6 +123,7 @@ mod tests {
         assert_eq!(site.owner_pubkey, test_owner);
         assert_eq!(site.storage_cid, test_cid);
         assert_eq!(site.name, Some("Test Site".to_string()));
+        assert_eq!(site.version, 1);
         
         // Cleanup
         let collection = db.collection::<db::Site>("sites");
@@ -127,6 +133,101 @@ mod tests {
         println!("✅ Site creation and retrieval test passed!");
     }
 
+    #[tokio::test]
+    #[ignore = "requires MongoDB + DATABASE_URL env var"]
+    async fn test_site_deployment_log() {
+        dotenv::dotenv().ok();
+        
+        let database_url = env::var("DATABASE_URL")
+            .expect("DATABASE_URL must be set");
+        
+        let client_options = ClientOptions::parse(&database_url).await
+            .expect("Failed to parse DATABASE_URL");
+        
+        let client = Client::with_options(client_options)
+            .expect("Failed to create MongoDB client");
+        
+        let db = client.database("shadow");
+        
+        let test_program = format!("test_program_{}", uuid::Uuid::new_v4());
+        let deployment = |version: u32, cid: &str, rollback_of: Option<u32>| db::SiteDeployment {
+            id: format!("{}:{}", test_program, version),
+            program_address: test_program.clone(),
+            version,
+            storage_cid: cid.to_string(),
+            deployer: "test_owner_123".to_string(),
+            deployed_at: chrono::Utc::now(),
+            rollback_of,
+        };
+        let log = vec![
+            deployment(1, "ipfs://first", None),
+            deployment(2, "ipfs://second", None),
+            deployment(3, "ipfs://first", Some(1)),
+        ];
+        
+        // Recording the same entries twice must not duplicate them
+        db::record_site_deployments(&db, &log).await.expect("Failed to record deployments");
+        db::record_site_deployments(&db, &log[1..]).await.expect("Failed to record deployments");
+        
+        let versions = db::list_site_deployments(&db, &test_program, 100).await
+            .expect("Failed to list deployments");
+        assert_eq!(versions.iter().map(|d| d.version).collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(versions[0].rollback_of, Some(1));
+        assert_eq!(db::latest_site_deployment_version(&db, &test_program).await.unwrap(), 3);
+        
+        let second = db::get_site_deployment(&db, &test_program, 2).await
+            .expect("Failed to get deployment");
+        assert_eq!(second.map(|d| d.storage_cid), Some("ipfs://second".to_string()));
+        
+        // Cleanup
+        let collection = db.collection::<db::SiteDeployment>("site_deployments");
+        collection.delete_many(mongodb::bson::doc! { "program_address": &test_program }, None).await
+            .expect("Failed to cleanup test deployments");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MongoDB + DATABASE_URL env var"]
+    async fn test_indexer_keeps_the_newest_slot() {
+        dotenv::dotenv().ok();
+        
+        let database_url = env::var("DATABASE_URL")
+            .expect("DATABASE_URL must be set");
+        
+        let client_options = ClientOptions::parse(&database_url).await
+            .expect("Failed to parse DATABASE_URL");
+        
+        let client = Client::with_options(client_options)
+            .expect("Failed to create MongoDB client");
+        
+        let db = client.database("shadow");
+        
+        let test_wallet = format!("test_wallet_{}", uuid::Uuid::new_v4());
+        let user = |cid: &str, slot: u64| db::User {
+            wallet_pubkey: test_wallet.clone(),
+            profile_cid: Some(cid.to_string()),
+            is_public: true,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            slot,
+        };
+        
+        assert!(db::index_user(&db, &user("ipfs://newer", 200)).await.expect("Failed to index user"));
+        // A read from an earlier slot arriving late is dropped, a replay is not an error
+        assert!(!db::index_user(&db, &user("ipfs://older", 100)).await.expect("Failed to index user"));
+        assert!(db::index_user(&db, &user("ipfs://newer", 200)).await.expect("Failed to index user"));
+        
+        let stored = db::get_user(&db, &test_wallet).await
+            .expect("Failed to get user")
+            .expect("User should exist");
+        assert_eq!(stored.profile_cid, Some("ipfs://newer".to_string()));
+        assert_eq!(stored.slot, 200);
+        
+        // Cleanup
+        let collection = db.collection::<db::User>("users");
+        collection.delete_one(mongodb::bson::doc! { "_id": &test_wallet }, None).await
+            .expect("Failed to cleanup test user");
+    }
+
     #[tokio::test]
     #[ignore = "requires MongoDB + DATABASE_URL env var"]
     async fn test_search_users() {
@@ -0,0 +1,353 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+declare_id!("7sn3PyHKqdUG28HXYa1oCdZk9iQwx5tnJaYK6WQRrTSH");
+
+/// Longest full name accepted, e.g. "example.shadow"
+pub const MAX_NAME_LEN: usize = 64;
+/// A registration or renewal buys one year
+pub const REGISTRATION_PERIOD: i64 = 365 * 24 * 60 * 60;
+/// After expiry only the owner may renew, until the grace period ends
+pub const GRACE_PERIOD: i64 = 30 * 24 * 60 * 60;
+/// Longest a transfer offer may stay open
+pub const MAX_OFFER_PERIOD: i64 = 30 * 24 * 60 * 60;
+
+#[program]
+pub mod shadow_domains {
+    use super::*;
+
+    pub fn register_domain(
+        ctx: Context<RegisterDomain>,
+        name: String,
+        target: Pubkey,
+    ) -> Result<()> {
+        validate_name(&name)?;
+        let now = Clock::get()?.unix_timestamp;
+
+        let domain = &mut ctx.accounts.domain;
+        domain.name = name;
+        domain.owner = ctx.accounts.owner.key();
+        domain.target = target;
+        domain.registered_at = now;
+        domain.updated_at = now;
+        domain.expires_at = now + REGISTRATION_PERIOD;
+        domain.pending_owner = None;
+        domain.offer_expires_at = 0;
+        domain.bump = ctx.bumps.domain;
+
+        emit!(DomainRegistered {
+            name: domain.name.clone(),
+            owner: domain.owner,
+            target,
+            expires_at: domain.expires_at,
+        });
+        msg!("Domain registered: {}", domain.name);
+        Ok(())
+    }
+
+    pub fn update_target(
+        ctx: Context<UpdateDomain>,
+        _name: String,
+        target: Pubkey,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &mut ctx.accounts.domain;
+        require!(now < domain.expires_at, DomainError::DomainExpired);
+
+        domain.target = target;
+        domain.updated_at = now;
+
+        emit!(DomainTargetUpdated {
+            name: domain.name.clone(),
+            target,
+        });
+        Ok(())
+    }
+
+    /// Offer the domain to `recipient`, who has to accept before it moves
+    ///
+    /// A new offer replaces any pending one. Offers lapse after `expires_in`
+    /// seconds or when the registration expires, whichever comes first.
+    pub fn offer_transfer(
+        ctx: Context<UpdateDomain>,
+        _name: String,
+        recipient: Pubkey,
+        expires_in: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &mut ctx.accounts.domain;
+        require!(now < domain.expires_at, DomainError::DomainExpired);
+        require!(recipient != domain.owner, DomainError::InvalidRecipient);
+        require!(
+            expires_in > 0 && expires_in <= MAX_OFFER_PERIOD,
+            DomainError::InvalidOfferPeriod
+        );
+
+        domain.pending_owner = Some(recipient);
+        domain.offer_expires_at = (now + expires_in).min(domain.expires_at);
+        domain.updated_at = now;
+
+        emit!(DomainTransferOffered {
+            name: domain.name.clone(),
+            owner: domain.owner,
+            recipient,
+            expires_at: domain.offer_expires_at,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_transfer(ctx: Context<UpdateDomain>, _name: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &mut ctx.accounts.domain;
+        let recipient = domain.pending_owner.take().ok_or(DomainError::NoPendingTransfer)?;
+        domain.offer_expires_at = 0;
+        domain.updated_at = now;
+
+        emit!(DomainTransferCancelled {
+            name: domain.name.clone(),
+            recipient,
+        });
+        Ok(())
+    }
+
+    /// Take ownership under a pending offer, signed by the recipient
+    pub fn accept_transfer(ctx: Context<AcceptTransfer>, _name: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &mut ctx.accounts.domain;
+        require!(
+            domain.pending_owner == Some(ctx.accounts.recipient.key()),
+            DomainError::NoPendingTransfer
+        );
+        require!(now < domain.offer_expires_at, DomainError::OfferExpired);
+        require!(now < domain.expires_at, DomainError::DomainExpired);
+
+        let previous_owner = domain.owner;
+        domain.owner = ctx.accounts.recipient.key();
+        domain.pending_owner = None;
+        domain.offer_expires_at = 0;
+        domain.updated_at = now;
+
+        emit!(DomainTransferred {
+            name: domain.name.clone(),
+            previous_owner,
+            new_owner: domain.owner,
+        });
+        Ok(())
+    }
+
+    pub fn renew_domain(ctx: Context<UpdateDomain>, _name: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &mut ctx.accounts.domain;
+        require!(
+            now <= domain.expires_at + GRACE_PERIOD,
+            DomainError::GracePeriodOver
+        );
+
+        // Renewing early stacks onto the current term; renewing in grace
+        // starts a fresh term from now
+        domain.expires_at = domain.expires_at.max(now) + REGISTRATION_PERIOD;
+        domain.updated_at = now;
+
+        emit!(DomainRenewed {
+            name: domain.name.clone(),
+            expires_at: domain.expires_at,
+        });
+        Ok(())
+    }
+
+    /// Close the domain account so the name can be registered again
+    ///
+    /// The owner may release at any time. Once the grace period has passed
+    /// anyone may release it; the rent still goes back to the owner.
+    pub fn release_domain(ctx: Context<ReleaseDomain>, _name: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let domain = &ctx.accounts.domain;
+        require!(
+            ctx.accounts.caller.key() == domain.owner
+                || now > domain.expires_at + GRACE_PERIOD,
+            DomainError::Unauthorized
+        );
+
+        emit!(DomainReleased {
+            name: domain.name.clone(),
+            owner: domain.owner,
+        });
+        Ok(())
+    }
+}
+
+/// A single lowercase label of a-z, 0-9 and '-', ending in ".shadow"
+///
+/// Subdomains are delegated by the owner off-chain, so they can't be
+/// registered here over the parent's head.
+fn validate_name(name: &str) -> Result<()> {
+    require!(name.len() <= MAX_NAME_LEN, DomainError::NameTooLong);
+
+    let label = name
+        .strip_suffix(".shadow")
+        .ok_or(DomainError::InvalidName)?;
+    require!(!label.is_empty(), DomainError::InvalidName);
+    require!(
+        !label.starts_with('-') && !label.ends_with('-'),
+        DomainError::InvalidName
+    );
+    require!(
+        label
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-'),
+        DomainError::InvalidName
+    );
+    Ok(())
+}
+
+// Seeds hash the name because a single seed is limited to 32 bytes
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct RegisterDomain<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DomainRecord::LEN,
+        seeds = [b"domain".as_ref(), &hash(name.as_bytes()).to_bytes()],
+        bump
+    )]
+    pub domain: Account<'info, DomainRecord>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct UpdateDomain<'info> {
+    #[account(
+        mut,
+        seeds = [b"domain".as_ref(), &hash(name.as_bytes()).to_bytes()],
+        bump = domain.bump,
+        has_one = owner @ DomainError::Unauthorized
+    )]
+    pub domain: Account<'info, DomainRecord>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct AcceptTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"domain".as_ref(), &hash(name.as_bytes()).to_bytes()],
+        bump = domain.bump
+    )]
+    pub domain: Account<'info, DomainRecord>,
+
+    pub recipient: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct ReleaseDomain<'info> {
+    #[account(
+        mut,
+        seeds = [b"domain".as_ref(), &hash(name.as_bytes()).to_bytes()],
+        bump = domain.bump,
+        has_one = owner @ DomainError::Unauthorized,
+        close = owner
+    )]
+    pub domain: Account<'info, DomainRecord>,
+
+    /// CHECK: Receives the rent; pinned to the domain owner by `has_one`
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    pub caller: Signer<'info>,
+}
+
+#[account]
+pub struct DomainRecord {
+    pub name: String,
+    pub owner: Pubkey,
+    pub target: Pubkey, // Program the domain resolves to
+    pub registered_at: i64,
+    pub updated_at: i64,
+    pub expires_at: i64,
+    pub pending_owner: Option<Pubkey>, // Recipient of an open transfer offer
+    pub offer_expires_at: i64,
+    pub bump: u8,
+}
+
+impl DomainRecord {
+    pub const LEN: usize = (4 + MAX_NAME_LEN) + 32 + 32 + 8 + 8 + 8 + (1 + 32) + 8 + 1;
+}
+
+#[event]
+pub struct DomainRegistered {
+    pub name: String,
+    pub owner: Pubkey,
+    pub target: Pubkey,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct DomainTargetUpdated {
+    pub name: String,
+    pub target: Pubkey,
+}
+
+#[event]
+pub struct DomainTransferOffered {
+    pub name: String,
+    pub owner: Pubkey,
+    pub recipient: Pubkey,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct DomainTransferCancelled {
+    pub name: String,
+    pub recipient: Pubkey,
+}
+
+#[event]
+pub struct DomainTransferred {
+    pub name: String,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct DomainRenewed {
+    pub name: String,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct DomainReleased {
+    pub name: String,
+    pub owner: Pubkey,
+}
+
+#[error_code]
+pub enum DomainError {
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Domain name must be a single lowercase label ending in .shadow")]
+    InvalidName,
+    #[msg("Domain name is too long")]
+    NameTooLong,
+    #[msg("Domain has expired")]
+    DomainExpired,
+    #[msg("Grace period is over; the domain can only be released")]
+    GracePeriodOver,
+    #[msg("Transfer recipient must be another wallet")]
+    InvalidRecipient,
+    #[msg("Transfer offers must expire within 30 days")]
+    InvalidOfferPeriod,
+    #[msg("No pending transfer for this wallet")]
+    NoPendingTransfer,
+    #[msg("Transfer offer has expired")]
+    OfferExpired,
+}
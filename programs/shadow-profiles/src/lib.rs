@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
 
-declare_id!("8Z9Ax0rS4tN3nQ2xW6uV5gH7iL9kM1eB");
+declare_id!("DvHmeTn3wawgkdMWJqow1ca5JCS4BDhhQHyfj5uDAD8G");
+
+pub const MAX_PROFILE_CID_LEN: usize = 100;
 
 #[program]
 pub mod shadow_profiles {
@@ -11,6 +13,8 @@ pub mod shadow_profiles {
         profile_cid: String,
         is_public: bool,
     ) -> Result<()> {
+        validate_profile_cid(&profile_cid)?;
+
         let profile = &mut ctx.accounts.profile;
         profile.wallet = ctx.accounts.wallet.key();
         profile.profile_cid = profile_cid;
@@ -27,6 +31,9 @@ pub mod shadow_profiles {
         profile_cid: Option<String>,
         is_public: Option<bool>,
     ) -> Result<()> {
+        if let Some(cid) = &profile_cid {
+            validate_profile_cid(cid)?;
+        }
         let profile = &mut ctx.accounts.profile;
         
         if let Some(cid) = profile_cid {
@@ -41,12 +48,34 @@ pub mod shadow_profiles {
     }
 }
 
+fn validate_profile_cid(profile_cid: &str) -> Result<()> {
+    require!(profile_cid.len() <= MAX_PROFILE_CID_LEN, ShadowError::ProfileCidTooLong);
+    require!(is_valid_cid(profile_cid), ShadowError::InvalidProfileCid);
+    Ok(())
+}
+
+/// An IPFS CID, with or without `ipfs://`
+fn is_valid_cid(profile_cid: &str) -> bool {
+    let is_base58 = |b: u8| b.is_ascii_alphanumeric() && !matches!(b, b'0' | b'O' | b'I' | b'l');
+
+    let cid = profile_cid.strip_prefix("ipfs://").unwrap_or(profile_cid);
+    match cid.as_bytes() {
+        // CIDv0: base58btc sha2-256 multihash
+        [b'Q', b'm', ..] => cid.len() == 46 && cid.bytes().all(is_base58),
+        // CIDv1 in base32 or base58btc multibase
+        [b'b', rest @ ..] if rest.len() >= 8 => rest.iter().all(|&b| b.is_ascii_lowercase() || (b'2'..=b'7').contains(&b)),
+        [b'z', rest @ ..] if rest.len() >= 8 => rest.iter().all(|&b| is_base58(b)),
+        _ => false,
+    }
+}
+
 #[derive(Accounts)]
+#[instruction(profile_cid: String)]
 pub struct CreateProfile<'info> {
     #[account(
         init,
         payer = wallet,
-        space = 8 + Profile::LEN,
+        space = 8 + Profile::space(&profile_cid),
         seeds = [b"profile", wallet.key().as_ref()],
         bump
     )]
@@ -59,16 +88,25 @@ pub struct CreateProfile<'info> {
 }
 
 #[derive(Accounts)]
+#[instruction(profile_cid: Option<String>)]
 pub struct UpdateProfile<'info> {
     #[account(
         mut,
         seeds = [b"profile", wallet.key().as_ref()],
         bump,
-        has_one = wallet @ ShadowError::Unauthorized
+        has_one = wallet @ ShadowError::Unauthorized,
+        // Grows for a longer CID, never shrinks
+        realloc = profile.to_account_info().data_len()
+            .max(8 + Profile::space(profile_cid.as_deref().unwrap_or(&profile.profile_cid))),
+        realloc::payer = wallet,
+        realloc::zero = false
     )]
     pub profile: Account<'info, Profile>,
     
+    #[account(mut)]
     pub wallet: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[account]
@@ -81,12 +119,24 @@ pub struct Profile {
 }
 
 impl Profile {
-    pub const LEN: usize = 32 + (4 + 100) + 1 + 8 + 8;
+    pub const LEN: usize = Self::space_for(MAX_PROFILE_CID_LEN);
+
+    pub fn space(profile_cid: &str) -> usize {
+        Self::space_for(profile_cid.len())
+    }
+
+    const fn space_for(profile_cid: usize) -> usize {
+        32 + (4 + profile_cid) + 1 + 8 + 8
+    }
 }
 
 #[error_code]
 pub enum ShadowError {
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Profile CID is longer than 100 bytes")]
+    ProfileCidTooLong,
+    #[msg("Profile CID must be an IPFS CID")]
+    InvalidProfileCid,
 }
 
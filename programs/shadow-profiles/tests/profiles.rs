@@ -0,0 +1,164 @@
+//! Runs natively with `cargo test -p shadow-profiles`, or against the SBF build with
+//! `cargo test-sbf -p shadow-profiles`
+
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use shadow_profiles::{Profile, ShadowError};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{Instruction, InstructionError},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+const CID: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+const CID_V1: &str = "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+fn profile_pda(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"profile", wallet.as_ref()], &shadow_profiles::ID).0
+}
+
+/// Native entrypoint for `cargo test`; `test-sbf` loads the built program instead
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // Anchor's entry ties the slice to the accounts' lifetime, which the processor signature
+    // doesn't spell out; both live for the whole instruction
+    let accounts = unsafe { std::mem::transmute::<&[AccountInfo], &[AccountInfo]>(accounts) };
+    shadow_profiles::entry(program_id, accounts, data)
+}
+
+async fn start() -> ProgramTestContext {
+    ProgramTest::new("shadow_profiles", shadow_profiles::ID, processor!(process))
+        .start_with_context()
+        .await
+}
+
+/// Send `ix` with the test payer covering fees alongside `signers`
+async fn send(
+    ctx: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = ctx.banks_client.get_latest_blockhash().await?;
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(&[ix], Some(&ctx.payer.pubkey()), &all_signers, blockhash);
+    ctx.banks_client.process_transaction(tx).await
+}
+
+async fn funded_wallet(ctx: &mut ProgramTestContext) -> Keypair {
+    let wallet = Keypair::new();
+    let ix = system_instruction::transfer(&ctx.payer.pubkey(), &wallet.pubkey(), LAMPORTS_PER_SOL);
+    send(ctx, ix, &[]).await.unwrap();
+    wallet
+}
+
+fn assert_error(result: Result<(), BanksClientError>, expected: u32) {
+    match result.expect_err("transaction should fail").unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => assert_eq!(code, expected),
+        other => panic!("expected error {}, got {:?}", expected, other),
+    }
+}
+
+async fn fetch_profile(ctx: &mut ProgramTestContext, wallet: &Pubkey) -> (Profile, usize) {
+    let account = ctx.banks_client.get_account(profile_pda(wallet)).await.unwrap().unwrap();
+    let profile = Profile::try_deserialize(&mut account.data.as_slice()).unwrap();
+    (profile, account.data.len())
+}
+
+fn create_ix(wallet: &Pubkey, profile_cid: &str) -> Instruction {
+    Instruction {
+        program_id: shadow_profiles::ID,
+        accounts: shadow_profiles::accounts::CreateProfile {
+            profile: profile_pda(wallet),
+            wallet: *wallet,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_profiles::instruction::CreateProfile {
+            profile_cid: profile_cid.to_string(),
+            is_public: true,
+        }
+        .data(),
+    }
+}
+
+fn update_ix(wallet: &Pubkey, profile: Pubkey, profile_cid: Option<&str>) -> Instruction {
+    Instruction {
+        program_id: shadow_profiles::ID,
+        accounts: shadow_profiles::accounts::UpdateProfile {
+            profile,
+            wallet: *wallet,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_profiles::instruction::UpdateProfile {
+            profile_cid: profile_cid.map(str::to_string),
+            is_public: None,
+        }
+        .data(),
+    }
+}
+
+#[tokio::test]
+async fn rejects_oversized_and_malformed_cids() {
+    let mut ctx = start().await;
+    let wallet = ctx.payer.pubkey();
+
+    let ix = create_ix(&wallet, &format!("b{}", "a".repeat(100)));
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::ProfileCidTooLong.into());
+    let ix = create_ix(&wallet, "arweave://not-a-profile");
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::InvalidProfileCid.into());
+
+    let ix = create_ix(&wallet, CID);
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let ix = update_ix(&wallet, profile_pda(&wallet), Some(""));
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::InvalidProfileCid.into());
+}
+
+#[tokio::test]
+async fn grows_profiles_for_longer_cids() {
+    let mut ctx = start().await;
+    let wallet = ctx.payer.pubkey();
+
+    let ix = create_ix(&wallet, CID);
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (_, len) = fetch_profile(&mut ctx, &wallet).await;
+    assert_eq!(len, 8 + Profile::space(CID));
+
+    let ix = update_ix(&wallet, profile_pda(&wallet), Some(CID_V1));
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (profile, len) = fetch_profile(&mut ctx, &wallet).await;
+    assert_eq!(profile.profile_cid, CID_V1);
+    assert_eq!(len, 8 + Profile::space(CID_V1));
+
+    // Going back to a shorter CID keeps the space
+    let ix = update_ix(&wallet, profile_pda(&wallet), Some(CID));
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (profile, shrunk) = fetch_profile(&mut ctx, &wallet).await;
+    assert_eq!(profile.profile_cid, CID);
+    assert_eq!(shrunk, len);
+}
+
+#[tokio::test]
+async fn only_the_wallet_may_update_its_profile() {
+    let mut ctx = start().await;
+    let wallet = ctx.payer.pubkey();
+    let stranger = funded_wallet(&mut ctx).await;
+
+    let ix = create_ix(&wallet, CID);
+    send(&mut ctx, ix, &[]).await.unwrap();
+
+    // The profile address is derived from the signer, so someone else's fails the seeds check
+    let ix = update_ix(&stranger.pubkey(), profile_pda(&wallet), Some(CID_V1));
+    assert_error(
+        send(&mut ctx, ix, &[&stranger]).await,
+        anchor_lang::error::ErrorCode::ConstraintSeeds.into(),
+    );
+
+    let (profile, _) = fetch_profile(&mut ctx, &wallet).await;
+    assert_eq!(profile.profile_cid, CID);
+}
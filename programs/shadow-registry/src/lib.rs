@@ -1,6 +1,12 @@
 use anchor_lang::prelude::*;
 
-declare_id!("7Y8Zx9qR3sN2mP1wV5tU4fG6hK8jL0dA");
+declare_id!("EzNTEahGqF6srHRjbjyUmGRPezzhMih3hrEjoiKMnw5k");
+
+/// Most wallets besides the owner that may update a site
+pub const MAX_MAINTAINERS: usize = 5;
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+pub const MAX_STORAGE_CID_LEN: usize = 100;
 
 #[program]
 pub mod shadow_registry {
@@ -12,65 +18,287 @@ pub mod shadow_registry {
         description: String,
         storage_cid: String,
     ) -> Result<()> {
+        validate_site_fields(Some(&name), Some(&description), Some(&storage_cid))?;
+
+        let now = Clock::get()?.unix_timestamp;
         let site = &mut ctx.accounts.site;
         site.owner = ctx.accounts.owner.key();
         site.program_address = ctx.accounts.program_account.key();
         site.name = name;
         site.description = description;
         site.storage_cid = storage_cid;
-        site.created_at = Clock::get()?.unix_timestamp;
-        site.updated_at = Clock::get()?.unix_timestamp;
+        site.created_at = now;
+        site.updated_at = now;
+        site.version = 0;
+        site.maintainers = Vec::new();
+
+        record_deployment(site, &mut ctx.accounts.deployment, ctx.accounts.owner.key(), None, now);
 
         msg!("Site registered: {}", site.program_address);
         Ok(())
     }
 
+    /// Change the site's name or description; content goes through `deploy_site`
+    ///
+    /// Like deploys and rollbacks, the owner or any maintainer may sign.
     pub fn update_site(
         ctx: Context<UpdateSite>,
         name: Option<String>,
         description: Option<String>,
-        storage_cid: Option<String>,
     ) -> Result<()> {
+        validate_site_fields(name.as_deref(), description.as_deref(), None)?;
         let site = &mut ctx.accounts.site;
-        
+
         if let Some(n) = name {
             site.name = n;
         }
         if let Some(d) = description {
             site.description = d;
         }
-        if let Some(cid) = storage_cid {
-            site.storage_cid = cid;
+
+        site.updated_at = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    /// Point the site at new content, logged as the next version
+    pub fn deploy_site(ctx: Context<DeploySite>, storage_cid: String) -> Result<()> {
+        validate_site_fields(None, None, Some(&storage_cid))?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let site = &mut ctx.accounts.site;
+        site.storage_cid = storage_cid;
+
+        record_deployment(site, &mut ctx.accounts.deployment, ctx.accounts.authority.key(), None, now);
+        Ok(())
+    }
+
+    /// Point the site back at an earlier version's content
+    ///
+    /// The log only grows: a rollback is itself a new version that
+    /// remembers which one it restored.
+    pub fn rollback_site(ctx: Context<RollbackSite>, version: u32) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let site = &mut ctx.accounts.site;
+        let target = &ctx.accounts.target;
+        require!(version < site.version, ShadowError::InvalidVersion);
+        require!(target.storage_cid != site.storage_cid, ShadowError::AlreadyDeployed);
+
+        site.storage_cid = target.storage_cid.clone();
+        record_deployment(site, &mut ctx.accounts.deployment, ctx.accounts.authority.key(), Some(version), now);
+        Ok(())
+    }
+
+    /// Hand the site to `new_owner`; maintainers are cleared for them to choose
+    pub fn transfer_site(ctx: Context<OwnerOnly>, new_owner: Pubkey) -> Result<()> {
+        let site = &mut ctx.accounts.site;
+        require_keys_neq!(new_owner, site.owner, ShadowError::InvalidNewOwner);
+
+        let previous_owner = site.owner;
+        site.owner = new_owner;
+        site.maintainers.clear();
+        site.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(SiteTransferred {
+            program_address: site.program_address,
+            previous_owner,
+            new_owner,
+        });
+        Ok(())
+    }
+
+    /// Replace the wallets allowed to update, deploy and roll back the site
+    pub fn set_maintainers(ctx: Context<SetMaintainers>, maintainers: Vec<Pubkey>) -> Result<()> {
+        let site = &mut ctx.accounts.site;
+        require!(maintainers.len() <= MAX_MAINTAINERS, ShadowError::TooManyMaintainers);
+        for (i, maintainer) in maintainers.iter().enumerate() {
+            require!(*maintainer != site.owner, ShadowError::InvalidMaintainer);
+            require!(!maintainers[..i].contains(maintainer), ShadowError::InvalidMaintainer);
         }
-        
+
+        site.maintainers = maintainers;
         site.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(SiteMaintainersChanged {
+            program_address: site.program_address,
+            maintainers: site.maintainers.clone(),
+        });
+        Ok(())
+    }
+
+    /// Close deployment log entries passed as remaining accounts, returning their rent
+    ///
+    /// Lets a long log be cleared over several transactions before `close_site`.
+    pub fn close_deployments<'info>(ctx: Context<'_, '_, 'info, 'info, OwnerOnly<'info>>) -> Result<()> {
+        let site = &ctx.accounts.site;
+        close_log_entries(site, ctx.remaining_accounts, &ctx.accounts.owner)
+    }
+
+    /// Close the site, and any deployments passed as remaining accounts,
+    /// returning their rent to the owner
+    ///
+    /// Deployments left open keep the site from being registered again.
+    pub fn close_site<'info>(ctx: Context<'_, '_, 'info, 'info, CloseSite<'info>>) -> Result<()> {
+        let site = &ctx.accounts.site;
+        close_log_entries(site, ctx.remaining_accounts, &ctx.accounts.owner)?;
+
+        emit!(SiteClosed {
+            program_address: site.program_address,
+            owner: site.owner,
+        });
         Ok(())
     }
 }
 
+fn validate_site_fields(
+    name: Option<&str>,
+    description: Option<&str>,
+    storage_cid: Option<&str>,
+) -> Result<()> {
+    if let Some(name) = name {
+        require!(name.len() <= MAX_NAME_LEN, ShadowError::NameTooLong);
+        require!(!name.chars().any(char::is_control), ShadowError::InvalidName);
+    }
+    if let Some(description) = description {
+        require!(description.len() <= MAX_DESCRIPTION_LEN, ShadowError::DescriptionTooLong);
+    }
+    if let Some(storage_cid) = storage_cid {
+        require!(storage_cid.len() <= MAX_STORAGE_CID_LEN, ShadowError::StorageCidTooLong);
+        require!(is_valid_storage_cid(storage_cid), ShadowError::InvalidStorageCid);
+    }
+    Ok(())
+}
+
+/// `arweave://<tx id>`, or an IPFS CID with or without `ipfs://`
+fn is_valid_storage_cid(storage_cid: &str) -> bool {
+    let is_base58 = |b: u8| b.is_ascii_alphanumeric() && !matches!(b, b'0' | b'O' | b'I' | b'l');
+
+    if let Some(tx_id) = storage_cid.strip_prefix("arweave://") {
+        return tx_id.len() == 43
+            && tx_id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    }
+    let cid = storage_cid.strip_prefix("ipfs://").unwrap_or(storage_cid);
+    match cid.as_bytes() {
+        // CIDv0: base58btc sha2-256 multihash
+        [b'Q', b'm', ..] => cid.len() == 46 && cid.bytes().all(is_base58),
+        // CIDv1 in base32 or base58btc multibase
+        [b'b', rest @ ..] if rest.len() >= 8 => rest.iter().all(|&b| b.is_ascii_lowercase() || (b'2'..=b'7').contains(&b)),
+        [b'z', rest @ ..] if rest.len() >= 8 => rest.iter().all(|&b| is_base58(b)),
+        _ => false,
+    }
+}
+
+/// Account size for `site` once the given fields change
+///
+/// Accounts only grow: shrinking would refund rent the owner paid to
+/// whichever maintainer signed.
+fn grown_site_space(
+    site: &Account<Site>,
+    name: Option<&str>,
+    description: Option<&str>,
+    storage_cid: Option<&str>,
+    maintainers: Option<usize>,
+) -> usize {
+    let needed = 8 + Site::space(
+        name.unwrap_or(&site.name),
+        description.unwrap_or(&site.description),
+        storage_cid.unwrap_or(&site.storage_cid),
+        maintainers.unwrap_or(site.maintainers.len()),
+    );
+    needed.max(site.to_account_info().data_len())
+}
+
+fn close_log_entries<'info>(
+    site: &Account<'info, Site>,
+    entries: &'info [AccountInfo<'info>],
+    owner: &Signer<'info>,
+) -> Result<()> {
+    for info in entries {
+        let deployment = Account::<Deployment>::try_from(info)?;
+        require_keys_eq!(deployment.site, site.program_address, ShadowError::WrongSite);
+        deployment.close(owner.to_account_info())?;
+    }
+    Ok(())
+}
+
+/// Bump the site's version and write its deployment record
+fn record_deployment(
+    site: &mut Account<Site>,
+    deployment: &mut Account<Deployment>,
+    deployer: Pubkey,
+    rollback_of: Option<u32>,
+    now: i64,
+) {
+    site.version += 1;
+    site.updated_at = now;
+
+    deployment.site = site.program_address;
+    deployment.version = site.version;
+    deployment.storage_cid = site.storage_cid.clone();
+    deployment.deployer = deployer;
+    deployment.deployed_at = now;
+    deployment.rollback_of = rollback_of;
+
+    emit!(SiteDeployed {
+        program_address: site.program_address,
+        version: site.version,
+        storage_cid: site.storage_cid.clone(),
+        deployer,
+        rollback_of,
+    });
+}
+
 #[derive(Accounts)]
 #[instruction(name: String, description: String, storage_cid: String)]
 pub struct RegisterSite<'info> {
     #[account(
         init,
         payer = owner,
-        space = 8 + Site::LEN,
+        space = 8 + Site::space(&name, &description, &storage_cid, 0),
         seeds = [b"site", program_account.key().as_ref()],
         bump
     )]
     pub site: Account<'info, Site>,
-    
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Deployment::space(&storage_cid),
+        seeds = [b"deployment", program_account.key().as_ref(), &1u32.to_le_bytes()],
+        bump
+    )]
+    pub deployment: Account<'info, Deployment>,
+
     /// CHECK: The program account being registered
     pub program_account: UncheckedAccount<'info>,
-    
+
     #[account(mut)]
     pub owner: Signer<'info>,
-    
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
+#[instruction(name: Option<String>, description: Option<String>)]
 pub struct UpdateSite<'info> {
+    #[account(
+        mut,
+        seeds = [b"site", site.program_address.as_ref()],
+        bump,
+        constraint = site.can_maintain(&authority.key()) @ ShadowError::Unauthorized,
+        realloc = grown_site_space(&site, name.as_deref(), description.as_deref(), None, None),
+        realloc::payer = authority,
+        realloc::zero = false
+    )]
+    pub site: Account<'info, Site>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OwnerOnly<'info> {
     #[account(
         mut,
         seeds = [b"site", site.program_address.as_ref()],
@@ -78,8 +306,108 @@ pub struct UpdateSite<'info> {
         has_one = owner @ ShadowError::Unauthorized
     )]
     pub site: Account<'info, Site>,
-    
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(maintainers: Vec<Pubkey>)]
+pub struct SetMaintainers<'info> {
+    #[account(
+        mut,
+        seeds = [b"site", site.program_address.as_ref()],
+        bump,
+        has_one = owner @ ShadowError::Unauthorized,
+        realloc = grown_site_space(&site, None, None, None, Some(maintainers.len())),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub site: Account<'info, Site>,
+
+    #[account(mut)]
     pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSite<'info> {
+    #[account(
+        mut,
+        seeds = [b"site", site.program_address.as_ref()],
+        bump,
+        has_one = owner @ ShadowError::Unauthorized,
+        close = owner
+    )]
+    pub site: Account<'info, Site>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(storage_cid: String)]
+pub struct DeploySite<'info> {
+    #[account(
+        mut,
+        seeds = [b"site", site.program_address.as_ref()],
+        bump,
+        constraint = site.can_maintain(&authority.key()) @ ShadowError::Unauthorized,
+        realloc = grown_site_space(&site, None, None, Some(&storage_cid), None),
+        realloc::payer = authority,
+        realloc::zero = false
+    )]
+    pub site: Account<'info, Site>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Deployment::space(&storage_cid),
+        seeds = [b"deployment", site.program_address.as_ref(), &(site.version + 1).to_le_bytes()],
+        bump
+    )]
+    pub deployment: Account<'info, Deployment>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(version: u32)]
+pub struct RollbackSite<'info> {
+    #[account(
+        mut,
+        seeds = [b"site", site.program_address.as_ref()],
+        bump,
+        constraint = site.can_maintain(&authority.key()) @ ShadowError::Unauthorized,
+        realloc = grown_site_space(&site, None, None, Some(&target.storage_cid), None),
+        realloc::payer = authority,
+        realloc::zero = false
+    )]
+    pub site: Account<'info, Site>,
+
+    #[account(
+        seeds = [b"deployment", site.program_address.as_ref(), &version.to_le_bytes()],
+        bump
+    )]
+    pub target: Account<'info, Deployment>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Deployment::space(&target.storage_cid),
+        seeds = [b"deployment", site.program_address.as_ref(), &(site.version + 1).to_le_bytes()],
+        bump
+    )]
+    pub deployment: Account<'info, Deployment>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[account]
@@ -91,15 +419,103 @@ pub struct Site {
     pub storage_cid: String,
     pub created_at: i64,
     pub updated_at: i64,
+    pub version: u32, // Latest deployment; 0 for sites registered before versioning
+    pub maintainers: Vec<Pubkey>, // May update, deploy and roll back, but not transfer or close
 }
 
 impl Site {
-    pub const LEN: usize = 32 + 32 + (4 + 100) + (4 + 500) + (4 + 100) + 8 + 8;
+    /// Largest a site can get; accounts are sized to their contents and
+    /// grown with realloc as fields do
+    pub const LEN: usize = Self::space_for(MAX_NAME_LEN, MAX_DESCRIPTION_LEN, MAX_STORAGE_CID_LEN, MAX_MAINTAINERS);
+
+    pub fn space(name: &str, description: &str, storage_cid: &str, maintainers: usize) -> usize {
+        Self::space_for(name.len(), description.len(), storage_cid.len(), maintainers)
+    }
+
+    const fn space_for(name: usize, description: usize, storage_cid: usize, maintainers: usize) -> usize {
+        32 + 32 + (4 + name) + (4 + description) + (4 + storage_cid) + 8 + 8 + 4 + (4 + 32 * maintainers)
+    }
+
+    pub fn can_maintain(&self, wallet: &Pubkey) -> bool {
+        self.owner == *wallet || self.maintainers.contains(wallet)
+    }
+}
+
+/// One entry of a site's deployment log, at ["deployment", program, version]
+#[account]
+pub struct Deployment {
+    pub site: Pubkey,
+    pub version: u32,
+    pub storage_cid: String,
+    pub deployer: Pubkey,
+    pub deployed_at: i64,
+    pub rollback_of: Option<u32>,
+}
+
+impl Deployment {
+    pub const LEN: usize = Self::space_for(MAX_STORAGE_CID_LEN);
+
+    pub fn space(storage_cid: &str) -> usize {
+        Self::space_for(storage_cid.len())
+    }
+
+    const fn space_for(storage_cid: usize) -> usize {
+        32 + 4 + (4 + storage_cid) + 32 + 8 + (1 + 4)
+    }
+}
+
+#[event]
+pub struct SiteDeployed {
+    pub program_address: Pubkey,
+    pub version: u32,
+    pub storage_cid: String,
+    pub deployer: Pubkey,
+    pub rollback_of: Option<u32>,
+}
+
+#[event]
+pub struct SiteTransferred {
+    pub program_address: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct SiteMaintainersChanged {
+    pub program_address: Pubkey,
+    pub maintainers: Vec<Pubkey>,
+}
+
+#[event]
+pub struct SiteClosed {
+    pub program_address: Pubkey,
+    pub owner: Pubkey,
 }
 
 #[error_code]
 pub enum ShadowError {
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Can only roll back to an earlier version")]
+    InvalidVersion,
+    #[msg("That version's content is already deployed")]
+    AlreadyDeployed,
+    #[msg("New owner must differ from the current owner")]
+    InvalidNewOwner,
+    #[msg("Too many maintainers")]
+    TooManyMaintainers,
+    #[msg("Maintainers must be unique and not the owner")]
+    InvalidMaintainer,
+    #[msg("Deployment belongs to another site")]
+    WrongSite,
+    #[msg("Site name is longer than 100 bytes")]
+    NameTooLong,
+    #[msg("Site name contains control characters")]
+    InvalidName,
+    #[msg("Site description is longer than 500 bytes")]
+    DescriptionTooLong,
+    #[msg("Storage CID is longer than 100 bytes")]
+    StorageCidTooLong,
+    #[msg("Storage CID must be an IPFS CID or arweave:// transaction ID")]
+    InvalidStorageCid,
 }
-
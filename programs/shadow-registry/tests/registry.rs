@@ -0,0 +1,308 @@
+//! Runs natively with `cargo test -p shadow-registry`, or against the SBF build with
+//! `cargo test-sbf -p shadow-registry`
+
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use shadow_registry::{Deployment, ShadowError, Site, MAX_MAINTAINERS};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{Instruction, InstructionError},
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+const CID: &str = "ipfs://QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+const CID_V1: &str = "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+fn site_pda(program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"site", program.as_ref()], &shadow_registry::ID).0
+}
+
+fn deployment_pda(program: &Pubkey, version: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"deployment", program.as_ref(), &version.to_le_bytes()],
+        &shadow_registry::ID,
+    )
+    .0
+}
+
+/// Native entrypoint for `cargo test`; `test-sbf` loads the built program instead
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // Anchor's entry ties the slice to the accounts' lifetime, which the processor signature
+    // doesn't spell out; both live for the whole instruction
+    let accounts = unsafe { std::mem::transmute::<&[AccountInfo], &[AccountInfo]>(accounts) };
+    shadow_registry::entry(program_id, accounts, data)
+}
+
+async fn start() -> ProgramTestContext {
+    ProgramTest::new("shadow_registry", shadow_registry::ID, processor!(process))
+        .start_with_context()
+        .await
+}
+
+/// Send `ix` with the test payer covering fees alongside `signers`
+async fn send(
+    ctx: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = ctx.banks_client.get_latest_blockhash().await?;
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(&[ix], Some(&ctx.payer.pubkey()), &all_signers, blockhash);
+    ctx.banks_client.process_transaction(tx).await
+}
+
+async fn funded_wallet(ctx: &mut ProgramTestContext) -> Keypair {
+    let wallet = Keypair::new();
+    let ix = system_instruction::transfer(&ctx.payer.pubkey(), &wallet.pubkey(), LAMPORTS_PER_SOL);
+    send(ctx, ix, &[]).await.unwrap();
+    wallet
+}
+
+fn assert_error(result: Result<(), BanksClientError>, expected: ShadowError) {
+    match result.expect_err("transaction should fail").unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, u32::from(expected), "expected {:?}", expected)
+        }
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+async fn fetch_site(ctx: &mut ProgramTestContext, program: &Pubkey) -> (Site, usize) {
+    let account = ctx.banks_client.get_account(site_pda(program)).await.unwrap().unwrap();
+    let site = Site::try_deserialize(&mut account.data.as_slice()).unwrap();
+    (site, account.data.len())
+}
+
+fn register_ix(owner: &Pubkey, program: &Pubkey, name: &str, description: &str, storage_cid: &str) -> Instruction {
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts: shadow_registry::accounts::RegisterSite {
+            site: site_pda(program),
+            deployment: deployment_pda(program, 1),
+            program_account: *program,
+            owner: *owner,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_registry::instruction::RegisterSite {
+            name: name.to_string(),
+            description: description.to_string(),
+            storage_cid: storage_cid.to_string(),
+        }
+        .data(),
+    }
+}
+
+fn update_ix(authority: &Pubkey, program: &Pubkey, name: Option<&str>, description: Option<&str>) -> Instruction {
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts: shadow_registry::accounts::UpdateSite {
+            site: site_pda(program),
+            authority: *authority,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_registry::instruction::UpdateSite {
+            name: name.map(str::to_string),
+            description: description.map(str::to_string),
+        }
+        .data(),
+    }
+}
+
+fn deploy_ix(authority: &Pubkey, program: &Pubkey, next_version: u32, storage_cid: &str) -> Instruction {
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts: shadow_registry::accounts::DeploySite {
+            site: site_pda(program),
+            deployment: deployment_pda(program, next_version),
+            authority: *authority,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_registry::instruction::DeploySite { storage_cid: storage_cid.to_string() }.data(),
+    }
+}
+
+fn set_maintainers_ix(owner: &Pubkey, program: &Pubkey, maintainers: Vec<Pubkey>) -> Instruction {
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts: shadow_registry::accounts::SetMaintainers {
+            site: site_pda(program),
+            owner: *owner,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: shadow_registry::instruction::SetMaintainers { maintainers }.data(),
+    }
+}
+
+fn transfer_ix(owner: &Pubkey, program: &Pubkey, new_owner: Pubkey) -> Instruction {
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts: shadow_registry::accounts::OwnerOnly { site: site_pda(program), owner: *owner }
+            .to_account_metas(None),
+        data: shadow_registry::instruction::TransferSite { new_owner }.data(),
+    }
+}
+
+fn close_ix(owner: &Pubkey, program: &Pubkey, versions: &[u32]) -> Instruction {
+    let mut accounts = shadow_registry::accounts::CloseSite { site: site_pda(program), owner: *owner }
+        .to_account_metas(None);
+    accounts.extend(versions.iter().map(|v| solana_sdk::instruction::AccountMeta::new(deployment_pda(program, *v), false)));
+    Instruction {
+        program_id: shadow_registry::ID,
+        accounts,
+        data: shadow_registry::instruction::CloseSite {}.data(),
+    }
+}
+
+/// Register a site owned by the test payer
+async fn register(ctx: &mut ProgramTestContext) -> Pubkey {
+    let program = Pubkey::new_unique();
+    let ix = register_ix(&ctx.payer.pubkey(), &program, "Site", "", CID);
+    send(ctx, ix, &[]).await.unwrap();
+    program
+}
+
+#[tokio::test]
+async fn sizes_sites_to_their_contents() {
+    let mut ctx = start().await;
+    let program = register(&mut ctx).await;
+
+    let (site, len) = fetch_site(&mut ctx, &program).await;
+    assert_eq!(site.version, 1);
+    assert_eq!(len, 8 + Site::space("Site", "", CID, 0));
+    assert!(len < 8 + Site::LEN);
+
+    let deployment = ctx.banks_client.get_account(deployment_pda(&program, 1)).await.unwrap().unwrap();
+    assert_eq!(deployment.data.len(), 8 + Deployment::space(CID));
+}
+
+#[tokio::test]
+async fn rejects_oversized_and_malformed_fields() {
+    let mut ctx = start().await;
+    let owner = ctx.payer.pubkey();
+
+    let cases = [
+        ("x".repeat(101), String::new(), CID.to_string(), ShadowError::NameTooLong),
+        ("tab\tname".to_string(), String::new(), CID.to_string(), ShadowError::InvalidName),
+        (String::new(), "x".repeat(501), CID.to_string(), ShadowError::DescriptionTooLong),
+        (String::new(), String::new(), format!("ipfs://b{}", "a".repeat(100)), ShadowError::StorageCidTooLong),
+        (String::new(), String::new(), "https://example.com/site".to_string(), ShadowError::InvalidStorageCid),
+        (String::new(), String::new(), "ipfs://Qm0000".to_string(), ShadowError::InvalidStorageCid),
+    ];
+    for (name, description, storage_cid, expected) in cases {
+        let ix = register_ix(&owner, &Pubkey::new_unique(), &name, &description, &storage_cid);
+        assert_error(send(&mut ctx, ix, &[]).await, expected);
+    }
+
+    let arweave = format!("arweave://{}", "A".repeat(43));
+    let ix = register_ix(&owner, &Pubkey::new_unique(), "", "", &arweave);
+    send(&mut ctx, ix, &[]).await.unwrap();
+
+    let program = register(&mut ctx).await;
+    let ix = update_ix(&owner, &program, None, Some(&"x".repeat(501)));
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::DescriptionTooLong);
+    let ix = deploy_ix(&owner, &program, 2, "not a cid");
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::InvalidStorageCid);
+}
+
+#[tokio::test]
+async fn grows_sites_as_fields_grow() {
+    let mut ctx = start().await;
+    let owner = ctx.payer.pubkey();
+    let program = register(&mut ctx).await;
+
+    let description = "d".repeat(500);
+    let ix = update_ix(&owner, &program, Some("A longer site name"), Some(&description));
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (site, len) = fetch_site(&mut ctx, &program).await;
+    assert_eq!(site.description, description);
+    assert_eq!(len, 8 + Site::space("A longer site name", &description, CID, 0));
+
+    let ix = deploy_ix(&owner, &program, 2, CID_V1);
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let maintainers: Vec<Pubkey> = (0..MAX_MAINTAINERS).map(|_| Pubkey::new_unique()).collect();
+    let ix = set_maintainers_ix(&owner, &program, maintainers.clone());
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (site, len) = fetch_site(&mut ctx, &program).await;
+    assert_eq!(site.maintainers, maintainers);
+    assert_eq!(len, 8 + Site::space("A longer site name", &description, CID_V1, MAX_MAINTAINERS));
+
+    // Shorter values leave the account as it is
+    let ix = update_ix(&owner, &program, Some("S"), Some(""));
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (_, shrunk) = fetch_site(&mut ctx, &program).await;
+    assert_eq!(shrunk, len);
+}
+
+#[tokio::test]
+async fn only_the_owner_and_maintainers_may_change_a_site() {
+    let mut ctx = start().await;
+    let owner = ctx.payer.pubkey();
+    let program = register(&mut ctx).await;
+    let maintainer = funded_wallet(&mut ctx).await;
+    let stranger = funded_wallet(&mut ctx).await;
+
+    let ix = update_ix(&stranger.pubkey(), &program, Some("Mine"), None);
+    assert_error(send(&mut ctx, ix, &[&stranger]).await, ShadowError::Unauthorized);
+    let ix = deploy_ix(&stranger.pubkey(), &program, 2, CID_V1);
+    assert_error(send(&mut ctx, ix, &[&stranger]).await, ShadowError::Unauthorized);
+
+    let ix = set_maintainers_ix(&owner, &program, vec![owner]);
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::InvalidMaintainer);
+    let ix = set_maintainers_ix(&owner, &program, (0..=MAX_MAINTAINERS).map(|_| Pubkey::new_unique()).collect());
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::TooManyMaintainers);
+    let ix = set_maintainers_ix(&owner, &program, vec![maintainer.pubkey()]);
+    send(&mut ctx, ix, &[]).await.unwrap();
+
+    // Maintainers deploy, but cannot hand the site over or manage each other
+    let ix = deploy_ix(&maintainer.pubkey(), &program, 2, CID_V1);
+    send(&mut ctx, ix, &[&maintainer]).await.unwrap();
+    let ix = transfer_ix(&maintainer.pubkey(), &program, maintainer.pubkey());
+    assert_error(send(&mut ctx, ix, &[&maintainer]).await, ShadowError::Unauthorized);
+    let ix = set_maintainers_ix(&maintainer.pubkey(), &program, vec![stranger.pubkey()]);
+    assert_error(send(&mut ctx, ix, &[&maintainer]).await, ShadowError::Unauthorized);
+
+    let ix = transfer_ix(&owner, &program, stranger.pubkey());
+    send(&mut ctx, ix, &[]).await.unwrap();
+    let (site, _) = fetch_site(&mut ctx, &program).await;
+    assert_eq!(site.owner, stranger.pubkey());
+    assert!(site.maintainers.is_empty());
+
+    let ix = update_ix(&maintainer.pubkey(), &program, Some("Still mine"), None);
+    assert_error(send(&mut ctx, ix, &[&maintainer]).await, ShadowError::Unauthorized);
+}
+
+#[tokio::test]
+async fn closing_returns_rent_to_the_owner() {
+    let mut ctx = start().await;
+    let owner = ctx.payer.pubkey();
+    let program = register(&mut ctx).await;
+    let stranger = funded_wallet(&mut ctx).await;
+
+    let ix = close_ix(&stranger.pubkey(), &program, &[1]);
+    assert_error(send(&mut ctx, ix, &[&stranger]).await, ShadowError::Unauthorized);
+
+    // Another site's log entry can't be swept up with this one
+    let other = register(&mut ctx).await;
+    let mut ix = close_ix(&owner, &program, &[]);
+    ix.accounts.push(solana_sdk::instruction::AccountMeta::new(deployment_pda(&other, 1), false));
+    assert_error(send(&mut ctx, ix, &[]).await, ShadowError::WrongSite);
+
+    let ix = close_ix(&owner, &program, &[1]);
+    send(&mut ctx, ix, &[]).await.unwrap();
+    assert!(ctx.banks_client.get_account(site_pda(&program)).await.unwrap().is_none());
+    assert!(ctx.banks_client.get_account(deployment_pda(&program, 1)).await.unwrap().is_none());
+
+    // With its log gone the address can be registered afresh
+    let ix = register_ix(&owner, &program, "Again", "", CID);
+    send(&mut ctx, ix, &[]).await.unwrap();
+}
@@ -0,0 +1,257 @@
+import * as anchor from "@coral-xyz/anchor";
+import { BankrunProvider, startAnchor } from "anchor-bankrun";
+import { Clock, ProgramTestContext } from "solana-bankrun";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram } from "@solana/web3.js";
+import { createHash } from "crypto";
+import { expect } from "chai";
+import { ShadowDomains } from "../target/types/shadow_domains";
+
+const IDL = require("../target/idl/shadow_domains.json");
+const PROGRAM_ID = new PublicKey("7sn3PyHKqdUG28HXYa1oCdZk9iQwx5tnJaYK6WQRrTSH");
+
+const YEAR = 365 * 24 * 60 * 60;
+const GRACE = 30 * 24 * 60 * 60;
+
+function domainPda(name: string): PublicKey {
+  const nameHash = createHash("sha256").update(name).digest();
+  return PublicKey.findProgramAddressSync([Buffer.from("domain"), nameHash], PROGRAM_ID)[0];
+}
+
+function funded(keypair: Keypair) {
+  return {
+    address: keypair.publicKey,
+    info: {
+      lamports: 10 * LAMPORTS_PER_SOL,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    },
+  };
+}
+
+async function expectError(promise: Promise<unknown>, code: string) {
+  try {
+    await promise;
+  } catch (e) {
+    expect(String(e)).to.contain(code);
+    return;
+  }
+  expect.fail(`expected ${code}`);
+}
+
+describe("shadow-domains", () => {
+  const alice = Keypair.generate();
+  const bob = Keypair.generate();
+  const target = Keypair.generate().publicKey;
+
+  let context: ProgramTestContext;
+  let program: anchor.Program<ShadowDomains>;
+
+  beforeEach(async () => {
+    context = await startAnchor("", [], [funded(alice), funded(bob)]);
+    const provider = new BankrunProvider(context);
+    program = new anchor.Program<ShadowDomains>(IDL, PROGRAM_ID, provider);
+  });
+
+  async function now(): Promise<number> {
+    const clock = await context.banksClient.getClock();
+    return Number(clock.unixTimestamp);
+  }
+
+  async function warpTo(unixTimestamp: number) {
+    const clock = await context.banksClient.getClock();
+    context.setClock(
+      new Clock(
+        clock.slot + 1n,
+        clock.epochStartTimestamp,
+        clock.epoch,
+        clock.leaderScheduleEpoch,
+        BigInt(unixTimestamp),
+      ),
+    );
+  }
+
+  function register(name: string, owner: Keypair) {
+    return program.methods
+      .registerDomain(name, target)
+      .accounts({ domain: domainPda(name), owner: owner.publicKey, systemProgram: SystemProgram.programId })
+      .signers([owner])
+      .rpc();
+  }
+
+  it("registers a domain for one year", async () => {
+    const start = await now();
+    await register("alice.shadow", alice);
+
+    const record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(record.name).to.equal("alice.shadow");
+    expect(record.owner.toBase58()).to.equal(alice.publicKey.toBase58());
+    expect(record.target.toBase58()).to.equal(target.toBase58());
+    expect(record.expiresAt.toNumber()).to.equal(start + YEAR);
+  });
+
+  it("rejects invalid names and duplicate registrations", async () => {
+    await expectError(register("Alice.shadow", alice), "InvalidName");
+    await expectError(register("alice.sol", alice), "InvalidName");
+    await expectError(register("-alice.shadow", alice), "InvalidName");
+    await expectError(register("app.alice.shadow", alice), "InvalidName");
+
+    await register("alice.shadow", alice);
+    await expectError(register("alice.shadow", bob), "already in use");
+  });
+
+  it("lets only the owner update the target", async () => {
+    await register("alice.shadow", alice);
+    const newTarget = Keypair.generate().publicKey;
+
+    await expectError(
+      program.methods
+        .updateTarget("alice.shadow", newTarget)
+        .accounts({ domain: domainPda("alice.shadow"), owner: bob.publicKey })
+        .signers([bob])
+        .rpc(),
+      "Unauthorized",
+    );
+
+    await program.methods
+      .updateTarget("alice.shadow", newTarget)
+      .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+      .signers([alice])
+      .rpc();
+    const record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(record.target.toBase58()).to.equal(newTarget.toBase58());
+  });
+
+  function offer(name: string, recipient: PublicKey, expiresIn: number) {
+    return program.methods
+      .offerTransfer(name, recipient, new anchor.BN(expiresIn))
+      .accounts({ domain: domainPda(name), owner: alice.publicKey })
+      .signers([alice])
+      .rpc();
+  }
+
+  function accept(name: string, recipient: Keypair) {
+    return program.methods
+      .acceptTransfer(name)
+      .accounts({ domain: domainPda(name), recipient: recipient.publicKey })
+      .signers([recipient])
+      .rpc();
+  }
+
+  it("transfers ownership once the recipient accepts", async () => {
+    await register("alice.shadow", alice);
+    await offer("alice.shadow", bob.publicKey, 24 * 60 * 60);
+
+    // The offer alone moves nothing
+    let record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(record.owner.toBase58()).to.equal(alice.publicKey.toBase58());
+    expect(record.pendingOwner.toBase58()).to.equal(bob.publicKey.toBase58());
+
+    await expectError(accept("alice.shadow", Keypair.generate()), "NoPendingTransfer");
+    await accept("alice.shadow", bob);
+
+    record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(record.owner.toBase58()).to.equal(bob.publicKey.toBase58());
+    expect(record.pendingOwner).to.equal(null);
+
+    await expectError(
+      program.methods
+        .renewDomain("alice.shadow")
+        .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+        .signers([alice])
+        .rpc(),
+      "Unauthorized",
+    );
+  });
+
+  it("rejects expired and cancelled offers", async () => {
+    await register("alice.shadow", alice);
+    await expectError(offer("alice.shadow", alice.publicKey, 60), "InvalidRecipient");
+    await expectError(offer("alice.shadow", bob.publicKey, 31 * 24 * 60 * 60), "InvalidOfferPeriod");
+
+    await offer("alice.shadow", bob.publicKey, 60);
+    await warpTo((await now()) + 61);
+    await expectError(accept("alice.shadow", bob), "OfferExpired");
+
+    await offer("alice.shadow", bob.publicKey, 60);
+    await program.methods
+      .cancelTransfer("alice.shadow")
+      .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+      .signers([alice])
+      .rpc();
+    await expectError(accept("alice.shadow", bob), "NoPendingTransfer");
+  });
+
+  it("freezes expired domains until renewed in grace", async () => {
+    await register("alice.shadow", alice);
+    const record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    const expiresAt = record.expiresAt.toNumber();
+
+    await warpTo(expiresAt + 1);
+    await expectError(
+      program.methods
+        .updateTarget("alice.shadow", Keypair.generate().publicKey)
+        .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+        .signers([alice])
+        .rpc(),
+      "DomainExpired",
+    );
+
+    await program.methods
+      .renewDomain("alice.shadow")
+      .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+      .signers([alice])
+      .rpc();
+    const renewed = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(renewed.expiresAt.toNumber()).to.equal(expiresAt + 1 + YEAR);
+  });
+
+  it("refuses renewal after the grace period", async () => {
+    await register("alice.shadow", alice);
+    const record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+
+    await warpTo(record.expiresAt.toNumber() + GRACE + 1);
+    await expectError(
+      program.methods
+        .renewDomain("alice.shadow")
+        .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey })
+        .signers([alice])
+        .rpc(),
+      "GracePeriodOver",
+    );
+  });
+
+  it("releases to anyone only after the grace period", async () => {
+    await register("alice.shadow", alice);
+    const record = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+
+    const release = () =>
+      program.methods
+        .releaseDomain("alice.shadow")
+        .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey, caller: bob.publicKey })
+        .signers([bob])
+        .rpc();
+
+    await warpTo(record.expiresAt.toNumber() + 1);
+    await expectError(release(), "Unauthorized");
+
+    await warpTo(record.expiresAt.toNumber() + GRACE + 1);
+    await release();
+    expect(await context.banksClient.getAccount(domainPda("alice.shadow"))).to.equal(null);
+
+    // The name is free again
+    await register("alice.shadow", bob);
+    const reclaimed = await program.account.domainRecord.fetch(domainPda("alice.shadow"));
+    expect(reclaimed.owner.toBase58()).to.equal(bob.publicKey.toBase58());
+  });
+
+  it("lets the owner release at any time", async () => {
+    await register("alice.shadow", alice);
+    await program.methods
+      .releaseDomain("alice.shadow")
+      .accounts({ domain: domainPda("alice.shadow"), owner: alice.publicKey, caller: alice.publicKey })
+      .signers([alice])
+      .rpc();
+    expect(await context.banksClient.getAccount(domainPda("alice.shadow"))).to.equal(null);
+  });
+});
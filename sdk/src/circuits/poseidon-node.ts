@@ -12,18 +12,21 @@ const BACKEND_URL = process.env.SHADOW_BACKEND_URL || "http://localhost:8080"
  */
 export async function uploadToIPFS(
   file: Buffer | Uint8Array,
-  filename: string
+  filename: string,
+  authHeader: string
 ): Promise<string> {
   const formData = new FormData()
-  formData.append("file", file, filename)
+  formData.append("file", Buffer.from(file), filename)
   
   const response = await axios.post(`${BACKEND_URL}/api/upload/ipfs`, formData, {
     headers: {
       ...formData.getHeaders(),
+      "X-Shadow-Auth": authHeader,
     },
+    maxBodyLength: Infinity,
   })
   
-  return response.data.cid || response.data.ipfs_hash || `ipfs://${response.data.IpfsHash}`
+  return response.data.uri || response.data.cid
 }
 
 /**
@@ -31,26 +34,32 @@ export async function uploadToIPFS(
  */
 export async function uploadToArweave(
   file: Buffer | Uint8Array,
-  filename: string
+  filename: string,
+  authHeader: string
 ): Promise<string> {
   const response = await axios.post(
     `${BACKEND_URL}/api/upload/arweave`,
     file,
     {
+      params: { name: filename },
       headers: {
         "Content-Type": "application/octet-stream",
+        "X-Shadow-Auth": authHeader,
       },
+      maxBodyLength: Infinity,
     }
   )
   
-  return response.data.tx_id || response.data.id || `arweave://${response.data.id}`
+  return response.data.uri || response.data.tx_id
 }
 
 /**
- * Upload multiple files to IPFS (directory)
+ * Upload multiple files as one directory; IPFS returns a root CID, Arweave a path manifest
  */
-export async function uploadDirectoryToIPFS(
-  files: Array<{ path: string; content: Buffer }>
+export async function uploadDirectory(
+  storage: "ipfs" | "arweave",
+  files: Array<{ path: string; content: Buffer }>,
+  authHeader: string
 ): Promise<string> {
   const formData = new FormData()
   
@@ -60,13 +69,80 @@ export async function uploadDirectoryToIPFS(
     })
   }
   
-  const response = await axios.post(`${BACKEND_URL}/api/upload/ipfs/directory`, formData, {
+  const response = await axios.post(`${BACKEND_URL}/api/upload/${storage}/directory`, formData, {
     headers: {
       ...formData.getHeaders(),
+      "X-Shadow-Auth": authHeader,
     },
+    maxBodyLength: Infinity,
   })
   
-  return response.data.cid || `ipfs://${response.data.IpfsHash}`
+  return response.data.uri
+}
+
+/**
+ * Upload multiple files to IPFS (directory)
+ */
+export async function uploadDirectoryToIPFS(
+  files: Array<{ path: string; content: Buffer }>,
+  authHeader: string
+): Promise<string> {
+  return uploadDirectory("ipfs", files, authHeader)
+}
+
+/**
+ * Upload a large file in resumable chunks; an interrupted upload picks up where the server left off
+ */
+export async function uploadResumable(
+  storage: "ipfs" | "arweave",
+  filePath: string,
+  authHeader: () => Promise<string>
+): Promise<string> {
+  const size = fs.statSync(filePath).size
+  const name = filePath.split(/[\\/]/).pop() || "upload"
+
+  const created = await axios.post(
+    `${BACKEND_URL}/api/upload/sessions`,
+    { scheme: storage, name, size },
+    { headers: { "X-Shadow-Auth": await authHeader() } }
+  )
+  const { id, chunk_bytes: chunkBytes } = created.data
+  let offset: number = created.data.offset
+
+  const fd = fs.openSync(filePath, "r")
+  try {
+    while (offset < size) {
+      const chunk = Buffer.alloc(Math.min(chunkBytes, size - offset))
+      fs.readSync(fd, chunk, 0, chunk.length, offset)
+      try {
+        const response = await axios.patch(`${BACKEND_URL}/api/upload/sessions/${id}`, chunk, {
+          headers: {
+            "Content-Type": "application/octet-stream",
+            "Upload-Offset": String(offset),
+            "X-Shadow-Auth": await authHeader(),
+          },
+          maxBodyLength: Infinity,
+        })
+        offset = response.data.offset
+      } catch (error) {
+        // Ask the server how much arrived before retrying
+        const status = await axios.get(`${BACKEND_URL}/api/upload/sessions/${id}`, {
+          headers: { "X-Shadow-Auth": await authHeader() },
+        })
+        if (status.data.offset === offset) {
+          throw error
+        }
+        offset = status.data.offset
+      }
+    }
+  } finally {
+    fs.closeSync(fd)
+  }
+
+  const completed = await axios.post(`${BACKEND_URL}/api/upload/sessions/${id}/complete`, null, {
+    headers: { "X-Shadow-Auth": await authHeader() },
+  })
+  return completed.data.uri
 }
 
 
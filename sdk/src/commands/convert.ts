@@ -9,7 +9,7 @@ import { glob } from "glob"
 import { Keypair, PublicKey } from "@solana/web3.js"
 import { createMint, getAssociatedTokenAddress, mintTo, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID } from "@solana/spl-token"
 import { Connection } from "@solana/web3.js"
-import { uploadToIPFS, uploadToArweave, uploadDirectoryToIPFS } from "../circuits/poseidon-node"
+import { uploadDirectory } from "../circuits/poseidon-node"
 import { registerDomain } from "../circuits/olympus"
 import { createAuthHeader } from "../circuits/ares"
 import { validateDomain } from "../circuits/apollo"
@@ -106,13 +106,12 @@ export async function convertSite(
       }))
     )
 
-    let storageCid: string
-    if (storage === "ipfs") {
-      storageCid = await uploadDirectoryToIPFS(fileContents)
-    } else {
-      // For Arweave, upload first file (or bundle in production)
-      storageCid = await uploadToArweave(fileContents[0].content, fileContents[0].path)
-    }
+    // IPFS gets one root CID, Arweave a path manifest over the uploaded files
+    const storageCid = await uploadDirectory(
+      storage as "ipfs" | "arweave",
+      fileContents,
+      await createAuthHeader(wallet.publicKey, wallet)
+    )
 
     spinner.succeed(`Assets uploaded: ${storageCid}`)
 
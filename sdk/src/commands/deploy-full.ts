@@ -22,7 +22,7 @@ import {
   TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
 } from "@solana/spl-token"
-import { uploadToIPFS, uploadToArweave, uploadDirectoryToIPFS } from "../circuits/poseidon-node"
+import { uploadDirectory } from "../circuits/poseidon-node"
 import { registerDomain } from "../circuits/olympus"
 import { createAuthHeader } from "../circuits/ares"
 import { validateDomain } from "../circuits/apollo"
@@ -94,7 +94,8 @@ async function deploySolanaProgram(
  * Step 2: Upload assets to IPFS/Arweave
  */
 async function uploadAssets(
-  storage: "ipfs" | "arweave"
+  storage: "ipfs" | "arweave",
+  wallet: Keypair
 ): Promise<string> {
   const spinner = ora(`Uploading assets to ${storage}...`).start()
 
@@ -119,23 +120,15 @@ async function uploadAssets(
 
     spinner.text = `Found ${files.length} files to upload`
 
-    // Use Poseidon circuit for storage
-    let cid: string
-    if (storage === "ipfs") {
-      // Upload all files as a directory
-      const fileContents = await Promise.all(
-        files.map(async (file) => ({
-          path: file,
-          content: await fs.readFile(file),
-        }))
-      )
-      cid = await uploadDirectoryToIPFS(fileContents)
-    } else {
-      // For Arweave, upload first file (or bundle in production)
-      const firstFile = files[0]
-      const content = await fs.readFile(firstFile)
-      cid = await uploadToArweave(content, path.basename(firstFile))
-    }
+    // Use Poseidon circuit for storage; the site goes up as one directory either way
+    const fileContents = await Promise.all(
+      files.map(async (file) => ({
+        path: file,
+        content: await fs.readFile(file),
+      }))
+    )
+    const authHeader = await createAuthHeader(wallet.publicKey, wallet)
+    const cid = await uploadDirectory(storage, fileContents, authHeader)
 
     spinner.succeed(`Assets uploaded: ${cid}`)
     return cid
@@ -283,7 +276,7 @@ export async function deployFull(
     const programAddress = await deploySolanaProgram(config, wallet, network)
 
     // Step 2: Upload assets
-    const storageCid = await uploadAssets(config.storage)
+    const storageCid = await uploadAssets(config.storage, wallet)
 
     // Step 3: Mint token (optional)
     let tokenMint: PublicKey | null = null